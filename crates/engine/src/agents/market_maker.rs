@@ -0,0 +1,160 @@
+use crate::agents::{Agent, AgentContext, FillTracker};
+use crate::simulator::SimTime;
+use mm_core::lob_core::{
+    OrderQty, Price,
+    market_events::{ClientEvent, MarketEvent, MarketEventType},
+    market_orders::OrderSide,
+};
+
+/// Simple market maker that quotes a fixed half-spread around the last traded price. On every wake-up it cancels its
+/// resting quotes and posts a fresh bid and ask, skipping the side that would push its inventory past `max_inventory`
+#[derive(Clone)]
+pub struct MarketMakerAgent {
+    reference_price: Price,
+    half_spread: Price,
+    qty: OrderQty,
+    max_inventory: i64,
+    requote_interval: SimTime,
+    fills: FillTracker,
+}
+impl MarketMakerAgent {
+    /// Prices are in cents, the requote interval is in nanoseconds
+    pub fn new(
+        initial_price: Price,
+        half_spread: Price,
+        qty: OrderQty,
+        max_inventory: i64,
+        requote_interval: SimTime,
+    ) -> Self {
+        Self {
+            reference_price: initial_price,
+            half_spread,
+            qty,
+            max_inventory,
+            requote_interval,
+            fills: FillTracker::new(),
+        }
+    }
+    /// Net inventory of the market maker, positive when long
+    pub fn inventory(&self) -> i64 {
+        self.fills.position()
+    }
+}
+impl Agent for MarketMakerAgent {
+    fn on_market_event(&mut self, event: &MarketEvent, _ctx: &mut AgentContext) {
+        if let MarketEventType::Trade(trade) = event.kind {
+            self.reference_price = trade.price;
+        }
+    }
+    fn on_client_event(&mut self, event: &ClientEvent, _ctx: &mut AgentContext) {
+        self.fills.on_client_event(event);
+    }
+    fn on_wakeup(&mut self, ctx: &mut AgentContext) {
+        let resting: Vec<_> = self.fills.live_orders().collect();
+        for (order_id, side) in resting {
+            let cancel_id = ctx.cancel(side, order_id);
+            self.fills.cancel(order_id, cancel_id);
+        }
+        let inventory = self.inventory();
+        if inventory < self.max_inventory {
+            let price = self.reference_price.saturating_sub(self.half_spread).max(1);
+            let order_id = ctx.limit(OrderSide::Bid, self.qty, price);
+            self.fills.track(order_id, OrderSide::Bid, self.qty);
+        }
+        if inventory > -self.max_inventory {
+            let price = self.reference_price.saturating_add(self.half_spread);
+            let order_id = ctx.limit(OrderSide::Ask, self.qty, price);
+            self.fills.track(order_id, OrderSide::Ask, self.qty);
+        }
+        ctx.wake_after(self.requote_interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mm_core::lob_core::{
+        market_events::{ClientEventType, LiquidityFlag, TradeEvent},
+        market_orders::{Order, OrderType},
+    };
+
+    #[test]
+    fn requotes_around_last_trade() {
+        let mut agent = MarketMakerAgent::new(1000, 5, 10, 100, 1_000);
+        let mut id_counter = 0;
+        let mut orders: Vec<Order> = Vec::new();
+        let mut wakeups: Vec<SimTime> = Vec::new();
+        let mut ctx = AgentContext::new(0, 1, &mut id_counter, &mut orders, &mut wakeups);
+        agent.on_wakeup(&mut ctx);
+        agent.on_market_event(
+            &MarketEvent::new(
+                0,
+                0,
+                MarketEventType::Trade(TradeEvent {
+                    price: 1020,
+                    quantity: 1,
+                    aggressor_side: OrderSide::Bid,
+                    maker_id: 0,
+                }),
+            ),
+            &mut ctx,
+        );
+        agent.on_wakeup(&mut ctx);
+        let kinds: Vec<OrderType> = orders.iter().map(|o| o.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                OrderType::Limit {
+                    qty: 10,
+                    price: 995
+                },
+                OrderType::Limit {
+                    qty: 10,
+                    price: 1005
+                },
+                OrderType::Cancel { old_id: 0 },
+                OrderType::Cancel { old_id: 1 },
+                OrderType::Limit {
+                    qty: 10,
+                    price: 1015
+                },
+                OrderType::Limit {
+                    qty: 10,
+                    price: 1025
+                },
+            ]
+        );
+        assert_eq!(wakeups, vec![1_000, 1_000]);
+    }
+
+    #[test]
+    fn stops_bidding_at_inventory_limit() {
+        let mut agent = MarketMakerAgent::new(1000, 5, 10, 10, 1_000);
+        let mut id_counter = 0;
+        let mut orders: Vec<Order> = Vec::new();
+        let mut wakeups: Vec<SimTime> = Vec::new();
+        let mut ctx = AgentContext::new(0, 1, &mut id_counter, &mut orders, &mut wakeups);
+        agent.on_wakeup(&mut ctx);
+        agent.on_client_event(
+            &ClientEvent {
+                id: 0,
+                timestamp: 0,
+                client_id: 1,
+                order_id: 0,
+                order_side: OrderSide::Bid,
+                kind: ClientEventType::Filled,
+                liquidity_flag: LiquidityFlag::Maker,
+            },
+            &mut ctx,
+        );
+        assert_eq!(agent.inventory(), 10);
+        agent.on_wakeup(&mut ctx);
+        let last = orders.last().unwrap();
+        assert_eq!(last.side, OrderSide::Ask);
+        assert!(
+            !orders[2..]
+                .iter()
+                .any(|o| o.side == OrderSide::Bid && matches!(o.kind, OrderType::Limit { .. }))
+        );
+    }
+}
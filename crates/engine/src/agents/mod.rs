@@ -0,0 +1,306 @@
+pub mod market_maker;
+pub mod momentum;
+pub mod zero_intelligence;
+
+use crate::simulator::SimTime;
+use mm_core::lob_core::{
+    ClientId, OrderId, OrderQty, Price,
+    market_events::{ClientEvent, ClientEventType, EventSink, MarketEvent},
+    market_orders::{Order, OrderSide, OrderType},
+};
+use std::collections::BTreeMap;
+
+/// Client ids at or above this value are reserved for in-process agents. The agent registered at index `i` in the
+/// Simulator trades under `AGENT_CLIENT_ID_BASE + i`
+pub const AGENT_CLIENT_ID_BASE: ClientId = 1 << 32;
+
+/// Trait implemented by in-process trading agents. Agents are driven by the Simulator in sim time, they receive every
+/// public market event, the client events for their own orders, and any wake-ups they have scheduled.
+/// Agents must be Clone so that a running simulation can be forked
+pub trait Agent: AgentClone {
+    /// Called for every market event emitted by the order book
+    fn on_market_event(&mut self, _event: &MarketEvent, _ctx: &mut AgentContext) {}
+    /// Called for every client event belonging to an order submitted by this agent
+    fn on_client_event(&mut self, _event: &ClientEvent, _ctx: &mut AgentContext) {}
+    /// Called when sim time reaches a wake-up scheduled by this agent. Every agent is woken once when it is added to
+    /// the Simulator
+    fn on_wakeup(&mut self, ctx: &mut AgentContext);
+}
+
+/// Object-safe cloning of boxed agents, implemented for every agent that is Clone
+pub trait AgentClone {
+    fn clone_box(&self) -> Box<dyn Agent>;
+}
+impl<T: Agent + Clone + 'static> AgentClone for T {
+    fn clone_box(&self) -> Box<dyn Agent> {
+        Box::new(self.clone())
+    }
+}
+
+/// Handle passed to agent callbacks, used to read the current sim time and to submit orders and schedule wake-ups.
+/// Orders are assigned their engine order id on submission and are delivered to the book after simulated latency
+pub struct AgentContext<'a> {
+    time: SimTime,
+    client_id: ClientId,
+    id_counter: &'a mut OrderId,
+    orders: &'a mut Vec<Order>,
+    wakeups: &'a mut Vec<SimTime>,
+}
+impl<'a> AgentContext<'a> {
+    pub fn new(
+        time: SimTime,
+        client_id: ClientId,
+        id_counter: &'a mut OrderId,
+        orders: &'a mut Vec<Order>,
+        wakeups: &'a mut Vec<SimTime>,
+    ) -> Self {
+        Self {
+            time,
+            client_id,
+            id_counter,
+            orders,
+            wakeups,
+        }
+    }
+    /// Getter for the current simulation time
+    pub fn time(&self) -> SimTime {
+        self.time
+    }
+    /// Getter for the client id the agent trades under
+    pub fn client_id(&self) -> ClientId {
+        self.client_id
+    }
+    /// Submits an order of any type, returning the order id the book will know it by
+    pub fn submit(&mut self, side: OrderSide, kind: OrderType) -> OrderId {
+        let order_id = *self.id_counter;
+        *self.id_counter += 1;
+        self.orders
+            .push(Order::new(self.client_id, order_id, side, self.time, kind));
+        order_id
+    }
+    /// Submits a new limit order
+    pub fn limit(&mut self, side: OrderSide, qty: OrderQty, price: Price) -> OrderId {
+        self.submit(side, OrderType::Limit { qty, price })
+    }
+    /// Submits a new market order
+    pub fn market(&mut self, side: OrderSide, qty: OrderQty) -> OrderId {
+        self.submit(side, OrderType::Market { qty })
+    }
+    /// Submits a cancel for a previously submitted order
+    pub fn cancel(&mut self, side: OrderSide, old_id: OrderId) -> OrderId {
+        self.submit(side, OrderType::Cancel { old_id })
+    }
+    /// Submits a replacement for a previously submitted order
+    pub fn update(
+        &mut self,
+        side: OrderSide,
+        old_id: OrderId,
+        qty: OrderQty,
+        price: Price,
+    ) -> OrderId {
+        self.submit(side, OrderType::Update { old_id, qty, price })
+    }
+    /// Schedules a wake-up at an absolute sim time, times in the past are clamped to now
+    pub fn wake_at(&mut self, time: SimTime) {
+        self.wakeups.push(time.max(self.time));
+    }
+    /// Schedules a wake-up `dt` nanoseconds from now
+    pub fn wake_after(&mut self, dt: SimTime) {
+        self.wakeups.push(self.time.saturating_add(dt));
+    }
+}
+
+/// EventSink wrapper that forwards every event to an inner sink and, when capturing, keeps a copy so that the
+/// Simulator can dispatch them to agents after each order is processed
+pub struct AgentFeed<S: EventSink> {
+    inner: S,
+    capture: bool,
+    pub market_events: Vec<MarketEvent>,
+    pub client_events: Vec<ClientEvent>,
+}
+impl<S: EventSink> AgentFeed<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            capture: false,
+            market_events: Vec::new(),
+            client_events: Vec::new(),
+        }
+    }
+    /// Enables or disables keeping copies of events, disabled by default so runs without agents pay nothing
+    pub fn set_capture(&mut self, capture: bool) {
+        self.capture = capture;
+    }
+    /// Mutable access to the wrapped event sink
+    pub fn inner_mut(&mut self) -> &mut S {
+        &mut self.inner
+    }
+}
+impl<S: EventSink> EventSink for AgentFeed<S> {
+    fn push_event(&mut self, event: MarketEvent) {
+        if self.capture {
+            self.market_events.push(event);
+        }
+        self.inner.push_event(event);
+    }
+    fn push_client_event(&mut self, event: ClientEvent) {
+        if self.capture {
+            self.client_events.push(event);
+        }
+        self.inner.push_client_event(event);
+    }
+}
+
+/// Bookkeeping helper for agents that need to know how much of their own orders has been executed.
+/// Client events only carry the remaining quantity, so the tracker remembers the last known remaining quantity of
+/// every tracked order and converts fill events into signed executed quantities
+#[derive(Debug, Clone, Default)]
+pub struct FillTracker {
+    remaining: BTreeMap<OrderId, (OrderSide, OrderQty)>,
+    /// Orders the agent asked to cancel by the id of the cancel, tracked until the cancel takes effect since they can
+    /// still be filled meanwhile
+    canceling: BTreeMap<OrderId, OrderId>,
+    position: i64,
+}
+impl FillTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Starts tracking an order, should be called with the id returned on submission
+    pub fn track(&mut self, order_id: OrderId, side: OrderSide, qty: OrderQty) {
+        self.remaining.insert(order_id, (side, qty));
+    }
+    /// Marks an order as being canceled by the cancel submitted as `cancel_id`. It stays tracked until the cancel is
+    /// processed or the order is filled
+    pub fn cancel(&mut self, order_id: OrderId, cancel_id: OrderId) {
+        if self.remaining.contains_key(&order_id) {
+            self.canceling.insert(cancel_id, order_id);
+        }
+    }
+    /// Ids of all orders that are tracked and have not been fully filled, rejected or asked to cancel, in submission
+    /// order
+    pub fn live_orders(&self) -> impl Iterator<Item = (OrderId, OrderSide)> + '_ {
+        self.remaining
+            .iter()
+            .filter(|(id, _)| !self.canceling.values().any(|target| target == *id))
+            .map(|(id, (side, _))| (*id, *side))
+    }
+    /// Net executed quantity, positive when long
+    pub fn position(&self) -> i64 {
+        self.position
+    }
+    /// Updates state from a client event, returning the signed quantity executed by it
+    pub fn on_client_event(&mut self, event: &ClientEvent) -> i64 {
+        // NOTE: Events of a cancel carry the id of the cancel, whether it took effect or found the order gone
+        if let ClientEventType::Canceled | ClientEventType::Rejected = event.kind
+            && let Some(target) = self.canceling.remove(&event.order_id)
+        {
+            self.remaining.remove(&target);
+            return 0;
+        }
+        let Some(&(side, remaining)) = self.remaining.get(&event.order_id) else {
+            return 0;
+        };
+        let executed = match event.kind {
+            ClientEventType::PartiallyFilled(left) => {
+                self.remaining.insert(event.order_id, (side, left));
+                remaining.saturating_sub(left)
+            }
+            ClientEventType::Filled => {
+                self.remaining.remove(&event.order_id);
+                remaining
+            }
+            ClientEventType::Rejected => {
+                self.remaining.remove(&event.order_id);
+                0
+            }
+            _ => 0,
+        } as i64;
+        let signed = match side {
+            OrderSide::Bid => executed,
+            OrderSide::Ask => -executed,
+        };
+        self.position += signed;
+        signed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mm_core::lob_core::market_events::LiquidityFlag;
+
+    fn client_event(order_id: OrderId, side: OrderSide, kind: ClientEventType) -> ClientEvent {
+        ClientEvent {
+            id: 0,
+            timestamp: 0,
+            client_id: AGENT_CLIENT_ID_BASE,
+            order_id,
+            order_side: side,
+            kind,
+            liquidity_flag: LiquidityFlag::Maker,
+        }
+    }
+
+    #[test]
+    fn context_assigns_sequential_ids() {
+        let mut id_counter = 10;
+        let mut orders = Vec::new();
+        let mut wakeups = Vec::new();
+        let mut ctx = AgentContext::new(
+            500,
+            AGENT_CLIENT_ID_BASE,
+            &mut id_counter,
+            &mut orders,
+            &mut wakeups,
+        );
+        assert_eq!(ctx.limit(OrderSide::Bid, 5, 100), 10);
+        assert_eq!(ctx.market(OrderSide::Ask, 5), 11);
+        ctx.wake_at(100);
+        ctx.wake_after(50);
+        assert_eq!(id_counter, 12);
+        assert_eq!(orders.len(), 2);
+        assert!(orders.iter().all(|o| o.client_id == AGENT_CLIENT_ID_BASE));
+        assert_eq!(wakeups, vec![500, 550]);
+    }
+
+    #[test]
+    fn fill_tracker_accumulates_position() {
+        let mut tracker = FillTracker::new();
+        tracker.track(1, OrderSide::Bid, 10);
+        tracker.track(2, OrderSide::Ask, 4);
+        assert_eq!(
+            tracker.on_client_event(&client_event(
+                1,
+                OrderSide::Bid,
+                ClientEventType::PartiallyFilled(7)
+            )),
+            3
+        );
+        assert_eq!(
+            tracker.on_client_event(&client_event(1, OrderSide::Bid, ClientEventType::Filled)),
+            7
+        );
+        assert_eq!(
+            tracker.on_client_event(&client_event(2, OrderSide::Ask, ClientEventType::Filled)),
+            -4
+        );
+        assert_eq!(tracker.position(), 6);
+        assert_eq!(tracker.live_orders().count(), 0);
+    }
+
+    #[test]
+    fn fills_count_until_the_cancel_takes_effect() {
+        let mut tracker = FillTracker::new();
+        tracker.track(1, OrderSide::Ask, 10);
+        tracker.cancel(1, 2);
+        assert_eq!(tracker.live_orders().count(), 0);
+        // The fill was matched before the cancel was processed
+        let fill = client_event(1, OrderSide::Ask, ClientEventType::PartiallyFilled(6));
+        assert_eq!(tracker.on_client_event(&fill), -4);
+        let canceled = client_event(2, OrderSide::Ask, ClientEventType::Canceled);
+        assert_eq!(tracker.on_client_event(&canceled), 0);
+        assert_eq!(tracker.on_client_event(&fill), 0);
+        assert_eq!(tracker.position(), -4);
+    }
+}
@@ -0,0 +1,136 @@
+use crate::agents::{Agent, AgentContext, FillTracker};
+use crate::simulator::SimTime;
+use mm_core::lob_core::{
+    OrderQty, Price,
+    market_events::{ClientEvent, MarketEvent, MarketEventType},
+    market_orders::OrderSide,
+};
+
+/// Trend follower that keeps a fast and a slow exponential moving average of traded prices. On every wake-up it sends
+/// a market order in the direction of the trend when the two averages diverge by more than `threshold` cents, as long
+/// as its position stays within `max_position`
+#[derive(Clone)]
+pub struct MomentumAgent {
+    fast_alpha: f64,
+    slow_alpha: f64,
+    fast_avg: Option<f64>,
+    slow_avg: Option<f64>,
+    threshold: f64,
+    qty: OrderQty,
+    max_position: i64,
+    interval: SimTime,
+    fills: FillTracker,
+}
+impl MomentumAgent {
+    /// Smoothing factors must be in (0, 1], with the fast one larger than the slow one
+    pub fn new(
+        fast_alpha: f64,
+        slow_alpha: f64,
+        threshold: f64,
+        qty: OrderQty,
+        max_position: i64,
+        interval: SimTime,
+    ) -> Self {
+        assert!(fast_alpha > 0.0 && fast_alpha <= 1.0);
+        assert!(slow_alpha > 0.0 && slow_alpha <= 1.0);
+        assert!(fast_alpha > slow_alpha);
+        Self {
+            fast_alpha,
+            slow_alpha,
+            fast_avg: None,
+            slow_avg: None,
+            threshold,
+            qty,
+            max_position,
+            interval,
+            fills: FillTracker::new(),
+        }
+    }
+    /// Net position of the agent, positive when long
+    pub fn position(&self) -> i64 {
+        self.fills.position()
+    }
+    fn update_averages(&mut self, price: Price) {
+        let price = price as f64;
+        self.fast_avg = Some(match self.fast_avg {
+            Some(avg) => avg + self.fast_alpha * (price - avg),
+            None => price,
+        });
+        self.slow_avg = Some(match self.slow_avg {
+            Some(avg) => avg + self.slow_alpha * (price - avg),
+            None => price,
+        });
+    }
+}
+impl Agent for MomentumAgent {
+    fn on_market_event(&mut self, event: &MarketEvent, _ctx: &mut AgentContext) {
+        if let MarketEventType::Trade(trade) = event.kind {
+            self.update_averages(trade.price);
+        }
+    }
+    fn on_client_event(&mut self, event: &ClientEvent, _ctx: &mut AgentContext) {
+        self.fills.on_client_event(event);
+    }
+    fn on_wakeup(&mut self, ctx: &mut AgentContext) {
+        if let (Some(fast), Some(slow)) = (self.fast_avg, self.slow_avg) {
+            let signal = fast - slow;
+            let side = if signal > self.threshold && self.position() < self.max_position {
+                Some(OrderSide::Bid)
+            } else if signal < -self.threshold && self.position() > -self.max_position {
+                Some(OrderSide::Ask)
+            } else {
+                None
+            };
+            if let Some(side) = side {
+                let order_id = ctx.market(side, self.qty);
+                self.fills.track(order_id, side, self.qty);
+            }
+        }
+        ctx.wake_after(self.interval);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mm_core::lob_core::{
+        market_events::TradeEvent,
+        market_orders::{Order, OrderType},
+    };
+
+    fn trade(price: Price) -> MarketEvent {
+        MarketEvent::new(
+            0,
+            0,
+            MarketEventType::Trade(TradeEvent {
+                price,
+                quantity: 1,
+                aggressor_side: OrderSide::Bid,
+                maker_id: 0,
+            }),
+        )
+    }
+
+    #[test]
+    fn buys_into_rising_prices_and_sells_into_falling() {
+        let mut agent = MomentumAgent::new(0.5, 0.1, 1.0, 3, 100, 1_000);
+        let mut id_counter = 0;
+        let mut orders: Vec<Order> = Vec::new();
+        let mut wakeups: Vec<SimTime> = Vec::new();
+        let mut ctx = AgentContext::new(0, 1, &mut id_counter, &mut orders, &mut wakeups);
+        agent.on_wakeup(&mut ctx);
+        for price in 100..120 {
+            agent.on_market_event(&trade(price), &mut ctx);
+        }
+        agent.on_wakeup(&mut ctx);
+        for price in (60..120).rev() {
+            agent.on_market_event(&trade(price), &mut ctx);
+        }
+        agent.on_wakeup(&mut ctx);
+        assert_eq!(orders.len(), 2);
+        assert_eq!(orders[0].side, OrderSide::Bid);
+        assert_eq!(orders[0].kind, OrderType::Market { qty: 3 });
+        assert_eq!(orders[1].side, OrderSide::Ask);
+        assert_eq!(wakeups.len(), 3);
+    }
+}
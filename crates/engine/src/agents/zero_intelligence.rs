@@ -0,0 +1,102 @@
+use crate::agents::{Agent, AgentContext};
+use crate::data_generator::rate_controllers::NANOSECONDS_PER_SECOND;
+use mm_core::lob_core::{
+    OrderId, OrderQty, Price,
+    market_orders::{OrderSide, OrderType},
+};
+use rand::RngExt;
+use rand_chacha::ChaCha8Rng;
+use rand_distr::{Distribution, Exp, Uniform};
+use std::collections::VecDeque;
+
+/// Zero-intelligence trader in the style of Gode and Sunder. Wakes up at exponentially distributed intervals and
+/// submits a limit order with a random side, a price sampled uniformly between two bounds and a random quantity.
+/// Keeps at most `max_live` resting orders, cancelling the oldest one when the limit is exceeded
+#[derive(Clone)]
+pub struct ZeroIntelligenceAgent {
+    interval_dist: Exp<f64>,
+    price_dist: Uniform<Price>,
+    qty_dist: Uniform<OrderQty>,
+    max_live: usize,
+    live: VecDeque<(OrderId, OrderSide)>,
+    rng: ChaCha8Rng,
+}
+impl ZeroIntelligenceAgent {
+    /// Rate is in orders per second, prices are in cents and inclusive
+    pub fn new(
+        rate: f64,
+        min_price: Price,
+        max_price: Price,
+        max_qty: OrderQty,
+        max_live: usize,
+        rng: ChaCha8Rng,
+    ) -> Self {
+        assert!(max_qty > 0);
+        Self {
+            interval_dist: Exp::new(rate).unwrap(),
+            price_dist: Uniform::new_inclusive(min_price, max_price).unwrap(),
+            qty_dist: Uniform::new_inclusive(1, max_qty).unwrap(),
+            max_live,
+            live: VecDeque::with_capacity(max_live + 1),
+            rng,
+        }
+    }
+    fn next_interval(&mut self) -> u64 {
+        (self.interval_dist.sample(&mut self.rng) * NANOSECONDS_PER_SECOND as f64) as u64
+    }
+}
+impl Agent for ZeroIntelligenceAgent {
+    fn on_wakeup(&mut self, ctx: &mut AgentContext) {
+        let side = match self.rng.random_bool(0.5) {
+            true => OrderSide::Bid,
+            false => OrderSide::Ask,
+        };
+        let price = self.price_dist.sample(&mut self.rng);
+        let qty = self.qty_dist.sample(&mut self.rng);
+        let order_id = ctx.submit(side, OrderType::Limit { qty, price });
+        self.live.push_back((order_id, side));
+        while self.live.len() > self.max_live {
+            let (old_id, old_side) = self.live.pop_front().unwrap();
+            ctx.cancel(old_side, old_id);
+        }
+        let dt = self.next_interval();
+        ctx.wake_after(dt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::simulator::SimTime;
+    use mm_core::lob_core::market_orders::Order;
+    use rand::SeedableRng;
+
+    #[test]
+    fn orders_respect_bounds_and_live_limit() {
+        let mut agent =
+            ZeroIntelligenceAgent::new(1000.0, 90, 110, 5, 3, ChaCha8Rng::seed_from_u64(3));
+        let mut id_counter = 0;
+        let mut orders: Vec<Order> = Vec::new();
+        let mut wakeups: Vec<SimTime> = Vec::new();
+        for _ in 0..100 {
+            let mut ctx = AgentContext::new(0, 1, &mut id_counter, &mut orders, &mut wakeups);
+            agent.on_wakeup(&mut ctx);
+        }
+        let mut limits = 0;
+        let mut cancels = 0;
+        for order in &orders {
+            match order.kind {
+                OrderType::Limit { qty, price } => {
+                    assert!((90..=110).contains(&price));
+                    assert!((1..=5).contains(&qty));
+                    limits += 1;
+                }
+                OrderType::Cancel { .. } => cancels += 1,
+                _ => panic!("zero-intelligence agent only submits limits and cancels"),
+            }
+        }
+        assert_eq!(limits, 100);
+        assert_eq!(cancels, 97);
+        assert_eq!(wakeups.len(), 100);
+    }
+}
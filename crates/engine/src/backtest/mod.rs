@@ -0,0 +1,382 @@
+use crate::agents::Agent;
+use crate::data_generator::event_source::EventSource;
+use crate::simulator::latency_config::{LatencyConfig, SimJitter};
+use crate::simulator::scheduler::SimEvent;
+use crate::simulator::{SimTime, Simulator};
+use mm_core::lob_core::{
+    ClientId, OrderId, OrderQty, Price,
+    market_events::{
+        ClientEvent, ClientEventType, EventSink, LiquidityFlag, MarketEvent, MarketEventType,
+    },
+    market_orders::{Order, OrderSide},
+};
+use rand_chacha::ChaCha8Rng;
+use ringbuf::{HeapRb, traits::*};
+
+/// Fees charged per share executed, in cents. Negative values are rebates
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct FeeSchedule {
+    pub maker: f64,
+    pub taker: f64,
+}
+impl FeeSchedule {
+    pub fn new(maker: f64, taker: f64) -> Self {
+        Self { maker, taker }
+    }
+    fn fee(&self, liquidity_flag: LiquidityFlag, qty: OrderQty) -> f64 {
+        match liquidity_flag {
+            LiquidityFlag::Maker => self.maker * qty as f64,
+            LiquidityFlag::Taker => self.taker * qty as f64,
+            LiquidityFlag::Invalid => 0.0,
+        }
+    }
+}
+
+/// Settings of a backtest run
+#[derive(Debug, Clone, Copy)]
+pub struct BacktestConfig {
+    /// Simulated latency applied to the strategy's orders
+    pub latency: LatencyConfig,
+    pub fees: FeeSchedule,
+    /// Sim time at which the run stops, the run otherwise ends when the source is exhausted
+    pub end_time: Option<SimTime>,
+    /// Spacing of the samples in the PnL series, in nanoseconds of sim time
+    pub sample_interval: SimTime,
+}
+impl Default for BacktestConfig {
+    fn default() -> Self {
+        Self {
+            latency: LatencyConfig {
+                latency: 0,
+                jitter: SimJitter::None,
+            },
+            fees: FeeSchedule::default(),
+            end_time: None,
+            sample_interval: 1_000_000_000,
+        }
+    }
+}
+
+/// Single execution of one of the strategy's orders
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Fill {
+    pub time: SimTime,
+    pub order_id: OrderId,
+    pub side: OrderSide,
+    pub price: Price,
+    pub qty: OrderQty,
+    pub liquidity_flag: LiquidityFlag,
+    pub fee: f64,
+}
+
+/// State of the strategy's account at a point in sim time. Cash and PnL are in cents, open inventory is marked at the
+/// last traded price
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PnlSample {
+    pub time: SimTime,
+    pub inventory: i64,
+    pub cash: f64,
+    pub mark_price: Option<Price>,
+    pub pnl: f64,
+}
+
+/// Results of a backtest run
+#[derive(Debug, Clone, Default)]
+pub struct BacktestReport {
+    pub fills: Vec<Fill>,
+    /// PnL sampled every `sample_interval`, with a final sample at the end of the run
+    pub pnl_series: Vec<PnlSample>,
+    pub inventory: i64,
+    pub cash: f64,
+    pub fees: f64,
+    pub pnl: f64,
+    pub orders_processed: u64,
+    pub sim_time: SimTime,
+}
+impl BacktestReport {
+    /// Total quantity executed by the strategy
+    pub fn volume(&self) -> u64 {
+        self.fills.iter().map(|fill| fill.qty as u64).sum()
+    }
+}
+
+/// EventSink that keeps the events emitted while processing the last order, used by the backtest to attribute trades
+/// to the strategy's orders
+#[derive(Debug, Default)]
+pub struct BacktestFeed {
+    market_events: Vec<MarketEvent>,
+    client_events: Vec<ClientEvent>,
+}
+impl EventSink for BacktestFeed {
+    fn push_event(&mut self, event: MarketEvent) {
+        self.market_events.push(event);
+    }
+    fn push_client_event(&mut self, event: ClientEvent) {
+        self.client_events.push(event);
+    }
+}
+
+/// Offline backtest runner. Wires a Simulator, an event source and an in-process strategy together without any
+/// networking, and runs as fast as possible while keeping the strategy's account
+pub struct Backtest<E: EventSource> {
+    sim: Simulator<E, BacktestFeed, ChaCha8Rng>,
+    strategy_id: ClientId,
+    config: BacktestConfig,
+    report: BacktestReport,
+    last_price: Option<Price>,
+    next_sample: SimTime,
+}
+impl<E: EventSource> Backtest<E> {
+    /// The strategy is registered as the first agent of the Simulator, the rng drives simulated latency
+    pub fn new(
+        source: E,
+        strategy: Box<dyn Agent>,
+        config: BacktestConfig,
+        rng: ChaCha8Rng,
+    ) -> Self {
+        // NOTE: The producer is dropped straight away, a backtest takes no orders from outside the process
+        let (_, user_orders) = HeapRb::<Order>::new(1).split();
+        let mut sim = Simulator::new(
+            source,
+            BacktestFeed::default(),
+            user_orders,
+            config.latency,
+            rng,
+            false,
+        );
+        let strategy_id = sim.add_agent(strategy);
+        Self {
+            sim,
+            strategy_id,
+            config,
+            report: BacktestReport::default(),
+            last_price: None,
+            next_sample: config.sample_interval,
+        }
+    }
+    /// Adds an agent trading alongside the strategy, its fills are not part of the report
+    pub fn add_agent(&mut self, agent: Box<dyn Agent>) -> ClientId {
+        self.sim.add_agent(agent)
+    }
+    /// Schedules an event on the simulation clock, such as the scripted events of a scenario. Returns the engine
+    /// order id of scheduled orders
+    pub fn schedule(&mut self, time: SimTime, event: SimEvent) -> Option<OrderId> {
+        self.sim.schedule(time, event)
+    }
+    /// Client id that the strategy's orders are tagged with
+    pub fn strategy_id(&self) -> ClientId {
+        self.strategy_id
+    }
+    /// Runs until the source is exhausted or the end time is reached, and returns the report.
+    /// The order that crosses the end time is the last one processed
+    pub fn run(mut self) -> BacktestReport {
+        while self.sim.step().is_ok() {
+            self.report.orders_processed += 1;
+            let time = self.sim.time();
+            while self.config.sample_interval > 0 && self.next_sample <= time {
+                let sample = self.sample(self.next_sample);
+                self.report.pnl_series.push(sample);
+                self.next_sample += self.config.sample_interval;
+            }
+            self.account_events(time);
+            if self.config.end_time.is_some_and(|end| time >= end) {
+                break;
+            }
+        }
+        let time = self.sim.time();
+        let sample = self.sample(time);
+        // A periodic sample at the final instant was taken before the last order, the final state supersedes it
+        if self
+            .report
+            .pnl_series
+            .last()
+            .is_some_and(|s| s.time == time)
+        {
+            self.report.pnl_series.pop();
+        }
+        self.report.pnl_series.push(sample);
+        self.report.pnl = sample.pnl;
+        self.report.sim_time = time;
+        self.report
+    }
+    /// Attributes the trades emitted by the last processed order. The book emits a taker and then a maker fill event
+    /// for every trade, in the same order as the trade events, so fills are paired with trades by position
+    fn account_events(&mut self, time: SimTime) {
+        let feed = self.sim.event_sink_mut();
+        let trades = feed
+            .market_events
+            .iter()
+            .filter_map(|event| match event.kind {
+                MarketEventType::Trade(trade) => Some(trade),
+                _ => None,
+            });
+        let fills = feed.client_events.iter().filter(|event| {
+            matches!(
+                event.kind,
+                ClientEventType::Filled | ClientEventType::PartiallyFilled(_)
+            )
+        });
+        let mut fills_out = Vec::new();
+        for (trade, pair) in trades.zip(fills.collect::<Vec<_>>().chunks_exact(2)) {
+            self.last_price = Some(trade.price);
+            for event in pair {
+                if event.client_id == self.strategy_id {
+                    fills_out.push(Fill {
+                        time,
+                        order_id: event.order_id,
+                        side: event.order_side,
+                        price: trade.price,
+                        qty: trade.quantity,
+                        liquidity_flag: event.liquidity_flag,
+                        fee: self.config.fees.fee(event.liquidity_flag, trade.quantity),
+                    });
+                }
+            }
+        }
+        feed.market_events.clear();
+        feed.client_events.clear();
+        for fill in fills_out {
+            let notional = fill.price as f64 * fill.qty as f64;
+            match fill.side {
+                OrderSide::Bid => {
+                    self.report.inventory += fill.qty as i64;
+                    self.report.cash -= notional;
+                }
+                OrderSide::Ask => {
+                    self.report.inventory -= fill.qty as i64;
+                    self.report.cash += notional;
+                }
+            }
+            self.report.cash -= fill.fee;
+            self.report.fees += fill.fee;
+            self.report.fills.push(fill);
+        }
+    }
+    fn sample(&self, time: SimTime) -> PnlSample {
+        let mark = self.last_price.unwrap_or(0) as f64;
+        PnlSample {
+            time,
+            inventory: self.report.inventory,
+            cash: self.report.cash,
+            mark_price: self.last_price,
+            pnl: self.report.cash + self.report.inventory as f64 * mark,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        agents::{AgentContext, market_maker::MarketMakerAgent},
+        data_generator::{
+            event_source::{ConstantPoissonSource, SourceFunction},
+            order_generators::GaussianOrderGenerator,
+            rate_controllers::ConstantPoissonRate,
+            type_selectors::UniformTypeSelector,
+        },
+    };
+    use mm_core::lob_core::market_orders::OrderType;
+    use rand::SeedableRng;
+
+    /// Rests a single bid on its first wake-up
+    #[derive(Clone)]
+    struct Bidder;
+    impl Agent for Bidder {
+        fn on_wakeup(&mut self, ctx: &mut AgentContext) {
+            ctx.limit(OrderSide::Bid, 10, 100);
+        }
+    }
+
+    fn scripted_source(orders: Vec<Order>) -> SourceFunction {
+        let mut orders = orders.into_iter();
+        SourceFunction::new(Box::new(move || orders.next()))
+    }
+
+    #[test]
+    fn fills_are_priced_and_charged() {
+        let source = scripted_source(vec![
+            Order::new(
+                0,
+                0,
+                OrderSide::Ask,
+                10,
+                OrderType::Limit { qty: 5, price: 105 },
+            ),
+            Order::new(0, 0, OrderSide::Ask, 20, OrderType::Market { qty: 4 }),
+            Order::new(0, 0, OrderSide::Bid, 30, OrderType::Market { qty: 5 }),
+        ]);
+        let config = BacktestConfig {
+            fees: FeeSchedule::new(-0.2, 0.3),
+            sample_interval: 15,
+            ..Default::default()
+        };
+        let report = Backtest::new(
+            source,
+            Box::new(Bidder),
+            config,
+            ChaCha8Rng::seed_from_u64(0),
+        )
+        .run();
+
+        assert_eq!(report.orders_processed, 4);
+        assert_eq!(report.fills.len(), 1);
+        let fill = report.fills[0];
+        assert_eq!(fill.side, OrderSide::Bid);
+        assert_eq!((fill.price, fill.qty), (100, 4));
+        assert_eq!(fill.liquidity_flag, LiquidityFlag::Maker);
+        assert!((report.fees + 0.8).abs() < 1e-9);
+        assert_eq!(report.inventory, 4);
+        assert!((report.cash + 399.2).abs() < 1e-9);
+        // Inventory is marked at the last trade, which happened at 105
+        assert!((report.pnl - 20.8).abs() < 1e-9);
+        let times: Vec<SimTime> = report.pnl_series.iter().map(|s| s.time).collect();
+        assert_eq!(times, vec![15, 30]);
+        assert_eq!(report.pnl_series[0].inventory, 0);
+        assert_eq!(report.pnl_series[1].inventory, 4);
+    }
+
+    fn market_maker_backtest(seed: u64) -> BacktestReport {
+        let source = ConstantPoissonSource::new(
+            ConstantPoissonRate::new(10_000.0),
+            UniformTypeSelector::new(0.5, 0.6, 0.2, 0.2, 0.0),
+            GaussianOrderGenerator::new(1000.0, 5.0, 1000.0, 5.0),
+            ChaCha8Rng::seed_from_u64(seed),
+            Some(20_000),
+        );
+        let config = BacktestConfig {
+            fees: FeeSchedule::new(-0.1, 0.3),
+            sample_interval: 100_000_000,
+            ..Default::default()
+        };
+        Backtest::new(
+            source,
+            Box::new(MarketMakerAgent::new(1000, 2, 10, 200, 1_000_000)),
+            config,
+            ChaCha8Rng::seed_from_u64(seed),
+        )
+        .run()
+    }
+
+    #[test]
+    fn report_is_consistent_and_deterministic() {
+        let report = market_maker_backtest(7);
+        assert!(!report.fills.is_empty());
+        let signed: i64 = report
+            .fills
+            .iter()
+            .map(|fill| match fill.side {
+                OrderSide::Bid => fill.qty as i64,
+                OrderSide::Ask => -(fill.qty as i64),
+            })
+            .sum();
+        assert_eq!(signed, report.inventory);
+        let fees: f64 = report.fills.iter().map(|fill| fill.fee).sum();
+        assert!((fees - report.fees).abs() < 1e-6);
+        assert!(report.pnl_series.windows(2).all(|w| w[0].time <= w[1].time));
+
+        let again = market_maker_backtest(7);
+        assert_eq!(report.fills, again.fills);
+        assert_eq!(report.pnl_series, again.pnl_series);
+    }
+}
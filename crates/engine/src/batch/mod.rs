@@ -0,0 +1,348 @@
+use crate::data_generator::event_source::EventSource;
+use crate::simulator::{SimTime, Simulator};
+use mm_core::lob_core::{
+    market_events::{ClientEvent, EventSink, MarketEvent, MarketEventType},
+    market_orders::OrderSide,
+};
+use rand::Rng;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Instant;
+
+/// Statistics gathered from a single headless simulation run. Spread and depth are averaged over samples taken on a
+/// fixed sim time grid, volatility is the standard deviation of log returns of the mid price between samples
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct RunStats {
+    pub seed: u64,
+    pub orders: u64,
+    pub trades: u64,
+    pub volume: u64,
+    /// Mean quoted spread in cents, over samples where both sides of the book were populated
+    pub mean_spread: f64,
+    pub volatility: f64,
+    /// Mean quantity resting at the best bid plus the best ask
+    pub mean_depth: f64,
+    pub sim_time: SimTime,
+    pub run_time_ns: u64,
+    /// Orders processed per second of wall time
+    pub throughput: f64,
+}
+impl RunStats {
+    pub const CSV_HEADER: &'static str =
+        "seed,orders,trades,volume,mean_spread,volatility,mean_depth,sim_time,run_time,throughput";
+    /// Named numeric statistics of the run, in CSV column order excluding the seed
+    pub fn metrics(&self) -> [(&'static str, f64); 9] {
+        [
+            ("orders", self.orders as f64),
+            ("trades", self.trades as f64),
+            ("volume", self.volume as f64),
+            ("mean_spread", self.mean_spread),
+            ("volatility", self.volatility),
+            ("mean_depth", self.mean_depth),
+            ("sim_time", self.sim_time as f64),
+            ("run_time", self.run_time_ns as f64),
+            ("throughput", self.throughput),
+        ]
+    }
+    pub fn to_csv_row(&self) -> String {
+        format!(
+            "{},{},{},{},{},{},{},{},{},{}",
+            self.seed,
+            self.orders,
+            self.trades,
+            self.volume,
+            self.mean_spread,
+            self.volatility,
+            self.mean_depth,
+            self.sim_time,
+            self.run_time_ns,
+            self.throughput
+        )
+    }
+}
+
+/// EventSink that only counts trades, used by headless runs that have no consumers for the event feeds
+#[derive(Debug, Default)]
+pub struct StatsFeed {
+    trades: u64,
+    volume: u64,
+}
+impl EventSink for StatsFeed {
+    fn push_event(&mut self, event: MarketEvent) {
+        if let MarketEventType::Trade(trade) = event.kind {
+            self.trades += 1;
+            self.volume += trade.quantity as u64;
+        }
+    }
+    fn push_client_event(&mut self, _event: ClientEvent) {}
+}
+
+/// Steps a simulation until its source is exhausted, sampling the top of the book every `sample_interval`
+/// nanoseconds of sim time, and returns the statistics of the run
+pub fn collect_run_stats<E: EventSource, R: Rng>(
+    sim: &mut Simulator<E, StatsFeed, R>,
+    seed: u64,
+    sample_interval: SimTime,
+) -> RunStats {
+    let mut stats = RunStats {
+        seed,
+        ..Default::default()
+    };
+    let mut spreads = Welford::default();
+    let mut depths = Welford::default();
+    let mut returns = Welford::default();
+    let mut last_mid: Option<f64> = None;
+    let mut next_sample = sample_interval;
+    let start = Instant::now();
+    while sim.step().is_ok() {
+        stats.orders += 1;
+        if sim.time() < next_sample {
+            continue;
+        }
+        while next_sample <= sim.time() {
+            next_sample += sample_interval.max(1);
+        }
+        let book = sim.order_book();
+        let (bid, ask) = book.bbo();
+        let depth = bid.map_or(0, |price| book.get_qty(price, OrderSide::Bid))
+            + ask.map_or(0, |price| book.get_qty(price, OrderSide::Ask));
+        depths.push(depth as f64);
+        if let (Some(bid), Some(ask)) = (bid, ask) {
+            // NOTE: The book can be momentarily crossed by a lazily cancelled level, such samples are skipped
+            if ask > bid {
+                spreads.push((ask - bid) as f64);
+                let mid = (ask + bid) as f64 / 2.0;
+                if let Some(last) = last_mid {
+                    returns.push((mid / last).ln());
+                }
+                last_mid = Some(mid);
+            }
+        }
+    }
+    let elapsed = start.elapsed();
+    let feed = sim.event_sink_mut();
+    stats.trades = feed.trades;
+    stats.volume = feed.volume;
+    stats.mean_spread = spreads.mean();
+    stats.mean_depth = depths.mean();
+    stats.volatility = returns.std_dev();
+    stats.sim_time = sim.time();
+    stats.run_time_ns = elapsed.as_nanos() as u64;
+    stats.throughput = stats.orders as f64 / elapsed.as_secs_f64().max(f64::MIN_POSITIVE);
+    stats
+}
+
+/// Runs `jobs` independent jobs on up to `threads` worker threads and returns their results in job order.
+/// Jobs are handed out one at a time, so long and short runs balance across workers
+pub fn run_parallel<T: Send, F: Fn(usize) -> T + Sync>(
+    jobs: usize,
+    threads: usize,
+    job: F,
+) -> Vec<T> {
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<(usize, T)>> = Mutex::new(Vec::with_capacity(jobs));
+    std::thread::scope(|scope| {
+        for _ in 0..threads.clamp(1, jobs.max(1)) {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    if index >= jobs {
+                        break;
+                    }
+                    let result = job(index);
+                    results.lock().unwrap().push((index, result));
+                }
+            });
+        }
+    });
+    let mut results = results.into_inner().unwrap();
+    results.sort_unstable_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+/// Summary of one statistic across runs, the confidence interval is a two-sided 95% Student's t interval on the mean
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Summary {
+    pub mean: f64,
+    pub std_dev: f64,
+    pub ci_low: f64,
+    pub ci_high: f64,
+    pub min: f64,
+    pub max: f64,
+}
+impl Summary {
+    pub fn from_samples(samples: &[f64]) -> Self {
+        if samples.is_empty() {
+            return Self::default();
+        }
+        let mut welford = Welford::default();
+        for sample in samples {
+            welford.push(*sample);
+        }
+        let (mean, std_dev) = (welford.mean(), welford.std_dev());
+        let half_width = match samples.len() {
+            1 => 0.0,
+            n => t_critical_95(n - 1) * std_dev / (n as f64).sqrt(),
+        };
+        Self {
+            mean,
+            std_dev,
+            ci_low: mean - half_width,
+            ci_high: mean + half_width,
+            min: samples.iter().copied().fold(f64::INFINITY, f64::min),
+            max: samples.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+        }
+    }
+}
+
+/// Writes one row per run
+pub fn write_runs_csv(path: &str, runs: &[RunStats]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(writer, "{}", RunStats::CSV_HEADER)?;
+    for run in runs {
+        writeln!(writer, "{}", run.to_csv_row())?;
+    }
+    writer.flush()
+}
+
+/// Writes one row per statistic, summarised across all runs
+pub fn write_summary_csv(path: &str, runs: &[RunStats]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    writeln!(
+        writer,
+        "metric,runs,mean,std_dev,ci95_low,ci95_high,min,max"
+    )?;
+    let Some(first) = runs.first() else {
+        return writer.flush();
+    };
+    for (column, (name, _)) in first.metrics().iter().enumerate() {
+        let samples: Vec<f64> = runs.iter().map(|run| run.metrics()[column].1).collect();
+        let summary = Summary::from_samples(&samples);
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{}",
+            name,
+            runs.len(),
+            summary.mean,
+            summary.std_dev,
+            summary.ci_low,
+            summary.ci_high,
+            summary.min,
+            summary.max
+        )?;
+    }
+    writer.flush()
+}
+
+/// Two-sided 95% critical value of Student's t distribution
+fn t_critical_95(degrees_of_freedom: usize) -> f64 {
+    const TABLE: [f64; 30] = [
+        12.706, 4.303, 3.182, 2.776, 2.571, 2.447, 2.365, 2.306, 2.262, 2.228, 2.201, 2.179, 2.160,
+        2.145, 2.131, 2.120, 2.110, 2.101, 2.093, 2.086, 2.080, 2.074, 2.069, 2.064, 2.060, 2.056,
+        2.052, 2.048, 2.045, 2.042,
+    ];
+    match degrees_of_freedom {
+        0 => f64::NAN,
+        df if df <= TABLE.len() => TABLE[df - 1],
+        df if df <= 60 => 2.000,
+        df if df <= 120 => 1.980,
+        _ => 1.960,
+    }
+}
+
+/// Running mean and variance using Welford's algorithm
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct Welford {
+    count: u64,
+    mean: f64,
+    m2: f64,
+}
+impl Welford {
+    pub(crate) fn push(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+    pub(crate) fn count(&self) -> u64 {
+        self.count
+    }
+    pub(crate) fn mean(&self) -> f64 {
+        self.mean
+    }
+    /// Sample standard deviation, zero with fewer than two values
+    pub(crate) fn std_dev(&self) -> f64 {
+        match self.count {
+            0 | 1 => 0.0,
+            n => (self.m2 / (n - 1) as f64).sqrt(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data_generator::{
+            event_source::ConstantPoissonSource, order_generators::GaussianOrderGenerator,
+            rate_controllers::ConstantPoissonRate, type_selectors::UniformTypeSelector,
+        },
+        simulator::latency_config::{LatencyConfig, SimJitter},
+    };
+    use mm_core::lob_core::market_orders::Order;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use ringbuf::{HeapRb, traits::*};
+
+    fn poisson_run(seed: u64) -> RunStats {
+        let rng = ChaCha8Rng::seed_from_u64(seed);
+        let (_, user_orders) = HeapRb::<Order>::new(1).split();
+        let mut sim = Simulator::new(
+            ConstantPoissonSource::new(
+                ConstantPoissonRate::new(100_000.0),
+                UniformTypeSelector::new(0.5, 0.5, 0.05, 0.4, 0.05),
+                GaussianOrderGenerator::new(1000.0, 50.0, 1000.0, 50.0),
+                rng.clone(),
+                Some(20_000),
+            ),
+            StatsFeed::default(),
+            user_orders,
+            LatencyConfig {
+                latency: 0,
+                jitter: SimJitter::None,
+            },
+            rng,
+            false,
+        );
+        collect_run_stats(&mut sim, seed, 1_000_000)
+    }
+
+    #[test]
+    fn parallel_runs_match_sequential_runs() {
+        let seeds = [3, 4, 5, 6];
+        let parallel = run_parallel(seeds.len(), 3, |i| poisson_run(seeds[i]));
+        for (stats, seed) in parallel.iter().zip(seeds) {
+            let sequential = poisson_run(seed);
+            assert_eq!(stats.seed, seed);
+            assert_eq!(stats.orders, 20_000);
+            assert_eq!(stats.trades, sequential.trades);
+            assert_eq!(stats.mean_spread, sequential.mean_spread);
+            assert_eq!(stats.volatility, sequential.volatility);
+            assert!(stats.trades > 0 && stats.mean_depth > 0.0);
+        }
+    }
+
+    #[test]
+    fn summary_interval_contains_mean() {
+        let summary = Summary::from_samples(&[1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(summary.mean, 2.5);
+        assert!((summary.std_dev - 1.2909944).abs() < 1e-6);
+        let half_width = 3.182 * summary.std_dev / 2.0;
+        assert!((summary.ci_low - (2.5 - half_width)).abs() < 1e-9);
+        assert!((summary.ci_high - (2.5 + half_width)).abs() < 1e-9);
+        assert_eq!((summary.min, summary.max), (1.0, 4.0));
+        assert_eq!(Summary::from_samples(&[7.0]).ci_low, 7.0);
+    }
+}
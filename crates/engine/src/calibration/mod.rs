@@ -0,0 +1,292 @@
+use crate::batch::Welford;
+use crate::data_generator::event_source::{EventSource, FileReplaySource};
+use crate::data_generator::rate_controllers::NANOSECONDS_PER_SECOND;
+use clap::ValueEnum;
+use mm_core::lob_core::OrderQty;
+use mm_core::lob_core::market_orders::{OrderSide, OrderType};
+use std::fmt::Write;
+
+/// Format of the configuration written by the calibrate subcommand
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum CalibrationFormat {
+    /// Command-line arguments for the poisson subcommand
+    Cli,
+    /// A scenario file with a single phase
+    Scenario,
+}
+
+fn gcd(a: OrderQty, b: OrderQty) -> OrderQty {
+    match b {
+        0 => a,
+        _ => gcd(b, a % b),
+    }
+}
+
+/// Parameters of the poisson source fitted to an order stream: the arrival rate, the side and type proportions of the
+/// UniformTypeSelector, the price distributions of the GaussianOrderGenerator, and a log-normal size distribution
+/// with the lot size and largest size seen
+#[derive(Debug, Clone, PartialEq)]
+pub struct Calibration {
+    pub orders: u64,
+    /// Sim time in seconds between the first and last order
+    pub duration: f64,
+    pub order_rate: f64,
+    pub bid_rate: f64,
+    pub new_limit_rate: f64,
+    pub cancel_rate: f64,
+    pub market_rate: f64,
+    pub update_rate: f64,
+    pub bid_avg_price: f64,
+    pub bid_price_dev: f64,
+    pub ask_avg_price: f64,
+    pub ask_price_dev: f64,
+    /// Mean of the log of the order sizes, in lots
+    pub size_mu: f64,
+    /// Standard deviation of the log of the order sizes, in lots
+    pub size_sigma: f64,
+    pub lot_size: OrderQty,
+    pub max_size: OrderQty,
+}
+impl Calibration {
+    /// Fits the parameters to every order of a binary recording
+    pub fn from_recording(path: &str) -> Result<Self, String> {
+        let mut source =
+            FileReplaySource::new(path, 512).map_err(|e| format!("calibrate: {path}: {e}"))?;
+        Self::fit(&mut source).map_err(|e| format!("calibrate: {path}: {e}"))
+    }
+    /// Fits the parameters to every order of a source, which must end
+    pub fn fit(source: &mut impl EventSource) -> Result<Self, String> {
+        let mut orders: u64 = 0;
+        let mut bids: u64 = 0;
+        let mut kinds = [0u64; 4];
+        let (mut first, mut last) = (u64::MAX, 0);
+        let mut bid_prices = Welford::default();
+        let mut ask_prices = Welford::default();
+        let mut sizes: Vec<OrderQty> = Vec::new();
+        while let Some(order) = source.next_event() {
+            orders += 1;
+            first = first.min(order.timestamp);
+            last = last.max(order.timestamp);
+            if order.side == OrderSide::Bid {
+                bids += 1;
+            }
+            let prices = match order.side {
+                OrderSide::Bid => &mut bid_prices,
+                OrderSide::Ask => &mut ask_prices,
+            };
+            match order.kind {
+                OrderType::Limit { qty, price } => {
+                    kinds[0] += 1;
+                    prices.push(price as f64);
+                    sizes.push(qty);
+                }
+                OrderType::Cancel { .. } => kinds[1] += 1,
+                OrderType::Market { qty } => {
+                    kinds[2] += 1;
+                    sizes.push(qty);
+                }
+                OrderType::Update { qty, price, .. } => {
+                    kinds[3] += 1;
+                    prices.push(price as f64);
+                    sizes.push(qty);
+                }
+            }
+        }
+        if orders < 2 || last == first {
+            return Err("at least two orders at different times are needed".into());
+        }
+        if bid_prices.count() + ask_prices.count() == 0 {
+            return Err("no limit or update orders to fit prices to".into());
+        }
+        // A side without prices mirrors the other one
+        if bid_prices.count() == 0 {
+            bid_prices = ask_prices;
+        }
+        if ask_prices.count() == 0 {
+            ask_prices = bid_prices;
+        }
+        sizes.retain(|size| *size > 0);
+        let lot_size = sizes.iter().fold(0, |lot, size| gcd(lot, *size)).max(1);
+        let mut log_sizes = Welford::default();
+        for size in &sizes {
+            log_sizes.push(((size / lot_size) as f64).ln());
+        }
+        let duration = (last - first) as f64 / NANOSECONDS_PER_SECOND as f64;
+        let share = |count: u64| count as f64 / orders as f64;
+        Ok(Self {
+            orders,
+            duration,
+            order_rate: (orders - 1) as f64 / duration,
+            bid_rate: share(bids),
+            new_limit_rate: share(kinds[0]),
+            cancel_rate: share(kinds[1]),
+            market_rate: share(kinds[2]),
+            update_rate: share(kinds[3]),
+            // NOTE: The generator rounds prices down, so the averages it is given sit half a tick above the prices
+            bid_avg_price: bid_prices.mean() + 0.5,
+            // NOTE: Price deviations must be positive, a single price level gets a deviation of one tick
+            bid_price_dev: bid_prices.std_dev().max(1.0),
+            ask_avg_price: ask_prices.mean() + 0.5,
+            ask_price_dev: ask_prices.std_dev().max(1.0),
+            size_mu: log_sizes.mean(),
+            size_sigma: log_sizes.std_dev(),
+            lot_size,
+            max_size: sizes.iter().copied().max().unwrap_or(lot_size),
+        })
+    }
+    /// Global size arguments, which apply to the sources that draw sizes from --sizes
+    fn size_args(&self) -> String {
+        format!(
+            "--sizes lognormal:{:.4}:{:.4} --lot-size {} --max-size {}",
+            self.size_mu + (self.lot_size as f64).ln(),
+            self.size_sigma,
+            self.lot_size,
+            self.max_size
+        )
+    }
+    /// Arguments reproducing the fitted stream with the poisson subcommand
+    pub fn to_cli(&self) -> String {
+        format!(
+            "{} poisson --count {} --order-rate {:.1} --bid-rate {:.4} --new-limit-rate {:.4} --cancel-rate {:.4} \
+             --market-rate {:.4} --update-rate {:.4} --bid-avg-price {:.2} --bid-price-dev {:.2} --ask-avg-price {:.2} \
+             --ask-price-dev {:.2}",
+            self.size_args(),
+            self.orders,
+            self.order_rate,
+            self.bid_rate,
+            self.new_limit_rate,
+            self.cancel_rate,
+            self.market_rate,
+            self.update_rate,
+            self.bid_avg_price,
+            self.bid_price_dev,
+            self.ask_avg_price,
+            self.ask_price_dev,
+        )
+    }
+    /// Scenario file with a single phase reproducing the fitted stream
+    pub fn to_scenario(&self) -> String {
+        let mut text = String::new();
+        let _ = writeln!(
+            text,
+            "# Calibrated from {} orders over {:.3} seconds",
+            self.orders, self.duration
+        );
+        let _ = writeln!(
+            text,
+            "# Order sizes are not part of scenario files, the fitted sizes are: {}",
+            self.size_args()
+        );
+        let _ = writeln!(text, "\n[end]\norders = {}", self.orders);
+        let _ = writeln!(text, "\n[[phase]]\nname = \"calibrated\"\nstart = 0.0");
+        for (field, value) in [
+            ("order_rate", self.order_rate),
+            ("bid_rate", self.bid_rate),
+            ("new_limit_rate", self.new_limit_rate),
+            ("cancel_rate", self.cancel_rate),
+            ("market_rate", self.market_rate),
+            ("update_rate", self.update_rate),
+            ("bid_avg_price", self.bid_avg_price),
+            ("bid_price_dev", self.bid_price_dev),
+            ("ask_avg_price", self.ask_avg_price),
+            ("ask_price_dev", self.ask_price_dev),
+        ] {
+            let _ = writeln!(text, "{field} = {value:.4}");
+        }
+        text
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_generator::event_source::RandomSource;
+    use crate::data_generator::order_generators::{GaussianOrderGenerator, OrderGenerator};
+    use crate::data_generator::order_sizes::{OrderSizes, SizeDistribution};
+    use crate::data_generator::rate_controllers::ConstantPoissonRate;
+    use crate::data_generator::type_selectors::UniformTypeSelector;
+    use crate::scenario::Scenario;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    #[test]
+    fn fit_recovers_generator_parameters() {
+        let sizes = OrderSizes::new(
+            SizeDistribution::parse("lognormal:6:0.5").unwrap(),
+            100,
+            None,
+        )
+        .unwrap();
+        let mut source = RandomSource::new(
+            ConstantPoissonRate::new(5_000.0),
+            UniformTypeSelector::new(0.3, 0.5, 0.1, 0.3, 0.1),
+            GaussianOrderGenerator::new(990.0, 20.0, 1010.0, 40.0).with_sizes(sizes),
+            ChaCha8Rng::seed_from_u64(0),
+            Some(100_000),
+        );
+        let fit = Calibration::fit(&mut source).unwrap();
+        let close = |value: f64, expected: f64, tolerance: f64| {
+            assert!(
+                (value - expected).abs() < tolerance,
+                "{value} vs {expected}"
+            );
+        };
+        close(fit.order_rate, 5_000.0, 100.0);
+        close(fit.bid_rate, 0.3, 0.01);
+        close(fit.new_limit_rate, 0.5, 0.01);
+        close(fit.market_rate, 0.1, 0.01);
+        close(fit.cancel_rate, 0.3, 0.01);
+        close(fit.update_rate, 0.1, 0.01);
+        close(fit.bid_avg_price, 990.0, 0.5);
+        close(fit.bid_price_dev, 20.0, 0.5);
+        close(fit.ask_avg_price, 1010.0, 0.5);
+        close(fit.ask_price_dev, 40.0, 0.5);
+        assert_eq!(fit.lot_size, 100);
+        // Sizes are rounded to lots, so the log of the size in lots is centered on 6 - ln(100)
+        close(fit.size_mu, 6.0 - 100f64.ln(), 0.05);
+        assert!(Scenario::parse(&fit.to_scenario()).is_ok());
+        assert!(fit.to_cli().contains("--lot-size 100"));
+    }
+
+    #[test]
+    fn fit_reads_historical_sources() {
+        // Four LOBSTER submissions a second apart and a deletion, prices in units of 1/10000
+        let messages = "\
+34200,1,1,100,1000000,1
+34201,1,2,200,1010000,-1
+34202,1,3,100,1002000,1
+34203,1,4,300,1012000,-1
+34204,3,1,100,1000000,1
+";
+        let mut source = crate::data_generator::csv_replay::CsvReplaySource::lobster(
+            std::io::Cursor::new(messages),
+            34_200_000_000_000,
+            100.0,
+            None,
+        )
+        .unwrap();
+        let fit = Calibration::fit(&mut source).unwrap();
+        assert_eq!(fit.orders, 5);
+        assert_eq!(fit.order_rate, 1.0);
+        assert_eq!((fit.new_limit_rate, fit.cancel_rate), (0.8, 0.2));
+        assert_eq!(fit.lot_size, 100);
+        assert_eq!(fit.bid_avg_price, 10_010.5);
+    }
+
+    #[test]
+    fn fit_needs_a_time_span() {
+        struct Single(Option<mm_core::lob_core::market_orders::Order>);
+        impl EventSource for Single {
+            fn next_event(&mut self) -> Option<mm_core::lob_core::market_orders::Order> {
+                self.0.take()
+            }
+        }
+        let order = GaussianOrderGenerator::new(1000.0, 1.0, 1000.0, 1.0).generate(
+            0,
+            0,
+            (OrderSide::Bid, OrderType::Limit { qty: 0, price: 0 }),
+            &mut ChaCha8Rng::seed_from_u64(0),
+        );
+        assert!(Calibration::fit(&mut Single(Some(order))).is_err());
+    }
+}
@@ -1,5 +1,27 @@
-use crate::{event_recorder::RecorderType, simulator::latency_config::JitterKind};
 use clap::{Parser, Subcommand};
+use engine::{
+    agents::AGENT_CLIENT_ID_BASE,
+    calibration::CalibrationFormat,
+    data_generator::{
+        csv_replay::{ColumnMapping, TimeUnit},
+        live_orders::TargetSelection,
+        order_generators::{PriceProcess, PriceProcessKind, TouchAnchor, TouchOffsetGenerator},
+        order_sizes::{OrderSizes, SizeDistribution},
+        rate_controllers::{HawkesRate, PiecewiseRate, UShapedRate},
+        toxic_flow::ToxicPattern,
+        type_selectors::{
+            BookRateTypeSelector, ImbalanceTypeSelector, MARKOV_STATES, MarkovTypeSelector,
+            TypeSelectorEnum, UniformTypeSelector,
+        },
+    },
+    event_recorder::RecorderType,
+    scenario::{Scenario, regimes::RegimeModel},
+    session::{Date, SessionCalendar, parse_time_of_day},
+    simulator::latency_config::{JitterKind, SimJitter},
+};
+use mm_core::lob_core::{ClientId, OrderQty, Price};
+use rand_distr::{Normal, Uniform};
+use std::ops::Range;
 
 /// Helper function that verifies that a float is between 0 and 1.0
 pub fn prob_parser(s: &str) -> Result<f64, String> {
@@ -21,6 +43,29 @@ pub fn positive_float_parser(s: &str) -> Result<f64, String> {
     }
 }
 
+/// Helper function that parses a segment of a rate schedule written as `START:RATE`
+pub fn rate_segment_parser(s: &str) -> Result<(f64, f64), String> {
+    let (start, rate) = s.split_once(':').ok_or("expected START:RATE")?;
+    let start: f64 = start.parse().map_err(|_| "invalid start")?;
+    let rate: f64 = rate.parse().map_err(|_| "invalid rate")?;
+    if !start.is_finite() || !rate.is_finite() {
+        return Err("start and rate must be finite".into());
+    }
+    Ok((start, rate))
+}
+
+/// Helper function that parses a client id range written as `START..END`
+pub fn client_range_parser(s: &str) -> Result<Range<ClientId>, String> {
+    let (start, end) = s.split_once("..").ok_or("expected START..END")?;
+    let start: ClientId = start.parse().map_err(|_| "invalid start")?;
+    let end: ClientId = end.parse().map_err(|_| "invalid end")?;
+    if start < end {
+        Ok(start..end)
+    } else {
+        Err("range must not be empty".into())
+    }
+}
+
 #[derive(Parser, Clone)]
 #[command(version, about, long_about = None)]
 pub struct Args {
@@ -66,7 +111,8 @@ pub struct Args {
     #[arg(long = "record")]
     pub record_type: Option<RecorderType>,
 
-    /// The name of the file that the run should be recorded to
+    /// The name of the file that the run should be recorded to. Binary and archive recordings also store a fingerprint
+    /// trail in the same file with a .fp suffix, used by the verify subcommand
     #[arg(long, default_value = "run.mm")]
     pub record_file: String,
 
@@ -74,6 +120,125 @@ pub struct Args {
     #[arg(long, default_value_t = 512)]
     pub record_batch_size: usize,
 
+    /// Number of in-process zero-intelligence agents trading alongside the event source
+    #[arg(long, default_value_t = 0)]
+    pub zi_agents: usize,
+
+    /// Number of in-process market maker agents trading alongside the event source
+    #[arg(long, default_value_t = 0)]
+    pub market_maker_agents: usize,
+
+    /// Number of in-process momentum agents trading alongside the event source
+    #[arg(long, default_value_t = 0)]
+    pub momentum_agents: usize,
+
+    /// Reference price in cents that in-process agents quote around when they start
+    #[arg(long, default_value_t = 1000)]
+    pub agent_ref_price: Price,
+
+    /// Run this many independent headless simulations with consecutive seeds instead of serving clients, and write
+    /// statistics aggregated across runs to --batch-output. The event source must be finite
+    #[arg(long)]
+    pub batch: Option<usize>,
+
+    /// Number of worker threads used for batch runs, defaults to the number of available cores
+    #[arg(long)]
+    pub batch_threads: Option<usize>,
+
+    /// File that statistics aggregated across batch runs are written to, in CSV format
+    #[arg(long, default_value = "batch.csv")]
+    pub batch_output: String,
+
+    /// File that the statistics of every individual batch run are written to, in CSV format
+    #[arg(long)]
+    pub batch_runs_output: Option<String>,
+
+    /// Sweep a parameter over a range given as name=start:stop:step, or over a list given as name=v1,v2,... Can be
+    /// repeated, every combination of the swept values is run headless --batch times (once by default)
+    ///
+    /// Parameters: sim_latency, and with the poisson source order_rate, bid_rate, new_limit_rate, cancel_rate,
+    /// market_rate, update_rate, bid_avg_price, bid_price_dev, ask_avg_price, ask_price_dev
+    #[arg(long)]
+    pub sweep: Vec<String>,
+
+    /// File that sweep results are written to, one row per configuration. Written as JSON if the file name ends in
+    /// .json and as CSV otherwise
+    #[arg(long, default_value = "sweep.csv")]
+    pub sweep_output: String,
+
+    /// Interval in nanoseconds of sim time between samples of the top of the book in batch runs
+    #[arg(long, default_value_t = 1_000_000)]
+    pub stats_interval: u64,
+
+    /// Distribution of the sizes of synthetic orders from the poisson and hawkes sources, one of uniform:MIN:MAX,
+    /// geometric:MEAN, lognormal:MU:SIGMA, power-law:MIN:EXPONENT, round-lots:SIZE=WEIGHT,... or empirical:FILE.
+    /// Distributions joined with + and prefixed with WEIGHT* are mixed, e.g. 0.8*round-lots:100=3,500=1+0.2*geometric:40.
+    /// Defaults to uniform:1:20
+    #[arg(long)]
+    pub sizes: Option<String>,
+
+    /// Synthetic order sizes are rounded to a multiple of this many shares, and are at least one lot
+    #[arg(long, default_value_t = 1)]
+    pub lot_size: OrderQty,
+
+    /// Largest synthetic order size, rounded down to a whole lot
+    #[arg(long)]
+    pub max_size: Option<OrderQty>,
+
+    /// Synthetic cancels and updates target live orders weighted by (1 + age in seconds)^EXPONENT, positive values
+    /// favour old orders and negative values recent ones
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub cancel_age_exponent: f64,
+
+    /// Synthetic cancels and updates target live orders weighted by (1 + ticks from the touch)^EXPONENT, positive values
+    /// favour orders deep in the book and negative values orders near the touch
+    #[arg(long, default_value_t = 0.0, allow_negative_numbers = true)]
+    pub cancel_distance_exponent: f64,
+
+    /// TOML file of user orders injected at exact sim times alongside the event source, such as a large market order
+    /// at a given second. Scripted orders are reported like any other client's
+    #[arg(long)]
+    pub orders: Option<String>,
+
+    /// Lay a trading calendar over sim time: pre-open, opening auction, continuous trading, closing auction and
+    /// post-close phases on every weekday, announced with ITCH System Event messages. Orders outside continuous
+    /// trading and the auctions are rejected
+    #[arg(long, default_value_t = false)]
+    pub session: bool,
+
+    /// Trading date at sim time 0, as YYYY-MM-DD
+    #[arg(long, default_value = "2024-03-01", value_parser = Date::parse)]
+    pub session_date: Date,
+
+    /// Time of day at sim time 0, as HH:MM[:SS[.f]]. ITCH timestamps are nanoseconds since midnight, so they are
+    /// offset by this time. Defaults to the market open with --session and to midnight otherwise
+    #[arg(long, value_parser = parse_time_of_day)]
+    pub session_start: Option<u64>,
+
+    /// Time of day the pre-open phase starts, system hours begin [default: 04:00]
+    #[arg(long, value_parser = parse_time_of_day)]
+    pub pre_open: Option<u64>,
+
+    /// Time of day the opening auction starts [default: 09:28]
+    #[arg(long, value_parser = parse_time_of_day)]
+    pub opening_auction: Option<u64>,
+
+    /// Time of day the opening auction uncrosses and continuous trading starts [default: 09:30]
+    #[arg(long, value_parser = parse_time_of_day)]
+    pub market_open: Option<u64>,
+
+    /// Time of day the closing auction starts [default: 15:50]
+    #[arg(long, value_parser = parse_time_of_day)]
+    pub closing_auction: Option<u64>,
+
+    /// Time of day the closing auction uncrosses and the post-close phase starts [default: 16:00]
+    #[arg(long, value_parser = parse_time_of_day)]
+    pub market_close: Option<u64>,
+
+    /// Time of day the post-close phase and system hours end [default: 20:00]
+    #[arg(long, value_parser = parse_time_of_day)]
+    pub end_of_day: Option<u64>,
+
     /// Records runtime and events processed and outputs to stdout after simulator finishes generating orders
     ///
     /// Output is in CSV format: step_count,run_time(nanosec),sim_time(nanosec)
@@ -90,10 +255,59 @@ pub enum EventSourceType {
         #[arg(long)]
         count: Option<u64>,
 
-        /// Rate of production of synthetic orders in orders per second
+        /// Rate of production of synthetic orders in orders per second. With --u-shape or --volume-csv this is the
+        /// average rate over the day
         #[arg(long, default_value_t = 100_000.0, value_parser = positive_float_parser)]
         order_rate: f64,
 
+        /// Piecewise-constant arrival rate instead of --order-rate, as comma separated START:RATE segments with the
+        /// start in seconds of sim time, e.g. 0:200000,1800:50000. The first segment must start at 0
+        #[arg(long, value_delimiter = ',', value_parser = rate_segment_parser, conflicts_with_all = ["u_shape", "volume_csv"])]
+        rate_schedule: Vec<(f64, f64)>,
+
+        /// Length in seconds after which the --rate-schedule repeats, if unused the last rate holds
+        #[arg(long, requires = "rate_schedule", value_parser = positive_float_parser)]
+        rate_period: Option<f64>,
+
+        /// Follow the U-shaped intraday curve, with arrival rates at the open and the close this many times the rate at
+        /// midday. Sim time 0 is the open
+        #[arg(long, conflicts_with = "volume_csv")]
+        u_shape: Option<f64>,
+
+        /// Length in seconds of the trading day of --u-shape
+        #[arg(long, default_value_t = 23_400.0, value_parser = positive_float_parser)]
+        day_length: f64,
+
+        /// CSV file of per-minute volumes, one row per minute starting at sim time 0 and taken from the last column.
+        /// Arrival rates follow the volumes and the profile repeats after the last minute
+        #[arg(long)]
+        volume_csv: Option<String>,
+
+        /// Place prices around a latent fundamental price that moves over sim time instead of around fixed averages.
+        /// The reference starts halfway between the bid and ask averages, which become offsets around it
+        #[arg(long)]
+        price_process: Option<PriceProcessKind>,
+
+        /// Volatility of the reference price per square root of a second, relative to the starting price
+        #[arg(long, default_value_t = 0.0005)]
+        volatility: f64,
+
+        /// Drift of the reference price per second, relative to the price. Unused by the random walk
+        #[arg(long, default_value_t = 0.0)]
+        drift: f64,
+
+        /// Expected number of jumps of the reference price per second, at most 1000, for the jump diffusion
+        #[arg(long, default_value_t = 0.01)]
+        jump_rate: f64,
+
+        /// Mean log size of a jump of the reference price, for the jump diffusion
+        #[arg(long, default_value_t = 0.0)]
+        jump_mean: f64,
+
+        /// Standard deviation of the log size of a jump of the reference price, for the jump diffusion
+        #[arg(long, default_value_t = 0.01)]
+        jump_std: f64,
+
         /// Proportion of synthetic orders that are bids vs asks, value must be between 0-1
         #[arg(long, default_value_t = 0.5, value_parser = prob_parser)]
         bid_rate: f64,
@@ -114,6 +328,76 @@ pub enum EventSourceType {
         #[arg(long, default_value_t = 0.05, value_parser = prob_parser)]
         update_rate: f64,
 
+        /// Probability that an order repeats the side of the previous one, otherwise the side is drawn with --bid-rate.
+        /// Gives the signs of consecutive orders this autocorrelation
+        #[arg(long, value_parser = prob_parser, conflicts_with_all = ["type_transitions", "imbalance_weight", "flow_weight"])]
+        side_persistence: Option<f64>,
+
+        /// Markov chain over (side, type) pairs instead of independent draws, as 64 comma separated transition weights
+        /// row by row. States are bid limit, bid market, bid cancel, bid update, then the same for asks
+        #[arg(long, value_delimiter = ',', conflicts_with_all = ["imbalance_weight", "flow_weight"])]
+        type_transitions: Vec<f64>,
+
+        /// Tilts the odds of a bid by exp(WEIGHT * imbalance), with the imbalance (bids - asks) / (bids + asks) of the
+        /// resting quantity over the top --imbalance-levels levels
+        #[arg(long, allow_negative_numbers = true)]
+        imbalance_weight: Option<f64>,
+
+        /// Tilts the odds of a bid by exp(WEIGHT * flow), with the flow an average of recent trade signs, +1 for
+        /// buyer-initiated trades
+        #[arg(long, allow_negative_numbers = true)]
+        flow_weight: Option<f64>,
+
+        /// Weight of every new trade in the average of trade signs
+        #[arg(long, default_value_t = 0.1)]
+        flow_decay: f64,
+
+        /// Number of price levels per side the imbalance is measured over
+        #[arg(long, default_value_t = 1)]
+        imbalance_levels: usize,
+
+        /// Average order price in cents for bids, must be a positive, non-zero value
+        #[arg(long, default_value_t = 1000.0, value_parser = positive_float_parser)]
+        bid_avg_price: f64,
+
+        /// Standard deviation of order price in cents for bids, must be a positive, non-zero value
+        #[arg(long, default_value_t = 50.0, value_parser = positive_float_parser)]
+        bid_price_dev: f64,
+
+        /// Average order price in cents for asks, must be a positive, non-zero value
+        #[arg(long, default_value_t = 1000.0, value_parser = positive_float_parser)]
+        ask_avg_price: f64,
+
+        /// Standard deviation of order price in cents for asks, must be a positive, non-zero value
+        #[arg(long, default_value_t = 50.0, value_parser = positive_float_parser)]
+        ask_price_dev: f64,
+    },
+    /// Generate synthetic orders whose arrivals follow a self-exciting Hawkes process, with one component per order
+    /// type in the order limit, market, cancel, update. Every event raises the intensity of the other types, which then
+    /// decays exponentially, so orders arrive in clusters
+    Hawkes {
+        /// Number of orders to generate before terminating, if unused the simulation runs indefinitely
+        #[arg(long)]
+        count: Option<u64>,
+
+        /// Baseline rate of each order type in orders per second, 4 comma separated values
+        #[arg(long, default_value = "25000,2500,20000,2500", value_delimiter = ',')]
+        baseline: Vec<f64>,
+
+        /// Expected number of orders of type i triggered by one order of type j. 1 or 4 values set the diagonal, so that
+        /// each type only excites itself, 16 values set the full matrix row by row. The spectral radius must be below 1
+        #[arg(long, default_value = "0.5", value_delimiter = ',')]
+        excitation: Vec<f64>,
+
+        /// Decay rate of the excitation in 1/seconds. 1 value applies to every type, 4 values to each excited type,
+        /// 16 values set the full matrix row by row
+        #[arg(long, default_value = "1000", value_delimiter = ',')]
+        decay: Vec<f64>,
+
+        /// Proportion of synthetic orders that are bids vs asks, value must be between 0-1
+        #[arg(long, default_value_t = 0.5, value_parser = prob_parser)]
+        bid_rate: f64,
+
         /// Average order price in cents for bids, must be a positive, non-zero value
         #[arg(long, default_value_t = 1000.0, value_parser = positive_float_parser)]
         bid_avg_price: f64,
@@ -130,6 +414,134 @@ pub enum EventSourceType {
         #[arg(long, default_value_t = 50.0, value_parser = positive_float_parser)]
         ask_price_dev: f64,
     },
+    /// Generate synthetic orders that react to the book, with limit prices a random number of ticks from the current
+    /// best quotes and cancels in proportion to the resting depth. Uniform placement over the window is the Santa Fe
+    /// zero-intelligence model, a positive --offset-exponent gives the power law placement of Cont-Stoikov-Talreja
+    Book {
+        /// Number of orders to generate before terminating, if unused the simulation runs indefinitely
+        #[arg(long)]
+        count: Option<u64>,
+
+        /// Rate of production of synthetic orders in orders per second
+        #[arg(long, default_value_t = 100_000.0, value_parser = positive_float_parser)]
+        order_rate: f64,
+
+        /// Proportion of limit and market orders that are bids vs asks, value must be between 0-1
+        #[arg(long, default_value_t = 0.5, value_parser = prob_parser)]
+        bid_rate: f64,
+
+        /// Relative rate of limit orders
+        #[arg(long, default_value_t = 1.0, value_parser = positive_float_parser)]
+        limit_rate: f64,
+
+        /// Relative rate of market orders
+        #[arg(long, default_value_t = 0.2, value_parser = positive_float_parser)]
+        market_rate: f64,
+
+        /// Relative rate at which every resting share is canceled, in the units of --limit-rate
+        #[arg(long, default_value_t = 0.001)]
+        cancel_rate: f64,
+
+        /// Quote that limit prices are measured from
+        #[arg(long, value_enum, default_value_t = TouchAnchor::Opposite)]
+        anchor: TouchAnchor,
+
+        /// Number of price levels from the anchor that limit orders are placed at
+        #[arg(long, default_value_t = 20)]
+        window: usize,
+
+        /// Limit orders at level i are placed with probability proportional to 1 / i^EXPONENT, 0 places them uniformly
+        #[arg(long, default_value_t = 0.0)]
+        offset_exponent: f64,
+
+        /// Price increment in cents between levels
+        #[arg(long, default_value_t = 1)]
+        tick: Price,
+
+        /// Price in cents that orders are placed around until the book has quotes
+        #[arg(long, default_value_t = 1000)]
+        reference_price: Price,
+    },
+    /// Generate the orders of an informed trader who knows where a latent fundamental price will be --horizon seconds
+    /// ahead, blurred by --signal-noise. Meant to be merged with background flow, e.g. --source "book --count 100000"
+    /// --source "informed --count 1000", so that market makers face adverse selection
+    Informed {
+        /// Number of orders to generate before terminating, if unused the simulation runs indefinitely
+        #[arg(long)]
+        count: Option<u64>,
+
+        /// Number of trading decisions per second, each cancels the previous resting order and places a new one
+        #[arg(long, default_value_t = 10.0, value_parser = positive_float_parser)]
+        rate: f64,
+
+        /// How far ahead in seconds the trader knows the fundamental price
+        #[arg(long, default_value_t = 10.0)]
+        horizon: f64,
+
+        /// Standard deviation in cents of the error of the trader's view of the future price, 0 for perfect foresight
+        #[arg(long, default_value_t = 0.0)]
+        signal_noise: f64,
+
+        /// Edge in cents the trader demands before taking liquidity, otherwise it joins its own side of the book
+        #[arg(long, default_value_t = 2.0)]
+        threshold: f64,
+
+        /// Process followed by the fundamental price
+        #[arg(long, value_enum, default_value_t = PriceProcessKind::RandomWalk)]
+        price_process: PriceProcessKind,
+
+        /// Volatility of the fundamental price per square root of a second, relative to the starting price
+        #[arg(long, default_value_t = 0.0005)]
+        volatility: f64,
+
+        /// Drift of the fundamental price per second, relative to the price. Unused by the random walk
+        #[arg(long, default_value_t = 0.0)]
+        drift: f64,
+
+        /// Expected number of jumps of the fundamental price per second, at most 1000, for the jump diffusion
+        #[arg(long, default_value_t = 0.01)]
+        jump_rate: f64,
+
+        /// Mean log size of a jump of the fundamental price, for the jump diffusion
+        #[arg(long, default_value_t = 0.0)]
+        jump_mean: f64,
+
+        /// Standard deviation of the log size of a jump of the fundamental price, for the jump diffusion
+        #[arg(long, default_value_t = 0.01)]
+        jump_std: f64,
+
+        /// Starting value of the fundamental price in cents
+        #[arg(long, default_value_t = 1000)]
+        reference_price: Price,
+    },
+    /// Generate manipulative order patterns: quote stuffing floods the best quote with orders that are canceled at
+    /// once, momentum ignition pushes the price with market orders and unwinds into the move. Meant to be merged with
+    /// background flow
+    Toxic {
+        /// Pattern of the generated episodes
+        #[arg(long, value_enum, default_value_t = ToxicPattern::QuoteStuffing)]
+        pattern: ToxicPattern,
+
+        /// Number of orders to generate before terminating, if unused the simulation runs indefinitely
+        #[arg(long)]
+        count: Option<u64>,
+
+        /// Number of episodes per second
+        #[arg(long, default_value_t = 1.0, value_parser = positive_float_parser)]
+        episode_rate: f64,
+
+        /// Number of orders in the burst of an episode
+        #[arg(long, default_value_t = 50)]
+        burst: usize,
+
+        /// Sim time in microseconds between the orders of a burst
+        #[arg(long, default_value_t = 100)]
+        spacing: u64,
+
+        /// Price in cents that orders are placed at until the book has quotes
+        #[arg(long, default_value_t = 1000)]
+        reference_price: Price,
+    },
     /// Replay a historical record of order data from a file, file must contain binary data logged using --record
     File {
         /// File path to file containing binary-mapped order data
@@ -140,6 +552,213 @@ pub enum EventSourceType {
         #[arg(long, default_value_t = 64)]
         batch_size: usize,
     },
+    /// Replay a recording made with --record archive from a memory map, reading the orders in place. The replay can
+    /// start at any order, the book then starts empty and cancels of earlier orders are rejected
+    Archive {
+        /// File path to an archive recording
+        #[arg(required = true)]
+        file_name: String,
+
+        /// Index of the first order to replay
+        #[arg(long, conflicts_with = "from_time")]
+        from_index: Option<usize>,
+
+        /// Replay from the first order at or after this sim time, in seconds
+        #[arg(long)]
+        from_time: Option<f64>,
+    },
+    /// Replay the order flow of one symbol from a decompressed Nasdaq TotalView-ITCH 5.0 file, rebuilding its
+    /// historical book. ITCH timestamps are offset by --session-start, messages before it are replayed at sim time 0.
+    /// Executions reduce the orders they hit without publishing trades, and a partially executed order loses its
+    /// queue position
+    Itch {
+        /// File path to an ITCH 5.0 file
+        #[arg(required = true)]
+        file_name: String,
+
+        /// Stock symbol to replay
+        #[arg(long, required = true)]
+        symbol: String,
+
+        /// Number of orders to replay before terminating, if unused the whole file is replayed
+        #[arg(long)]
+        count: Option<u64>,
+
+        /// ITCH prices have 4 decimals and are divided by this, the default gives prices in cents
+        #[arg(long, default_value_t = 100)]
+        price_scale: u32,
+    },
+    /// Replay a LOBSTER message file, rebuilding its historical book. Times are offset by --session-start, messages
+    /// before it are replayed at sim time 0. Executions reduce the orders they hit without publishing trades, and a
+    /// partially executed order loses its queue position
+    Lobster {
+        /// File path to a LOBSTER message file
+        #[arg(required = true)]
+        file_name: String,
+
+        /// Number of orders to replay before terminating, if unused the whole file is replayed
+        #[arg(long)]
+        count: Option<u64>,
+
+        /// LOBSTER prices are in units of 1/10000 and are divided by this, the default gives prices in cents
+        #[arg(long, default_value_t = 100.0, value_parser = positive_float_parser)]
+        price_scale: f64,
+    },
+    /// Replay a CSV file of order-level data with the columns timestamp, type, side, price, qty and order_id, see
+    /// CsvReplaySource for the values they take. Timestamps are offset by --session-start, rows before it are replayed
+    /// at sim time 0. Executions reduce the orders they hit without publishing trades, and a partially executed order
+    /// loses its queue position
+    Csv {
+        /// File path to a CSV file
+        #[arg(required = true)]
+        file_name: String,
+
+        /// Columns of the fields as FIELD=COLUMN pairs, where COLUMN is a header name or a 0-based index, e.g.
+        /// --columns timestamp=Time,qty=Size. Fields left out are found under their own name
+        #[arg(long, value_parser = ColumnMapping::parse)]
+        columns: Option<ColumnMapping>,
+
+        /// The file has no header row, columns are then given by index and default to the order above
+        #[arg(long, default_value_t = false)]
+        no_header: bool,
+
+        /// Character separating the columns
+        #[arg(long, default_value_t = ',')]
+        delimiter: char,
+
+        /// Unit of the timestamps, which may have decimals
+        #[arg(long, value_enum, default_value_t = TimeUnit::Ns)]
+        time_unit: TimeUnit,
+
+        /// Prices are divided by this and rounded to a whole tick, e.g. 0.01 for prices in dollars and ticks in cents
+        #[arg(long, default_value_t = 1.0, value_parser = positive_float_parser)]
+        price_scale: f64,
+
+        /// Number of orders to replay before terminating, if unused the whole file is replayed
+        #[arg(long)]
+        count: Option<u64>,
+    },
+    /// Run a scenario file describing a timeline of market phases, scripted events and an ending condition
+    Scenario {
+        /// File path to a TOML scenario file
+        #[arg(required = true)]
+        file_name: String,
+    },
+    /// Run a regime file describing named market regimes that the order flow switches between at random. The regimes
+    /// the run enters can be written to a CSV file with --labels
+    Regimes {
+        /// File path to a TOML regime file
+        #[arg(required = true)]
+        file_name: String,
+
+        /// CSV file to write the regimes the run enters to, as time,regime rows with the sim time in nanoseconds
+        #[arg(long)]
+        labels: Option<String>,
+    },
+    /// Interleave several event sources by timestamp, e.g. a replayed file with synthetic background flow. Each
+    /// source is written like its own subcommand, e.g. --source "file day.bin --clients 0..100" --source "poisson
+    /// --count 1000"
+    Merge {
+        /// Source to merge, written as a source subcommand and its arguments. `--clients START..END` sets the client
+        /// ids the source trades under, by default each source gets its own block of 2^24 ids
+        #[arg(long = "source", required = true)]
+        sources: Vec<String>,
+    },
+    /// Re-run a binary recording through a fresh simulator and compare it with the fingerprint trail stored next to
+    /// it, reporting the first event that differs. Runs with a trading session are verified with the same --session
+    /// arguments. Nothing is published
+    Verify {
+        /// File path to a recording made with --record binary, or with --record archive when --archive is set
+        #[arg(required = true)]
+        file_name: String,
+
+        /// File path to the fingerprint trail, defaults to the recording path with a .fp suffix
+        #[arg(long)]
+        trail: Option<String>,
+
+        /// The recording was made with --record archive
+        #[arg(long)]
+        archive: bool,
+    },
+    /// Fit the parameters of the poisson source to a binary recording, or to the orders of any other source such as an
+    /// imported historical file, and print them as a ready-to-use configuration: arrival rate, side and type
+    /// proportions, price distributions and a log-normal size distribution
+    Calibrate {
+        /// File path to a recording made with --record binary
+        #[arg(required_unless_present = "source", conflicts_with = "source")]
+        file_name: Option<String>,
+
+        /// Source to fit instead of a recording, written as a source subcommand and its arguments, e.g. --source "itch
+        /// day.itch --symbol AAPL". Its orders are fitted as generated, so it needs to end
+        #[arg(long)]
+        source: Option<String>,
+
+        /// Whether to write command-line arguments for the poisson subcommand or a scenario file
+        #[arg(long, value_enum, default_value_t = CalibrationFormat::Cli)]
+        format: CalibrationFormat,
+
+        /// File to write the configuration to instead of stdout
+        #[arg(long)]
+        output: Option<String>,
+    },
+    /// Compute the stylized facts of a market, from a binary recording or from a headless run of a source: return
+    /// kurtosis and autocorrelations, volatility clustering, spread and depth profiles and trade sign autocorrelation
+    Facts {
+        /// File path to a recording made with --record binary
+        #[arg(required_unless_present = "source", conflicts_with = "source")]
+        file_name: Option<String>,
+
+        /// Source to run instead of reading a recording, written as a source subcommand and its arguments, e.g.
+        /// --source "poisson --count 100000". The source ends the run, so it needs a `count`
+        #[arg(long)]
+        source: Option<String>,
+
+        /// Sim time in milliseconds between samples of the book, returns are measured over this interval
+        #[arg(long, default_value_t = 100)]
+        interval: u64,
+
+        /// Largest lag of the autocorrelations
+        #[arg(long, default_value_t = 10)]
+        lags: usize,
+
+        /// File to write the report to, as metric,value rows
+        #[arg(long, default_value = "facts.csv")]
+        output: String,
+    },
+}
+
+/// One input of a merge source, parsed from a --source argument
+#[derive(Parser, Clone)]
+#[command(no_binary_name = true)]
+pub struct SourceSpec {
+    #[command(subcommand)]
+    pub source: EventSourceType,
+
+    /// Client ids the source trades under
+    #[arg(long, global = true, value_parser = client_range_parser)]
+    pub clients: Option<Range<ClientId>>,
+}
+impl SourceSpec {
+    /// Client ids given to the i-th merged source when --clients is not used
+    pub fn default_clients(i: usize) -> Range<ClientId> {
+        let block: ClientId = 1 << 24;
+        i as ClientId * block..(i as ClientId + 1) * block
+    }
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let spec = Self::try_parse_from(spec.split_whitespace())
+            .map_err(|e| format!("merge: `{}`: {}", spec, e.render()))?;
+        match spec.source {
+            EventSourceType::Merge { .. }
+            | EventSourceType::Verify { .. }
+            | EventSourceType::Calibrate { .. }
+            | EventSourceType::Facts { .. } => {
+                Err("merge: only poisson, hawkes, book, informed, toxic, file, archive, itch, lobster, csv, \
+                     scenario and regimes sources can be merged"
+                    .into())
+            }
+            _ => Ok(spec),
+        }
+    }
 }
 
 /// Validate additional parameters that cannot be handled with value parsers
@@ -161,8 +780,161 @@ pub fn validate(args: &Args) -> Result<(), String> {
             }
         }
     }
-    match &args.event_source {
-        EventSourceType::Poisson { .. } => {}
+    if let Some(session) = session_calendar(args) {
+        session.validate()?;
+    }
+    order_sizes(args)?;
+    target_selection(args)?;
+    if args.batch.is_some() || !args.sweep.is_empty() {
+        if args.batch == Some(0) {
+            return Err("batch: at least one run is required".into());
+        }
+        if args.record_type.is_some() || args.real_time {
+            return Err("batch: runs cannot be recorded or paced to real time".into());
+        }
+    }
+    validate_source(args, &args.event_source)
+}
+
+fn validate_source(args: &Args, source: &EventSourceType) -> Result<(), String> {
+    match source {
+        EventSourceType::Poisson {
+            count,
+            order_rate,
+            rate_schedule,
+            rate_period,
+            u_shape,
+            day_length,
+            ..
+        } => {
+            if (args.batch.is_some() || !args.sweep.is_empty()) && count.is_none() {
+                return Err("batch: poisson sources need a `count` so that every run ends".into());
+            }
+            if !rate_schedule.is_empty() {
+                PiecewiseRate::new(rate_schedule.clone(), *rate_period)?;
+            }
+            if let Some(edge_ratio) = u_shape {
+                UShapedRate::new(*order_rate, *edge_ratio, *day_length)?;
+            }
+            if let Some(process) = price_process(source) {
+                process.validate()?;
+            }
+            type_selector(source)?;
+        }
+        EventSourceType::Hawkes {
+            count,
+            baseline,
+            excitation,
+            decay,
+            ..
+        } => {
+            if (args.batch.is_some() || !args.sweep.is_empty()) && count.is_none() {
+                return Err("batch: hawkes sources need a `count` so that every run ends".into());
+            }
+            hawkes_rate(baseline, excitation, decay)?;
+        }
+        EventSourceType::Book { count, .. } => {
+            if (args.batch.is_some() || !args.sweep.is_empty()) && count.is_none() {
+                return Err("batch: book sources need a `count` so that every run ends".into());
+            }
+            book_model(source)?;
+        }
+        EventSourceType::Informed {
+            count,
+            horizon,
+            signal_noise,
+            threshold,
+            ..
+        } => {
+            if (args.batch.is_some() || !args.sweep.is_empty()) && count.is_none() {
+                return Err("batch: informed sources need a `count` so that every run ends".into());
+            }
+            if !(*horizon >= 0.0 && *signal_noise >= 0.0 && *threshold >= 0.0) {
+                return Err(
+                    "informed: the horizon, signal noise and threshold must be >= 0".into(),
+                );
+            }
+            if let Some(process) = price_process(source) {
+                process.validate()?;
+            }
+        }
+        EventSourceType::Toxic { count, burst, .. } => {
+            if (args.batch.is_some() || !args.sweep.is_empty()) && count.is_none() {
+                return Err("batch: toxic sources need a `count` so that every run ends".into());
+            }
+            if *burst == 0 {
+                return Err("toxic: bursts need at least one order".into());
+            }
+        }
+        EventSourceType::Scenario { file_name } => {
+            let scenario = Scenario::load(file_name)?;
+            if (args.batch.is_some() || !args.sweep.is_empty())
+                && scenario.end.time.is_none()
+                && scenario.end.orders.is_none()
+            {
+                return Err("batch: scenario files need an `[end]` so that every run ends".into());
+            }
+        }
+        EventSourceType::Lobster { .. }
+        | EventSourceType::Csv { .. }
+        | EventSourceType::Verify { .. }
+        | EventSourceType::Calibrate { source: None, .. } => {}
+        EventSourceType::Calibrate {
+            source: Some(spec), ..
+        } => {
+            let spec = SourceSpec::parse(spec)?;
+            // NOTE: Fitting reads the source to its end like a batch run, so it is validated as one
+            let run = Args {
+                batch: Some(1),
+                ..args.clone()
+            };
+            validate_source(&run, &spec.source)?;
+        }
+        EventSourceType::Facts {
+            source: Some(spec),
+            interval,
+            ..
+        } => {
+            if *interval == 0 {
+                return Err("facts: `interval` must be > 0".into());
+            }
+            let spec = SourceSpec::parse(spec)?;
+            // NOTE: The run has to end like a batch run, so the source is validated as one
+            let run = Args {
+                batch: Some(1),
+                ..args.clone()
+            };
+            validate_source(&run, &spec.source)?;
+        }
+        EventSourceType::Facts { interval, .. } => {
+            if *interval == 0 {
+                return Err("facts: `interval` must be > 0".into());
+            }
+        }
+        EventSourceType::Regimes { file_name, labels } => {
+            let model = RegimeModel::load(file_name)?;
+            let batch = args.batch.is_some() || !args.sweep.is_empty();
+            if batch && model.end.time.is_none() && model.end.orders.is_none() {
+                return Err("batch: regime files need an `[end]` so that every run ends".into());
+            }
+            if batch && labels.is_some() {
+                return Err("batch: `labels` can only be written by a single run".into());
+            }
+        }
+        EventSourceType::Itch {
+            symbol,
+            price_scale,
+            ..
+        } => {
+            if symbol.is_empty() || symbol.len() > 8 || !symbol.is_ascii() {
+                return Err(format!(
+                    "itch: `{symbol}` is not a symbol of 1 to 8 characters"
+                ));
+            }
+            if *price_scale == 0 {
+                return Err("itch: `price_scale` must be > 0".into());
+            }
+        }
         EventSourceType::File {
             file_name,
             batch_size: _,
@@ -173,6 +945,344 @@ pub fn validate(args: &Args) -> Result<(), String> {
                 return Err("file replay: attempting to read and write from the same file".into());
             }
         }
+        EventSourceType::Archive {
+            file_name,
+            from_time,
+            ..
+        } => {
+            if args.record_type.is_some() && args.record_file == *file_name {
+                return Err(
+                    "archive replay: attempting to read and write from the same file".into(),
+                );
+            }
+            if from_time.is_some_and(|time| time.is_nan() || time < 0.0) {
+                return Err("archive replay: `from_time` must be >= 0.0".into());
+            }
+        }
+        EventSourceType::Merge { sources } => {
+            let mut ranges: Vec<Range<ClientId>> = Vec::with_capacity(sources.len());
+            let mut fundamentals = Vec::new();
+            for (i, spec) in sources.iter().enumerate() {
+                let spec = SourceSpec::parse(spec)?;
+                validate_source(args, &spec.source)?;
+                fundamentals.extend(fundamental(&spec.source));
+                if fundamentals.windows(2).any(|pair| pair[0] != pair[1]) {
+                    return Err("merge: sources with a price process follow one fundamental, their processes and \
+                         starting prices must match"
+                        .into());
+                }
+                let clients = spec.clients.unwrap_or(SourceSpec::default_clients(i));
+                if clients.end > AGENT_CLIENT_ID_BASE {
+                    return Err(format!(
+                        "merge: client ids must be below {}, the agents' ids",
+                        AGENT_CLIENT_ID_BASE
+                    ));
+                }
+                if let Some(other) = ranges
+                    .iter()
+                    .find(|other| other.start < clients.end && clients.start < other.end)
+                {
+                    return Err(format!(
+                        "merge: client ids {:?} overlap {:?}",
+                        clients, other
+                    ));
+                }
+                ranges.push(clients);
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Sizes of synthetic orders requested with --sizes, --lot-size and --max-size
+pub fn order_sizes(args: &Args) -> Result<OrderSizes, String> {
+    let distribution = SizeDistribution::parse(args.sizes.as_deref().unwrap_or("uniform:1:20"))?;
+    OrderSizes::new(distribution, args.lot_size, args.max_size)
+}
+
+/// Selection of the live orders targeted by synthetic cancels and updates, requested with --cancel-age-exponent and
+/// --cancel-distance-exponent
+pub fn target_selection(args: &Args) -> Result<TargetSelection, String> {
+    TargetSelection::new(args.cancel_age_exponent, args.cancel_distance_exponent)
+}
+
+/// Type selector of a poisson source: independent draws, or a Markov chain or book-dependent sides when requested
+pub fn type_selector(source: &EventSourceType) -> Result<TypeSelectorEnum, String> {
+    let EventSourceType::Poisson {
+        bid_rate,
+        new_limit_rate,
+        market_rate,
+        cancel_rate,
+        update_rate,
+        side_persistence,
+        type_transitions,
+        imbalance_weight,
+        flow_weight,
+        flow_decay,
+        imbalance_levels,
+        ..
+    } = source
+    else {
+        return Err("poisson: not a poisson source".into());
+    };
+    let type_rates = [*new_limit_rate, *market_rate, *cancel_rate, *update_rate];
+    if let Some(persistence) = side_persistence {
+        return Ok(TypeSelectorEnum::Markov(
+            MarkovTypeSelector::side_persistence(*bid_rate, type_rates, *persistence)?,
+        ));
+    }
+    if !type_transitions.is_empty() {
+        if type_transitions.len() != MARKOV_STATES * MARKOV_STATES {
+            return Err(format!(
+                "markov: --type-transitions takes {} values",
+                MARKOV_STATES * MARKOV_STATES
+            ));
+        }
+        let rows = type_transitions
+            .chunks(MARKOV_STATES)
+            .map(|row| row.to_vec())
+            .collect();
+        return Ok(TypeSelectorEnum::Markov(MarkovTypeSelector::new(rows)?));
+    }
+    if imbalance_weight.is_some() || flow_weight.is_some() {
+        return Ok(TypeSelectorEnum::Imbalance(ImbalanceTypeSelector::new(
+            *bid_rate,
+            type_rates,
+            imbalance_weight.unwrap_or(0.0),
+            flow_weight.unwrap_or(0.0),
+            *flow_decay,
+            *imbalance_levels,
+        )?));
+    }
+    Ok(TypeSelectorEnum::Uniform(UniformTypeSelector::new(
+        *bid_rate,
+        *new_limit_rate,
+        *market_rate,
+        *cancel_rate,
+        *update_rate,
+    )))
+}
+
+/// Type selector and order generator of a book source
+pub fn book_model(
+    source: &EventSourceType,
+) -> Result<(BookRateTypeSelector, TouchOffsetGenerator), String> {
+    let &EventSourceType::Book {
+        bid_rate,
+        limit_rate,
+        market_rate,
+        cancel_rate,
+        anchor,
+        window,
+        offset_exponent,
+        tick,
+        reference_price,
+        ..
+    } = source
+    else {
+        return Err("book: not a book source".into());
+    };
+    if cancel_rate.is_nan() || cancel_rate < 0.0 {
+        return Err("book: the cancel rate must be >= 0".into());
+    }
+    if !offset_exponent.is_finite() {
+        return Err("book: the offset exponent must be finite".into());
+    }
+    let weights = (1..=window)
+        .map(|i| (i as f64).powf(-offset_exponent))
+        .collect();
+    Ok((
+        BookRateTypeSelector::new(bid_rate, limit_rate, market_rate, cancel_rate),
+        TouchOffsetGenerator::new(weights, anchor, tick, reference_price)?,
+    ))
+}
+
+/// Starting price and process of the latent fundamental of a poisson source with a price process or of an informed
+/// source, None for other sources
+pub fn fundamental(source: &EventSourceType) -> Option<(f64, PriceProcess)> {
+    let price = match *source {
+        EventSourceType::Poisson {
+            bid_avg_price,
+            ask_avg_price,
+            ..
+        } => (bid_avg_price + ask_avg_price) / 2.0,
+        EventSourceType::Informed {
+            reference_price, ..
+        } => reference_price as f64,
+        _ => return None,
+    };
+    Some((price, price_process(source)?))
+}
+
+/// Process followed by the reference price of a poisson source or the fundamental price of an informed source, None
+/// when a poisson source places prices around fixed averages
+pub fn price_process(source: &EventSourceType) -> Option<PriceProcess> {
+    let (kind, volatility, drift, jump_rate, jump_mean, jump_std, price) = match *source {
+        EventSourceType::Poisson {
+            price_process: Some(kind),
+            volatility,
+            drift,
+            jump_rate,
+            jump_mean,
+            jump_std,
+            bid_avg_price,
+            ask_avg_price,
+            ..
+        } => (
+            kind,
+            volatility,
+            drift,
+            jump_rate,
+            jump_mean,
+            jump_std,
+            (bid_avg_price + ask_avg_price) / 2.0,
+        ),
+        EventSourceType::Informed {
+            price_process,
+            volatility,
+            drift,
+            jump_rate,
+            jump_mean,
+            jump_std,
+            reference_price,
+            ..
+        } => (
+            price_process,
+            volatility,
+            drift,
+            jump_rate,
+            jump_mean,
+            jump_std,
+            reference_price as f64,
+        ),
+        _ => return None,
+    };
+    Some(match kind {
+        PriceProcessKind::RandomWalk => PriceProcess::RandomWalk {
+            volatility: volatility * price,
+        },
+        PriceProcessKind::Gbm => PriceProcess::Gbm { drift, volatility },
+        PriceProcessKind::JumpDiffusion => PriceProcess::JumpDiffusion {
+            drift,
+            volatility,
+            jump_rate,
+            jump_mean,
+            jump_std,
+        },
+    })
+}
+
+/// Number of components of the hawkes source, one per order type
+const HAWKES_COMPONENTS: usize = 4;
+
+/// Builds the rate controller of the hawkes source, expanding the excitation and decay lists into matrices
+pub fn hawkes_rate(
+    baseline: &[f64],
+    excitation: &[f64],
+    decay: &[f64],
+) -> Result<HawkesRate, String> {
+    let n = HAWKES_COMPONENTS;
+    if baseline.len() != n {
+        return Err(format!("hawkes: `baseline` needs {n} values"));
+    }
+    let matrix =
+        |name: &str, values: &[f64], fill: fn(&[f64], usize, usize) -> f64| match values.len() {
+            1 | HAWKES_COMPONENTS => Ok((0..n)
+                .map(|i| (0..n).map(|j| fill(values, i, j)).collect())
+                .collect::<Vec<Vec<f64>>>()),
+            len if len == n * n => Ok(values.chunks(n).map(|row| row.to_vec()).collect()),
+            _ => Err(format!("hawkes: `{name}` needs 1, {n} or {} values", n * n)),
+        };
+    let excitation = matrix("excitation", excitation, |values, i, j| match i == j {
+        true => values[i % values.len()],
+        false => 0.0,
+    })?;
+    let decay = matrix("decay", decay, |values, i, _| values[i % values.len()])?;
+    HawkesRate::new(baseline.to_vec(), excitation, decay)
+}
+
+/// Time of day at sim time 0 in nanoseconds since midnight, which replayed timestamps of day are offset by
+pub fn replay_start(args: &Args) -> u64 {
+    session_calendar(args).map_or(args.session_start.unwrap_or(0), |session| session.start)
+}
+
+/// Trading calendar requested with --session, phase times that are not given keep their defaults
+pub fn session_calendar(args: &Args) -> Option<SessionCalendar> {
+    if !args.session {
+        return None;
+    }
+    let mut session = SessionCalendar::new(args.session_date, 0);
+    session.pre_open = args.pre_open.unwrap_or(session.pre_open);
+    session.opening_auction = args.opening_auction.unwrap_or(session.opening_auction);
+    session.market_open = args.market_open.unwrap_or(session.market_open);
+    session.closing_auction = args.closing_auction.unwrap_or(session.closing_auction);
+    session.market_close = args.market_close.unwrap_or(session.market_close);
+    session.end_of_day = args.end_of_day.unwrap_or(session.end_of_day);
+    session.start = args.session_start.unwrap_or(session.market_open);
+    Some(session)
+}
+
+impl From<&Args> for SimJitter {
+    fn from(args: &Args) -> Self {
+        match args.sim_jitter_type {
+            JitterKind::None => SimJitter::None,
+            JitterKind::Uniform => SimJitter::Uniform(
+                Uniform::new_inclusive(args.low.unwrap(), args.high.unwrap()).unwrap(),
+            ),
+            JitterKind::Normal => {
+                SimJitter::Normal(Normal::new(args.mean.unwrap(), args.std_dev.unwrap()).unwrap())
+            }
+        }
+    }
+}
+
+/// Overrides a numeric parameter by name, used by parameter sweeps. Source parameters can only be swept with the
+/// poisson source
+pub fn apply_param(args: &mut Args, name: &str, value: f64) -> Result<(), String> {
+    if name == "sim_latency" {
+        if value < 0.0 {
+            return Err("sweep: `sim_latency` must be >= 0".into());
+        }
+        args.sim_latency = value as u64;
+        return Ok(());
+    }
+    let EventSourceType::Poisson {
+        order_rate,
+        bid_rate,
+        new_limit_rate,
+        cancel_rate,
+        market_rate,
+        update_rate,
+        bid_avg_price,
+        bid_price_dev,
+        ask_avg_price,
+        ask_price_dev,
+        ..
+    } = &mut args.event_source
+    else {
+        return Err(format!(
+            "sweep: `{}` can only be swept with the poisson source",
+            name
+        ));
+    };
+    let (field, is_probability) = match name {
+        "order_rate" => (order_rate, false),
+        "bid_rate" => (bid_rate, true),
+        "new_limit_rate" => (new_limit_rate, true),
+        "cancel_rate" => (cancel_rate, true),
+        "market_rate" => (market_rate, true),
+        "update_rate" => (update_rate, true),
+        "bid_avg_price" => (bid_avg_price, false),
+        "bid_price_dev" => (bid_price_dev, false),
+        "ask_avg_price" => (ask_avg_price, false),
+        "ask_price_dev" => (ask_price_dev, false),
+        _ => return Err(format!("sweep: unknown parameter `{}`", name)),
+    };
+    let value_str = value.to_string();
+    *field = match is_probability {
+        true => prob_parser(&value_str),
+        false => positive_float_parser(&value_str),
     }
+    .map_err(|e| format!("sweep: `{}` {}", name, e))?;
     Ok(())
 }
@@ -0,0 +1,125 @@
+use crate::limit_order_book::OrderBook;
+use mm_core::lob_core::Price;
+use mm_core::lob_core::market_events::{EventSink, TradeEvent};
+use mm_core::lob_core::market_orders::OrderSide;
+
+/// Number of price levels per side kept in a BookView
+pub const BOOK_VIEW_LEVELS: usize = 10;
+
+/// Read-only snapshot of the book handed to book-aware generators before every order: the top price levels and total
+/// resting quantity of each side, and the last trade
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BookView {
+    /// Price and resting quantity of the best bid levels, best first
+    pub bids: Vec<(Price, u64)>,
+    /// Price and resting quantity of the best ask levels, best first
+    pub asks: Vec<(Price, u64)>,
+    pub total_bids: u64,
+    pub total_asks: u64,
+    pub last_trade: Option<Price>,
+    /// Side of the order that took liquidity in the last trade, a bid for a buyer-initiated trade
+    pub last_aggressor: Option<OrderSide>,
+    /// Number of trades so far, used to tell new trades from ones already seen
+    pub trade_count: u64,
+}
+impl BookView {
+    /// Copies the top levels and depth of a book, keeping the last trade
+    pub fn refresh<T: EventSink>(&mut self, book: &OrderBook<T>) {
+        self.bids.clear();
+        self.bids.extend(book.bid_levels().take(BOOK_VIEW_LEVELS));
+        self.asks.clear();
+        self.asks.extend(book.ask_levels().take(BOOK_VIEW_LEVELS));
+        (self.total_bids, self.total_asks) = book.total_depth();
+    }
+    pub fn record_trade(&mut self, trade: &TradeEvent) {
+        self.last_trade = Some(trade.price);
+        self.last_aggressor = Some(trade.aggressor_side);
+        self.trade_count += 1;
+    }
+    pub fn best_bid(&self) -> Option<Price> {
+        self.bids.first().map(|(price, _)| *price)
+    }
+    pub fn best_ask(&self) -> Option<Price> {
+        self.asks.first().map(|(price, _)| *price)
+    }
+    /// Best quote on one side
+    pub fn best(&self, side: OrderSide) -> Option<Price> {
+        match side {
+            OrderSide::Bid => self.best_bid(),
+            OrderSide::Ask => self.best_ask(),
+        }
+    }
+    /// Total resting quantity on one side
+    pub fn depth(&self, side: OrderSide) -> u64 {
+        match side {
+            OrderSide::Bid => self.total_bids,
+            OrderSide::Ask => self.total_asks,
+        }
+    }
+    pub fn mid(&self) -> Option<f64> {
+        Some((self.best_bid()? as f64 + self.best_ask()? as f64) / 2.0)
+    }
+    pub fn spread(&self) -> Option<Price> {
+        Some(self.best_ask()?.saturating_sub(self.best_bid()?))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mm_core::lob_core::market_events::NullFeeds;
+    use mm_core::lob_core::market_orders::{Order, OrderType};
+
+    #[test]
+    fn view_copies_top_levels() {
+        let mut book = OrderBook::new(NullFeeds {});
+        for (id, (side, price)) in [
+            (OrderSide::Bid, 99),
+            (OrderSide::Bid, 98),
+            (OrderSide::Bid, 99),
+            (OrderSide::Ask, 102),
+        ]
+        .into_iter()
+        .enumerate()
+        {
+            book.process_order(Order::new(
+                0,
+                id as u64,
+                side,
+                0,
+                OrderType::Limit { qty: 10, price },
+            ));
+        }
+        for level in 0..BOOK_VIEW_LEVELS as u32 + 5 {
+            book.process_order(Order::new(
+                0,
+                10 + level as u64,
+                OrderSide::Ask,
+                0,
+                OrderType::Limit {
+                    qty: 1,
+                    price: 110 + level,
+                },
+            ));
+        }
+        book.process_order(Order::new(
+            0,
+            99,
+            OrderSide::Bid,
+            0,
+            OrderType::Cancel { old_id: 1 },
+        ));
+        let mut view = BookView {
+            last_trade: Some(100),
+            ..BookView::default()
+        };
+        view.refresh(&book);
+        assert_eq!(view.bids, vec![(99, 20)]);
+        assert_eq!(view.asks.len(), BOOK_VIEW_LEVELS);
+        assert_eq!(view.asks[..2], [(102, 10), (110, 1)]);
+        assert_eq!((view.total_bids, view.total_asks), (20, 25));
+        assert_eq!(view.spread(), Some(3));
+        assert_eq!(view.mid(), Some(100.5));
+        assert_eq!(view.last_trade, Some(100));
+    }
+}
@@ -0,0 +1,514 @@
+use crate::data_generator::event_source::EventSource;
+use crate::data_generator::historical_book::{FeedMessage, HistoricalBook};
+use crate::simulator::SimTime;
+use clap::ValueEnum;
+use mm_core::lob_core::market_orders::{Order, OrderSide};
+use mm_core::lob_core::{OrderQty, Price};
+use std::fs::File;
+use std::io::{BufRead, BufReader};
+
+/// Unit of the timestamps of a CSV file
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum TimeUnit {
+    S,
+    Ms,
+    Us,
+    Ns,
+}
+impl TimeUnit {
+    fn nanoseconds(self) -> u64 {
+        match self {
+            TimeUnit::S => 1_000_000_000,
+            TimeUnit::Ms => 1_000_000,
+            TimeUnit::Us => 1_000,
+            TimeUnit::Ns => 1,
+        }
+    }
+}
+
+/// Converts a non-negative decimal timestamp to nanoseconds without going through a float, which would lose the
+/// nanoseconds of a time of day given in seconds
+fn parse_timestamp(text: &str, unit: TimeUnit) -> Option<u64> {
+    let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+    if !fraction.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let scale = unit.nanoseconds();
+    let mut nanoseconds = whole.parse::<u64>().ok()?.checked_mul(scale)?;
+    let mut place = scale;
+    for digit in fraction.bytes() {
+        place /= 10;
+        nanoseconds += (digit - b'0') as u64 * place;
+    }
+    Some(nanoseconds)
+}
+
+/// Fields of the generic CSV layout, in their default column order
+pub const CSV_FIELDS: [&str; 6] = ["timestamp", "type", "side", "price", "qty", "order_id"];
+
+/// Where the fields of the generic CSV layout are found, as header names or 0-based column indices. Parsed from
+/// comma separated `FIELD=COLUMN` pairs, e.g. `timestamp=Time,qty=Size`, fields that are left out are looked up by
+/// their own name
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnMapping {
+    columns: [String; 6],
+}
+impl Default for ColumnMapping {
+    fn default() -> Self {
+        Self {
+            columns: CSV_FIELDS.map(String::from),
+        }
+    }
+}
+impl ColumnMapping {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut mapping = Self::default();
+        for pair in s.split(',').filter(|pair| !pair.trim().is_empty()) {
+            let (field, column) = pair
+                .split_once('=')
+                .ok_or_else(|| format!("`{pair}` is not of the form FIELD=COLUMN"))?;
+            let index = CSV_FIELDS
+                .iter()
+                .position(|name| *name == field.trim())
+                .ok_or_else(|| {
+                    format!(
+                        "unknown field `{}`, expected one of {}",
+                        field.trim(),
+                        CSV_FIELDS.join(", ")
+                    )
+                })?;
+            mapping.columns[index] = column.trim().to_string();
+        }
+        Ok(mapping)
+    }
+    /// Column index of every field. Without a header, columns that are not given as indices keep the field's default
+    /// position
+    fn resolve(&self, header: Option<&[&str]>) -> Result<[usize; 6], String> {
+        let mut indices = [0; 6];
+        for (i, column) in self.columns.iter().enumerate() {
+            indices[i] = match (column.parse::<usize>(), header) {
+                (Ok(index), _) => index,
+                (Err(_), Some(header)) => header
+                    .iter()
+                    .position(|name| name.trim() == column)
+                    .ok_or_else(|| format!("no column `{column}` in the header"))?,
+                (Err(_), None) => i,
+            };
+        }
+        Ok(indices)
+    }
+}
+
+/// How a CSV file in the generic layout is written
+#[derive(Debug, Clone, PartialEq)]
+pub struct CsvLayout {
+    pub columns: ColumnMapping,
+    /// Whether the first row holds the column names
+    pub header: bool,
+    pub delimiter: char,
+    pub time_unit: TimeUnit,
+}
+impl Default for CsvLayout {
+    fn default() -> Self {
+        Self {
+            columns: ColumnMapping::default(),
+            header: true,
+            delimiter: ',',
+            time_unit: TimeUnit::Ns,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum CsvFormat {
+    Lobster,
+    Generic([usize; 6]),
+}
+
+/// EventSource that replays order-level data from a CSV file through a HistoricalBook, in one of two formats.
+///
+/// LOBSTER message files have no header and the columns time (seconds after midnight), type, order id, size, price
+/// (in units of 1/10000) and direction (1 buy, -1 sell). Types 1 to 4 are submissions, partial cancels, deletions and
+/// visible executions. Hidden executions, cross trades and halts do not change the visible book and are skipped.
+///
+/// The generic layout has the columns `timestamp,type,side,price,qty,order_id`, which can be mapped to other columns
+/// with a CsvLayout. Types are `add`, `execute`, `cancel` (removes `qty` shares), `delete`, `update` (moves the
+/// order to a new `price` and `qty`) and `market`. Market rows are skipped and counted, the orders they hit are reduced
+/// by their own execute rows. Sides are `buy`, `bid`, `b` or `1` and `sell`, `ask`, `s` or `-1`, and fields a type
+/// does not use may be empty.
+///
+/// Timestamps are offset by `start` nanoseconds, rows before it are replayed at sim time 0. Prices are divided by
+/// `price_scale` and rounded to the nearest tick. Rows that cannot be read are skipped and counted
+pub struct CsvReplaySource<R: BufRead> {
+    reader: R,
+    format: CsvFormat,
+    delimiter: char,
+    time_unit: TimeUnit,
+    start: u64,
+    price_scale: f64,
+    line: String,
+    book: HistoricalBook,
+    limit: Option<u64>,
+    skipped: u64,
+}
+impl CsvReplaySource<BufReader<File>> {
+    /// Opens a LOBSTER message file for replay
+    pub fn open_lobster(
+        path: &str,
+        start: u64,
+        price_scale: f64,
+        limit: Option<u64>,
+    ) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("lobster: {path}: {e}"))?;
+        Self::lobster(BufReader::new(file), start, price_scale, limit)
+            .map_err(|e| format!("{e} in {path}"))
+    }
+    /// Opens a CSV file in the generic layout for replay
+    pub fn open_generic(
+        path: &str,
+        layout: &CsvLayout,
+        start: u64,
+        price_scale: f64,
+        limit: Option<u64>,
+    ) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("csv: {path}: {e}"))?;
+        Self::generic(BufReader::new(file), layout, start, price_scale, limit)
+            .map_err(|e| format!("{e} in {path}"))
+    }
+}
+impl<R: BufRead> CsvReplaySource<R> {
+    pub fn lobster(
+        reader: R,
+        start: u64,
+        price_scale: f64,
+        limit: Option<u64>,
+    ) -> Result<Self, String> {
+        Self::new(
+            reader,
+            CsvFormat::Lobster,
+            ',',
+            TimeUnit::S,
+            start,
+            price_scale,
+            limit,
+        )
+        .map_err(|e| format!("lobster: {e}"))
+    }
+    /// Reads the header, if the layout has one, and resolves the columns of the layout
+    pub fn generic(
+        mut reader: R,
+        layout: &CsvLayout,
+        start: u64,
+        price_scale: f64,
+        limit: Option<u64>,
+    ) -> Result<Self, String> {
+        let columns = match layout.header {
+            true => {
+                let mut line = String::new();
+                reader
+                    .read_line(&mut line)
+                    .map_err(|e| format!("csv: {e}"))?;
+                let names: Vec<&str> = line.trim_end().split(layout.delimiter).collect();
+                layout.columns.resolve(Some(&names))
+            }
+            false => layout.columns.resolve(None),
+        }
+        .map_err(|e| format!("csv: {e}"))?;
+        Self::new(
+            reader,
+            CsvFormat::Generic(columns),
+            layout.delimiter,
+            layout.time_unit,
+            start,
+            price_scale,
+            limit,
+        )
+        .map_err(|e| format!("csv: {e}"))
+    }
+    fn new(
+        reader: R,
+        format: CsvFormat,
+        delimiter: char,
+        time_unit: TimeUnit,
+        start: u64,
+        price_scale: f64,
+        limit: Option<u64>,
+    ) -> Result<Self, String> {
+        if price_scale.is_nan() || price_scale <= 0.0 {
+            return Err("`price_scale` must be > 0.0".into());
+        }
+        Ok(Self {
+            reader,
+            format,
+            delimiter,
+            time_unit,
+            start,
+            price_scale,
+            line: String::new(),
+            book: HistoricalBook::default(),
+            limit,
+            skipped: 0,
+        })
+    }
+    /// Number of orders resting in the historical book
+    pub fn resting(&self) -> usize {
+        self.book.resting()
+    }
+    fn price(&self, text: &str) -> Option<Price> {
+        let price = (text.trim().parse::<f64>().ok()? / self.price_scale).round();
+        (0.0..=Price::MAX as f64)
+            .contains(&price)
+            .then_some(price as Price)
+    }
+    /// Decodes the row in `line`. The outer None marks a row that cannot be read, the inner one a row that does not
+    /// change the visible book
+    fn decode(&self) -> Option<Option<(SimTime, FeedMessage)>> {
+        let fields: Vec<&str> = self.line.trim_end().split(self.delimiter).collect();
+        let field = |index: usize| fields.get(index).map(|field| field.trim());
+        let qty = |index: usize| field(index)?.parse::<OrderQty>().ok();
+        let side = |text: &str| match text.to_ascii_lowercase().as_str() {
+            "buy" | "bid" | "b" | "1" => Some(OrderSide::Bid),
+            "sell" | "ask" | "s" | "-1" => Some(OrderSide::Ask),
+            _ => None,
+        };
+        let message = match self.format {
+            CsvFormat::Lobster => {
+                let time = parse_timestamp(field(0)?, TimeUnit::S)?;
+                let reference = field(2)?.parse::<u64>().ok()?;
+                let message = match field(1)? {
+                    "1" => FeedMessage::Add {
+                        reference,
+                        side: side(field(5)?)?,
+                        price: self.price(field(4)?)?,
+                        qty: qty(3)?,
+                    },
+                    "2" => FeedMessage::Cancel {
+                        reference,
+                        qty: qty(3)?,
+                    },
+                    "3" => FeedMessage::Delete { reference },
+                    "4" => FeedMessage::Execute {
+                        reference,
+                        qty: qty(3)?,
+                    },
+                    "5" | "6" | "7" => return Some(None),
+                    _ => return None,
+                };
+                (time, message)
+            }
+            CsvFormat::Generic([timestamp, kind, side_column, price, qty_column, order_id]) => {
+                let time = parse_timestamp(field(timestamp)?, self.time_unit)?;
+                let reference = || field(order_id)?.parse::<u64>().ok();
+                let message = match field(kind)?.to_ascii_lowercase().as_str() {
+                    "add" => FeedMessage::Add {
+                        reference: reference()?,
+                        side: side(field(side_column)?)?,
+                        price: self.price(field(price)?)?,
+                        qty: qty(qty_column)?,
+                    },
+                    "execute" => FeedMessage::Execute {
+                        reference: reference()?,
+                        qty: qty(qty_column)?,
+                    },
+                    "cancel" => FeedMessage::Cancel {
+                        reference: reference()?,
+                        qty: qty(qty_column)?,
+                    },
+                    "delete" => FeedMessage::Delete {
+                        reference: reference()?,
+                    },
+                    "update" => FeedMessage::Replace {
+                        reference: reference()?,
+                        new_reference: reference()?,
+                        price: self.price(field(price)?)?,
+                        qty: qty(qty_column)?,
+                    },
+                    // NOTE: The resting orders a market row hit have their own execute rows, replaying it would
+                    // take their liquidity twice
+                    "market" => return None,
+                    _ => return None,
+                };
+                (time, message)
+            }
+        };
+        let (time, message) = message;
+        Some(Some((time.saturating_sub(self.start) as SimTime, message)))
+    }
+}
+impl<R: BufRead> EventSource for CsvReplaySource<R> {
+    fn next_event(&mut self) -> Option<Order> {
+        if self.limit.is_some_and(|limit| self.book.count() >= limit) {
+            return None;
+        }
+        loop {
+            self.line.clear();
+            match self.reader.read_line(&mut self.line) {
+                Ok(0) | Err(_) => return None,
+                Ok(_) if self.line.trim().is_empty() => continue,
+                Ok(_) => {}
+            }
+            match self.decode() {
+                Some(Some((time, message))) => {
+                    if let Some(order) = self.book.apply(time, message) {
+                        return Some(order);
+                    }
+                }
+                Some(None) => {}
+                None => self.skipped += 1,
+            }
+        }
+    }
+    fn tracks_orders(&self) -> bool {
+        true
+    }
+    fn on_submitted(&mut self, order: &Order) {
+        self.book.on_submitted(order);
+    }
+    fn skipped(&self) -> u64 {
+        self.skipped
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mm_core::lob_core::market_orders::OrderType;
+    use std::io::Cursor;
+
+    fn kinds(source: &mut impl EventSource) -> Vec<(OrderSide, SimTime, OrderType)> {
+        std::iter::from_fn(|| source.next_event())
+            .map(|order| (order.side, order.timestamp, order.kind))
+            .collect()
+    }
+
+    #[test]
+    fn lobster_messages_become_orders() {
+        let messages = "\
+34199.5,1,11,100,1000000,1
+34200.000000001,1,12,300,1010000,-1
+34200.25,4,12,100,1010000,-1
+34200.5,5,0,50,1005000,1
+34200.75,2,12,50,1010000,-1
+34201,3,11,100,1000000,1
+oops
+";
+        let mut source =
+            CsvReplaySource::lobster(Cursor::new(messages), 34_200_000_000_000, 100.0, None)
+                .unwrap();
+        assert_eq!(
+            kinds(&mut source),
+            vec![
+                (
+                    OrderSide::Bid,
+                    0,
+                    OrderType::Limit {
+                        qty: 100,
+                        price: 10_000
+                    }
+                ),
+                (
+                    OrderSide::Ask,
+                    1,
+                    OrderType::Limit {
+                        qty: 300,
+                        price: 10_100
+                    }
+                ),
+                (
+                    OrderSide::Ask,
+                    250_000_000,
+                    OrderType::Update {
+                        old_id: 1,
+                        qty: 200,
+                        price: 10_100
+                    }
+                ),
+                (
+                    OrderSide::Ask,
+                    750_000_000,
+                    OrderType::Update {
+                        old_id: 2,
+                        qty: 150,
+                        price: 10_100
+                    }
+                ),
+                (
+                    OrderSide::Bid,
+                    1_000_000_000,
+                    OrderType::Cancel { old_id: 0 }
+                ),
+            ]
+        );
+        assert_eq!(source.skipped(), 1);
+        assert_eq!(source.resting(), 1);
+    }
+
+    #[test]
+    fn generic_columns_are_mapped() {
+        let rows = "\
+Size;Px;Id;Kind;Dir;Time
+100;99.50;7;add;buy;1000
+200;100.25;8;ADD;S;1500
+50;;8;execute;;2000
+;;;market;sell;2500
+300;99.75;7;update;;3000
+";
+        let layout = CsvLayout {
+            columns: ColumnMapping::parse(
+                "timestamp=Time,type=Kind,side=Dir,price=Px,qty=Size,order_id=Id",
+            )
+            .unwrap(),
+            delimiter: ';',
+            time_unit: TimeUnit::Ms,
+            ..CsvLayout::default()
+        };
+        let mut source =
+            CsvReplaySource::generic(Cursor::new(rows), &layout, 0, 0.01, None).unwrap();
+        assert_eq!(
+            kinds(&mut source),
+            vec![
+                (
+                    OrderSide::Bid,
+                    1_000_000_000,
+                    OrderType::Limit {
+                        qty: 100,
+                        price: 9_950
+                    }
+                ),
+                (
+                    OrderSide::Ask,
+                    1_500_000_000,
+                    OrderType::Limit {
+                        qty: 200,
+                        price: 10_025
+                    }
+                ),
+                (
+                    OrderSide::Ask,
+                    2_000_000_000,
+                    OrderType::Update {
+                        old_id: 1,
+                        qty: 150,
+                        price: 10_025
+                    }
+                ),
+                (
+                    OrderSide::Bid,
+                    3_000_000_000,
+                    OrderType::Update {
+                        old_id: 0,
+                        qty: 300,
+                        price: 9_975
+                    }
+                ),
+            ]
+        );
+        // The market row is skipped
+        assert_eq!(source.skipped(), 1);
+        assert!(ColumnMapping::parse("size=Qty").is_err());
+        assert!(
+            CsvReplaySource::generic(Cursor::new("a,b\n"), &CsvLayout::default(), 0, 1.0, None)
+                .is_err()
+        );
+    }
+}
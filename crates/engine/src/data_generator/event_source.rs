@@ -1,16 +1,49 @@
+use crate::data_generator::book_view::BookView;
+use crate::data_generator::live_orders::{LiveOrders, TargetSelection};
 use crate::data_generator::order_generators::{GaussianOrderGenerator, OrderGenerator};
-use crate::data_generator::rate_controllers::{ConstantPoissonRate, RateController};
+use crate::data_generator::rate_controllers::{ConstantPoissonRate, HawkesRate, RateController};
 use crate::data_generator::type_selectors::{TypeSelector, UniformTypeSelector};
-use mm_core::lob_core::market_orders::{Order, OrderByteArray};
-use rand::Rng;
+use crate::simulator::SimTime;
+use mm_core::lob_core::market_events::{ClientEvent, ClientEventType};
+use mm_core::lob_core::market_orders::{Order, OrderByteArray, OrderSide, OrderType};
+use mm_core::lob_core::{ClientId, OrderId};
+use rand::distr::weighted::WeightedIndex;
+use rand::{Rng, RngExt};
 use rand_chacha::ChaCha8Rng;
+use rand_distr::Distribution;
+use std::collections::HashMap;
 use std::fs::File;
-use std::io::{BufReader, Read};
+use std::io::{BufReader, LineWriter, Read, Write};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
 use std::vec::Vec;
 
 /// Trait that must be implemented by all sources of events
 pub trait EventSource {
     fn next_event(&mut self) -> Option<Order>;
+    /// Whether the source wants to hear back about its orders through `on_submitted` and `on_client_event`
+    fn tracks_orders(&self) -> bool {
+        false
+    }
+    /// Called with every order taken from the source once the simulator has assigned its engine order id
+    fn on_submitted(&mut self, _order: &Order) {}
+    /// Called with the client events of the book that are not addressed to an agent
+    fn on_client_event(&mut self, _event: &ClientEvent) {}
+    /// Whether the source reads the book, in which case `on_book` is called with the current book before every order
+    fn reads_book(&self) -> bool {
+        false
+    }
+    fn on_book(&mut self, _book: &BookView) {}
+    /// Copy of the source in its current state, used to fork a simulation whose source was picked at run time. None
+    /// for sources that cannot be copied, such as replays of a file
+    fn fork_source(&self) -> Option<SourceFunction> {
+        None
+    }
+    /// Number of input records the source skipped because it could not read or replay them, for replays of imported
+    /// files
+    fn skipped(&self) -> u64 {
+        0
+    }
 }
 /// Enum holding multiple event source types. It is more limited than using SourceFunction, but potentially faster
 /// by avoiding dynamic dispatch and allowing inlining
@@ -25,25 +58,91 @@ impl EventSource for SourceEnum {
             SourceEnum::File(this) => this.next_event(),
         }
     }
+    fn tracks_orders(&self) -> bool {
+        match self {
+            SourceEnum::Poisson(this) => this.tracks_orders(),
+            SourceEnum::File(this) => this.tracks_orders(),
+        }
+    }
+    fn on_submitted(&mut self, order: &Order) {
+        match self {
+            SourceEnum::Poisson(this) => this.on_submitted(order),
+            SourceEnum::File(this) => this.on_submitted(order),
+        }
+    }
+    fn on_client_event(&mut self, event: &ClientEvent) {
+        match self {
+            SourceEnum::Poisson(this) => this.on_client_event(event),
+            SourceEnum::File(this) => this.on_client_event(event),
+        }
+    }
+    fn reads_book(&self) -> bool {
+        match self {
+            SourceEnum::Poisson(this) => this.reads_book(),
+            SourceEnum::File(this) => this.reads_book(),
+        }
+    }
+    fn on_book(&mut self, book: &BookView) {
+        match self {
+            SourceEnum::Poisson(this) => this.on_book(book),
+            SourceEnum::File(this) => this.on_book(book),
+        }
+    }
 }
 
-/// Struct that contains a function pointer to any function that generates Option<Order>. More flexible than SourceEnum
-/// if more event sources are implemented, but potentially slower due to using dynamic dispatch and being unable to inline
+/// Struct that contains a boxed event source or a function pointer to any function that generates Option<Order>. More
+/// flexible than SourceEnum if more event sources are implemented, but potentially slower due to using dynamic dispatch
+/// and being unable to inline
 pub struct SourceFunction {
-    func: Box<dyn FnMut() -> Option<Order>>,
+    source: Box<dyn EventSource>,
 }
 impl SourceFunction {
+    /// Wraps a plain function, which never hears back about its orders
     pub fn new(func: Box<dyn FnMut() -> Option<Order>>) -> Self {
-        Self { func }
+        Self::from_source(FnSource(func))
+    }
+    /// Wraps an event source, forwarding feedback about its orders
+    pub fn from_source(source: impl EventSource + 'static) -> Self {
+        Self {
+            source: Box::new(source),
+        }
     }
 }
 impl EventSource for SourceFunction {
     fn next_event(&mut self) -> Option<Order> {
-        (self.func)()
+        self.source.next_event()
+    }
+    fn tracks_orders(&self) -> bool {
+        self.source.tracks_orders()
+    }
+    fn on_submitted(&mut self, order: &Order) {
+        self.source.on_submitted(order);
+    }
+    fn on_client_event(&mut self, event: &ClientEvent) {
+        self.source.on_client_event(event);
+    }
+    fn reads_book(&self) -> bool {
+        self.source.reads_book()
+    }
+    fn on_book(&mut self, book: &BookView) {
+        self.source.on_book(book);
+    }
+    fn fork_source(&self) -> Option<SourceFunction> {
+        self.source.fork_source()
+    }
+    fn skipped(&self) -> u64 {
+        self.source.skipped()
+    }
+}
+struct FnSource(Box<dyn FnMut() -> Option<Order>>);
+impl EventSource for FnSource {
+    fn next_event(&mut self) -> Option<Order> {
+        (self.0)()
     }
 }
 
 /// EventSource that randomly samples event parameters from distributions
+#[derive(Clone)]
 pub struct RandomSource<R: RateController, T: TypeSelector, G: OrderGenerator, N: Rng> {
     rate_controller: R,
     type_selector: T,
@@ -51,8 +150,11 @@ pub struct RandomSource<R: RateController, T: TypeSelector, G: OrderGenerator, N
     rng: N,
     limit: Option<u64>,
     count: u64,
+    live: LiveOrders,
 }
-impl<R: RateController, T: TypeSelector, G: OrderGenerator, N: Rng> RandomSource<R, T, G, N> {
+impl<R: RateController, T: TypeSelector, G: OrderGenerator, N: Rng + Clone>
+    RandomSource<R, T, G, N>
+{
     pub fn new(
         rate_controller: R,
         type_selector: T,
@@ -60,6 +162,7 @@ impl<R: RateController, T: TypeSelector, G: OrderGenerator, N: Rng> RandomSource
         rng: N,
         limit: Option<u64>,
     ) -> Self {
+        let live = LiveOrders::new(TargetSelection::default(), LiveOrders::target_rng(&rng));
         Self {
             rate_controller,
             type_selector,
@@ -67,11 +170,27 @@ impl<R: RateController, T: TypeSelector, G: OrderGenerator, N: Rng> RandomSource
             rng,
             limit,
             count: 0,
+            live,
         }
     }
+    /// Replaces uniform selection of the live orders targeted by cancels and updates
+    pub fn with_target_selection(mut self, selection: TargetSelection) -> Self {
+        self.live.set_selection(selection);
+        self
+    }
+    /// Mutable access to the rate controller, used to change the arrival rate of a running source
+    pub fn rate_controller_mut(&mut self) -> &mut R {
+        &mut self.rate_controller
+    }
+    /// Mutable access to the type selector, used to change the order type proportions of a running source
+    pub fn type_selector_mut(&mut self) -> &mut T {
+        &mut self.type_selector
+    }
 }
 impl<R: RateController, T: TypeSelector, G: OrderGenerator, N: Rng> EventSource
     for RandomSource<R, T, G, N>
+where
+    Self: Clone + 'static,
 {
     fn next_event(&mut self) -> Option<Order> {
         if let Some(limit) = self.limit
@@ -82,12 +201,418 @@ impl<R: RateController, T: TypeSelector, G: OrderGenerator, N: Rng> EventSource
         self.count += 1;
         let dt = self.rate_controller.next_dt(&mut self.rng);
         let kind = self.type_selector.sample(&mut self.rng);
-        Some(self.order_generator.generate(0, dt, kind, &mut self.rng))
+        let mut order = self.order_generator.generate(0, dt, kind, &mut self.rng);
+        self.live.retarget(&mut order);
+        Some(order)
+    }
+    fn tracks_orders(&self) -> bool {
+        true
+    }
+    fn on_submitted(&mut self, order: &Order) {
+        self.live.on_submitted(order);
+    }
+    fn on_client_event(&mut self, event: &ClientEvent) {
+        self.live.on_client_event(event);
+    }
+    fn reads_book(&self) -> bool {
+        self.type_selector.reads_book()
+            || self.order_generator.reads_book()
+            || self.live.reads_book()
+    }
+    fn on_book(&mut self, book: &BookView) {
+        self.type_selector.observe(book);
+        self.order_generator.observe(book);
+        self.live.observe(book);
+    }
+    fn fork_source(&self) -> Option<SourceFunction> {
+        Some(SourceFunction::from_source(self.clone()))
     }
 }
 pub type ConstantPoissonSource =
     RandomSource<ConstantPoissonRate, UniformTypeSelector, GaussianOrderGenerator, ChaCha8Rng>;
 
+/// Parameters for one phase of a ScenarioSource, the phase is active from its start time until the next phase starts
+#[derive(Clone)]
+pub struct ScenarioPhase {
+    pub start: SimTime,
+    pub rate_controller: ConstantPoissonRate,
+    pub type_selector: UniformTypeSelector,
+    pub order_generator: GaussianOrderGenerator,
+}
+
+/// EventSource that walks through a timeline of phases, each with its own arrival rate, order type proportions and
+/// price distributions. Inter-arrival times are memoryless, so on reaching a phase boundary the clock jumps to the
+/// boundary and sampling restarts with the new phase's parameters
+#[derive(Clone)]
+pub struct ScenarioSource {
+    phases: Vec<ScenarioPhase>,
+    current: usize,
+    time: SimTime,
+    end_time: Option<SimTime>,
+    limit: Option<u64>,
+    count: u64,
+    rng: ChaCha8Rng,
+    live: LiveOrders,
+}
+impl ScenarioSource {
+    /// Phases must be sorted by start time. The source ends at whichever comes first of `end_time` and `limit` orders
+    pub fn new(
+        phases: Vec<ScenarioPhase>,
+        end_time: Option<SimTime>,
+        limit: Option<u64>,
+        rng: ChaCha8Rng,
+    ) -> Self {
+        let live = LiveOrders::new(TargetSelection::default(), LiveOrders::target_rng(&rng));
+        assert!(!phases.is_empty());
+        assert!(phases.windows(2).all(|p| p[0].start < p[1].start));
+        Self {
+            time: phases[0].start,
+            phases,
+            current: 0,
+            end_time,
+            limit,
+            count: 0,
+            rng,
+            live,
+        }
+    }
+    /// Replaces uniform selection of the live orders targeted by cancels and updates
+    pub fn with_target_selection(mut self, selection: TargetSelection) -> Self {
+        self.live.set_selection(selection);
+        self
+    }
+}
+impl EventSource for ScenarioSource {
+    fn next_event(&mut self) -> Option<Order> {
+        if let Some(limit) = self.limit
+            && self.count >= limit
+        {
+            return None;
+        }
+        loop {
+            let dt = self.phases[self.current]
+                .rate_controller
+                .next_dt(&mut self.rng);
+            let next_time = self.time.saturating_add(dt);
+            match self.phases.get(self.current + 1) {
+                Some(next_phase) if next_time >= next_phase.start => {
+                    self.time = next_phase.start;
+                    self.current += 1;
+                }
+                _ => {
+                    self.time = next_time;
+                    break;
+                }
+            }
+        }
+        if let Some(end_time) = self.end_time
+            && self.time > end_time
+        {
+            return None;
+        }
+        self.count += 1;
+        let phase = &mut self.phases[self.current];
+        let kind = phase.type_selector.sample(&mut self.rng);
+        let mut order = phase.order_generator.generate(0, 0, kind, &mut self.rng);
+        order.timestamp = self.time; // NOTE: Generators keep their own clock, but the scenario owns the timeline
+        self.live.retarget(&mut order);
+        Some(order)
+    }
+    fn tracks_orders(&self) -> bool {
+        true
+    }
+    fn on_submitted(&mut self, order: &Order) {
+        self.live.on_submitted(order);
+    }
+    fn on_client_event(&mut self, event: &ClientEvent) {
+        self.live.on_client_event(event);
+    }
+    fn reads_book(&self) -> bool {
+        self.live.reads_book()
+    }
+    fn on_book(&mut self, book: &BookView) {
+        self.live.observe(book);
+    }
+    fn fork_source(&self) -> Option<SourceFunction> {
+        Some(SourceFunction::from_source(self.clone()))
+    }
+}
+
+/// Parameters for one regime of a RegimeSource
+#[derive(Clone)]
+pub struct Regime {
+    pub name: String,
+    pub rate_controller: ConstantPoissonRate,
+    pub type_selector: UniformTypeSelector,
+    pub order_generator: GaussianOrderGenerator,
+    /// Mean time spent in the regime before switching, holding times are exponentially distributed
+    pub mean_duration: SimTime,
+    /// Relative odds of switching to each regime, indexed like the regimes of the source. The regime's own entry is
+    /// ignored, a regime with no odds left never switches
+    pub transitions: Vec<f64>,
+}
+
+/// EventSource that switches between regimes, each with its own arrival rate, order type proportions and price
+/// distributions, following a hidden continuous-time Markov chain in sim time. The regimes entered so far are kept in
+/// `switches`, and can be written to a labels file as they happen so that recordings come with their ground truth
+#[derive(Clone)]
+pub struct RegimeSource {
+    regimes: Vec<Regime>,
+    jumps: Vec<Option<WeightedIndex<f64>>>,
+    current: usize,
+    time: SimTime,
+    switch_time: SimTime,
+    switches: Vec<(SimTime, usize)>,
+    end_time: Option<SimTime>,
+    limit: Option<u64>,
+    count: u64,
+    rng: ChaCha8Rng,
+    live: LiveOrders,
+    labels: Option<Arc<Mutex<LineWriter<File>>>>,
+}
+impl RegimeSource {
+    /// Starts in regime `initial` at time 0. The source ends at whichever comes first of `end_time` and `limit` orders
+    pub fn new(
+        regimes: Vec<Regime>,
+        initial: usize,
+        end_time: Option<SimTime>,
+        limit: Option<u64>,
+        rng: ChaCha8Rng,
+    ) -> Self {
+        let live = LiveOrders::new(TargetSelection::default(), LiveOrders::target_rng(&rng));
+        assert!(initial < regimes.len());
+        let jumps = regimes
+            .iter()
+            .enumerate()
+            .map(|(i, regime)| {
+                assert_eq!(regime.transitions.len(), regimes.len());
+                let mut odds = regime.transitions.clone();
+                odds[i] = 0.0;
+                WeightedIndex::new(odds).ok()
+            })
+            .collect();
+        let mut source = Self {
+            regimes,
+            jumps,
+            current: initial,
+            time: 0,
+            switch_time: 0,
+            switches: vec![(0, initial)],
+            end_time,
+            limit,
+            count: 0,
+            rng,
+            live,
+            labels: None,
+        };
+        source.switch_time = source.holding_time();
+        source
+    }
+    /// Writes the regimes the source enters to a CSV file of `time,regime` rows, with the sim time in nanoseconds and
+    /// the regime's name, starting with the regimes entered so far
+    pub fn with_labels(mut self, path: &str) -> Result<Self, String> {
+        let file = File::create(path).map_err(|e| format!("regimes: {path}: {e}"))?;
+        self.labels = Some(Arc::new(Mutex::new(LineWriter::new(file))));
+        self.write_label("time,regime".into())
+            .map_err(|e| format!("regimes: {path}: {e}"))?;
+        for (time, regime) in self.switches.clone() {
+            self.write_label(format!("{time},{}", self.regimes[regime].name))
+                .map_err(|e| format!("regimes: {path}: {e}"))?;
+        }
+        Ok(self)
+    }
+    fn write_label(&mut self, line: String) -> std::io::Result<()> {
+        match &self.labels {
+            Some(labels) => writeln!(labels.lock().unwrap(), "{line}"),
+            None => Ok(()),
+        }
+    }
+    /// Replaces uniform selection of the live orders targeted by cancels and updates
+    pub fn with_target_selection(mut self, selection: TargetSelection) -> Self {
+        self.live.set_selection(selection);
+        self
+    }
+    /// Index of the regime the last order was generated in
+    pub fn regime(&self) -> usize {
+        self.current
+    }
+    pub fn regime_name(&self) -> &str {
+        &self.regimes[self.current].name
+    }
+    /// Names of the regimes, indexed like `regime` and `switches`
+    pub fn regime_names(&self) -> Vec<&str> {
+        self.regimes
+            .iter()
+            .map(|regime| regime.name.as_str())
+            .collect()
+    }
+    /// Sim times at which the source entered each regime so far, starting with the initial regime at time 0
+    pub fn switches(&self) -> &[(SimTime, usize)] {
+        &self.switches
+    }
+    fn holding_time(&mut self) -> SimTime {
+        if self.jumps[self.current].is_none() {
+            return SimTime::MAX;
+        }
+        let u: f64 = self.rng.random();
+        let holding = -(1.0 - u).ln() * self.regimes[self.current].mean_duration as f64;
+        self.time.saturating_add(holding as SimTime)
+    }
+}
+impl EventSource for RegimeSource {
+    fn next_event(&mut self) -> Option<Order> {
+        if let Some(limit) = self.limit
+            && self.count >= limit
+        {
+            return None;
+        }
+        loop {
+            let dt = self.regimes[self.current]
+                .rate_controller
+                .next_dt(&mut self.rng);
+            let next_time = self.time.saturating_add(dt);
+            if next_time < self.switch_time {
+                self.time = next_time;
+                break;
+            }
+            // Inter-arrival times are memoryless, so sampling restarts from the switch with the new regime
+            self.time = self.switch_time;
+            self.current = self.jumps[self.current]
+                .as_ref()
+                .unwrap()
+                .sample(&mut self.rng);
+            self.switches.push((self.time, self.current));
+            let label = format!("{},{}", self.time, self.regimes[self.current].name);
+            if let Err(e) = self.write_label(label) {
+                eprintln!("regimes: labels: {e}");
+                self.labels = None;
+            }
+            self.switch_time = self.holding_time();
+        }
+        if let Some(end_time) = self.end_time
+            && self.time > end_time
+        {
+            return None;
+        }
+        self.count += 1;
+        let regime = &mut self.regimes[self.current];
+        let kind = regime.type_selector.sample(&mut self.rng);
+        let mut order = regime.order_generator.generate(0, 0, kind, &mut self.rng);
+        order.timestamp = self.time; // NOTE: Generators keep their own clock, but the regime chain owns the timeline
+        self.live.retarget(&mut order);
+        Some(order)
+    }
+    fn tracks_orders(&self) -> bool {
+        true
+    }
+    fn on_submitted(&mut self, order: &Order) {
+        self.live.on_submitted(order);
+    }
+    fn on_client_event(&mut self, event: &ClientEvent) {
+        self.live.on_client_event(event);
+    }
+    fn reads_book(&self) -> bool {
+        self.live.reads_book()
+    }
+    fn on_book(&mut self, book: &BookView) {
+        self.live.observe(book);
+    }
+    fn fork_source(&self) -> Option<SourceFunction> {
+        // NOTE: Forks are what-ifs, only the original run writes labels
+        Some(SourceFunction::from_source(RegimeSource {
+            labels: None,
+            ..self.clone()
+        }))
+    }
+}
+
+/// EventSource whose arrivals follow a multivariate Hawkes process with one component per order type, so that e.g.
+/// market orders trigger bursts of cancels. The component of an event picks its type, its side is drawn with
+/// probability `bid_rate` of being a bid
+#[derive(Clone)]
+pub struct HawkesSource<G: OrderGenerator, N: Rng> {
+    rate_controller: HawkesRate,
+    kinds: Vec<OrderType>,
+    bid_rate: f64,
+    order_generator: G,
+    rng: N,
+    limit: Option<u64>,
+    count: u64,
+    live: LiveOrders,
+}
+impl<G: OrderGenerator, N: Rng + Clone> HawkesSource<G, N> {
+    /// `kinds` holds the order type of every component of the rate controller
+    pub fn new(
+        rate_controller: HawkesRate,
+        kinds: Vec<OrderType>,
+        bid_rate: f64,
+        order_generator: G,
+        rng: N,
+        limit: Option<u64>,
+    ) -> Self {
+        let live = LiveOrders::new(TargetSelection::default(), LiveOrders::target_rng(&rng));
+        assert_eq!(rate_controller.dimension(), kinds.len());
+        Self {
+            rate_controller,
+            kinds,
+            bid_rate,
+            order_generator,
+            rng,
+            limit,
+            count: 0,
+            live,
+        }
+    }
+    /// Replaces uniform selection of the live orders targeted by cancels and updates
+    pub fn with_target_selection(mut self, selection: TargetSelection) -> Self {
+        self.live.set_selection(selection);
+        self
+    }
+}
+impl<G: OrderGenerator, N: Rng> EventSource for HawkesSource<G, N>
+where
+    Self: Clone + 'static,
+{
+    fn next_event(&mut self) -> Option<Order> {
+        if let Some(limit) = self.limit
+            && self.count >= limit
+        {
+            return None;
+        }
+        self.count += 1;
+        let dt = self.rate_controller.next_dt(&mut self.rng);
+        let kind = self.kinds[self.rate_controller.last_component()];
+        let side = match self.rng.random_bool(self.bid_rate) {
+            true => OrderSide::Bid,
+            false => OrderSide::Ask,
+        };
+        let mut order = self
+            .order_generator
+            .generate(0, dt, (side, kind), &mut self.rng);
+        self.live.retarget(&mut order);
+        Some(order)
+    }
+    fn tracks_orders(&self) -> bool {
+        true
+    }
+    fn on_submitted(&mut self, order: &Order) {
+        self.live.on_submitted(order);
+    }
+    fn on_client_event(&mut self, event: &ClientEvent) {
+        self.live.on_client_event(event);
+    }
+    fn reads_book(&self) -> bool {
+        self.order_generator.reads_book() || self.live.reads_book()
+    }
+    fn on_book(&mut self, book: &BookView) {
+        self.order_generator.observe(book);
+        self.live.observe(book);
+    }
+    fn fork_source(&self) -> Option<SourceFunction> {
+        Some(SourceFunction::from_source(self.clone()))
+    }
+}
+
 /// EventSource that replays orders from a binary file created by OrderLogger
 /// Expects that binary file contains binary-serialized Orders
 pub struct FileReplaySource {
@@ -136,6 +661,200 @@ impl EventSource for FileReplaySource {
     }
 }
 
+/// One input of a MergedSource, with the next order it has produced and the merged ids of its orders that may still be
+/// referred to, keyed by local id
+#[derive(Clone)]
+struct MergedInput<E: EventSource> {
+    source: E,
+    client_ids: Range<ClientId>,
+    peeked: Option<Order>,
+    emitted: HashMap<OrderId, OrderId>,
+    next_local: OrderId,
+}
+
+/// EventSource that interleaves several sources by timestamp, e.g. a replayed file alongside synthetic background
+/// flow. Each input trades under its own range of client ids, and the cancels and updates of an input refer to the
+/// input's own orders: the k-th order of an input is addressed as id k, as if the input were running alone. In a
+/// simulator the engine ids of merged orders are fed back through `on_submitted`, otherwise ids are translated assuming
+/// orders are numbered in the sequence they are merged. An input produces its next order only after its previous one
+/// has been taken and submitted, so feedback reaches it in time
+#[derive(Clone)]
+pub struct MergedSource<E: EventSource> {
+    inputs: Vec<MergedInput<E>>,
+    primed: bool,
+    count: OrderId,
+    last: Option<(usize, Order)>,
+    /// Input and local id of the orders submitted for an input, with the order a cancel or update refers to
+    owners: HashMap<OrderId, (usize, OrderId, Option<OrderId>)>,
+}
+impl<E: EventSource> Default for MergedSource<E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+impl<E: EventSource> MergedSource<E> {
+    pub fn new() -> Self {
+        Self {
+            inputs: Vec::new(),
+            primed: false,
+            count: 0,
+            last: None,
+            owners: HashMap::new(),
+        }
+    }
+    /// Adds an input whose orders are tagged with client ids in `client_ids`, the input's own client ids are mapped
+    /// into the range by wrapping. Ranges of different inputs must not overlap
+    pub fn add_source(&mut self, source: E, client_ids: Range<ClientId>) -> Result<(), String> {
+        if client_ids.is_empty() {
+            return Err("merge: client id range is empty".into());
+        }
+        if let Some(other) = self.inputs.iter().find(|input| {
+            input.client_ids.start < client_ids.end && client_ids.start < input.client_ids.end
+        }) {
+            return Err(format!(
+                "merge: client ids {:?} overlap {:?}",
+                client_ids, other.client_ids
+            ));
+        }
+        self.inputs.push(MergedInput {
+            source,
+            client_ids,
+            peeked: None,
+            emitted: HashMap::new(),
+            next_local: 0,
+        });
+        self.primed = false;
+        Ok(())
+    }
+    pub fn len(&self) -> usize {
+        self.inputs.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.inputs.is_empty()
+    }
+}
+impl<E: EventSource> EventSource for MergedSource<E> {
+    fn next_event(&mut self) -> Option<Order> {
+        if !self.primed {
+            for input in self
+                .inputs
+                .iter_mut()
+                .filter(|input| input.peeked.is_none())
+            {
+                input.peeked = input.source.next_event();
+            }
+            self.primed = true;
+        } else if let Some((index, _)) = self.last {
+            let input = &mut self.inputs[index];
+            input.peeked = input.source.next_event();
+        }
+        // Ties go to the input that was added first
+        let (index, input) = self
+            .inputs
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, input)| input.peeked.is_some())
+            .min_by_key(|(_, input)| input.peeked.unwrap().timestamp)?;
+        let mut order = input.peeked.take().unwrap();
+        self.last = Some((index, order));
+
+        // Ids the input never emitted, or whose orders are gone, refer to no order of the merged stream
+        let translate = |old_id: OrderId| match input.emitted.get(&old_id) {
+            Some(&id) => id,
+            None => OrderId::MAX,
+        };
+        match &mut order.kind {
+            OrderType::Cancel { old_id } | OrderType::Update { old_id, .. } => {
+                *old_id = translate(*old_id);
+            }
+            OrderType::Limit { .. } | OrderType::Market { .. } => {}
+        }
+        let width = input.client_ids.end - input.client_ids.start;
+        order.client_id = input.client_ids.start + order.client_id % width;
+        input.emitted.insert(input.next_local, self.count);
+        input.next_local += 1;
+        self.count += 1;
+        Some(order)
+    }
+    fn tracks_orders(&self) -> bool {
+        self.inputs.iter().any(|input| input.source.tracks_orders())
+    }
+    fn on_submitted(&mut self, order: &Order) {
+        let Some((index, mut original)) = self.last else {
+            return;
+        };
+        let input = &mut self.inputs[index];
+        let local = input.next_local - 1;
+        input.emitted.insert(local, order.order_id);
+        let referenced = match order.kind {
+            OrderType::Cancel { old_id } | OrderType::Update { old_id, .. } => Some(old_id),
+            OrderType::Limit { .. } | OrderType::Market { .. } => None,
+        };
+        self.owners
+            .insert(order.order_id, (index, local, referenced));
+        original.order_id = local;
+        input.source.on_submitted(&original);
+    }
+    fn on_client_event(&mut self, event: &ClientEvent) {
+        let Some(&(index, local, referenced)) = self.owners.get(&event.order_id) else {
+            return;
+        };
+        if matches!(
+            event.kind,
+            ClientEventType::Filled | ClientEventType::Canceled | ClientEventType::Rejected
+        ) {
+            self.owners.remove(&event.order_id);
+            self.inputs[index].emitted.remove(&local);
+        }
+        // Canceled and Updated carry the id of the cancel or update, the order it refers to is gone
+        if let Some(referenced) = referenced
+            && matches!(
+                event.kind,
+                ClientEventType::Canceled | ClientEventType::Updated
+            )
+            && let Some((index, local, _)) = self.owners.remove(&referenced)
+        {
+            self.inputs[index].emitted.remove(&local);
+        }
+        let mut event = *event;
+        event.order_id = local;
+        self.inputs[index].source.on_client_event(&event);
+    }
+    fn reads_book(&self) -> bool {
+        self.inputs.iter().any(|input| input.source.reads_book())
+    }
+    fn on_book(&mut self, book: &BookView) {
+        for input in &mut self.inputs {
+            input.source.on_book(book);
+        }
+    }
+    fn skipped(&self) -> u64 {
+        self.inputs.iter().map(|input| input.source.skipped()).sum()
+    }
+    fn fork_source(&self) -> Option<SourceFunction> {
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|input| {
+                Some(MergedInput {
+                    source: input.source.fork_source()?,
+                    client_ids: input.client_ids.clone(),
+                    peeked: input.peeked,
+                    emitted: input.emitted.clone(),
+                    next_local: input.next_local,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        Some(SourceFunction::from_source(MergedSource {
+            inputs,
+            primed: self.primed,
+            count: self.count,
+            last: self.last,
+            owners: self.owners.clone(),
+        }))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -143,6 +862,8 @@ mod tests {
         order_generators::GaussianOrderGenerator, rate_controllers::ConstantPoissonRate,
         type_selectors::UniformTypeSelector,
     };
+    use mm_core::lob_core::market_events::LiquidityFlag;
+    use mm_core::lob_core::market_orders::OrderSide;
     use rand::SeedableRng;
     use rand_chacha::ChaCha8Rng;
 
@@ -170,4 +891,256 @@ mod tests {
                 .all(|e| e[0].unwrap().timestamp <= e[1].unwrap().timestamp)
         );
     }
+
+    fn scenario_phase(start: SimTime, rate: f64, price: f64) -> ScenarioPhase {
+        ScenarioPhase {
+            start,
+            rate_controller: ConstantPoissonRate::new(rate),
+            type_selector: UniformTypeSelector::new(0.5, 1.0, 0.0, 0.0, 0.0),
+            order_generator: GaussianOrderGenerator::new(price, 1.0, price, 1.0),
+        }
+    }
+
+    #[test]
+    fn scenario_switches_phases_and_ends_on_time() {
+        let mut source = ScenarioSource::new(
+            vec![
+                scenario_phase(0, 1_000.0, 100.0),
+                scenario_phase(1_000_000_000, 10_000.0, 500.0),
+            ],
+            Some(2_000_000_000),
+            None,
+            ChaCha8Rng::seed_from_u64(0),
+        );
+        let orders: Vec<Order> = std::iter::from_fn(|| source.next_event()).collect();
+        assert!(orders.windows(2).all(|o| o[0].timestamp <= o[1].timestamp));
+        assert!(orders.last().unwrap().timestamp <= 2_000_000_000);
+        let (first, second): (Vec<Order>, Vec<Order>) = orders
+            .iter()
+            .partition(|order| order.timestamp < 1_000_000_000);
+        // Roughly 1k orders in the first second and 10k in the second
+        assert!((800..1_200).contains(&first.len()), "{}", first.len());
+        assert!((9_000..11_000).contains(&second.len()), "{}", second.len());
+        let price = |order: &Order| match order.kind {
+            OrderType::Limit { price, .. } => price,
+            _ => unreachable!(),
+        };
+        assert!(first.iter().all(|order| price(order) < 200));
+        assert!(second.iter().all(|order| price(order) > 400));
+    }
+
+    #[test]
+    fn scenario_respects_order_limit() {
+        let mut source = ScenarioSource::new(
+            vec![scenario_phase(0, 1_000.0, 100.0)],
+            None,
+            Some(10),
+            ChaCha8Rng::seed_from_u64(0),
+        );
+        assert_eq!(std::iter::from_fn(|| source.next_event()).count(), 10);
+    }
+    fn regime(name: &str, rate: f64, price: f64, transitions: Vec<f64>) -> Regime {
+        Regime {
+            name: name.into(),
+            rate_controller: ConstantPoissonRate::new(rate),
+            type_selector: UniformTypeSelector::new(0.5, 1.0, 0.0, 0.0, 0.0),
+            order_generator: GaussianOrderGenerator::new(price, 1.0, price, 1.0),
+            mean_duration: 1_000_000_000,
+            transitions,
+        }
+    }
+
+    #[test]
+    fn regime_source_follows_its_switches() {
+        let mut source = RegimeSource::new(
+            vec![
+                regime("calm", 1_000.0, 100.0, vec![0.0, 1.0, 0.0]),
+                regime("volatile", 10_000.0, 500.0, vec![1.0, 0.0, 0.0]),
+                regime("unreachable", 1_000.0, 900.0, vec![1.0, 1.0, 0.0]),
+            ],
+            0,
+            Some(100_000_000_000),
+            None,
+            ChaCha8Rng::seed_from_u64(0),
+        );
+        let orders: Vec<Order> = std::iter::from_fn(|| source.next_event()).collect();
+        assert!(orders.windows(2).all(|o| o[0].timestamp <= o[1].timestamp));
+        let switches = source.switches().to_vec();
+        // Regimes alternate every second on average
+        assert!((70..140).contains(&switches.len()), "{}", switches.len());
+        assert!(switches.iter().all(|(_, regime)| *regime < 2));
+        let regime = |order: &Order| {
+            switches
+                .iter()
+                .rev()
+                .find(|(time, _)| *time <= order.timestamp)
+                .unwrap()
+                .1
+        };
+        for order in &orders {
+            assert_eq!(order.client_id, 0);
+            let OrderType::Limit { price, .. } = order.kind else {
+                unreachable!()
+            };
+            assert_eq!(price > 300, regime(order) == 1);
+        }
+        // Both regimes hold about half the time, so the fast one has about 10 of every 11 orders
+        let volatile = orders.iter().filter(|order| regime(order) == 1).count();
+        assert!(volatile * 10 > orders.len() * 8, "{volatile}");
+    }
+    struct VecSource(std::vec::IntoIter<Order>);
+    impl EventSource for VecSource {
+        fn next_event(&mut self) -> Option<Order> {
+            self.0.next()
+        }
+    }
+    fn order(client_id: ClientId, timestamp: SimTime, kind: OrderType) -> Order {
+        Order::new(client_id, 0, OrderSide::Bid, timestamp, kind)
+    }
+
+    #[test]
+    fn merge_interleaves_by_time_and_tags_inputs() {
+        let limit = OrderType::Limit { qty: 1, price: 100 };
+        let replay = VecSource(
+            vec![
+                order(7, 10, limit),
+                order(7, 30, OrderType::Cancel { old_id: 0 }),
+                order(7, 50, OrderType::Cancel { old_id: 5 }),
+            ]
+            .into_iter(),
+        );
+        let background = VecSource(
+            vec![
+                order(0, 10, limit),
+                order(1, 20, limit),
+                order(
+                    2,
+                    40,
+                    OrderType::Update {
+                        old_id: 1,
+                        qty: 2,
+                        price: 101,
+                    },
+                ),
+            ]
+            .into_iter(),
+        );
+        let mut merged = MergedSource::new();
+        merged.add_source(replay, 0..100).unwrap();
+        merged.add_source(background, 100..102).unwrap();
+        let orders: Vec<Order> = std::iter::from_fn(|| merged.next_event()).collect();
+        let timestamps: Vec<SimTime> = orders.iter().map(|o| o.timestamp).collect();
+        let clients: Vec<ClientId> = orders.iter().map(|o| o.client_id).collect();
+        assert_eq!(timestamps, [10, 10, 20, 30, 40, 50]);
+        assert_eq!(clients, [7, 100, 101, 7, 100, 7]);
+        // Each input addresses its own orders, which are merged orders 0 and 2
+        assert_eq!(orders[3].kind, OrderType::Cancel { old_id: 0 });
+        assert_eq!(
+            orders[4].kind,
+            OrderType::Update {
+                old_id: 2,
+                qty: 2,
+                price: 101
+            }
+        );
+        // An id the input never emitted matches no merged order
+        assert_eq!(
+            orders[5].kind,
+            OrderType::Cancel {
+                old_id: OrderId::MAX
+            }
+        );
+    }
+
+    #[test]
+    fn merge_forgets_ids_of_finished_orders() {
+        let limit = OrderType::Limit { qty: 1, price: 100 };
+        let replay = VecSource(
+            vec![
+                order(0, 10, limit),
+                order(0, 20, limit),
+                order(0, 30, OrderType::Cancel { old_id: 0 }),
+                order(0, 40, OrderType::Cancel { old_id: 0 }),
+            ]
+            .into_iter(),
+        );
+        let mut merged = MergedSource::new();
+        merged.add_source(replay, 0..1).unwrap();
+        let submit = |merged: &mut MergedSource<VecSource>, order_id, kind| {
+            let mut order = merged.next_event().unwrap();
+            order.order_id = order_id;
+            merged.on_submitted(&order);
+            if let Some(kind) = kind {
+                merged.on_client_event(&ClientEvent {
+                    id: 0,
+                    timestamp: order.timestamp,
+                    client_id: 0,
+                    order_id,
+                    order_side: OrderSide::Bid,
+                    kind,
+                    liquidity_flag: LiquidityFlag::Maker,
+                });
+            }
+        };
+        submit(&mut merged, 50, None);
+        submit(&mut merged, 51, Some(ClientEventType::Filled));
+        submit(&mut merged, 52, Some(ClientEventType::Canceled));
+        // The fill and the cancel removed the limits and the cancel itself
+        assert!(merged.inputs[0].emitted.is_empty());
+        assert!(merged.owners.is_empty());
+        assert_eq!(
+            merged.next_event().unwrap().kind,
+            OrderType::Cancel {
+                old_id: OrderId::MAX
+            }
+        );
+    }
+
+    #[test]
+    fn merge_rejects_overlapping_client_ids() {
+        let mut merged = MergedSource::new();
+        merged
+            .add_source(VecSource(Vec::new().into_iter()), 0..10)
+            .unwrap();
+        assert!(
+            merged
+                .add_source(VecSource(Vec::new().into_iter()), 5..20)
+                .is_err()
+        );
+        assert!(
+            merged
+                .add_source(VecSource(Vec::new().into_iter()), 20..20)
+                .is_err()
+        );
+        assert!(merged.next_event().is_none());
+    }
+    #[test]
+    fn hawkes_components_pick_order_types() {
+        let rate = HawkesRate::new(
+            vec![1_000.0, 0.0],
+            vec![vec![0.0, 0.0], vec![0.5, 0.0]],
+            vec![vec![100.0; 2]; 2],
+        )
+        .unwrap();
+        let mut source = HawkesSource::new(
+            rate,
+            vec![
+                OrderType::Limit { qty: 0, price: 0 },
+                OrderType::Market { qty: 0 },
+            ],
+            1.0,
+            GaussianOrderGenerator::new(100.0, 1.0, 100.0, 1.0),
+            ChaCha8Rng::seed_from_u64(0),
+            Some(30_000),
+        );
+        let orders: Vec<Order> = std::iter::from_fn(|| source.next_event()).collect();
+        assert!(orders.iter().all(|order| order.side == OrderSide::Bid));
+        // Market orders are only triggered by limits, half a market order per limit
+        let markets = orders
+            .iter()
+            .filter(|order| matches!(order.kind, OrderType::Market { .. }))
+            .count();
+        let ratio = markets as f64 / (orders.len() - markets) as f64;
+        assert!((ratio - 0.5).abs() < 0.03, "{ratio}");
+    }
 }
@@ -0,0 +1,165 @@
+use crate::simulator::SimTime;
+use mm_core::lob_core::market_orders::{Order, OrderSide, OrderType};
+use mm_core::lob_core::{OrderId, OrderQty, Price};
+use std::collections::HashMap;
+
+/// A message of a historical order-level feed, addressing orders by the feed's own reference numbers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FeedMessage {
+    Add {
+        reference: u64,
+        side: OrderSide,
+        price: Price,
+        qty: OrderQty,
+    },
+    /// Shares of a resting order traded against an incoming order
+    Execute {
+        reference: u64,
+        qty: OrderQty,
+    },
+    /// Shares removed from a resting order, which stays on the book if any are left
+    Cancel {
+        reference: u64,
+        qty: OrderQty,
+    },
+    Delete {
+        reference: u64,
+    },
+    /// A resting order changed to a new price and size, and addressed as `new_reference` from then on
+    Replace {
+        reference: u64,
+        new_reference: u64,
+        price: Price,
+        qty: OrderQty,
+    },
+}
+
+/// A visible order of the historical book
+#[derive(Debug, Clone, Copy)]
+struct HistoricalOrder {
+    order_id: OrderId,
+    side: OrderSide,
+    price: Price,
+    shares: OrderQty,
+}
+
+/// Follows the book of a historical feed and turns its messages into engine orders. Adds become limit orders, deletes
+/// cancels, and replaces and partial cancels updates to the remaining shares. Executions reduce the executed order the
+/// same way, an update to the shares left or a cancel when none are, so the rebuilt book follows the historical one
+/// by reference rather than through whatever a market order would match. No trade is published for an execution, so
+/// trade feeds, stylized facts and agents that follow trades do not see historical trades, and a partially executed
+/// order goes to the back of its queue like after a partial cancel. Messages about orders that were never added are
+/// dropped.
+///
+/// Until `on_submitted` says otherwise the k-th order is assumed to get engine id k, as it does when a replay source
+/// runs alone
+#[derive(Debug, Clone, Default)]
+pub struct HistoricalBook {
+    orders: HashMap<u64, HistoricalOrder>,
+    /// Reference number of the last order emitted that rests under its own engine id
+    pending: Option<u64>,
+    count: u64,
+}
+impl HistoricalBook {
+    /// Number of orders emitted so far
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+    /// Number of orders resting in the historical book
+    pub fn resting(&self) -> usize {
+        self.orders.len()
+    }
+    /// Applies a message at sim time `time`, returning the order that reproduces it if any
+    pub fn apply(&mut self, time: SimTime, message: FeedMessage) -> Option<Order> {
+        self.pending = None;
+        let order_id = self.count as OrderId;
+        let (side, kind) = match message {
+            FeedMessage::Add {
+                reference,
+                side,
+                price,
+                qty,
+            } => {
+                self.rest(reference, order_id, side, price, qty);
+                (side, OrderType::Limit { qty, price })
+            }
+            // NOTE: The trade already happened in the historical market, replaying it as a market order would let it
+            // sweep whatever else the rebuilt book holds, so only the executed shares are taken off the order
+            FeedMessage::Execute { reference, qty } => {
+                return self.apply(time, FeedMessage::Cancel { reference, qty });
+            }
+            FeedMessage::Cancel { reference, qty } => {
+                let order = *self.orders.get(&reference)?;
+                let shares = order.shares - qty.min(order.shares);
+                if shares == 0 {
+                    return self.apply(time, FeedMessage::Delete { reference });
+                }
+                // NOTE: The engine has no in-place size reduction, the update sends the order to the back of its queue
+                self.rest(reference, order_id, order.side, order.price, shares);
+                (
+                    order.side,
+                    OrderType::Update {
+                        old_id: order.order_id,
+                        qty: shares,
+                        price: order.price,
+                    },
+                )
+            }
+            FeedMessage::Delete { reference } => {
+                let order = self.orders.remove(&reference)?;
+                (
+                    order.side,
+                    OrderType::Cancel {
+                        old_id: order.order_id,
+                    },
+                )
+            }
+            FeedMessage::Replace {
+                reference,
+                new_reference,
+                price,
+                qty,
+            } => {
+                let order = self.orders.remove(&reference)?;
+                self.rest(new_reference, order_id, order.side, price, qty);
+                (
+                    order.side,
+                    OrderType::Update {
+                        old_id: order.order_id,
+                        qty,
+                        price,
+                    },
+                )
+            }
+        };
+        self.count += 1;
+        Some(Order::new(0, 0, side, time, kind))
+    }
+    fn rest(
+        &mut self,
+        reference: u64,
+        order_id: OrderId,
+        side: OrderSide,
+        price: Price,
+        shares: OrderQty,
+    ) {
+        self.orders.insert(
+            reference,
+            HistoricalOrder {
+                order_id,
+                side,
+                price,
+                shares,
+            },
+        );
+        self.pending = Some(reference);
+    }
+    /// Records the engine id given to the last order returned by `apply`
+    pub fn on_submitted(&mut self, order: &Order) {
+        if let Some(reference) = self.pending.take()
+            && let Some(historical) = self.orders.get_mut(&reference)
+        {
+            historical.order_id = order.order_id;
+        }
+    }
+}
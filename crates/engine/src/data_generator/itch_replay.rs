@@ -0,0 +1,329 @@
+use crate::data_generator::event_source::EventSource;
+use crate::data_generator::historical_book::{FeedMessage, HistoricalBook};
+use crate::simulator::SimTime;
+use mm_core::itch_core::messages::{
+    ITCH_MESSAGE_TYPE_ADD_ORDER, ITCH_MESSAGE_TYPE_ADD_ORDER_MPID, ITCH_MESSAGE_TYPE_ORDER_CANCEL,
+    ITCH_MESSAGE_TYPE_ORDER_DELETE, ITCH_MESSAGE_TYPE_ORDER_EXECUTED,
+    ITCH_MESSAGE_TYPE_ORDER_EXECUTED_WITH_PRICE, ITCH_MESSAGE_TYPE_ORDER_REPLACE,
+    add_order::AddOrder, add_order_mpid::AddOrderMPID, order_cancel::OrderCancel,
+    order_delete::OrderDelete, order_executed::OrderExecuted,
+    order_executed_with_price::OrderExecutedWithPrice, order_replace::OrderReplace,
+};
+use mm_core::lob_core::Price;
+use mm_core::lob_core::market_orders::{Order, OrderSide};
+use std::fs::File;
+use std::io::{BufReader, Read};
+
+/// EventSource that replays the order flow of one symbol from a Nasdaq TotalView-ITCH 5.0 file, a sequence of
+/// messages each preceded by its length as a big-endian u16. The file must be decompressed. Add, execute, cancel,
+/// delete and replace messages are replayed through a HistoricalBook, other messages and other symbols are skipped.
+///
+/// Sim time 0 is `start` nanoseconds after midnight, messages before it are replayed at sim time 0 so the run starts
+/// on the historical book. ITCH prices have 4 decimals and are divided by `price_scale`, rounding to the nearest tick
+pub struct ItchReplaySource<R: Read> {
+    reader: R,
+    symbol: [u8; 8],
+    start: u64,
+    price_scale: u32,
+    message: Vec<u8>,
+    book: HistoricalBook,
+    limit: Option<u64>,
+}
+impl ItchReplaySource<BufReader<File>> {
+    /// Opens an ITCH file for replay
+    pub fn open(
+        path: &str,
+        symbol: &str,
+        start: u64,
+        price_scale: u32,
+        limit: Option<u64>,
+    ) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("itch: {path}: {e}"))?;
+        Self::new(BufReader::new(file), symbol, start, price_scale, limit)
+    }
+}
+impl<R: Read> ItchReplaySource<R> {
+    pub fn new(
+        reader: R,
+        symbol: &str,
+        start: u64,
+        price_scale: u32,
+        limit: Option<u64>,
+    ) -> Result<Self, String> {
+        if symbol.is_empty() || symbol.len() > 8 || !symbol.is_ascii() {
+            return Err(format!(
+                "itch: `{symbol}` is not a symbol of 1 to 8 characters"
+            ));
+        }
+        if price_scale == 0 {
+            return Err("itch: `price_scale` must be > 0".into());
+        }
+        // NOTE: Symbols are right padded with spaces
+        let mut padded = [b' '; 8];
+        padded[..symbol.len()].copy_from_slice(symbol.to_ascii_uppercase().as_bytes());
+        Ok(Self {
+            reader,
+            symbol: padded,
+            start,
+            price_scale,
+            message: Vec::new(),
+            book: HistoricalBook::default(),
+            limit,
+        })
+    }
+    /// Number of orders of the symbol resting in the historical book
+    pub fn resting(&self) -> usize {
+        self.book.resting()
+    }
+    /// Reads the next message into `message`, false at the end of the file or on a truncated message
+    fn read_message(&mut self) -> bool {
+        let mut length = [0u8; 2];
+        if self.reader.read_exact(&mut length).is_err() {
+            return false;
+        }
+        self.message.resize(u16::from_be_bytes(length) as usize, 0);
+        self.reader.read_exact(&mut self.message).is_ok()
+    }
+    fn price(&self, itch_price: u32) -> Price {
+        ((itch_price as u64 + self.price_scale as u64 / 2) / self.price_scale as u64) as Price
+    }
+    /// Decodes the message in `message` with its sim time, None if it is not about the book of the symbol. Messages
+    /// that address orders by reference number need no symbol check, only orders of the symbol are in the book
+    fn decode(&self) -> Option<(SimTime, FeedMessage)> {
+        let m = &self.message;
+        let side = |indicator: u8| match indicator {
+            b'B' => OrderSide::Bid,
+            _ => OrderSide::Ask,
+        };
+        let (timestamp, message) = match *m.first()? {
+            ITCH_MESSAGE_TYPE_ADD_ORDER => {
+                let add = AddOrder::decode(m)?;
+                if add.stock != self.symbol {
+                    return None;
+                }
+                let message = FeedMessage::Add {
+                    reference: add.order_reference_number,
+                    side: side(add.buy_sell_indicator),
+                    qty: add.shares,
+                    price: self.price(add.price),
+                };
+                (add.timestamp, message)
+            }
+            ITCH_MESSAGE_TYPE_ADD_ORDER_MPID => {
+                let add = AddOrderMPID::decode(m)?;
+                if add.stock != self.symbol {
+                    return None;
+                }
+                let message = FeedMessage::Add {
+                    reference: add.order_reference_number,
+                    side: side(add.buy_sell_indicator),
+                    qty: add.shares,
+                    price: self.price(add.price),
+                };
+                (add.timestamp, message)
+            }
+            ITCH_MESSAGE_TYPE_ORDER_EXECUTED => {
+                let executed = OrderExecuted::decode(m)?;
+                let message = FeedMessage::Execute {
+                    reference: executed.order_reference_number,
+                    qty: executed.executed_shares,
+                };
+                (executed.timestamp, message)
+            }
+            ITCH_MESSAGE_TYPE_ORDER_EXECUTED_WITH_PRICE => {
+                let executed = OrderExecutedWithPrice::decode(m)?;
+                let message = FeedMessage::Execute {
+                    reference: executed.order_reference_number,
+                    qty: executed.executed_shares,
+                };
+                (executed.timestamp, message)
+            }
+            ITCH_MESSAGE_TYPE_ORDER_CANCEL => {
+                let cancel = OrderCancel::decode(m)?;
+                let message = FeedMessage::Cancel {
+                    reference: cancel.order_reference_number,
+                    qty: cancel.canceled_shares,
+                };
+                (cancel.timestamp, message)
+            }
+            ITCH_MESSAGE_TYPE_ORDER_DELETE => {
+                let delete = OrderDelete::decode(m)?;
+                let message = FeedMessage::Delete {
+                    reference: delete.order_reference_number,
+                };
+                (delete.timestamp, message)
+            }
+            ITCH_MESSAGE_TYPE_ORDER_REPLACE => {
+                let replace = OrderReplace::decode(m)?;
+                let message = FeedMessage::Replace {
+                    reference: replace.original_order_reference_number,
+                    new_reference: replace.new_order_reference_number,
+                    qty: replace.shares,
+                    price: self.price(replace.price),
+                };
+                (replace.timestamp, message)
+            }
+            _ => return None,
+        };
+        Some((timestamp.saturating_sub(self.start) as SimTime, message))
+    }
+}
+impl<R: Read> EventSource for ItchReplaySource<R> {
+    fn next_event(&mut self) -> Option<Order> {
+        if self.limit.is_some_and(|limit| self.book.count() >= limit) {
+            return None;
+        }
+        while self.read_message() {
+            if let Some((time, message)) = self.decode()
+                && let Some(order) = self.book.apply(time, message)
+            {
+                return Some(order);
+            }
+        }
+        None
+    }
+    fn tracks_orders(&self) -> bool {
+        true
+    }
+    fn on_submitted(&mut self, order: &Order) {
+        self.book.on_submitted(order);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limit_order_book::OrderBook;
+    use mm_core::itch_core::messages::system_event::SystemEvent;
+    use mm_core::lob_core::market_events::NullFeeds;
+    use mm_core::lob_core::market_orders::OrderType;
+    use std::io::Cursor;
+
+    const OPEN: u64 = 34_200_000_000_000;
+
+    /// Appends a message written by `encode` to a length-prefixed ITCH stream
+    fn push(stream: &mut Vec<u8>, length: usize, encode: impl FnOnce(&mut [u8])) {
+        let mut message = vec![0u8; length];
+        encode(&mut message);
+        stream.extend_from_slice(&(length as u16).to_be_bytes());
+        stream.extend_from_slice(&message);
+    }
+
+    fn add(
+        stream: &mut Vec<u8>,
+        time: u64,
+        reference: u64,
+        side: u8,
+        shares: u32,
+        symbol: &[u8; 8],
+        price: u32,
+    ) {
+        push(stream, 36, |buf| {
+            AddOrder::encode_into(buf, 1, 0, time, reference, side, shares, *symbol, price)
+        });
+    }
+
+    fn session() -> Vec<u8> {
+        let mut stream = Vec::new();
+        push(&mut stream, 12, |buf| {
+            SystemEvent::encode_into(buf, 0, 0, 0, b'O')
+        });
+        add(&mut stream, OPEN - 5, 1, b'B', 100, b"AAPL    ", 1_000_000);
+        add(&mut stream, OPEN - 4, 2, b'S', 300, b"AAPL    ", 1_010_000);
+        add(&mut stream, OPEN - 3, 3, b'B', 500, b"MSFT    ", 1_000_000);
+        push(&mut stream, 31, |buf| {
+            OrderExecuted::encode_into(buf, 1, 0, OPEN + 10, 2, 100, 1)
+        });
+        push(&mut stream, 23, |buf| {
+            OrderCancel::encode_into(buf, 1, 0, OPEN + 20, 2, 50)
+        });
+        push(&mut stream, 35, |buf| {
+            OrderReplace::encode_into(buf, 1, 0, OPEN + 30, 1, 4, 200, 1_005_000)
+        });
+        push(&mut stream, 19, |buf| {
+            OrderDelete::encode_into(buf, 1, 0, OPEN + 40, 3)
+        });
+        push(&mut stream, 19, |buf| {
+            OrderDelete::encode_into(buf, 1, 0, OPEN + 50, 2)
+        });
+        stream
+    }
+
+    #[test]
+    fn itch_messages_become_orders() {
+        let mut source =
+            ItchReplaySource::new(Cursor::new(session()), "aapl", OPEN, 100, None).unwrap();
+        let orders: Vec<Order> = std::iter::from_fn(|| source.next_event()).collect();
+        let kinds: Vec<(OrderSide, SimTime, OrderType)> = orders
+            .iter()
+            .map(|o| (o.side, o.timestamp, o.kind))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (
+                    OrderSide::Bid,
+                    0,
+                    OrderType::Limit {
+                        qty: 100,
+                        price: 10_000
+                    }
+                ),
+                (
+                    OrderSide::Ask,
+                    0,
+                    OrderType::Limit {
+                        qty: 300,
+                        price: 10_100
+                    }
+                ),
+                (
+                    OrderSide::Ask,
+                    10,
+                    OrderType::Update {
+                        old_id: 1,
+                        qty: 200,
+                        price: 10_100
+                    }
+                ),
+                (
+                    OrderSide::Ask,
+                    20,
+                    OrderType::Update {
+                        old_id: 2,
+                        qty: 150,
+                        price: 10_100
+                    }
+                ),
+                (
+                    OrderSide::Bid,
+                    30,
+                    OrderType::Update {
+                        old_id: 0,
+                        qty: 200,
+                        price: 10_050
+                    }
+                ),
+                (OrderSide::Ask, 50, OrderType::Cancel { old_id: 3 }),
+            ]
+        );
+        assert_eq!(source.resting(), 1);
+    }
+
+    #[test]
+    fn replay_rebuilds_the_historical_book() {
+        let mut source =
+            ItchReplaySource::new(Cursor::new(session()), "AAPL", OPEN, 100, Some(5)).unwrap();
+        let mut book = OrderBook::new(NullFeeds {});
+        let mut next_id = 10;
+        while let Some(mut order) = source.next_event() {
+            // Engine ids do not follow the order count, as they would not with agents trading alongside
+            order.order_id = next_id;
+            next_id += 1;
+            source.on_submitted(&order);
+            book.process_order(order);
+        }
+        assert_eq!(book.bid_levels().collect::<Vec<_>>(), vec![(10_050, 200)]);
+        assert_eq!(book.ask_levels().collect::<Vec<_>>(), vec![(10_100, 150)]);
+    }
+}
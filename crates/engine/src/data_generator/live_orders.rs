@@ -0,0 +1,353 @@
+use crate::data_generator::book_view::BookView;
+use crate::data_generator::rate_controllers::NANOSECONDS_PER_SECOND;
+use crate::simulator::SimTime;
+use mm_core::lob_core::market_events::{ClientEvent, ClientEventType};
+use mm_core::lob_core::market_orders::{Order, OrderSide, OrderType};
+use mm_core::lob_core::{OrderId, Price};
+use rand::{Rng, RngExt, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use std::collections::HashMap;
+
+/// How cancels and updates pick the live order they target. Every live order on the side of the cancel is weighted by
+/// `(1 + age)^age_exponent * (1 + distance)^distance_exponent`, with the age in seconds and the distance in ticks from
+/// the best quote of the book on that side, or the best live order of the source before the book has been seen. Both
+/// exponents at zero pick uniformly
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TargetSelection {
+    pub age_exponent: f64,
+    pub distance_exponent: f64,
+}
+impl TargetSelection {
+    pub fn new(age_exponent: f64, distance_exponent: f64) -> Result<Self, String> {
+        if !age_exponent.is_finite() || !distance_exponent.is_finite() {
+            return Err("cancel targeting: the exponents must be finite".into());
+        }
+        Ok(Self {
+            age_exponent,
+            distance_exponent,
+        })
+    }
+    fn is_uniform(&self) -> bool {
+        self.age_exponent == 0.0 && self.distance_exponent == 0.0
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct LiveOrder {
+    side: OrderSide,
+    price: Price,
+    since: SimTime,
+    slot: usize,
+}
+
+/// The resting orders of one event source, kept up to date from the client events of the book so that the cancels and
+/// updates of the source hit orders that still exist. Tracking starts with the first submitted order, until then
+/// orders pass through unchanged. Targets are drawn from a random stream of their own, so that the arrivals, types and
+/// prices of the source do not depend on the state of the book
+#[derive(Debug, Clone)]
+pub struct LiveOrders {
+    selection: TargetSelection,
+    rng: ChaCha8Rng,
+    tracking: bool,
+    submitted: HashMap<OrderId, Order>,
+    orders: HashMap<OrderId, LiveOrder>,
+    bids: Vec<OrderId>,
+    asks: Vec<OrderId>,
+    best_bid: Option<Price>,
+    best_ask: Option<Price>,
+}
+/// Stream for the targets of a source, derived from the source's rng without drawing from it, so that the source
+/// generates the same orders for a seed whether or not its targets are tracked
+const TARGET_STREAM: u64 = 0x7461_7267_6574;
+impl LiveOrders {
+    /// Random stream for target selection that leaves `rng` untouched
+    pub fn target_rng<N: Rng + Clone>(rng: &N) -> ChaCha8Rng {
+        let mut target_rng = ChaCha8Rng::from_rng(&mut rng.clone());
+        target_rng.set_stream(TARGET_STREAM);
+        target_rng
+    }
+    pub fn new(selection: TargetSelection, rng: ChaCha8Rng) -> Self {
+        Self {
+            selection,
+            rng,
+            tracking: false,
+            submitted: HashMap::new(),
+            orders: HashMap::new(),
+            bids: Vec::new(),
+            asks: Vec::new(),
+            best_bid: None,
+            best_ask: None,
+        }
+    }
+    pub fn set_selection(&mut self, selection: TargetSelection) {
+        self.selection = selection;
+    }
+    pub fn len(&self) -> usize {
+        self.orders.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.orders.is_empty()
+    }
+    pub fn contains(&self, order_id: OrderId) -> bool {
+        self.orders.contains_key(&order_id)
+    }
+    /// Whether targets are weighted by their distance from the touch, in which case `observe` is called with the book
+    pub fn reads_book(&self) -> bool {
+        self.selection.distance_exponent != 0.0
+    }
+    pub fn observe(&mut self, book: &BookView) {
+        self.best_bid = book.bids.first().map(|(price, _)| *price);
+        self.best_ask = book.asks.first().map(|(price, _)| *price);
+    }
+    /// Remembers an order of the source once the simulator has assigned its engine order id
+    pub fn on_submitted(&mut self, order: &Order) {
+        self.tracking = true;
+        if !matches!(order.kind, OrderType::Market { .. }) {
+            self.submitted.insert(order.order_id, *order);
+        }
+    }
+    /// Updates the live orders from a client event. Events of orders that did not come from the source are ignored
+    pub fn on_client_event(&mut self, event: &ClientEvent) {
+        match event.kind {
+            ClientEventType::Accepted(_) => {
+                if let Some(order) = self.submitted.remove(&event.order_id)
+                    && let OrderType::Limit { price, .. } = order.kind
+                {
+                    self.insert(order.order_id, order.side, price, event.timestamp);
+                }
+            }
+            ClientEventType::Updated => {
+                if let Some(order) = self.submitted.remove(&event.order_id)
+                    && let OrderType::Update { old_id, price, .. } = order.kind
+                {
+                    self.remove(old_id);
+                    self.insert(order.order_id, order.side, price, event.timestamp);
+                }
+            }
+            ClientEventType::Canceled => {
+                if let Some(order) = self.submitted.remove(&event.order_id)
+                    && let OrderType::Cancel { old_id } = order.kind
+                {
+                    self.remove(old_id);
+                }
+            }
+            ClientEventType::Rejected => {
+                // Cancels always reach the book, so a rejected cancel means its target is gone
+                if let Some(order) = self.submitted.remove(&event.order_id)
+                    && let OrderType::Cancel { old_id } = order.kind
+                {
+                    self.remove(old_id);
+                }
+            }
+            ClientEventType::Filled => self.remove(event.order_id),
+            ClientEventType::PartiallyFilled(_) => {}
+        }
+    }
+    /// Points a cancel or update at a live order on its side. Without any live order on that side the target is an id
+    /// that never exists, so the book rejects it
+    pub fn retarget(&mut self, order: &mut Order) {
+        if !self.tracking {
+            return;
+        }
+        if let OrderType::Cancel { old_id } | OrderType::Update { old_id, .. } = &mut order.kind {
+            *old_id = self
+                .sample(order.side, order.timestamp)
+                .unwrap_or(OrderId::MAX);
+        }
+    }
+    fn sample(&mut self, side: OrderSide, now: SimTime) -> Option<OrderId> {
+        let ids = match side {
+            OrderSide::Bid => &self.bids,
+            OrderSide::Ask => &self.asks,
+        };
+        if ids.is_empty() {
+            return None;
+        }
+        if self.selection.is_uniform() {
+            return Some(ids[self.rng.random_range(0..ids.len())]);
+        }
+        let prices = ids.iter().map(|id| self.orders[id].price);
+        let touch = match side {
+            OrderSide::Bid => self.best_bid.or_else(|| prices.max()),
+            OrderSide::Ask => self.best_ask.or_else(|| prices.min()),
+        }
+        .unwrap();
+        let weights: Vec<f64> = ids
+            .iter()
+            .map(|id| {
+                let order = &self.orders[id];
+                let age = now.saturating_sub(order.since) as f64 / NANOSECONDS_PER_SECOND as f64;
+                let distance = order.price.abs_diff(touch) as f64;
+                (1.0 + age).powf(self.selection.age_exponent)
+                    * (1.0 + distance).powf(self.selection.distance_exponent)
+            })
+            .collect();
+        let mut pick = self.rng.random::<f64>() * weights.iter().sum::<f64>();
+        for (id, weight) in ids.iter().zip(&weights) {
+            if pick < *weight {
+                return Some(*id);
+            }
+            pick -= weight;
+        }
+        ids.last().copied()
+    }
+    fn insert(&mut self, order_id: OrderId, side: OrderSide, price: Price, since: SimTime) {
+        let ids = match side {
+            OrderSide::Bid => &mut self.bids,
+            OrderSide::Ask => &mut self.asks,
+        };
+        ids.push(order_id);
+        self.orders.insert(
+            order_id,
+            LiveOrder {
+                side,
+                price,
+                since,
+                slot: ids.len() - 1,
+            },
+        );
+    }
+    fn remove(&mut self, order_id: OrderId) {
+        let Some(order) = self.orders.remove(&order_id) else {
+            return;
+        };
+        let ids = match order.side {
+            OrderSide::Bid => &mut self.bids,
+            OrderSide::Ask => &mut self.asks,
+        };
+        ids.swap_remove(order.slot);
+        if let Some(moved) = ids.get(order.slot) {
+            self.orders.get_mut(moved).unwrap().slot = order.slot;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mm_core::lob_core::market_events::LiquidityFlag;
+
+    fn submit(live: &mut LiveOrders, order_id: OrderId, side: OrderSide, kind: OrderType) {
+        live.on_submitted(&Order::new(0, order_id, side, 0, kind));
+    }
+    fn event(live: &mut LiveOrders, order_id: OrderId, timestamp: SimTime, kind: ClientEventType) {
+        live.on_client_event(&ClientEvent {
+            client_id: 0,
+            id: 0,
+            timestamp,
+            order_id,
+            order_side: OrderSide::Bid,
+            kind,
+            liquidity_flag: LiquidityFlag::Invalid,
+        });
+    }
+    fn rest(live: &mut LiveOrders, order_id: OrderId, price: Price, since: SimTime) {
+        submit(
+            live,
+            order_id,
+            OrderSide::Bid,
+            OrderType::Limit { qty: 10, price },
+        );
+        event(live, order_id, since, ClientEventType::Accepted(10));
+    }
+    fn live_orders(selection: TargetSelection) -> LiveOrders {
+        LiveOrders::new(selection, ChaCha8Rng::seed_from_u64(0))
+    }
+    fn targets(live: &mut LiveOrders, now: SimTime, count: usize) -> HashMap<OrderId, usize> {
+        let mut counts = HashMap::new();
+        for _ in 0..count {
+            let mut cancel = Order::new(0, 0, OrderSide::Bid, now, OrderType::Cancel { old_id: 0 });
+            live.retarget(&mut cancel);
+            if let OrderType::Cancel { old_id } = cancel.kind {
+                *counts.entry(old_id).or_default() += 1;
+            }
+        }
+        counts
+    }
+
+    #[test]
+    fn client_events_keep_live_orders_in_sync() {
+        let mut live = live_orders(TargetSelection::default());
+        for order_id in 0..4 {
+            rest(&mut live, order_id, 100, 0);
+        }
+        event(&mut live, 0, 1, ClientEventType::PartiallyFilled(5));
+        event(&mut live, 1, 1, ClientEventType::Filled);
+        submit(
+            &mut live,
+            4,
+            OrderSide::Bid,
+            OrderType::Cancel { old_id: 2 },
+        );
+        event(&mut live, 4, 2, ClientEventType::Canceled);
+        submit(
+            &mut live,
+            5,
+            OrderSide::Bid,
+            OrderType::Update {
+                old_id: 3,
+                qty: 5,
+                price: 101,
+            },
+        );
+        event(&mut live, 5, 3, ClientEventType::Updated);
+        submit(
+            &mut live,
+            6,
+            OrderSide::Bid,
+            OrderType::Limit { qty: 0, price: 1 },
+        );
+        event(&mut live, 6, 4, ClientEventType::Rejected);
+        assert_eq!(live.len(), 2);
+        assert!(live.contains(0) && live.contains(5));
+        let counts = targets(&mut live, 4, 1_000);
+        assert_eq!(counts.len(), 2);
+        assert!(counts.keys().all(|id| live.contains(*id)));
+        let mut ask = Order::new(0, 0, OrderSide::Ask, 4, OrderType::Cancel { old_id: 0 });
+        live.retarget(&mut ask);
+        assert_eq!(
+            ask.kind,
+            OrderType::Cancel {
+                old_id: OrderId::MAX
+            }
+        );
+    }
+
+    #[test]
+    fn untracked_orders_pass_through() {
+        let mut live = live_orders(TargetSelection::default());
+        let mut cancel = Order::new(0, 0, OrderSide::Bid, 0, OrderType::Cancel { old_id: 7 });
+        live.retarget(&mut cancel);
+        assert_eq!(cancel.kind, OrderType::Cancel { old_id: 7 });
+    }
+
+    #[test]
+    fn selection_weights_age_and_distance() {
+        let second = NANOSECONDS_PER_SECOND as SimTime;
+        let mut old = live_orders(TargetSelection::new(2.0, 0.0).unwrap());
+        rest(&mut old, 0, 100, 0);
+        rest(&mut old, 1, 100, 9 * second);
+        // Weights (1 + 10)^2 and (1 + 1)^2
+        let counts = targets(&mut old, 10 * second, 10_000);
+        let share = counts[&0] as f64 / 10_000.0;
+        assert!((share - 121.0 / 125.0).abs() < 0.01, "{share}");
+
+        let mut near = live_orders(TargetSelection::new(0.0, -1.0).unwrap());
+        rest(&mut near, 0, 100, 0);
+        rest(&mut near, 1, 97, 0);
+        // Weights 1 and 1 / (1 + 3)
+        let counts = targets(&mut near, 0, 10_000);
+        let share = counts[&0] as f64 / 10_000.0;
+        assert!((share - 0.8).abs() < 0.015, "{share}");
+        // Distances are measured from the book's touch, which may be another client's order
+        near.observe(&BookView {
+            bids: vec![(101, 5)],
+            ..BookView::default()
+        });
+        // Weights 1 / (1 + 1) and 1 / (1 + 4)
+        let counts = targets(&mut near, 0, 10_000);
+        let share = counts[&0] as f64 / 10_000.0;
+        assert!((share - 5.0 / 7.0).abs() < 0.015, "{share}");
+        assert!(TargetSelection::new(f64::NAN, 0.0).is_err());
+    }
+}
@@ -0,0 +1,157 @@
+use crate::data_generator::event_source::EventSource;
+use crate::event_recorder::ARCHIVED_ORDER_SIZE;
+use crate::simulator::SimTime;
+use memmap2::Mmap;
+use mm_core::lob_core::market_orders::{ArchivedOrder, Order};
+use rkyv::rancor::Error;
+use std::fs::File;
+
+/// EventSource that replays a recording made with --record archive from a memory map. Orders are read in place, the
+/// file is paged in by the OS as the replay advances and nothing is copied besides the order being returned. Records
+/// are a fixed size, so any order can be looked up by its index, and by time since a recording is in time order.
+///
+/// Every record is validated once when the file is opened, a record that is not a valid archived order is an error
+pub struct MmapReplaySource {
+    map: Mmap,
+    len: usize,
+    position: usize,
+}
+impl MmapReplaySource {
+    pub fn open(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("archive: {path}: {e}"))?;
+        // SAFETY: The map is read only. Like any memory map it assumes the file is not truncated or rewritten while
+        // it is replayed, which would be a recording made to the same file and is rejected by validation
+        let map = unsafe { Mmap::map(&file) }.map_err(|e| format!("archive: {path}: {e}"))?;
+        if map.len() % ARCHIVED_ORDER_SIZE != 0 {
+            return Err(format!(
+                "archive: {path}: size is not a multiple of {ARCHIVED_ORDER_SIZE} bytes, not an archive recording"
+            ));
+        }
+        for (i, record) in map.chunks_exact(ARCHIVED_ORDER_SIZE).enumerate() {
+            rkyv::access::<ArchivedOrder, Error>(record).map_err(|e| {
+                format!("archive: {path}: order #{i} is not a valid archived order: {e}")
+            })?;
+        }
+        Ok(Self {
+            len: map.len() / ARCHIVED_ORDER_SIZE,
+            map,
+            position: 0,
+        })
+    }
+    /// Number of orders in the recording
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    /// Index of the next order to be replayed
+    pub fn position(&self) -> usize {
+        self.position
+    }
+    /// The archived order at `index`, read in place
+    pub fn get(&self, index: usize) -> Option<&ArchivedOrder> {
+        let start = index.checked_mul(ARCHIVED_ORDER_SIZE)?;
+        let record = self.map.get(start..start + ARCHIVED_ORDER_SIZE)?;
+        // SAFETY: Every record of the map was validated by `open`
+        Some(unsafe { rkyv::access_unchecked::<ArchivedOrder>(record) })
+    }
+    /// Index of the first order at or after `time`, the number of orders if there is none
+    pub fn index_at(&self, time: SimTime) -> usize {
+        let (mut low, mut high) = (0, self.len);
+        while low < high {
+            let middle = low + (high - low) / 2;
+            match self.get(middle) {
+                Some(order) if order.timestamp.to_native() < time => low = middle + 1,
+                _ => high = middle,
+            }
+        }
+        low
+    }
+    /// Continues the replay from the order at `index`
+    pub fn seek(&mut self, index: usize) {
+        self.position = index.min(self.len);
+    }
+    /// Continues the replay from the first order at or after `time`
+    pub fn seek_time(&mut self, time: SimTime) {
+        self.position = self.index_at(time);
+    }
+}
+impl EventSource for MmapReplaySource {
+    fn next_event(&mut self) -> Option<Order> {
+        let order = rkyv::deserialize::<Order, Error>(self.get(self.position)?).ok()?;
+        self.position += 1;
+        Some(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::event_recorder::{ArchiveRecorder, Recorder};
+    use mm_core::lob_core::market_orders::{OrderSide, OrderType};
+
+    fn record(name: &str, orders: &[Order]) -> String {
+        let dir = std::env::temp_dir().join(format!("mm_archive_{name}_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("run.mma").to_string_lossy().into_owned();
+        let mut recorder = ArchiveRecorder::new(&path, 2).unwrap();
+        for order in orders {
+            recorder.record_event(*order).unwrap();
+        }
+        recorder.shutdown().unwrap();
+        path
+    }
+
+    #[test]
+    fn archived_orders_are_read_in_place() {
+        let orders: Vec<Order> = (0..10)
+            .map(|i| {
+                let kind = match i % 3 {
+                    0 => OrderType::Limit {
+                        qty: i as u32 + 1,
+                        price: 1000 + i as u32,
+                    },
+                    1 => OrderType::Market { qty: 5 },
+                    _ => OrderType::Cancel { old_id: i - 2 },
+                };
+                Order::new(i, i, OrderSide::Ask, i / 2 * 100, kind)
+            })
+            .collect();
+        let path = record("in_place", &orders);
+        let mut source = MmapReplaySource::open(&path).unwrap();
+        assert_eq!(source.len(), orders.len());
+        assert_eq!(source.get(4).unwrap().order_id.to_native(), 4);
+        assert!(source.get(10).is_none());
+
+        let replayed: Vec<Order> = std::iter::from_fn(|| source.next_event()).collect();
+        assert_eq!(replayed, orders);
+
+        // Orders 4 and 5 are at time 200, the first at or after 150 is order 4
+        assert_eq!(source.index_at(150), 4);
+        assert_eq!(source.index_at(200), 4);
+        assert_eq!(source.index_at(1000), 10);
+        source.seek_time(201);
+        assert_eq!(source.next_event(), Some(orders[6]));
+        source.seek(9);
+        assert_eq!(source.next_event(), Some(orders[9]));
+        assert_eq!(source.next_event(), None);
+    }
+
+    #[test]
+    fn other_files_are_rejected() {
+        let path = record("rejected", &[]);
+        std::fs::write(&path, [0u8; ARCHIVED_ORDER_SIZE + 1]).unwrap();
+        assert!(MmapReplaySource::open(&path).is_err());
+        // A record of the right size that does not hold an order is found when the file is opened
+        let mut records = std::fs::read(record(
+            "corrupt",
+            &[Order::new(0, 0, OrderSide::Bid, 0, OrderType::Market { qty: 1 }); 3],
+        ))
+        .unwrap();
+        records[ARCHIVED_ORDER_SIZE..2 * ARCHIVED_ORDER_SIZE].fill(0xff);
+        std::fs::write(&path, records).unwrap();
+        let error = MmapReplaySource::open(&path).err().unwrap();
+        assert!(error.contains("order #1"), "{error}");
+    }
+}
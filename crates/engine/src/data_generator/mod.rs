@@ -1,4 +1,12 @@
+pub mod book_view;
+pub mod csv_replay;
 pub mod event_source;
+pub mod historical_book;
+pub mod itch_replay;
+pub mod live_orders;
+pub mod mmap_replay;
 pub mod order_generators;
+pub mod order_sizes;
 pub mod rate_controllers;
+pub mod toxic_flow;
 pub mod type_selectors;
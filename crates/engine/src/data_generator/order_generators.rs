@@ -1,11 +1,16 @@
 use mm_core::lob_core::{
-    ClientId, OrderId, OrderQty, Price, Timestamp,
+    ClientId, OrderId, Price, Timestamp,
     market_orders::{Order, OrderSide, OrderType},
 };
-use rand::{Rng, RngExt};
-use rand_distr::{Distribution, Normal, Uniform, uniform::UniformSampler};
+use rand::Rng;
+use rand::distr::weighted::WeightedIndex;
+use rand_distr::{Distribution, Normal, Poisson, StandardNormal, Uniform, uniform::UniformSampler};
 
+use crate::data_generator::book_view::BookView;
+use crate::data_generator::order_sizes::OrderSizes;
+use crate::data_generator::toxic_flow::Fundamental;
 use crate::simulator::SimTime;
+use clap::ValueEnum;
 
 /// Generates the next event, also handles selection of price level, memory of orders for cancellation
 pub trait OrderGenerator {
@@ -17,15 +22,22 @@ pub trait OrderGenerator {
         order_variant: (OrderSide, OrderType),
         rng: &mut impl Rng,
     ) -> Order;
+    /// Whether the generator reads the book, in which case `observe` is called with the current book before every order.
+    /// The book is the one seen when the order is generated, not when it arrives after the simulated latency
+    fn reads_book(&self) -> bool {
+        false
+    }
+    fn observe(&mut self, _book: &BookView) {}
 }
 
 /// OrderGenerator that samples order prices from a gaussian distribution
+#[derive(Clone)]
 pub struct GaussianOrderGenerator {
     bid_dist: Normal<f64>,
     ask_dist: Normal<f64>,
     current_time: SimTime,
     order_counter: u64,
-    qty_dist: Uniform<OrderQty>,
+    sizes: OrderSizes,
 }
 impl GaussianOrderGenerator {
     pub fn new(bid_mean: f64, bid_deviation: f64, ask_mean: f64, ask_deviation: f64) -> Self {
@@ -34,15 +46,21 @@ impl GaussianOrderGenerator {
             ask_dist: Normal::new(ask_mean, ask_deviation).unwrap(),
             current_time: 0,
             order_counter: 0,
-            qty_dist: Uniform::new_inclusive(0, 20).unwrap(), // TODO: Make this configurable
+            sizes: OrderSizes::default(),
         }
     }
+    /// Replaces the default distribution of order sizes
+    pub fn with_sizes(mut self, sizes: OrderSizes) -> Self {
+        self.sizes = sizes;
+        self
+    }
     fn compute_price(&mut self, side: OrderSide, rng: &mut impl Rng) -> Price {
         match side {
             OrderSide::Ask => self.ask_dist.sample(rng) as Price,
             OrderSide::Bid => self.bid_dist.sample(rng) as Price,
         }
     }
+    /// Any order generated so far, sources that track their live orders replace this with a resting order
     fn get_active_order(&self, rng: &mut impl Rng) -> OrderId {
         let dist = Uniform::new(0, self.order_counter).unwrap();
         dist.sample(rng)
@@ -58,20 +76,17 @@ impl OrderGenerator for GaussianOrderGenerator {
     ) -> Order {
         let (side, kind) = order_variant;
         let price = self.compute_price(side, rng);
-        let qty = self.qty_dist.sample(rng);
+        let qty = self.sizes.sample(rng);
         self.order_counter += 1;
         self.current_time += time_stamp;
         match kind {
-            OrderType::Limit { .. } => {
-                self.order_counter += 1;
-                Order::new(
-                    client_id,
-                    0, // NOTE: Use a junk value, simulator sets this on receipt
-                    side,
-                    self.current_time,
-                    OrderType::Limit { qty, price },
-                )
-            }
+            OrderType::Limit { .. } => Order::new(
+                client_id,
+                0, // NOTE: Use a junk value, simulator sets this on receipt
+                side,
+                self.current_time,
+                OrderType::Limit { qty, price },
+            ),
             OrderType::Market { .. } => Order::new(
                 client_id,
                 0, // NOTE: Use a junk value, simulator sets this on receipt
@@ -103,6 +118,310 @@ impl OrderGenerator for GaussianOrderGenerator {
     }
 }
 
+/// Enum denoting the type of process followed by the reference price. Used for selecting the process from command-line args
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum PriceProcessKind {
+    RandomWalk,
+    Gbm,
+    JumpDiffusion,
+}
+
+/// Largest accepted jump rate, in jumps per second
+const MAX_JUMP_RATE: f64 = 1000.0;
+
+/// Stochastic process followed by a latent fundamental price, in sim time. Rates and volatilities are per second
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PriceProcess {
+    /// The price never moves
+    Fixed,
+    /// Arithmetic Brownian motion, `volatility` is in cents per square root of a second
+    RandomWalk { volatility: f64 },
+    /// Geometric Brownian motion with relative `drift` and `volatility`
+    Gbm { drift: f64, volatility: f64 },
+    /// Merton jump diffusion, a GBM with log-normal jumps arriving at `jump_rate` per second whose log sizes have mean
+    /// `jump_mean` and standard deviation `jump_std`. The drift is compensated so the expected return is still `drift`
+    JumpDiffusion {
+        drift: f64,
+        volatility: f64,
+        jump_rate: f64,
+        jump_mean: f64,
+        jump_std: f64,
+    },
+}
+impl PriceProcess {
+    pub fn validate(&self) -> Result<(), String> {
+        let (drift, volatility, jump_rate, jump_mean, jump_std) = match *self {
+            PriceProcess::Fixed => (0.0, 0.0, 0.0, 0.0, 0.0),
+            PriceProcess::RandomWalk { volatility } => (0.0, volatility, 0.0, 0.0, 0.0),
+            PriceProcess::Gbm { drift, volatility } => (drift, volatility, 0.0, 0.0, 0.0),
+            PriceProcess::JumpDiffusion {
+                drift,
+                volatility,
+                jump_rate,
+                jump_mean,
+                jump_std,
+            } => (drift, volatility, jump_rate, jump_mean, jump_std),
+        };
+        if ![drift, volatility, jump_rate, jump_mean, jump_std]
+            .iter()
+            .all(|param| param.is_finite())
+        {
+            return Err("price process: parameters must be finite".into());
+        }
+        if !(volatility >= 0.0 && jump_rate >= 0.0 && jump_std >= 0.0) {
+            return Err("price process: volatility, jump rate and jump std must be >= 0".into());
+        }
+        if jump_rate > MAX_JUMP_RATE {
+            return Err(format!(
+                "price process: jump rate must be <= {MAX_JUMP_RATE} per second"
+            ));
+        }
+        Ok(())
+    }
+    /// Moves `price` forward by `dt` seconds
+    pub fn advance(&self, price: f64, dt: f64, rng: &mut impl Rng) -> f64 {
+        match *self {
+            PriceProcess::Fixed => price,
+            PriceProcess::RandomWalk { volatility } => price + volatility * dt.sqrt() * normal(rng),
+            PriceProcess::Gbm { drift, volatility } => {
+                price
+                    * ((drift - volatility * volatility / 2.0) * dt
+                        + volatility * dt.sqrt() * normal(rng))
+                    .exp()
+            }
+            PriceProcess::JumpDiffusion {
+                drift,
+                volatility,
+                jump_rate,
+                jump_mean,
+                jump_std,
+            } => {
+                let compensator = jump_rate * ((jump_mean + jump_std * jump_std / 2.0).exp() - 1.0);
+                let mut log_return = (drift - volatility * volatility / 2.0 - compensator) * dt
+                    + volatility * dt.sqrt() * normal(rng);
+                // NOTE: validate bounds the jump rate, so the mean is a valid Poisson mean for any gap between orders
+                if let Ok(jumps) = Poisson::new(jump_rate * dt) {
+                    for _ in 0..jumps.sample(rng) as u64 {
+                        log_return += jump_mean + jump_std * normal(rng);
+                    }
+                }
+                price * log_return.exp()
+            }
+        }
+    }
+}
+
+fn normal(rng: &mut impl Rng) -> f64 {
+    StandardNormal.sample(rng)
+}
+
+/// OrderGenerator that places prices around a latent Fundamental price following a PriceProcess, so the generated
+/// market trends and has volatility. Bid and ask prices are the reference price plus offsets sampled from gaussian
+/// distributions, a negative bid offset is a bid below the reference. An informed trader given the same Fundamental
+/// trades on the prices this generator follows
+#[derive(Clone)]
+pub struct ReferencePriceGenerator {
+    fundamental: Fundamental,
+    bid_offset: Normal<f64>,
+    ask_offset: Normal<f64>,
+    current_time: SimTime,
+    sizes: OrderSizes,
+}
+impl ReferencePriceGenerator {
+    pub fn new(
+        fundamental: Fundamental,
+        bid_offset_mean: f64,
+        bid_offset_deviation: f64,
+        ask_offset_mean: f64,
+        ask_offset_deviation: f64,
+    ) -> Self {
+        Self {
+            fundamental,
+            bid_offset: Normal::new(bid_offset_mean, bid_offset_deviation).unwrap(),
+            ask_offset: Normal::new(ask_offset_mean, ask_offset_deviation).unwrap(),
+            current_time: 0,
+            sizes: OrderSizes::default(),
+        }
+    }
+    /// Replaces the default distribution of order sizes
+    pub fn with_sizes(mut self, sizes: OrderSizes) -> Self {
+        self.sizes = sizes;
+        self
+    }
+    /// Current value of the fundamental price in cents
+    pub fn reference_price(&mut self) -> f64 {
+        self.fundamental.value(self.current_time)
+    }
+    fn compute_price(&mut self, side: OrderSide, rng: &mut impl Rng) -> Price {
+        let offset = match side {
+            OrderSide::Ask => self.ask_offset.sample(rng),
+            OrderSide::Bid => self.bid_offset.sample(rng),
+        };
+        (self.reference_price() + offset).max(1.0) as Price
+    }
+}
+impl OrderGenerator for ReferencePriceGenerator {
+    fn generate(
+        &mut self,
+        client_id: ClientId,
+        time_stamp: Timestamp,
+        order_variant: (OrderSide, OrderType),
+        rng: &mut impl Rng,
+    ) -> Order {
+        self.current_time += time_stamp;
+        self.fundamental.forget_before(self.current_time);
+        let (side, kind) = order_variant;
+        let price = self.compute_price(side, rng);
+        let qty = self.sizes.sample(rng);
+        let kind = match kind {
+            OrderType::Limit { .. } => OrderType::Limit { qty, price },
+            OrderType::Market { .. } => OrderType::Market { qty },
+            // NOTE: Targets are left to the source, which points them at its live orders
+            OrderType::Cancel { .. } => OrderType::Cancel {
+                old_id: OrderId::MAX,
+            },
+            OrderType::Update { .. } => OrderType::Update {
+                old_id: OrderId::MAX,
+                qty,
+                price,
+            },
+        };
+        Order::new(
+            client_id,
+            0, // NOTE: Use a junk value, simulator sets this on receipt
+            side,
+            self.current_time,
+            kind,
+        )
+    }
+}
+
+/// Where the limit prices of a TouchOffsetGenerator are measured from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum TouchAnchor {
+    /// The best quote on the order's own side, level 1 joins it and deeper levels queue behind it
+    Same,
+    /// The best quote on the opposite side, level 1 is one tick away from it, as in the Santa Fe and
+    /// Cont-Stoikov-Talreja models
+    Opposite,
+}
+
+/// Book-aware OrderGenerator that places limit prices a sampled number of ticks from the current best quotes, instead
+/// of at absolute prices. Until the anchor quote exists prices are measured from the last trade, or from the
+/// reference price before any trade. Quotes are read when the order is generated, so with latency the book may have
+/// moved by the time the order arrives
+#[derive(Clone)]
+pub struct TouchOffsetGenerator {
+    levels: WeightedIndex<f64>,
+    anchor: TouchAnchor,
+    tick: Price,
+    reference: Price,
+    book: BookView,
+    current_time: SimTime,
+    sizes: OrderSizes,
+}
+impl TouchOffsetGenerator {
+    /// `level_weights[i]` is the relative probability of placing a limit order at level `i + 1` from the anchor
+    pub fn new(
+        level_weights: Vec<f64>,
+        anchor: TouchAnchor,
+        tick: Price,
+        reference: Price,
+    ) -> Result<Self, String> {
+        if tick == 0 || reference == 0 {
+            return Err("book: the tick size and reference price must be > 0".into());
+        }
+        Ok(Self {
+            levels: WeightedIndex::new(level_weights).map_err(|e| format!("book: {e}"))?,
+            anchor,
+            tick,
+            reference,
+            book: BookView::default(),
+            current_time: 0,
+            sizes: OrderSizes::default(),
+        })
+    }
+    /// Limit orders spread evenly over `window` ticks from the opposite quote, as in the Santa Fe zero-intelligence
+    /// model
+    pub fn santa_fe(window: usize, tick: Price, reference: Price) -> Result<Self, String> {
+        Self::new(vec![1.0; window], TouchAnchor::Opposite, tick, reference)
+    }
+    /// Limit orders at level i from the opposite quote with probability proportional to 1 / i^exponent, the power law
+    /// placement of the Cont-Stoikov-Talreja model
+    pub fn cont_stoikov_talreja(
+        window: usize,
+        exponent: f64,
+        tick: Price,
+        reference: Price,
+    ) -> Result<Self, String> {
+        let weights = (1..=window).map(|i| (i as f64).powf(-exponent)).collect();
+        Self::new(weights, TouchAnchor::Opposite, tick, reference)
+    }
+    /// Replaces the default distribution of order sizes
+    pub fn with_sizes(mut self, sizes: OrderSizes) -> Self {
+        self.sizes = sizes;
+        self
+    }
+    fn compute_price(&self, side: OrderSide, rng: &mut impl Rng) -> Price {
+        let level = self.levels.sample(rng) as Price + 1;
+        let (anchor_side, steps) = match (self.anchor, side) {
+            (TouchAnchor::Same, _) => (side, level - 1),
+            (TouchAnchor::Opposite, OrderSide::Bid) => (OrderSide::Ask, level),
+            (TouchAnchor::Opposite, OrderSide::Ask) => (OrderSide::Bid, level),
+        };
+        let anchor = self
+            .book
+            .best(anchor_side)
+            .or(self.book.last_trade)
+            .unwrap_or(self.reference);
+        let offset = steps.saturating_mul(self.tick);
+        match side {
+            OrderSide::Bid => anchor.saturating_sub(offset).max(self.tick),
+            OrderSide::Ask => anchor.saturating_add(offset),
+        }
+    }
+}
+impl OrderGenerator for TouchOffsetGenerator {
+    fn generate(
+        &mut self,
+        client_id: ClientId,
+        time_stamp: Timestamp,
+        order_variant: (OrderSide, OrderType),
+        rng: &mut impl Rng,
+    ) -> Order {
+        self.current_time += time_stamp;
+        let (side, kind) = order_variant;
+        let price = self.compute_price(side, rng);
+        let qty = self.sizes.sample(rng);
+        let kind = match kind {
+            OrderType::Limit { .. } => OrderType::Limit { qty, price },
+            OrderType::Market { .. } => OrderType::Market { qty },
+            // NOTE: Targets are left to the source, which points them at its live orders
+            OrderType::Cancel { .. } => OrderType::Cancel {
+                old_id: OrderId::MAX,
+            },
+            OrderType::Update { .. } => OrderType::Update {
+                old_id: OrderId::MAX,
+                qty,
+                price,
+            },
+        };
+        Order::new(
+            client_id,
+            0, // NOTE: Use a junk value, simulator sets this on receipt
+            side,
+            self.current_time,
+            kind,
+        )
+    }
+    fn reads_book(&self) -> bool {
+        true
+    }
+    fn observe(&mut self, book: &BookView) {
+        self.book.clone_from(book);
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use rand::SeedableRng;
@@ -110,6 +429,7 @@ mod tests {
     use std::vec::Vec;
 
     use super::*;
+    use crate::data_generator::rate_controllers::NANOSECONDS_PER_SECOND;
 
     const PRECISION: f64 = 0.025;
 
@@ -220,4 +540,172 @@ mod tests {
             );
         }
     }
+    /// Log returns of `steps` one-second steps of a price process
+    fn log_returns(process: PriceProcess, steps: usize) -> Vec<f64> {
+        let mut rng = ChaCha8Rng::seed_from_u64(7);
+        (0..steps)
+            .map(|_| process.advance(100.0, 1.0, &mut rng).ln() - 100f64.ln())
+            .collect()
+    }
+    fn moments(values: &[f64]) -> (f64, f64, f64) {
+        let n = values.len() as f64;
+        let mean = values.iter().sum::<f64>() / n;
+        let variance = values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+        let kurtosis =
+            values.iter().map(|v| (v - mean).powi(4)).sum::<f64>() / n / variance.powi(2);
+        (mean, variance.sqrt(), kurtosis)
+    }
+
+    #[test]
+    fn gbm_log_returns_are_gaussian() {
+        let (mean, std, kurtosis) = moments(&log_returns(
+            PriceProcess::Gbm {
+                drift: 0.01,
+                volatility: 0.02,
+            },
+            200_000,
+        ));
+        assert!((mean - (0.01 - 0.0002)).abs() < 2e-4, "{mean}");
+        assert!((std - 0.02).abs() < 5e-4, "{std}");
+        assert!((kurtosis - 3.0).abs() < 0.1, "{kurtosis}");
+    }
+
+    #[test]
+    fn jump_diffusion_has_fat_tails_and_compensated_drift() {
+        let process = PriceProcess::JumpDiffusion {
+            drift: 0.0,
+            volatility: 0.01,
+            jump_rate: 0.1,
+            jump_mean: -0.05,
+            jump_std: 0.05,
+        };
+        let returns = log_returns(process, 200_000);
+        let (_, _, kurtosis) = moments(&returns);
+        assert!(kurtosis > 5.0, "{kurtosis}");
+        // The expected gross return is exp(drift) despite the negative jumps
+        let gross = returns.iter().map(|r| r.exp()).sum::<f64>() / returns.len() as f64;
+        assert!((gross - 1.0).abs() < 1e-3, "{gross}");
+    }
+
+    #[test]
+    fn non_finite_or_huge_jump_rates_are_rejected() {
+        let jumps = |jump_rate| PriceProcess::JumpDiffusion {
+            drift: 0.0,
+            volatility: 0.01,
+            jump_rate,
+            jump_mean: 0.0,
+            jump_std: 0.01,
+        };
+        assert!(jumps(f64::INFINITY).validate().is_err());
+        assert!(jumps(1e20).validate().is_err());
+        assert!(jumps(f64::NAN).validate().is_err());
+        assert!(
+            PriceProcess::Gbm {
+                drift: f64::NAN,
+                volatility: 0.1
+            }
+            .validate()
+            .is_err()
+        );
+        assert!(jumps(10.0).validate().is_ok());
+    }
+
+    #[test]
+    fn prices_follow_the_reference_price() {
+        let mut order_gen = ReferencePriceGenerator::new(
+            Fundamental::new(
+                1_000.0,
+                PriceProcess::RandomWalk { volatility: 50.0 },
+                ChaCha8Rng::seed_from_u64(1),
+            ),
+            -5.0,
+            1.0,
+            5.0,
+            1.0,
+        );
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut references = Vec::new();
+        for i in 0..10_000 {
+            let side = match i % 2 {
+                0 => OrderSide::Bid,
+                _ => OrderSide::Ask,
+            };
+            let order = order_gen.generate(
+                0,
+                NANOSECONDS_PER_SECOND,
+                (side, OrderType::Limit { qty: 0, price: 0 }),
+                &mut rng,
+            );
+            let OrderType::Limit { price, qty } = order.kind else {
+                unreachable!()
+            };
+            assert!((1..=20).contains(&qty), "{qty}");
+            let reference = order_gen.reference_price();
+            // Prices are truncated to whole cents
+            let offset = price as f64 - reference;
+            match side {
+                OrderSide::Bid => assert!((-12.0..1.0).contains(&offset), "{offset}"),
+                OrderSide::Ask => assert!((-1.0..12.0).contains(&offset), "{offset}"),
+            }
+            references.push(reference);
+        }
+        // The market actually moves
+        let (min, max) = references
+            .iter()
+            .fold((f64::MAX, f64::MIN), |(min, max), r| {
+                (min.min(*r), max.max(*r))
+            });
+        assert!(max - min > 500.0, "{min}..{max}");
+    }
+
+    #[test]
+    fn touch_offsets_follow_the_quotes() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut generator = TouchOffsetGenerator::santa_fe(5, 2, 1000).unwrap();
+        let limit = (OrderSide::Bid, OrderType::Limit { qty: 0, price: 0 });
+        let price = |order: Order| match order.kind {
+            OrderType::Limit { price, .. } => price,
+            _ => unreachable!(),
+        };
+        // Before any quote the reference price is the anchor
+        let bid = price(generator.generate(0, 0, limit, &mut rng));
+        assert!((990..1000).contains(&bid) && bid % 2 == 0, "{bid}");
+        generator.observe(&BookView {
+            bids: vec![(1500, 10)],
+            asks: vec![(1510, 10)],
+            ..BookView::default()
+        });
+        let mut bids: Vec<Price> = (0..1_000)
+            .map(|_| price(generator.generate(0, 0, limit, &mut rng)))
+            .collect();
+        bids.sort();
+        bids.dedup();
+        assert_eq!(bids, vec![1500, 1502, 1504, 1506, 1508]);
+
+        let mut joiner = TouchOffsetGenerator::new(vec![1.0], TouchAnchor::Same, 1, 1000).unwrap();
+        joiner.observe(&BookView {
+            bids: vec![(1500, 10)],
+            asks: vec![(1510, 10)],
+            ..BookView::default()
+        });
+        let ask = joiner.generate(
+            0,
+            0,
+            (OrderSide::Ask, OrderType::Limit { qty: 0, price: 0 }),
+            &mut rng,
+        );
+        assert_eq!(price(ask), 1510);
+
+        // Level i of the power law is picked in proportion to 1 / i
+        let mut power_law = TouchOffsetGenerator::cont_stoikov_talreja(2, 1.0, 1, 1000).unwrap();
+        power_law.observe(&BookView {
+            asks: vec![(1000, 10)],
+            ..BookView::default()
+        });
+        let near = (0..30_000)
+            .filter(|_| price(power_law.generate(0, 0, limit, &mut rng)) == 999)
+            .count() as f64
+            / 30_000.0;
+        assert!((near - 2.0 / 3.0).abs() < 0.01, "{near}");
+    }
 }
@@ -0,0 +1,295 @@
+use mm_core::lob_core::OrderQty;
+use rand::distr::weighted::WeightedIndex;
+use rand::{Rng, RngExt};
+use rand_distr::{Distribution, Geometric, LogNormal, Uniform};
+
+/// Distribution of raw order sizes, before lot constraints are applied
+#[derive(Debug, Clone)]
+pub enum SizeDistribution {
+    /// Every size between the bounds is equally likely
+    Uniform(Uniform<OrderQty>),
+    /// Sizes 1, 2, 3, ... with geometrically decaying probabilities
+    Geometric(Geometric),
+    LogNormal(LogNormal<f64>),
+    /// Pareto tail, P(size > x) = (min / x)^(exponent - 1) for x >= min
+    PowerLaw {
+        min: f64,
+        exponent: f64,
+    },
+    /// Fixed sizes picked with given weights, e.g. round lots of 100, 200 and 500
+    Discrete {
+        sizes: Vec<OrderQty>,
+        weights: WeightedIndex<f64>,
+    },
+    /// Sizes resampled from observed data
+    Empirical(Vec<OrderQty>),
+    /// Weighted mixture of other distributions, e.g. round lots mixed with geometric odd lots
+    Mixture {
+        parts: Vec<SizeDistribution>,
+        weights: WeightedIndex<f64>,
+    },
+}
+impl SizeDistribution {
+    /// Parses a distribution written as `KIND:PARAMS`:
+    ///
+    /// - `uniform:MIN:MAX`
+    /// - `geometric:MEAN`
+    /// - `lognormal:MU:SIGMA`, the parameters of the log of the size
+    /// - `power-law:MIN:EXPONENT`, with an exponent above 1
+    /// - `round-lots:SIZE=WEIGHT,SIZE=WEIGHT,...`
+    /// - `empirical:FILE`, one size per line taken from the last CSV column
+    ///
+    /// Distributions joined with `+` and prefixed with `WEIGHT*` form a mixture, e.g.
+    /// `0.8*round-lots:100=3,500=1+0.2*geometric:40`
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        if spec.contains('+') {
+            let mut parts = Vec::new();
+            let mut weights = Vec::new();
+            for part in spec.split('+') {
+                let (weight, part) = match part.split_once('*') {
+                    Some((weight, part)) => (
+                        weight
+                            .trim()
+                            .parse::<f64>()
+                            .map_err(|_| format!("sizes: invalid weight `{weight}`"))?,
+                        part,
+                    ),
+                    None => (1.0, part),
+                };
+                parts.push(Self::parse(part.trim())?);
+                weights.push(weight);
+            }
+            let weights = WeightedIndex::new(weights).map_err(|e| format!("sizes: {e}"))?;
+            return Ok(SizeDistribution::Mixture { parts, weights });
+        }
+        let (kind, params) = spec.split_once(':').unwrap_or((spec, ""));
+        let numbers = || -> Result<Vec<f64>, String> {
+            params
+                .split(':')
+                .map(|p| {
+                    p.trim()
+                        .parse::<f64>()
+                        .map_err(|_| format!("sizes: invalid number `{p}` in `{spec}`"))
+                })
+                .collect()
+        };
+        let arity = |values: Vec<f64>, n: usize| match values.len() == n {
+            true => Ok(values),
+            false => Err(format!("sizes: `{kind}` takes {n} parameters")),
+        };
+        match kind {
+            "uniform" => {
+                let values = arity(numbers()?, 2)?;
+                let (min, max) = (values[0].max(1.0) as OrderQty, values[1] as OrderQty);
+                Uniform::new_inclusive(min, max)
+                    .map(SizeDistribution::Uniform)
+                    .map_err(|_| "sizes: uniform bounds must satisfy 1 <= MIN <= MAX".into())
+            }
+            "geometric" => {
+                let mean = arity(numbers()?, 1)?[0];
+                if mean.is_nan() || mean < 1.0 {
+                    return Err("sizes: the geometric mean must be >= 1".into());
+                }
+                Ok(SizeDistribution::Geometric(
+                    Geometric::new(1.0 / mean).unwrap(),
+                ))
+            }
+            "lognormal" => {
+                let values = arity(numbers()?, 2)?;
+                LogNormal::new(values[0], values[1])
+                    .map(SizeDistribution::LogNormal)
+                    .map_err(|_| "sizes: the lognormal sigma must be >= 0".into())
+            }
+            "power-law" => {
+                let values = arity(numbers()?, 2)?;
+                let (min, exponent) = (values[0], values[1]);
+                if min.is_nan() || min < 1.0 || exponent.is_nan() || exponent <= 1.0 {
+                    return Err("sizes: the power law needs MIN >= 1 and EXPONENT > 1".into());
+                }
+                Ok(SizeDistribution::PowerLaw { min, exponent })
+            }
+            "round-lots" => {
+                let mut sizes = Vec::new();
+                let mut weights = Vec::new();
+                for entry in params.split(',') {
+                    let (size, weight) = entry
+                        .split_once('=')
+                        .ok_or_else(|| format!("sizes: expected SIZE=WEIGHT, got `{entry}`"))?;
+                    sizes.push(
+                        size.trim()
+                            .parse::<OrderQty>()
+                            .map_err(|_| format!("sizes: invalid size `{size}`"))?,
+                    );
+                    weights.push(
+                        weight
+                            .trim()
+                            .parse::<f64>()
+                            .map_err(|_| format!("sizes: invalid weight `{weight}`"))?,
+                    );
+                }
+                let weights = WeightedIndex::new(weights).map_err(|e| format!("sizes: {e}"))?;
+                Ok(SizeDistribution::Discrete { sizes, weights })
+            }
+            "empirical" => Self::load_empirical(params),
+            _ => Err(format!("sizes: unknown distribution `{kind}`")),
+        }
+    }
+    /// Reads observed sizes from a file with one size per line, taken from the last CSV column. A header row and
+    /// zero sizes are skipped
+    pub fn load_empirical(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("sizes: {path}: {e}"))?;
+        let mut sizes = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let field = line.rsplit(',').next().unwrap_or_default().trim();
+            match field.parse::<f64>() {
+                Ok(size) if size >= 1.0 => sizes.push(size as OrderQty),
+                Ok(_) => {}
+                Err(_) if i == 0 || field.is_empty() => {}
+                Err(_) => return Err(format!("sizes: {path}: invalid size on line {}", i + 1)),
+            }
+        }
+        if sizes.is_empty() {
+            return Err(format!("sizes: {path}: no sizes found"));
+        }
+        Ok(SizeDistribution::Empirical(sizes))
+    }
+    fn sample(&self, rng: &mut impl Rng) -> f64 {
+        match self {
+            SizeDistribution::Uniform(dist) => dist.sample(rng) as f64,
+            SizeDistribution::Geometric(dist) => (dist.sample(rng) + 1) as f64,
+            SizeDistribution::LogNormal(dist) => dist.sample(rng),
+            SizeDistribution::PowerLaw { min, exponent } => {
+                let u: f64 = rng.random();
+                min * (1.0f64 - u).powf(-1.0 / (exponent - 1.0))
+            }
+            SizeDistribution::Discrete { sizes, weights } => sizes[weights.sample(rng)] as f64,
+            SizeDistribution::Empirical(sizes) => sizes[rng.random_range(0..sizes.len())] as f64,
+            SizeDistribution::Mixture { parts, weights } => parts[weights.sample(rng)].sample(rng),
+        }
+    }
+}
+
+/// Order sizes drawn from a SizeDistribution and rounded to whole lots. Every size is at least one lot, so the
+/// generated orders are never empty, and at most `max_size` rounded down to a lot
+#[derive(Debug, Clone)]
+pub struct OrderSizes {
+    distribution: SizeDistribution,
+    lot_size: OrderQty,
+    max_size: OrderQty,
+}
+impl Default for OrderSizes {
+    /// Uniform sizes between 1 and 20
+    fn default() -> Self {
+        Self::new(
+            SizeDistribution::Uniform(Uniform::new_inclusive(1, 20).unwrap()),
+            1,
+            None,
+        )
+        .unwrap()
+    }
+}
+impl OrderSizes {
+    pub fn new(
+        distribution: SizeDistribution,
+        lot_size: OrderQty,
+        max_size: Option<OrderQty>,
+    ) -> Result<Self, String> {
+        if lot_size == 0 {
+            return Err("sizes: the lot size must be > 0".into());
+        }
+        let max_size = max_size.unwrap_or(OrderQty::MAX);
+        if max_size < lot_size {
+            return Err("sizes: the maximum size must be at least one lot".into());
+        }
+        Ok(Self {
+            distribution,
+            lot_size,
+            max_size: max_size / lot_size * lot_size,
+        })
+    }
+    pub fn sample(&self, rng: &mut impl Rng) -> OrderQty {
+        let lots = (self.distribution.sample(rng) / self.lot_size as f64).round();
+        let size = (lots.max(1.0) * self.lot_size as f64).min(self.max_size as f64);
+        size as OrderQty
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+
+    fn samples(sizes: &OrderSizes, count: usize) -> Vec<OrderQty> {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        (0..count).map(|_| sizes.sample(&mut rng)).collect()
+    }
+    fn mean(values: &[OrderQty]) -> f64 {
+        values.iter().map(|v| *v as f64).sum::<f64>() / values.len() as f64
+    }
+
+    #[test]
+    fn default_sizes_are_never_empty() {
+        let values = samples(&OrderSizes::default(), 100_000);
+        assert!(values.iter().all(|size| (1..=20).contains(size)));
+        assert!((mean(&values) - 10.5).abs() < 0.1);
+    }
+
+    #[test]
+    fn sizes_respect_lot_constraints() {
+        let sizes = OrderSizes::new(
+            SizeDistribution::parse("lognormal:5:1.5").unwrap(),
+            100,
+            Some(1_050),
+        )
+        .unwrap();
+        let values = samples(&sizes, 100_000);
+        assert!(
+            values
+                .iter()
+                .all(|size| size % 100 == 0 && (100..=1_000).contains(size))
+        );
+        assert!(values.contains(&100) && values.contains(&1_000));
+        assert!(
+            OrderSizes::new(
+                SizeDistribution::parse("geometric:5").unwrap(),
+                100,
+                Some(50)
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn distributions_have_expected_means() {
+        let sizes =
+            |spec| OrderSizes::new(SizeDistribution::parse(spec).unwrap(), 1, None).unwrap();
+        let geometric = mean(&samples(&sizes("geometric:8"), 200_000));
+        assert!((geometric - 8.0).abs() < 0.1, "{geometric}");
+        // Pareto mean is min * (exponent - 1) / (exponent - 2)
+        let power_law = mean(&samples(&sizes("power-law:10:4"), 200_000));
+        assert!((power_law - 15.0).abs() < 0.3, "{power_law}");
+        let round_lots = samples(&sizes("round-lots:100=3,500=1"), 100_000);
+        assert!(round_lots.iter().all(|size| [100, 500].contains(size)));
+        assert!((mean(&round_lots) - 200.0).abs() < 3.0);
+        let mixture = samples(&sizes("0.5*round-lots:100=1+0.5*uniform:1:9"), 100_000);
+        let odd = mixture.iter().filter(|size| **size < 100).count() as f64 / 100_000.0;
+        assert!((odd - 0.5).abs() < 0.01, "{odd}");
+    }
+
+    #[test]
+    fn invalid_specs_are_rejected() {
+        for spec in [
+            "",
+            "uniform:5",
+            "uniform:9:1",
+            "geometric:0.5",
+            "power-law:1:1",
+            "round-lots:100",
+            "0.5*geometric:2+x*geometric:3",
+            "empirical:/nonexistent",
+        ] {
+            assert!(SizeDistribution::parse(spec).is_err(), "{spec}");
+        }
+    }
+}
@@ -1,7 +1,7 @@
-use rand::Rng;
+use rand::{Rng, RngExt};
 use rand_distr::{Distribution, Exp};
 
-const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
+pub const NANOSECONDS_PER_SECOND: u64 = 1_000_000_000;
 
 /// Determines what time the next event occurs
 pub trait RateController {
@@ -10,6 +10,7 @@ pub trait RateController {
 }
 
 /// Rate controller that samples inter-arrival time from an exponential distribution that does not change over time.
+#[derive(Clone)]
 pub struct ConstantPoissonRate {
     exp: Exp<f64>,
 }
@@ -28,6 +29,409 @@ impl RateController for ConstantPoissonRate {
     }
 }
 
+/// Rate controller driven by a multivariate Hawkes process with exponential kernels. Every event of component `j`
+/// raises the intensity of component `i` by `excitation[i][j] * decay[i][j]`, which then decays at rate
+/// `decay[i][j]` per second, so `excitation[i][j]` is the expected number of `i` events triggered by one `j` event.
+/// Events are sampled exactly with Ogata's thinning, `last_component()` tells which component produced the last event
+#[derive(Clone)]
+pub struct HawkesRate {
+    baseline: Vec<f64>,
+    excitation: Vec<Vec<f64>>,
+    decay: Vec<Vec<f64>>,
+    /// Excess intensity of component i caused by past events of component j, as of `time`
+    excess: Vec<Vec<f64>>,
+    time: f64,
+    emitted: u64,
+    last_component: usize,
+}
+impl HawkesRate {
+    /// Baselines are in events per second and decays in 1/seconds, both matrices are indexed [target][source]. The
+    /// process must be stationary, i.e. the spectral radius of `excitation` below 1
+    pub fn new(
+        baseline: Vec<f64>,
+        excitation: Vec<Vec<f64>>,
+        decay: Vec<Vec<f64>>,
+    ) -> Result<Self, String> {
+        let n = baseline.len();
+        if n == 0 {
+            return Err("hawkes: at least one component is required".into());
+        }
+        if excitation.len() != n
+            || decay.len() != n
+            || excitation.iter().chain(&decay).any(|row| row.len() != n)
+        {
+            return Err(format!(
+                "hawkes: excitation and decay must be {n}x{n} matrices"
+            ));
+        }
+        if baseline.iter().any(|mu| mu.is_nan() || *mu < 0.0) || baseline.iter().sum::<f64>() <= 0.0
+        {
+            return Err("hawkes: baselines must be >= 0 and not all 0".into());
+        }
+        if excitation
+            .iter()
+            .flatten()
+            .any(|alpha| alpha.is_nan() || *alpha < 0.0)
+        {
+            return Err("hawkes: excitation must be >= 0".into());
+        }
+        if decay
+            .iter()
+            .flatten()
+            .any(|beta| beta.is_nan() || *beta <= 0.0)
+        {
+            return Err("hawkes: decay must be > 0".into());
+        }
+        let rate = Self {
+            excess: vec![vec![0.0; n]; n],
+            baseline,
+            excitation,
+            decay,
+            time: 0.0,
+            emitted: 0,
+            last_component: 0,
+        };
+        if rate.branching_ratio() >= 1.0 {
+            return Err(format!(
+                "hawkes: branching ratio {:.3} must be < 1 for the process to be stationary",
+                rate.branching_ratio()
+            ));
+        }
+        Ok(rate)
+    }
+    /// Univariate process with the given baseline, branching ratio and decay
+    pub fn univariate(baseline: f64, excitation: f64, decay: f64) -> Result<Self, String> {
+        Self::new(vec![baseline], vec![vec![excitation]], vec![vec![decay]])
+    }
+    /// Number of components of the process
+    pub fn dimension(&self) -> usize {
+        self.baseline.len()
+    }
+    /// Component that produced the last sampled event
+    pub fn last_component(&self) -> usize {
+        self.last_component
+    }
+    /// Spectral radius of the excitation matrix, the expected number of events descending from one event
+    pub fn branching_ratio(&self) -> f64 {
+        // NOTE: Gelfand's formula, ||A^k||^(1/k) for k = 2^12. The matrix is renormalized after every squaring and its
+        // scale carried as a log
+        let max_entry = |m: &[Vec<f64>]| m.iter().flatten().fold(0.0f64, |max, x| max.max(*x));
+        let mut power = self.excitation.clone();
+        let mut log_scale = 0.0;
+        for _ in 0..12 {
+            let norm = max_entry(&power);
+            if norm == 0.0 {
+                return 0.0;
+            }
+            power.iter_mut().flatten().for_each(|x| *x /= norm);
+            log_scale = 2.0 * (log_scale + norm.ln());
+            power = mat_mul(&power, &power);
+        }
+        let norm = max_entry(&power);
+        if norm == 0.0 {
+            return 0.0;
+        }
+        ((log_scale + norm.ln()) / 4096.0).exp()
+    }
+    /// Long-run event rate of every component in events per second, the solution of `rate = baseline + excitation *
+    /// rate`
+    pub fn stationary_rates(&self) -> Vec<f64> {
+        // NOTE: Fixed-point iteration, converges because the branching ratio is below 1
+        let mut rates = self.baseline.clone();
+        for _ in 0..10_000 {
+            let next: Vec<f64> = (0..self.dimension())
+                .map(|i| {
+                    self.baseline[i]
+                        + (0..self.dimension())
+                            .map(|j| self.excitation[i][j] * rates[j])
+                            .sum::<f64>()
+                })
+                .collect();
+            let converged = next
+                .iter()
+                .zip(&rates)
+                .all(|(a, b)| (a - b).abs() <= 1e-12 * a.abs().max(1.0));
+            rates = next;
+            if converged {
+                break;
+            }
+        }
+        rates
+    }
+    /// Decays the excess intensities by `dt` seconds
+    fn decay_by(&mut self, dt: f64) {
+        for (excess, decay) in self.excess.iter_mut().zip(&self.decay) {
+            for (excess, decay) in excess.iter_mut().zip(decay) {
+                *excess *= (-decay * dt).exp();
+            }
+        }
+    }
+    fn intensity(&self, i: usize) -> f64 {
+        self.baseline[i] + self.excess[i].iter().sum::<f64>()
+    }
+}
+impl RateController for HawkesRate {
+    fn next_dt(&mut self, rng: &mut impl Rng) -> u64 {
+        loop {
+            // Intensities only decay between events, so the current total bounds them until the next event
+            let bound: f64 = (0..self.dimension()).map(|i| self.intensity(i)).sum();
+            let dt = Exp::new(bound).unwrap().sample(rng);
+            self.time += dt;
+            self.decay_by(dt);
+            let intensities: Vec<f64> = (0..self.dimension()).map(|i| self.intensity(i)).collect();
+            let mut u = rng.random::<f64>() * bound;
+            let Some(component) = intensities.iter().position(|lambda| {
+                u -= lambda;
+                u < 0.0
+            }) else {
+                continue; // Rejected, the intensity decayed below the bound
+            };
+            self.last_component = component;
+            for i in 0..self.dimension() {
+                self.excess[i][component] +=
+                    self.excitation[i][component] * self.decay[i][component];
+            }
+            let now = (self.time * NANOSECONDS_PER_SECOND as f64) as u64;
+            let dt = now - self.emitted;
+            self.emitted = now;
+            return dt;
+        }
+    }
+}
+
+/// Time-varying arrival rate of a non-homogeneous Poisson process, times are in seconds of sim time
+pub trait RateProfile {
+    /// Rate in events per second at `time`
+    fn rate(&self, time: f64) -> f64;
+    /// Upper bound of the rate from `time` until the returned end time
+    fn bound(&self, time: f64) -> (f64, f64);
+}
+
+/// Rate controller for a non-homogeneous Poisson process following a RateProfile. Candidate arrivals are sampled at
+/// the profile's local bound and kept with probability rate / bound (Lewis-Shedler thinning). Candidates past the end
+/// of the bound's interval are discarded and sampling restarts there, which is exact because arrivals are memoryless
+#[derive(Clone)]
+pub struct ThinnedPoissonRate<P: RateProfile> {
+    profile: P,
+    time: f64,
+    emitted: u64,
+}
+impl<P: RateProfile> ThinnedPoissonRate<P> {
+    pub fn new(profile: P) -> Self {
+        Self {
+            profile,
+            time: 0.0,
+            emitted: 0,
+        }
+    }
+}
+impl<P: RateProfile> RateController for ThinnedPoissonRate<P> {
+    fn next_dt(&mut self, rng: &mut impl Rng) -> u64 {
+        loop {
+            let (bound, until) = self.profile.bound(self.time);
+            // NOTE: Rounding at the end of an interval must not stall the clock
+            let until = until.max(self.time.next_up());
+            if bound <= 0.0 {
+                self.time = until;
+                continue;
+            }
+            let candidate = self.time + Exp::new(bound).unwrap().sample(rng);
+            if candidate >= until {
+                self.time = until;
+                continue;
+            }
+            self.time = candidate;
+            if rng.random::<f64>() * bound < self.profile.rate(candidate) {
+                break;
+            }
+        }
+        let now = (self.time * NANOSECONDS_PER_SECOND as f64) as u64;
+        let dt = now - self.emitted;
+        self.emitted = now;
+        dt
+    }
+}
+
+/// Piecewise-constant rate. With a period the schedule repeats, e.g. every day, otherwise the last rate holds forever
+#[derive(Clone)]
+pub struct PiecewiseRate {
+    starts: Vec<f64>,
+    rates: Vec<f64>,
+    period: Option<f64>,
+}
+impl PiecewiseRate {
+    /// Segments are (start time in seconds, rate in events per second) sorted by start, the first starting at 0
+    pub fn new(segments: Vec<(f64, f64)>, period: Option<f64>) -> Result<Self, String> {
+        match segments.first() {
+            None => return Err("rate schedule: at least one segment is required".into()),
+            Some((start, _)) if *start != 0.0 => {
+                return Err("rate schedule: the first segment must start at 0".into());
+            }
+            Some(_) => {}
+        }
+        if segments
+            .iter()
+            .any(|(start, rate)| !start.is_finite() || !rate.is_finite())
+        {
+            return Err("rate schedule: starts and rates must be finite".into());
+        }
+        if !segments.windows(2).all(|s| s[0].0 < s[1].0) {
+            return Err(
+                "rate schedule: segments must be listed in order of increasing start".into(),
+            );
+        }
+        if segments
+            .iter()
+            .any(|(_, rate)| rate.is_nan() || *rate < 0.0)
+        {
+            return Err("rate schedule: rates must be >= 0".into());
+        }
+        match period {
+            Some(period) if !period.is_finite() => {
+                return Err("rate schedule: the period must be finite".into());
+            }
+            Some(period) if period <= segments.last().unwrap().0 => {
+                return Err("rate schedule: the period must be longer than the last start".into());
+            }
+            Some(_) if segments.iter().all(|(_, rate)| *rate == 0.0) => {
+                return Err("rate schedule: rates must not all be 0".into());
+            }
+            None if segments.last().unwrap().1 == 0.0 => {
+                return Err(
+                    "rate schedule: the last rate must be > 0 when the schedule does not repeat"
+                        .into(),
+                );
+            }
+            _ => {}
+        }
+        let (starts, rates) = segments.into_iter().unzip();
+        Ok(Self {
+            starts,
+            rates,
+            period,
+        })
+    }
+    /// One segment per minute with rates proportional to the volumes, scaled so that the average rate over the
+    /// schedule is `mean_rate`. The schedule repeats after the last minute
+    pub fn from_minute_volumes(volumes: &[f64], mean_rate: f64) -> Result<Self, String> {
+        let total: f64 = volumes.iter().sum();
+        if volumes.is_empty() || total.is_nan() || total <= 0.0 {
+            return Err("minute volumes: volumes must not all be 0".into());
+        }
+        let scale = mean_rate * volumes.len() as f64 / total;
+        Self::new(
+            volumes
+                .iter()
+                .enumerate()
+                .map(|(minute, volume)| (minute as f64 * 60.0, volume * scale))
+                .collect(),
+            Some(volumes.len() as f64 * 60.0),
+        )
+    }
+    /// Reads per-minute volumes from a CSV file, one row per minute. The volume is taken from the last column so that
+    /// files with a leading time column work as well, a header row is skipped
+    pub fn load_minute_volumes(path: &str, mean_rate: f64) -> Result<Self, String> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| format!("minute volumes: {path}: {e}"))?;
+        let mut volumes = Vec::new();
+        for (i, line) in text.lines().enumerate() {
+            let field = line.rsplit(',').next().unwrap_or_default().trim();
+            if field.is_empty() {
+                continue;
+            }
+            match field.parse::<f64>() {
+                Ok(volume) if volume >= 0.0 => volumes.push(volume),
+                Err(_) if i == 0 => continue,
+                _ => {
+                    return Err(format!(
+                        "minute volumes: {path}: invalid volume on line {}",
+                        i + 1
+                    ));
+                }
+            }
+        }
+        Self::from_minute_volumes(&volumes, mean_rate).map_err(|e| format!("{e} in {path}"))
+    }
+    /// Position of `time` within the schedule, and the start time of the repetition it falls into
+    fn locate(&self, time: f64) -> (usize, f64) {
+        let (offset, local) = match self.period {
+            Some(period) => {
+                let offset = (time / period).floor() * period;
+                (offset, time - offset)
+            }
+            None => (0.0, time),
+        };
+        (
+            self.starts.partition_point(|start| *start <= local) - 1,
+            offset,
+        )
+    }
+}
+impl RateProfile for PiecewiseRate {
+    fn rate(&self, time: f64) -> f64 {
+        self.rates[self.locate(time).0]
+    }
+    fn bound(&self, time: f64) -> (f64, f64) {
+        let (segment, offset) = self.locate(time);
+        let end = match (self.starts.get(segment + 1), self.period) {
+            (Some(next), _) => offset + next,
+            (None, Some(period)) => offset + period,
+            (None, None) => f64::INFINITY,
+        };
+        (self.rates[segment], end)
+    }
+}
+
+/// The classic U-shaped intraday curve, busy around the open and the close and quiet at midday. The rate is quadratic
+/// in the time of day, `edge_ratio` times higher at both ends of the day than at midday, and averages `mean_rate`
+/// over the day. The curve repeats every `day_length` seconds
+#[derive(Clone)]
+pub struct UShapedRate {
+    trough: f64,
+    edge_ratio: f64,
+    day_length: f64,
+}
+impl UShapedRate {
+    pub fn new(mean_rate: f64, edge_ratio: f64, day_length: f64) -> Result<Self, String> {
+        if !(mean_rate > 0.0 && edge_ratio >= 1.0 && day_length > 0.0) {
+            return Err(
+                "u-shaped rate: the rate and day length must be > 0 and the edge ratio >= 1".into(),
+            );
+        }
+        // The average of (2x - 1)^2 over a day is 1/3
+        Ok(Self {
+            trough: mean_rate / (1.0 + (edge_ratio - 1.0) / 3.0),
+            edge_ratio,
+            day_length,
+        })
+    }
+}
+impl RateProfile for UShapedRate {
+    fn rate(&self, time: f64) -> f64 {
+        let x = 2.0 * (time % self.day_length) / self.day_length - 1.0;
+        self.trough * (1.0 + (self.edge_ratio - 1.0) * x * x)
+    }
+    fn bound(&self, time: f64) -> (f64, f64) {
+        // Falling in the morning, so the current rate bounds it until midday. Rising in the afternoon up to the close
+        let day_start = (time / self.day_length).floor() * self.day_length;
+        let midday = day_start + self.day_length / 2.0;
+        match time < midday {
+            true => (self.rate(time), midday),
+            false => (self.trough * self.edge_ratio, day_start + self.day_length),
+        }
+    }
+}
+
+fn mat_mul(a: &[Vec<f64>], b: &[Vec<f64>]) -> Vec<Vec<f64>> {
+    (0..a.len())
+        .map(|i| {
+            (0..b[0].len())
+                .map(|j| (0..b.len()).map(|k| a[i][k] * b[k][j]).sum())
+                .collect()
+        })
+        .collect()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -53,4 +457,139 @@ mod tests {
         const PRECISION: f64 = 1.0;
         assert!((total_elapsed_time - EXPECTED_RUNTIME).abs() < PRECISION);
     }
+    /// Simulates `seconds` of a Hawkes process and returns the event count of every component
+    fn hawkes_counts(rate: &mut HawkesRate, seconds: f64, rng: &mut ChaCha8Rng) -> Vec<u64> {
+        let mut counts = vec![0; rate.dimension()];
+        let mut time = 0;
+        while time < (seconds * NANOSECONDS_PER_SECOND as f64) as u64 {
+            time += rate.next_dt(rng);
+            counts[rate.last_component()] += 1;
+        }
+        counts
+    }
+
+    #[test]
+    fn hawkes_branching_ratio_matches_excitation() {
+        let rate = HawkesRate::new(
+            vec![1.0, 1.0],
+            vec![vec![0.2, 0.6], vec![0.3, 0.1]],
+            vec![vec![1.0; 2]; 2],
+        )
+        .unwrap();
+        assert!((rate.branching_ratio() - (0.3 + 0.73f64.sqrt()) / 2.0).abs() < 1e-3);
+        assert!(HawkesRate::univariate(1.0, 1.0, 1.0).is_err());
+        assert!(
+            HawkesRate::univariate(1.0, 0.0, 1.0)
+                .unwrap()
+                .branching_ratio()
+                == 0.0
+        );
+    }
+
+    #[test]
+    fn hawkes_empirical_branching_ratio() {
+        let mut rate = HawkesRate::univariate(1_000.0, 0.6, 500.0).unwrap();
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let seconds = 200.0;
+        let events = hawkes_counts(&mut rate, seconds, &mut rng)[0];
+        // Every event that is not an immigrant of the baseline was triggered by an earlier event
+        let branching_ratio = 1.0 - 1_000.0 * seconds / events as f64;
+        assert!((branching_ratio - 0.6).abs() < 0.02, "{branching_ratio}");
+    }
+
+    #[test]
+    fn hawkes_multivariate_rates_match_stationary_rates() {
+        let mut rate = HawkesRate::new(
+            vec![500.0, 100.0],
+            vec![vec![0.2, 0.6], vec![0.3, 0.1]],
+            vec![vec![200.0, 50.0], vec![100.0, 400.0]],
+        )
+        .unwrap();
+        let expected = rate.stationary_rates();
+        let mut rng = ChaCha8Rng::seed_from_u64(4);
+        let seconds = 200.0;
+        let counts = hawkes_counts(&mut rate, seconds, &mut rng);
+        for (count, expected) in counts.iter().zip(expected) {
+            let empirical = *count as f64 / seconds;
+            assert!(
+                (empirical / expected - 1.0).abs() < 0.05,
+                "{empirical} vs {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn hawkes_arrivals_cluster() {
+        // Counts in fixed windows are over-dispersed, a Poisson process has a variance to mean ratio of 1
+        let mut rate = HawkesRate::univariate(1_000.0, 0.5, 100.0).unwrap();
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        let window = NANOSECONDS_PER_SECOND / 10;
+        let mut counts = vec![0.0f64; 1_000];
+        let mut time = 0;
+        loop {
+            time += rate.next_dt(&mut rng);
+            match counts.get_mut((time / window) as usize) {
+                Some(count) => *count += 1.0,
+                None => break,
+            }
+        }
+        let mean = counts.iter().sum::<f64>() / counts.len() as f64;
+        let variance = counts.iter().map(|c| (c - mean).powi(2)).sum::<f64>() / counts.len() as f64;
+        assert!(variance / mean > 2.0, "{}", variance / mean);
+    }
+    /// Counts arrivals in consecutive windows of `window` seconds
+    fn window_counts(rate: &mut impl RateController, window: f64, windows: usize) -> Vec<u64> {
+        let mut rng = ChaCha8Rng::seed_from_u64(6);
+        let mut counts = vec![0; windows];
+        let mut time = 0;
+        loop {
+            time += rate.next_dt(&mut rng);
+            let index = (time as f64 / NANOSECONDS_PER_SECOND as f64 / window) as usize;
+            match counts.get_mut(index) {
+                Some(count) => *count += 1,
+                None => return counts,
+            }
+        }
+    }
+
+    #[test]
+    fn piecewise_rate_follows_schedule() {
+        let schedule =
+            PiecewiseRate::new(vec![(0.0, 1_000.0), (1.0, 0.0), (2.0, 5_000.0)], Some(3.0));
+        let counts = window_counts(&mut ThinnedPoissonRate::new(schedule.unwrap()), 1.0, 6);
+        assert_eq!(counts[1], 0);
+        assert_eq!(counts[4], 0);
+        for (count, expected) in [
+            (counts[0], 1_000.0),
+            (counts[2], 5_000.0),
+            (counts[5], 5_000.0),
+        ] {
+            assert!((count as f64 / expected - 1.0).abs() < 0.1, "{count}");
+        }
+        assert!(PiecewiseRate::new(vec![(1.0, 1.0)], None).is_err());
+        assert!(PiecewiseRate::new(vec![(0.0, 1.0), (1.0, 0.0)], None).is_err());
+        assert!(PiecewiseRate::new(vec![(0.0, 1.0), (1.0, 1.0)], Some(1.0)).is_err());
+        assert!(PiecewiseRate::new(vec![(0.0, 1.0)], Some(f64::INFINITY)).is_err());
+        assert!(PiecewiseRate::new(vec![(0.0, f64::INFINITY)], None).is_err());
+        assert!(PiecewiseRate::new(vec![(0.0, 1.0), (f64::NAN, 1.0)], None).is_err());
+    }
+
+    #[test]
+    fn u_shaped_rate_is_busy_at_open_and_close() {
+        let profile = UShapedRate::new(1_000.0, 4.0, 10.0).unwrap();
+        let counts = window_counts(&mut ThinnedPoissonRate::new(profile), 1.0, 10);
+        let total: u64 = counts.iter().sum();
+        assert!((total as f64 / 10_000.0 - 1.0).abs() < 0.05, "{total}");
+        assert!(counts[0] > 2 * counts[5]);
+        assert!(counts[9] > 2 * counts[4]);
+    }
+
+    #[test]
+    fn minute_volumes_are_scaled_to_mean_rate() {
+        let profile = PiecewiseRate::from_minute_volumes(&[3.0, 1.0], 100.0).unwrap();
+        assert_eq!(profile.rate(30.0), 150.0);
+        assert_eq!(profile.rate(90.0), 50.0);
+        assert_eq!(profile.rate(150.0), 150.0);
+        assert!(PiecewiseRate::from_minute_volumes(&[0.0], 100.0).is_err());
+    }
 }
@@ -0,0 +1,560 @@
+use crate::data_generator::book_view::BookView;
+use crate::data_generator::event_source::{EventSource, SourceFunction};
+use crate::data_generator::order_generators::PriceProcess;
+use crate::data_generator::order_sizes::OrderSizes;
+use crate::data_generator::rate_controllers::{
+    ConstantPoissonRate, NANOSECONDS_PER_SECOND, RateController,
+};
+use crate::simulator::SimTime;
+use clap::ValueEnum;
+use mm_core::lob_core::market_events::{ClientEvent, ClientEventType};
+use mm_core::lob_core::market_orders::{Order, OrderSide, OrderType};
+use mm_core::lob_core::{OrderId, OrderQty, Price};
+use rand::RngExt;
+use rand_chacha::ChaCha8Rng;
+use rand_distr::{Distribution, StandardNormal};
+use std::collections::VecDeque;
+
+/// Sim time between two points of the path of a Fundamental
+pub const FUNDAMENTAL_STEP: SimTime = 10_000_000;
+
+/// Stream of a run's rng that shared fundamentals are drawn from
+const FUNDAMENTAL_STREAM: u64 = 0x6675_6e64;
+
+/// Path of a latent fundamental price following a PriceProcess, sampled every FUNDAMENTAL_STEP of sim time. The path
+/// is drawn ahead of time on demand, so that its future values can be looked up, and forgotten once it is in the past
+#[derive(Clone)]
+pub struct Fundamental {
+    process: PriceProcess,
+    rng: ChaCha8Rng,
+    /// Sim time of the first point of `path`
+    start: SimTime,
+    path: VecDeque<f64>,
+}
+impl Fundamental {
+    pub fn new(initial_price: f64, process: PriceProcess, rng: ChaCha8Rng) -> Self {
+        Self {
+            process,
+            rng,
+            start: 0,
+            path: VecDeque::from([initial_price]),
+        }
+    }
+    /// Fundamental drawn from a stream of the run's rng of its own. Its path only depends on the seed, the price and the
+    /// process, so that every source of a run built from the same price and process, such as a background source and
+    /// an informed trader merged with it, follows the same fundamental
+    pub fn shared(initial_price: f64, process: PriceProcess, rng: &ChaCha8Rng) -> Self {
+        let mut rng = rng.clone();
+        rng.set_stream(FUNDAMENTAL_STREAM);
+        Self::new(initial_price, process, rng)
+    }
+    /// Value of the fundamental at `time`, which must not be before a time passed to `forget_before`
+    pub fn value(&mut self, time: SimTime) -> f64 {
+        let index = (time.saturating_sub(self.start) / FUNDAMENTAL_STEP) as usize;
+        let dt = FUNDAMENTAL_STEP as f64 / NANOSECONDS_PER_SECOND as f64;
+        while self.path.len() <= index {
+            let last = *self.path.back().unwrap();
+            // NOTE: Keep the price positive, an arithmetic walk could otherwise cross zero
+            let next = self.process.advance(last, dt, &mut self.rng).max(1.0);
+            self.path.push_back(next);
+        }
+        self.path[index]
+    }
+    /// Drops the points of the path that are no longer needed to look up `time` or later
+    pub fn forget_before(&mut self, time: SimTime) {
+        while self.path.len() > 1 && self.start + FUNDAMENTAL_STEP <= time {
+            self.path.pop_front();
+            self.start += FUNDAMENTAL_STEP;
+        }
+    }
+}
+
+/// EventSource of an informed trader who knows where the fundamental price will be `horizon` from now, blurred by
+/// gaussian noise. At every arrival it cancels its resting order, if any, then buys when its valuation is above the
+/// mid and sells when it is below. When the valuation is more than `threshold` through the opposite quote the order
+/// takes that quote, otherwise it joins its own side no closer than `threshold` to the valuation
+#[derive(Clone)]
+pub struct InformedSource {
+    fundamental: Fundamental,
+    rate_controller: ConstantPoissonRate,
+    horizon: SimTime,
+    signal_noise: f64,
+    threshold: f64,
+    sizes: OrderSizes,
+    rng: ChaCha8Rng,
+    book: BookView,
+    time: SimTime,
+    resting: Option<(OrderId, OrderSide)>,
+    trade_due: bool,
+    limit: Option<u64>,
+    count: u64,
+}
+impl InformedSource {
+    /// `rate` is the number of decisions per second, `horizon` how far ahead the trader sees and `signal_noise` the
+    /// standard deviation in cents of the error of its valuation
+    pub fn new(
+        fundamental: Fundamental,
+        rate: f64,
+        horizon: SimTime,
+        signal_noise: f64,
+        threshold: f64,
+        rng: ChaCha8Rng,
+        limit: Option<u64>,
+    ) -> Result<Self, String> {
+        if !(signal_noise >= 0.0 && threshold >= 0.0) {
+            return Err("informed: the signal noise and threshold must be >= 0".into());
+        }
+        Ok(Self {
+            fundamental,
+            rate_controller: ConstantPoissonRate::new(rate),
+            horizon,
+            signal_noise,
+            threshold,
+            sizes: OrderSizes::default(),
+            rng,
+            book: BookView::default(),
+            time: 0,
+            resting: None,
+            trade_due: false,
+            limit,
+            count: 0,
+        })
+    }
+    /// Replaces the default distribution of order sizes
+    pub fn with_sizes(mut self, sizes: OrderSizes) -> Self {
+        self.sizes = sizes;
+        self
+    }
+    /// Current value of the fundamental price in cents
+    pub fn fundamental(&mut self) -> f64 {
+        self.fundamental.value(self.time)
+    }
+    fn trade(&mut self) -> Order {
+        self.fundamental.forget_before(self.time);
+        let noise: f64 = StandardNormal.sample(&mut self.rng);
+        let valuation =
+            self.fundamental.value(self.time + self.horizon) + self.signal_noise * noise;
+        let fair = self
+            .book
+            .mid()
+            .or(self.book.last_trade.map(|price| price as f64))
+            .unwrap_or_else(|| self.fundamental.value(self.time));
+        let side = match valuation >= fair {
+            true => OrderSide::Bid,
+            false => OrderSide::Ask,
+        };
+        let price = match side {
+            OrderSide::Bid => {
+                let limit = (valuation - self.threshold).floor().max(1.0) as Price;
+                match (self.book.best_ask(), self.book.best_bid()) {
+                    (Some(ask), _) if ask <= limit => ask,
+                    (_, Some(bid)) => bid.min(limit),
+                    _ => limit,
+                }
+            }
+            OrderSide::Ask => {
+                let limit = (valuation + self.threshold).ceil().max(1.0) as Price;
+                match (self.book.best_bid(), self.book.best_ask()) {
+                    (Some(bid), _) if bid >= limit => bid,
+                    (_, Some(ask)) => ask.max(limit),
+                    _ => limit,
+                }
+            }
+        };
+        let qty = self.sizes.sample(&mut self.rng);
+        Order::new(0, 0, side, self.time, OrderType::Limit { qty, price })
+    }
+}
+impl EventSource for InformedSource {
+    fn next_event(&mut self) -> Option<Order> {
+        if let Some(limit) = self.limit
+            && self.count >= limit
+        {
+            return None;
+        }
+        self.count += 1;
+        if !self.trade_due {
+            self.time += self.rate_controller.next_dt(&mut self.rng);
+            self.trade_due = true;
+            if let Some((old_id, side)) = self.resting.take() {
+                return Some(Order::new(
+                    0,
+                    0,
+                    side,
+                    self.time,
+                    OrderType::Cancel { old_id },
+                ));
+            }
+        }
+        // NOTE: Decide after the cancel has been processed so that the decision sees the book without the old order
+        self.trade_due = false;
+        Some(self.trade())
+    }
+    fn tracks_orders(&self) -> bool {
+        true
+    }
+    fn on_submitted(&mut self, order: &Order) {
+        if let OrderType::Limit { .. } = order.kind {
+            self.resting = Some((order.order_id, order.side));
+        }
+    }
+    fn on_client_event(&mut self, event: &ClientEvent) {
+        if self
+            .resting
+            .is_some_and(|(order_id, _)| order_id == event.order_id)
+            && is_gone(event.kind)
+        {
+            self.resting = None;
+        }
+    }
+    fn reads_book(&self) -> bool {
+        true
+    }
+    fn on_book(&mut self, book: &BookView) {
+        self.book.clone_from(book);
+    }
+    fn fork_source(&self) -> Option<SourceFunction> {
+        Some(SourceFunction::from_source(self.clone()))
+    }
+}
+
+/// Whether a client event about a resting order means that it left the book, or never joined it
+fn is_gone(kind: ClientEventType) -> bool {
+    matches!(
+        kind,
+        ClientEventType::Filled | ClientEventType::Canceled | ClientEventType::Rejected
+    )
+}
+
+/// Manipulative order pattern produced by a ToxicSource
+#[derive(Debug, Clone, Copy, PartialEq, ValueEnum)]
+pub enum ToxicPattern {
+    /// Bursts of limit orders joining the best quote, each canceled right after it is placed
+    QuoteStuffing,
+    /// Bursts of market orders on one side that push the price, followed by a limit order on the other side that
+    /// unwinds the position into the move and is canceled if it is still resting after as long again
+    MomentumIgnition,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    Join(OrderSide),
+    Take(OrderSide, OrderQty),
+    Unwind(OrderSide, OrderQty),
+    CancelLast,
+}
+
+/// EventSource producing episodes of a ToxicPattern. Episodes start at Poisson arrivals and are made of `burst`
+/// orders `spacing` apart, limit prices follow the best quotes of the book
+#[derive(Clone)]
+pub struct ToxicSource {
+    pattern: ToxicPattern,
+    rate_controller: ConstantPoissonRate,
+    burst: usize,
+    spacing: SimTime,
+    reference: Price,
+    sizes: OrderSizes,
+    rng: ChaCha8Rng,
+    book: BookView,
+    time: SimTime,
+    steps: VecDeque<(SimTime, Step)>,
+    last_limit: Option<(OrderId, OrderSide)>,
+    limit: Option<u64>,
+    count: u64,
+}
+impl ToxicSource {
+    /// `rate` is the number of episodes per second, `reference` the price used until the book has quotes
+    pub fn new(
+        pattern: ToxicPattern,
+        rate: f64,
+        burst: usize,
+        spacing: SimTime,
+        reference: Price,
+        rng: ChaCha8Rng,
+        limit: Option<u64>,
+    ) -> Result<Self, String> {
+        if burst == 0 {
+            return Err("toxic: bursts need at least one order".into());
+        }
+        Ok(Self {
+            pattern,
+            rate_controller: ConstantPoissonRate::new(rate),
+            burst,
+            spacing,
+            reference,
+            sizes: OrderSizes::default(),
+            rng,
+            book: BookView::default(),
+            time: 0,
+            steps: VecDeque::new(),
+            last_limit: None,
+            limit,
+            count: 0,
+        })
+    }
+    /// Replaces the default distribution of order sizes
+    pub fn with_sizes(mut self, sizes: OrderSizes) -> Self {
+        self.sizes = sizes;
+        self
+    }
+    fn plan_episode(&mut self) {
+        self.time += self.rate_controller.next_dt(&mut self.rng);
+        let side = match self.rng.random_bool(0.5) {
+            true => OrderSide::Bid,
+            false => OrderSide::Ask,
+        };
+        let mut time = self.time;
+        match self.pattern {
+            ToxicPattern::QuoteStuffing => {
+                for _ in 0..self.burst {
+                    self.steps.push_back((time, Step::Join(side)));
+                    self.steps.push_back((time, Step::CancelLast));
+                    time += self.spacing;
+                }
+            }
+            ToxicPattern::MomentumIgnition => {
+                let mut position = 0;
+                for _ in 0..self.burst {
+                    let qty = self.sizes.sample(&mut self.rng);
+                    position += qty;
+                    self.steps.push_back((time, Step::Take(side, qty)));
+                    time += self.spacing;
+                }
+                let other = match side {
+                    OrderSide::Bid => OrderSide::Ask,
+                    OrderSide::Ask => OrderSide::Bid,
+                };
+                self.steps.push_back((time, Step::Unwind(other, position)));
+                time += self.spacing * self.burst as SimTime;
+                self.steps.push_back((time, Step::CancelLast));
+            }
+        }
+        // Episodes never overlap, the next one starts after this one ends
+        self.time = time;
+    }
+    fn quote(&self, side: OrderSide) -> Price {
+        self.book
+            .best(side)
+            .or(self.book.last_trade)
+            .unwrap_or(self.reference)
+    }
+}
+impl EventSource for ToxicSource {
+    fn next_event(&mut self) -> Option<Order> {
+        if let Some(limit) = self.limit
+            && self.count >= limit
+        {
+            return None;
+        }
+        self.count += 1;
+        let (time, step) = loop {
+            if self.steps.is_empty() {
+                self.plan_episode();
+            }
+            let (time, step) = self.steps.pop_front().unwrap();
+            // Nothing is left to cancel once the last limit order was filled or rejected
+            if matches!(step, Step::CancelLast) && self.last_limit.is_none() {
+                continue;
+            }
+            break (time, step);
+        };
+        let (side, kind) = match step {
+            Step::Join(side) => (
+                side,
+                OrderType::Limit {
+                    qty: self.sizes.sample(&mut self.rng),
+                    price: self.quote(side),
+                },
+            ),
+            Step::Take(side, qty) => (side, OrderType::Market { qty }),
+            Step::Unwind(side, qty) => (
+                side,
+                OrderType::Limit {
+                    qty,
+                    price: self.quote(side),
+                },
+            ),
+            Step::CancelLast => {
+                let (old_id, side) = self.last_limit.take().unwrap();
+                (side, OrderType::Cancel { old_id })
+            }
+        };
+        Some(Order::new(0, 0, side, time, kind))
+    }
+    fn tracks_orders(&self) -> bool {
+        true
+    }
+    fn on_submitted(&mut self, order: &Order) {
+        if let OrderType::Limit { .. } = order.kind {
+            self.last_limit = Some((order.order_id, order.side));
+        }
+    }
+    fn on_client_event(&mut self, event: &ClientEvent) {
+        if self
+            .last_limit
+            .is_some_and(|(order_id, _)| order_id == event.order_id)
+            && is_gone(event.kind)
+        {
+            self.last_limit = None;
+        }
+    }
+    fn reads_book(&self) -> bool {
+        true
+    }
+    fn on_book(&mut self, book: &BookView) {
+        self.book.clone_from(book);
+    }
+    fn fork_source(&self) -> Option<SourceFunction> {
+        Some(SourceFunction::from_source(self.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mm_core::lob_core::market_events::LiquidityFlag;
+    use rand::SeedableRng;
+
+    fn book(bid: Price, ask: Price) -> BookView {
+        BookView {
+            bids: vec![(bid, 100)],
+            asks: vec![(ask, 100)],
+            total_bids: 100,
+            total_asks: 100,
+            ..BookView::default()
+        }
+    }
+    /// Takes the next order of a source and hands it back with an engine order id
+    fn submit(source: &mut impl EventSource, order_id: OrderId) -> Order {
+        let mut order = source.next_event().unwrap();
+        order.order_id = order_id;
+        source.on_submitted(&order);
+        order
+    }
+    fn gone(source: &mut impl EventSource, order_id: OrderId, kind: ClientEventType) {
+        source.on_client_event(&ClientEvent {
+            client_id: 0,
+            id: 0,
+            timestamp: 0,
+            order_id,
+            order_side: OrderSide::Bid,
+            kind,
+            liquidity_flag: LiquidityFlag::Invalid,
+        });
+    }
+
+    #[test]
+    fn fundamental_path_is_fixed_ahead_of_time() {
+        let process = PriceProcess::RandomWalk { volatility: 10.0 };
+        let mut fundamental = Fundamental::new(1000.0, process, ChaCha8Rng::seed_from_u64(0));
+        let ahead = fundamental.value(50 * FUNDAMENTAL_STEP);
+        fundamental.forget_before(40 * FUNDAMENTAL_STEP);
+        assert_eq!(fundamental.value(50 * FUNDAMENTAL_STEP), ahead);
+        assert_ne!(fundamental.value(60 * FUNDAMENTAL_STEP), ahead);
+        // Merged sources draw from other streams of the run's rng, but share the fundamental
+        let rng = ChaCha8Rng::seed_from_u64(0);
+        let mut merged_rng = rng.clone();
+        merged_rng.set_stream(1 << 32);
+        let mut shared = Fundamental::shared(1000.0, process, &rng);
+        let mut merged = Fundamental::shared(1000.0, process, &merged_rng);
+        assert_eq!(
+            shared.value(50 * FUNDAMENTAL_STEP),
+            merged.value(50 * FUNDAMENTAL_STEP)
+        );
+    }
+
+    #[test]
+    fn informed_trader_takes_when_the_edge_is_large() {
+        let informed = |value: f64| {
+            let fundamental =
+                Fundamental::new(value, PriceProcess::Fixed, ChaCha8Rng::seed_from_u64(0));
+            let mut source = InformedSource::new(
+                fundamental,
+                100.0,
+                NANOSECONDS_PER_SECOND,
+                0.0,
+                5.0,
+                ChaCha8Rng::seed_from_u64(0),
+                None,
+            )
+            .unwrap();
+            source.on_book(&book(990, 1010));
+            source
+        };
+        let mut source = informed(1050.0);
+        let order = submit(&mut source, 7);
+        assert_eq!(order.side, OrderSide::Bid);
+        assert!(matches!(order.kind, OrderType::Limit { price: 1010, .. }));
+        // The next decision first pulls the resting order
+        let cancel = source.next_event().unwrap();
+        assert_eq!(cancel.kind, OrderType::Cancel { old_id: 7 });
+        assert!(cancel.timestamp > order.timestamp);
+        // A rejected order is not pulled
+        submit(&mut source, 8);
+        gone(&mut source, 8, ClientEventType::Rejected);
+        assert!(matches!(
+            source.next_event().unwrap().kind,
+            OrderType::Limit { .. }
+        ));
+
+        let mut source = informed(994.0);
+        let order = source.next_event().unwrap();
+        assert_eq!(order.side, OrderSide::Ask);
+        assert!(matches!(order.kind, OrderType::Limit { price: 1010, .. }));
+    }
+
+    #[test]
+    fn toxic_patterns_cancel_their_own_orders() {
+        let toxic = |pattern| {
+            let mut source = ToxicSource::new(
+                pattern,
+                10.0,
+                3,
+                1_000,
+                1000,
+                ChaCha8Rng::seed_from_u64(0),
+                None,
+            )
+            .unwrap();
+            source.on_book(&book(990, 1010));
+            source
+        };
+        let mut stuffing = toxic(ToxicPattern::QuoteStuffing);
+        for order_id in 0..3 {
+            let order = submit(&mut stuffing, order_id);
+            let price = if order.side == OrderSide::Bid {
+                990
+            } else {
+                1010
+            };
+            assert!(matches!(order.kind, OrderType::Limit { price: p, .. } if p == price));
+            let cancel = stuffing.next_event().unwrap();
+            assert_eq!(cancel.kind, OrderType::Cancel { old_id: order_id });
+            assert_eq!(cancel.timestamp, order.timestamp);
+        }
+        // Orders that are already gone are not canceled
+        submit(&mut stuffing, 3);
+        gone(&mut stuffing, 3, ClientEventType::Filled);
+        assert!(matches!(
+            stuffing.next_event().unwrap().kind,
+            OrderType::Limit { .. }
+        ));
+
+        let mut ignition = toxic(ToxicPattern::MomentumIgnition);
+        let pushes: Vec<Order> = (0..3).map(|_| ignition.next_event().unwrap()).collect();
+        let side = pushes[0].side;
+        let pushed: OrderQty = pushes
+            .iter()
+            .map(|order| match order.kind {
+                OrderType::Market { qty } if order.side == side => qty,
+                _ => panic!("{order:?}"),
+            })
+            .sum();
+        let unwind = submit(&mut ignition, 9);
+        assert_ne!(unwind.side, side);
+        assert!(matches!(unwind.kind, OrderType::Limit { qty, .. } if qty == pushed));
+        let cancel = ignition.next_event().unwrap();
+        assert_eq!(cancel.kind, OrderType::Cancel { old_id: 9 });
+        assert_eq!(cancel.timestamp - unwind.timestamp, 3_000);
+    }
+}
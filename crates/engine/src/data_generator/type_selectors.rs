@@ -1,14 +1,27 @@
+use crate::data_generator::book_view::BookView;
 use mm_core::lob_core::market_orders::{OrderSide, OrderType};
-use rand::Rng;
+use rand::distr::weighted::WeightedIndex;
+use rand::{Rng, RngExt};
 use rand_distr::{Distribution, Uniform};
 
+/// Number of (side, type) states of a MarkovTypeSelector
+pub const MARKOV_STATES: usize = 8;
+/// Largest change of the log-odds of a bid from imbalance and trade flow
+const MAX_TILT: f64 = 100.0;
+
 /// Determines the type of the next event (bid/ask, new,modify,cancel)
 pub trait TypeSelector {
     /// Randomly samples some distribution and returns a Side and OrderType
     fn sample(&mut self, rng: &mut impl Rng) -> (OrderSide, OrderType);
+    /// Whether the selector reads the book, in which case `observe` is called with the current book before every order
+    fn reads_book(&self) -> bool {
+        false
+    }
+    fn observe(&mut self, _book: &BookView) {}
 }
 
 /// Type selector that selects order side and type using uniform distributions
+#[derive(Clone)]
 pub struct UniformTypeSelector {
     bid_proportion: f64,
     new_limit_cutoff: f64,
@@ -69,6 +82,285 @@ impl TypeSelector for UniformTypeSelector {
     }
 }
 
+/// Book-aware type selector of the Santa Fe zero-intelligence and Cont-Stoikov-Talreja models. Limit and market orders
+/// arrive at fixed rates, while every resting share is canceled at `cancel_rate`, so cancels become more likely as the
+/// book fills up. Arrivals are bids with probability `bid_rate`, cancels pick a side in proportion to its depth. Only
+/// the proportions of order types follow the book, the overall arrival rate is set by the rate controller
+#[derive(Clone)]
+pub struct BookRateTypeSelector {
+    bid_rate: f64,
+    limit_rate: f64,
+    market_rate: f64,
+    cancel_rate: f64,
+    total_bids: u64,
+    total_asks: u64,
+}
+impl BookRateTypeSelector {
+    pub fn new(bid_rate: f64, limit_rate: f64, market_rate: f64, cancel_rate: f64) -> Self {
+        assert!((0.0..=1.0).contains(&bid_rate));
+        assert!(limit_rate >= 0.0);
+        assert!(market_rate >= 0.0);
+        assert!(cancel_rate >= 0.0);
+        assert!(limit_rate + market_rate > 0.0);
+        Self {
+            bid_rate,
+            limit_rate,
+            market_rate,
+            cancel_rate,
+            total_bids: 0,
+            total_asks: 0,
+        }
+    }
+}
+impl TypeSelector for BookRateTypeSelector {
+    fn sample(&mut self, rng: &mut impl Rng) -> (OrderSide, OrderType) {
+        let depth = (self.total_bids + self.total_asks) as f64;
+        let cancel_rate = self.cancel_rate * depth;
+        let sample = rng.random::<f64>() * (self.limit_rate + self.market_rate + cancel_rate);
+        if sample >= self.limit_rate + self.market_rate {
+            let side = match rng.random::<f64>() * depth < self.total_bids as f64 {
+                true => OrderSide::Bid,
+                false => OrderSide::Ask,
+            };
+            return (side, OrderType::Cancel { old_id: 0 });
+        }
+        let side = match rng.random_bool(self.bid_rate) {
+            true => OrderSide::Bid,
+            false => OrderSide::Ask,
+        };
+        match sample < self.limit_rate {
+            true => (side, OrderType::Limit { qty: 0, price: 0 }),
+            false => (side, OrderType::Market { qty: 0 }),
+        }
+    }
+    fn reads_book(&self) -> bool {
+        true
+    }
+    fn observe(&mut self, book: &BookView) {
+        self.total_bids = book.total_bids;
+        self.total_asks = book.total_asks;
+    }
+}
+
+/// Order type with placeholder fields, in the order limit, market, cancel, update
+fn order_type(index: usize) -> OrderType {
+    match index {
+        0 => OrderType::Limit { qty: 0, price: 0 },
+        1 => OrderType::Market { qty: 0 },
+        2 => OrderType::Cancel { old_id: 0 },
+        _ => OrderType::Update {
+            old_id: 0,
+            qty: 0,
+            price: 0,
+        },
+    }
+}
+
+/// Type selector following a Markov chain over the 8 (side, type) pairs, so consecutive orders are correlated, e.g.
+/// a market buy is likely followed by another. States are numbered bid limit, bid market, bid cancel, bid update, then
+/// the same types for asks
+#[derive(Clone)]
+pub struct MarkovTypeSelector {
+    transitions: Vec<WeightedIndex<f64>>,
+    state: Option<usize>,
+}
+impl MarkovTypeSelector {
+    /// `transitions[i][j]` is the relative probability of moving from state i to state j. The first state is drawn
+    /// from the average of the rows
+    pub fn new(transitions: Vec<Vec<f64>>) -> Result<Self, String> {
+        if transitions.len() != MARKOV_STATES
+            || transitions.iter().any(|row| row.len() != MARKOV_STATES)
+        {
+            return Err(format!(
+                "markov: the transition matrix must be {MARKOV_STATES}x{MARKOV_STATES}"
+            ));
+        }
+        let transitions = transitions
+            .into_iter()
+            .enumerate()
+            .map(|(i, row)| {
+                WeightedIndex::new(row).map_err(|e| format!("markov: row {}: {e}", i + 1))
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            transitions,
+            state: None,
+        })
+    }
+    /// Chain that repeats the previous side with probability `persistence` and otherwise draws it with `bid_rate`.
+    /// Types are drawn independently with the given rates in the order limit, market, cancel, update. The signs of
+    /// consecutive orders then have autocorrelation `persistence`
+    pub fn side_persistence(
+        bid_rate: f64,
+        type_rates: [f64; 4],
+        persistence: f64,
+    ) -> Result<Self, String> {
+        if !(0.0..=1.0).contains(&bid_rate) || !(0.0..=1.0).contains(&persistence) {
+            return Err("markov: the bid rate and persistence must be between 0 and 1".into());
+        }
+        let type_sum: f64 = type_rates.iter().sum();
+        let side_rates = [bid_rate, 1.0 - bid_rate];
+        let transitions = (0..MARKOV_STATES)
+            .map(|from| {
+                (0..MARKOV_STATES)
+                    .map(|to| {
+                        let repeat = (from / 4 == to / 4) as u8 as f64;
+                        let side = persistence * repeat + (1.0 - persistence) * side_rates[to / 4];
+                        side * type_rates[to % 4] / type_sum
+                    })
+                    .collect()
+            })
+            .collect();
+        Self::new(transitions)
+    }
+}
+impl TypeSelector for MarkovTypeSelector {
+    fn sample(&mut self, rng: &mut impl Rng) -> (OrderSide, OrderType) {
+        let state = match self.state {
+            Some(state) => self.transitions[state].sample(rng),
+            None => {
+                let row = self.transitions[rng.random_range(0..MARKOV_STATES)].sample(rng);
+                self.transitions[row].sample(rng)
+            }
+        };
+        self.state = Some(state);
+        let side = match state / 4 {
+            0 => OrderSide::Bid,
+            _ => OrderSide::Ask,
+        };
+        (side, order_type(state % 4))
+    }
+}
+
+/// Book-aware type selector whose side follows the state of the market: the imbalance of resting quantity over the
+/// top `levels` price levels, and an exponentially weighted average of the signs of recent trades, +1 for
+/// buyer-initiated. The odds of a bid are those of `bid_rate` times `exp(imbalance_weight * imbalance + flow_weight *
+/// flow)`, so positive weights make bid-heavy books and recent buying produce more bids. Types are drawn
+/// independently with fixed rates
+#[derive(Clone)]
+pub struct ImbalanceTypeSelector {
+    bid_rate: f64,
+    types: WeightedIndex<f64>,
+    imbalance_weight: f64,
+    flow_weight: f64,
+    flow_decay: f64,
+    levels: usize,
+    imbalance: f64,
+    flow: f64,
+    trades_seen: u64,
+}
+impl ImbalanceTypeSelector {
+    /// `type_rates` are in the order limit, market, cancel, update. Every trade moves the flow average by
+    /// `flow_decay` towards its sign
+    pub fn new(
+        bid_rate: f64,
+        type_rates: [f64; 4],
+        imbalance_weight: f64,
+        flow_weight: f64,
+        flow_decay: f64,
+        levels: usize,
+    ) -> Result<Self, String> {
+        if !(0.0..=1.0).contains(&bid_rate) {
+            return Err("imbalance: the bid rate must be between 0 and 1".into());
+        }
+        if !(flow_decay > 0.0 && flow_decay <= 1.0) {
+            return Err("imbalance: the flow decay must be in (0, 1]".into());
+        }
+        if !imbalance_weight.is_finite() || !flow_weight.is_finite() || levels == 0 {
+            return Err("imbalance: the weights must be finite and levels > 0".into());
+        }
+        Ok(Self {
+            bid_rate,
+            types: WeightedIndex::new(type_rates).map_err(|e| format!("imbalance: {e}"))?,
+            imbalance_weight,
+            flow_weight,
+            flow_decay,
+            levels,
+            imbalance: 0.0,
+            flow: 0.0,
+            trades_seen: 0,
+        })
+    }
+    /// Probability that the next order is a bid, a logistic of the bid rate's log-odds tilted by imbalance and flow
+    pub fn bid_probability(&self) -> f64 {
+        // NOTE: Large weights would overflow the odds, the tilt is bounded far past where the probability saturates
+        let tilt = (self.imbalance_weight * self.imbalance + self.flow_weight * self.flow)
+            .clamp(-MAX_TILT, MAX_TILT);
+        let log_odds = (self.bid_rate / (1.0 - self.bid_rate)).ln() + tilt;
+        1.0 / (1.0 + (-log_odds).exp())
+    }
+}
+impl TypeSelector for ImbalanceTypeSelector {
+    fn sample(&mut self, rng: &mut impl Rng) -> (OrderSide, OrderType) {
+        let side = match rng.random_bool(self.bid_probability().clamp(0.0, 1.0)) {
+            true => OrderSide::Bid,
+            false => OrderSide::Ask,
+        };
+        (side, order_type(self.types.sample(rng)))
+    }
+    fn reads_book(&self) -> bool {
+        true
+    }
+    fn observe(&mut self, book: &BookView) {
+        let depth = |levels: &[(_, u64)]| -> f64 {
+            levels
+                .iter()
+                .take(self.levels)
+                .map(|(_, qty)| *qty as f64)
+                .sum()
+        };
+        let (bids, asks) = (depth(&book.bids), depth(&book.asks));
+        self.imbalance = match bids + asks > 0.0 {
+            true => (bids - asks) / (bids + asks),
+            false => 0.0,
+        };
+        // NOTE: Trades since the last order are all counted with the sign of the latest one
+        let new_trades = book.trade_count - self.trades_seen;
+        if new_trades > 0
+            && let Some(aggressor) = book.last_aggressor
+        {
+            let sign = match aggressor {
+                OrderSide::Bid => 1.0,
+                OrderSide::Ask => -1.0,
+            };
+            let keep = (1.0 - self.flow_decay).powi(new_trades.min(i32::MAX as u64) as i32);
+            self.flow = keep * self.flow + (1.0 - keep) * sign;
+        }
+        self.trades_seen = book.trade_count;
+    }
+}
+
+/// Enum holding the type selectors that can be picked at run time, so that sources stay generic over one type
+#[derive(Clone)]
+pub enum TypeSelectorEnum {
+    Uniform(UniformTypeSelector),
+    Markov(MarkovTypeSelector),
+    Imbalance(ImbalanceTypeSelector),
+}
+impl TypeSelector for TypeSelectorEnum {
+    fn sample(&mut self, rng: &mut impl Rng) -> (OrderSide, OrderType) {
+        match self {
+            TypeSelectorEnum::Uniform(this) => this.sample(rng),
+            TypeSelectorEnum::Markov(this) => this.sample(rng),
+            TypeSelectorEnum::Imbalance(this) => this.sample(rng),
+        }
+    }
+    fn reads_book(&self) -> bool {
+        match self {
+            TypeSelectorEnum::Uniform(this) => this.reads_book(),
+            TypeSelectorEnum::Markov(this) => this.reads_book(),
+            TypeSelectorEnum::Imbalance(this) => this.reads_book(),
+        }
+    }
+    fn observe(&mut self, book: &BookView) {
+        match self {
+            TypeSelectorEnum::Uniform(this) => this.observe(book),
+            TypeSelectorEnum::Markov(this) => this.observe(book),
+            TypeSelectorEnum::Imbalance(this) => this.observe(book),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -179,4 +471,115 @@ mod tests {
         assert!((update_ratio) < update_rate + PRECISION);
         assert!((update_ratio) > update_rate - PRECISION);
     }
+
+    #[test]
+    fn book_rates_cancel_in_proportion_to_depth() {
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut selector = BookRateTypeSelector::new(0.5, 1.0, 1.0, 0.01);
+        let mut counts = |selector: &mut BookRateTypeSelector| {
+            let mut cancels = [0; 2];
+            for _ in 0..100_000 {
+                if let (side, OrderType::Cancel { .. }) = selector.sample(&mut rng) {
+                    cancels[(side == OrderSide::Ask) as usize] += 1;
+                }
+            }
+            cancels
+        };
+        assert_eq!(counts(&mut selector), [0, 0]);
+        // 200 resting shares cancel at the same total rate as limit and market orders together
+        selector.observe(&BookView {
+            total_bids: 150,
+            total_asks: 50,
+            ..BookView::default()
+        });
+        let [bids, asks] = counts(&mut selector);
+        let share = (bids + asks) as f64 / 100_000.0;
+        assert!((share - 0.5).abs() < 0.01, "{share}");
+        assert!((bids as f64 / asks as f64 - 3.0).abs() < 0.1);
+    }
+
+    fn sign_autocorrelation(selector: &mut impl TypeSelector, count: usize) -> f64 {
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        let signs: Vec<f64> = (0..count)
+            .map(|_| match selector.sample(&mut rng).0 {
+                OrderSide::Bid => 1.0,
+                OrderSide::Ask => -1.0,
+            })
+            .collect();
+        let mean = signs.iter().sum::<f64>() / count as f64;
+        let variance = signs.iter().map(|s| (s - mean).powi(2)).sum::<f64>();
+        let covariance = signs
+            .windows(2)
+            .map(|pair| (pair[0] - mean) * (pair[1] - mean))
+            .sum::<f64>();
+        covariance / variance
+    }
+
+    #[test]
+    fn markov_chain_follows_transitions() {
+        let mut cycle = vec![vec![0.0; MARKOV_STATES]; MARKOV_STATES];
+        for (from, row) in cycle.iter_mut().enumerate() {
+            row[(from + 4) % MARKOV_STATES] = 1.0;
+        }
+        let mut selector = MarkovTypeSelector::new(cycle).unwrap();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        // Every order repeats the type of the previous one on the other side
+        let first = selector.sample(&mut rng);
+        let second = selector.sample(&mut rng);
+        assert_ne!(first.0, second.0);
+        assert_eq!(first.1, second.1);
+        assert_eq!(selector.sample(&mut rng), first);
+        assert!(MarkovTypeSelector::new(vec![vec![1.0; 7]; 8]).is_err());
+
+        let mut sticky =
+            MarkovTypeSelector::side_persistence(0.5, [0.5, 0.2, 0.2, 0.1], 0.7).unwrap();
+        let correlation = sign_autocorrelation(&mut sticky, 200_000);
+        assert!((correlation - 0.7).abs() < 0.01, "{correlation}");
+        let mut independent = UniformTypeSelector::new(0.5, 0.5, 0.2, 0.2, 0.1);
+        assert!(sign_autocorrelation(&mut independent, 200_000).abs() < 0.01);
+    }
+
+    #[test]
+    fn imbalance_and_trade_flow_tilt_the_side() {
+        let mut selector =
+            ImbalanceTypeSelector::new(0.5, [1.0, 0.0, 0.0, 0.0], 2.0, 1.0, 0.5, 1).unwrap();
+        assert_eq!(selector.bid_probability(), 0.5);
+        selector.observe(&BookView {
+            bids: vec![(99, 300), (98, 1_000)],
+            asks: vec![(101, 100)],
+            ..BookView::default()
+        });
+        // Imbalance (300 - 100) / 400 at the top level
+        let expected = 1.0f64.exp() / (1.0f64.exp() + 1.0);
+        assert!((selector.bid_probability() - expected).abs() < 1e-12);
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let bids = (0..100_000)
+            .filter(|_| selector.sample(&mut rng).0 == OrderSide::Bid)
+            .count() as f64
+            / 100_000.0;
+        assert!((bids - expected).abs() < 0.01, "{bids}");
+
+        // Two sells move the flow average to -0.75, which outweighs the imbalance
+        selector.observe(&BookView {
+            bids: vec![(99, 300)],
+            asks: vec![(101, 100)],
+            last_aggressor: Some(OrderSide::Ask),
+            trade_count: 2,
+            ..BookView::default()
+        });
+        let expected = 0.25f64.exp() / (0.25f64.exp() + 1.0);
+        assert!((selector.bid_probability() - expected).abs() < 1e-12);
+        assert!(ImbalanceTypeSelector::new(0.5, [1.0; 4], 1.0, 1.0, 0.0, 1).is_err());
+
+        // Weights large enough to overflow the odds saturate the probability
+        let mut selector =
+            ImbalanceTypeSelector::new(0.5, [1.0, 0.0, 0.0, 0.0], 1000.0, 0.0, 0.5, 1).unwrap();
+        selector.observe(&BookView {
+            bids: vec![(99, 300)],
+            asks: vec![(101, 100)],
+            ..BookView::default()
+        });
+        assert_eq!(selector.bid_probability(), 1.0);
+        assert_eq!(selector.sample(&mut rng).0, OrderSide::Bid);
+    }
 }
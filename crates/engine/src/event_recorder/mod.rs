@@ -1,5 +1,5 @@
 use clap::ValueEnum;
-use mm_core::lob_core::market_orders::Order;
+use mm_core::lob_core::market_orders::{ArchivedOrder, Order};
 use std::{
     fs::File,
     io::{self, BufWriter, Write},
@@ -10,24 +10,29 @@ use std::{
 pub enum RecorderType {
     Binary,
     Text,
+    /// rkyv archived orders, which can be replayed in place from a memory map
+    Archive,
 }
 
 /// Enum containing Recorders for dynamic selection of recorders
 pub enum RecorderEnum {
     Binary(BinaryRecorder),
     Text(TextRecorder),
+    Archive(ArchiveRecorder),
 }
 impl RecorderEnum {
     pub fn record_event(&mut self, order: Order) -> Result<(), Box<dyn std::error::Error>> {
         match self {
             RecorderEnum::Binary(this) => this.record_event(order),
             RecorderEnum::Text(this) => this.record_event(order),
+            RecorderEnum::Archive(this) => this.record_event(order),
         }
     }
     pub fn shutdown(&mut self) -> io::Result<()> {
         match self {
             RecorderEnum::Binary(this) => this.shutdown(),
             RecorderEnum::Text(this) => this.shutdown(),
+            RecorderEnum::Archive(this) => this.shutdown(),
         }
     }
 }
@@ -103,3 +108,48 @@ impl Recorder for TextRecorder {
         Ok(())
     }
 }
+
+/// Size of an order in an archive recording
+pub const ARCHIVED_ORDER_SIZE: usize = size_of::<ArchivedOrder>();
+
+/// Recorder that writes orders as rkyv archives. An archived order has no out-of-line data, so every record takes
+/// ARCHIVED_ORDER_SIZE bytes and the n-th order starts at n * ARCHIVED_ORDER_SIZE
+pub struct ArchiveRecorder {
+    writer: BufWriter<File>,
+    batch_size: usize,
+    current: usize,
+}
+impl ArchiveRecorder {
+    pub fn new(path: &str, batch_size: usize) -> io::Result<Self> {
+        let file = File::create(path)?;
+        Ok(Self {
+            writer: BufWriter::new(file),
+            batch_size,
+            current: 0,
+        })
+    }
+}
+impl Recorder for ArchiveRecorder {
+    fn record_event(&mut self, order: Order) -> Result<(), Box<dyn std::error::Error>> {
+        let bytes = rkyv::to_bytes::<rkyv::rancor::Error>(&order)?;
+        // NOTE: Replays find records by offset, a record of another size would misalign every later one
+        if bytes.len() != ARCHIVED_ORDER_SIZE {
+            return Err(format!(
+                "archive: order archived to {} bytes instead of {ARCHIVED_ORDER_SIZE}",
+                bytes.len()
+            )
+            .into());
+        }
+        self.writer.write_all(&bytes)?;
+        self.current += 1;
+        if self.current == self.batch_size {
+            self.current = 0;
+            self.writer.flush()?;
+        }
+        Ok(())
+    }
+    fn shutdown(&mut self) -> io::Result<()> {
+        self.writer.flush()?;
+        Ok(())
+    }
+}
@@ -0,0 +1,596 @@
+use crate::data_generator::event_source::{EventSource, FileReplaySource};
+use crate::data_generator::mmap_replay::MmapReplaySource;
+use crate::session::SessionCalendar;
+use crate::simulator::Simulator;
+use crate::simulator::latency_config::{LatencyConfig, SimJitter};
+use crate::simulator::scheduler::{MarketPhase, SimEvent};
+use mm_core::lob_core::{
+    market_events::{
+        ClientEvent, ClientEventType, EventSink, L3EventExtra, LiquidityFlag, MarketEvent,
+        MarketEventType, NullFeeds,
+    },
+    market_orders::{Order, OrderSide, OrderType},
+};
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use ringbuf::{HeapRb, traits::*};
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+
+const FNV_OFFSET_BASIS: u64 = 0xcbf29ce484222325;
+const FNV_PRIME: u64 = 0x100000001b3;
+/// Magic bytes at the start of a fingerprint trail file
+const TRAIL_MAGIC: &[u8; 4] = b"MMFP";
+const TRAIL_ENTRY_SIZE: usize = 9;
+
+/// Rolling 64-bit FNV-1a hash over every processed order and every emitted market and client event. Fields are hashed
+/// in a fixed little-endian encoding, so the fingerprint of a run only depends on its content
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Fingerprint {
+    hash: u64,
+    count: u64,
+}
+impl Default for Fingerprint {
+    fn default() -> Self {
+        Self {
+            hash: FNV_OFFSET_BASIS,
+            count: 0,
+        }
+    }
+}
+impl Fingerprint {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Current value of the hash
+    pub fn value(&self) -> u64 {
+        self.hash
+    }
+    /// Number of entries hashed so far
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+    /// Mixes an entry into the hash and returns the new value
+    pub fn push(&mut self, entry: &TrailEvent) -> u64 {
+        self.count += 1;
+        self.write(&[entry.kind() as u8]);
+        match entry {
+            TrailEvent::Order(order) => self.write(&order.to_bytes()),
+            TrailEvent::Market(event) => {
+                self.write_u64(event.id as u64);
+                self.write_u64(event.timestamp);
+                match event.kind {
+                    MarketEventType::L3(l3) => {
+                        self.write_u64(0);
+                        self.write_u64(l3.order_id);
+                        self.write_side(l3.side);
+                        self.write_u64(l3.timestamp);
+                        self.write_order_type(l3.kind);
+                        match l3.extra {
+                            L3EventExtra::Cancel(qty) => self.write_u64(qty as u64),
+                            L3EventExtra::None => self.write_u64(u64::MAX),
+                        }
+                    }
+                    MarketEventType::Trade(trade) => {
+                        self.write_u64(1);
+                        self.write_u64(trade.price as u64);
+                        self.write_u64(trade.quantity as u64);
+                        self.write_side(trade.aggressor_side);
+                        self.write_u64(trade.maker_id);
+                    }
+                    MarketEventType::System(code) => {
+                        self.write_u64(2);
+                        self.write(&[code as u8]);
+                    }
+                }
+            }
+            TrailEvent::Client(event) => {
+                self.write_u64(event.id);
+                self.write_u64(event.timestamp);
+                self.write_u64(event.client_id);
+                self.write_u64(event.order_id);
+                self.write_side(event.order_side);
+                let (tag, qty) = match event.kind {
+                    ClientEventType::Accepted(qty) => (0, qty),
+                    ClientEventType::Rejected => (1, 0),
+                    ClientEventType::Updated => (2, 0),
+                    ClientEventType::Canceled => (3, 0),
+                    ClientEventType::PartiallyFilled(qty) => (4, qty),
+                    ClientEventType::Filled => (5, 0),
+                };
+                self.write_u64(tag);
+                self.write_u64(qty as u64);
+                self.write_u64(match event.liquidity_flag {
+                    LiquidityFlag::Maker => 0,
+                    LiquidityFlag::Taker => 1,
+                    LiquidityFlag::Invalid => 2,
+                });
+            }
+        }
+        self.hash
+    }
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.hash ^= *byte as u64;
+            self.hash = self.hash.wrapping_mul(FNV_PRIME);
+        }
+    }
+    fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+    fn write_side(&mut self, side: OrderSide) {
+        self.write(&[side as u8]);
+    }
+    fn write_order_type(&mut self, kind: OrderType) {
+        match kind {
+            OrderType::Limit { qty, price } => {
+                self.write_u64(0);
+                self.write_u64(qty as u64);
+                self.write_u64(price as u64);
+            }
+            OrderType::Market { qty } => {
+                self.write_u64(1);
+                self.write_u64(qty as u64);
+            }
+            OrderType::Update { old_id, qty, price } => {
+                self.write_u64(2);
+                self.write_u64(old_id);
+                self.write_u64(qty as u64);
+                self.write_u64(price as u64);
+            }
+            OrderType::Cancel { old_id } => {
+                self.write_u64(3);
+                self.write_u64(old_id);
+            }
+        }
+    }
+}
+
+/// Kind of a fingerprinted entry, stored in the trail next to the hash. Rejections are kept apart from other client
+/// events so that a replay can tell which orders the Simulator refused before they reached the book
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrailKind {
+    Order = 0,
+    MarketEvent = 1,
+    ClientEvent = 2,
+    Rejection = 3,
+}
+impl TryFrom<u8> for TrailKind {
+    type Error = String;
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        match value {
+            0 => Ok(TrailKind::Order),
+            1 => Ok(TrailKind::MarketEvent),
+            2 => Ok(TrailKind::ClientEvent),
+            3 => Ok(TrailKind::Rejection),
+            _ => Err(format!("invalid trail entry kind {}", value)),
+        }
+    }
+}
+
+/// Entry of a run that is mixed into its fingerprint
+#[derive(Debug, Clone, Copy)]
+pub enum TrailEvent {
+    Order(Order),
+    Market(MarketEvent),
+    Client(ClientEvent),
+}
+impl TrailEvent {
+    pub fn kind(&self) -> TrailKind {
+        match self {
+            TrailEvent::Order(_) => TrailKind::Order,
+            TrailEvent::Market(_) => TrailKind::MarketEvent,
+            TrailEvent::Client(event) if event.kind == ClientEventType::Rejected => {
+                TrailKind::Rejection
+            }
+            TrailEvent::Client(_) => TrailKind::ClientEvent,
+        }
+    }
+}
+
+/// Hash of the run after an entry, and the kind of that entry
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TrailEntry {
+    pub kind: TrailKind,
+    pub hash: u64,
+}
+
+/// EventSink wrapper that mixes every event into a fingerprint before forwarding it. When the trail is enabled the
+/// hashed entries are also kept until drained, so that they can be written next to a recording
+pub struct FingerprintFeed<S: EventSink> {
+    inner: S,
+    fingerprint: Fingerprint,
+    keep_trail: bool,
+    trail: Vec<(TrailEvent, TrailEntry)>,
+}
+impl<S: EventSink> FingerprintFeed<S> {
+    pub fn new(inner: S, keep_trail: bool) -> Self {
+        Self {
+            inner,
+            fingerprint: Fingerprint::new(),
+            keep_trail,
+            trail: Vec::new(),
+        }
+    }
+    pub fn fingerprint(&self) -> Fingerprint {
+        self.fingerprint
+    }
+    /// Mixes a processed order into the fingerprint. The Simulator returns orders after processing them, so an
+    /// order is hashed after the events it caused
+    pub fn push_order(&mut self, order: Order) {
+        self.push(TrailEvent::Order(order));
+    }
+    /// Removes and returns the entries hashed since the last call
+    pub fn drain_trail(&mut self) -> std::vec::Drain<'_, (TrailEvent, TrailEntry)> {
+        self.trail.drain(..)
+    }
+    fn push(&mut self, event: TrailEvent) {
+        let hash = self.fingerprint.push(&event);
+        if self.keep_trail {
+            self.trail.push((
+                event,
+                TrailEntry {
+                    kind: event.kind(),
+                    hash,
+                },
+            ));
+        }
+    }
+}
+impl<S: EventSink> EventSink for FingerprintFeed<S> {
+    fn push_event(&mut self, event: MarketEvent) {
+        self.push(TrailEvent::Market(event));
+        self.inner.push_event(event);
+    }
+    fn push_client_event(&mut self, event: ClientEvent) {
+        self.push(TrailEvent::Client(event));
+        self.inner.push_client_event(event);
+    }
+}
+
+/// Writes a fingerprint trail, the hash of the run after every entry. Stored next to a binary recording so that the
+/// recording can be verified by replaying it
+pub struct TrailWriter {
+    writer: BufWriter<File>,
+}
+impl TrailWriter {
+    pub fn new(path: &str) -> io::Result<Self> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writer.write_all(TRAIL_MAGIC)?;
+        Ok(Self { writer })
+    }
+    pub fn write(&mut self, entry: TrailEntry) -> io::Result<()> {
+        self.writer.write_all(&[entry.kind as u8])?;
+        self.writer.write_all(&entry.hash.to_le_bytes())
+    }
+    pub fn shutdown(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Reads a fingerprint trail written by TrailWriter
+pub struct TrailReader {
+    reader: BufReader<File>,
+}
+impl TrailReader {
+    pub fn new(path: &str) -> Result<Self, String> {
+        let file = File::open(path).map_err(|e| format!("{}: {}", path, e))?;
+        let mut reader = BufReader::new(file);
+        let mut magic = [0u8; 4];
+        reader
+            .read_exact(&mut magic)
+            .map_err(|e| format!("{}: {}", path, e))?;
+        if &magic != TRAIL_MAGIC {
+            return Err(format!("{}: not a fingerprint trail", path));
+        }
+        Ok(Self { reader })
+    }
+    /// Next entry, None at the end of the trail
+    pub fn next_entry(&mut self) -> Result<Option<TrailEntry>, String> {
+        let mut buf = [0u8; TRAIL_ENTRY_SIZE];
+        if self.reader.read_exact(&mut buf).is_err() {
+            return Ok(None);
+        }
+        Ok(Some(TrailEntry {
+            kind: TrailKind::try_from(buf[0])?,
+            hash: u64::from_le_bytes(buf[1..].try_into().unwrap()),
+        }))
+    }
+}
+
+/// Path of the fingerprint trail stored next to a recording
+pub fn trail_path(recording: &str) -> String {
+    format!("{}.fp", recording)
+}
+
+/// First entry where a replay differs from the stored trail
+#[derive(Debug, Clone)]
+pub struct Divergence {
+    /// Zero-based index of the entry in the trail
+    pub index: u64,
+    pub expected: Option<TrailEntry>,
+    pub actual: Option<(TrailEvent, TrailEntry)>,
+}
+
+/// Outcome of verifying a recording against its trail
+#[derive(Debug, Clone)]
+pub struct Verification {
+    pub fingerprint: Fingerprint,
+    pub divergence: Option<Divergence>,
+}
+
+/// Replays a binary recording through a fresh Simulator and compares every processed order and emitted event with the
+/// stored trail, stopping at the first divergence. Orders keep their recorded engine ids.
+/// Scripted halts are not part of the recording: an order the trail shows as a lone rejection halts the replayed
+/// market, and the next order that was not rejected resumes it, so that the Simulator itself rejects the orders of a
+/// halt. Runs with a trading session are verified by passing the same `session`, whose phases, system events and
+/// auction uncrosses are replayed by the Simulator
+pub fn verify_recording(
+    recording: &str,
+    trail: &str,
+    session: Option<SessionCalendar>,
+) -> Result<Verification, String> {
+    let source =
+        FileReplaySource::new(recording, 512).map_err(|e| format!("{}: {}", recording, e))?;
+    verify_source(source, trail, session)
+}
+
+/// Verifies a recording made with --record archive like `verify_recording`
+pub fn verify_archive(
+    recording: &str,
+    trail: &str,
+    session: Option<SessionCalendar>,
+) -> Result<Verification, String> {
+    verify_source(MmapReplaySource::open(recording)?, trail, session)
+}
+
+fn verify_source<E: EventSource>(
+    source: E,
+    trail: &str,
+    session: Option<SessionCalendar>,
+) -> Result<Verification, String> {
+    let mut trail = TrailReader::new(trail)?;
+    let (_, user_orders) = HeapRb::<Order>::new(1).split();
+    let mut sim = Simulator::new(
+        source,
+        FingerprintFeed::new(NullFeeds {}, true),
+        user_orders,
+        LatencyConfig {
+            latency: 0,
+            jitter: SimJitter::None,
+        },
+        ChaCha8Rng::seed_from_u64(0),
+        false,
+    );
+    sim.set_replay(true);
+    if let Some(session) = session {
+        sim.set_session(session);
+    }
+    let mut index: u64 = 0;
+    let mut expected: Vec<TrailEntry> = Vec::new();
+    loop {
+        expected.clear();
+        while let Some(entry) = trail.next_entry()? {
+            expected.push(entry);
+            if entry.kind == TrailKind::Order {
+                break;
+            }
+        }
+        let rejected =
+            matches!(expected[..], [rejection, _] if rejection.kind == TrailKind::Rejection);
+        match (rejected, sim.phase()) {
+            (true, MarketPhase::Continuous) => {
+                sim.schedule(sim.time(), SimEvent::Phase(MarketPhase::Halted));
+            }
+            (false, MarketPhase::Halted) => {
+                sim.schedule(sim.time(), SimEvent::Phase(MarketPhase::Continuous));
+            }
+            _ => {}
+        }
+        let Ok(order) = sim.step() else {
+            break;
+        };
+        let feed = sim.event_sink_mut();
+        feed.push_order(order);
+        let actual: Vec<_> = feed.drain_trail().collect();
+        for i in 0..actual.len().max(expected.len()) {
+            if actual.get(i).map(|(_, entry)| *entry) != expected.get(i).copied() {
+                return Ok(Verification {
+                    fingerprint: sim.event_sink_mut().fingerprint(),
+                    divergence: Some(Divergence {
+                        index: index + i as u64,
+                        expected: expected.get(i).copied(),
+                        actual: actual.get(i).copied(),
+                    }),
+                });
+            }
+        }
+        index += actual.len() as u64;
+    }
+    let fingerprint = sim.event_sink_mut().fingerprint();
+    let divergence = expected.first().map(|entry| Divergence {
+        index,
+        expected: Some(*entry),
+        actual: None,
+    });
+    Ok(Verification {
+        fingerprint,
+        divergence,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_generator::event_source::SourceFunction;
+    use crate::event_recorder::{ArchiveRecorder, BinaryRecorder, Recorder};
+    use crate::session::Date;
+    use crate::simulator::SimTime;
+
+    fn limit(order_id: u64, side: OrderSide, price: u32, timestamp: u64) -> Order {
+        Order::new(
+            1,
+            order_id,
+            side,
+            timestamp,
+            OrderType::Limit { qty: 5, price },
+        )
+    }
+
+    /// Runs orders through a Simulator like the engine does, with scripted phase changes and an optional session, and
+    /// writes the recording and its trail
+    fn record(
+        dir: &std::path::Path,
+        orders: &[Order],
+        phases: &[(SimTime, MarketPhase)],
+        session: Option<SessionCalendar>,
+    ) -> (String, String) {
+        let recording = dir.join("run.mm").to_str().unwrap().to_string();
+        let trail = trail_path(&recording);
+        let mut recorder = BinaryRecorder::new(&recording, 1).unwrap();
+        let mut writer = TrailWriter::new(&trail).unwrap();
+        let (orders, mut next) = (orders.to_vec(), 0);
+        let (_, user_orders) = HeapRb::<Order>::new(1).split();
+        let mut sim = Simulator::new(
+            SourceFunction::new(Box::new(move || {
+                next += 1;
+                orders.get(next - 1).copied()
+            })),
+            FingerprintFeed::new(NullFeeds {}, true),
+            user_orders,
+            LatencyConfig {
+                latency: 0,
+                jitter: SimJitter::None,
+            },
+            ChaCha8Rng::seed_from_u64(0),
+            false,
+        );
+        if let Some(session) = session {
+            sim.set_session(session);
+        }
+        for (time, phase) in phases {
+            sim.schedule(*time, SimEvent::Phase(*phase));
+        }
+        while let Ok(order) = sim.step() {
+            sim.event_sink_mut().push_order(order);
+            for (_, entry) in sim.event_sink_mut().drain_trail() {
+                writer.write(entry).unwrap();
+            }
+            recorder.record_event(order).unwrap();
+        }
+        writer.shutdown().unwrap();
+        recorder.shutdown().unwrap();
+        (recording, trail)
+    }
+
+    fn temp_dir(name: &str) -> std::path::PathBuf {
+        // NOTE: The process id keeps concurrent test runs from sharing files
+        let dir =
+            std::env::temp_dir().join(format!("mm_fingerprint_{}_{}", std::process::id(), name));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn fingerprint_depends_on_content_and_order() {
+        let a = TrailEvent::Order(limit(0, OrderSide::Bid, 100, 0));
+        let b = TrailEvent::Order(limit(1, OrderSide::Ask, 101, 1));
+        let mut first = Fingerprint::new();
+        first.push(&a);
+        first.push(&b);
+        let mut second = Fingerprint::new();
+        second.push(&b);
+        second.push(&a);
+        assert_ne!(first.value(), second.value());
+        let mut again = Fingerprint::new();
+        again.push(&a);
+        again.push(&b);
+        assert_eq!(first, again);
+        assert_eq!(first.count(), 2);
+    }
+
+    #[test]
+    fn recording_verifies_against_its_trail() {
+        let dir = temp_dir("verifies");
+        let orders = [
+            limit(0, OrderSide::Bid, 100, 0),
+            limit(1, OrderSide::Ask, 105, 1),
+            limit(2, OrderSide::Ask, 100, 2),
+            limit(3, OrderSide::Bid, 106, 3),
+            limit(4, OrderSide::Bid, 106, 5),
+        ];
+        // The market is halted for order 3 and resumes before order 4
+        let phases = [(3, MarketPhase::Halted), (4, MarketPhase::Continuous)];
+        let (recording, trail) = record(&dir, &orders, &phases, None);
+        let verification = verify_recording(&recording, &trail, None).unwrap();
+        assert!(verification.divergence.is_none());
+        assert!(verification.fingerprint.count() > orders.len() as u64);
+        // The same orders archived verify against the same trail
+        let archive = dir.join("run.mma").to_str().unwrap().to_string();
+        let mut recorder = ArchiveRecorder::new(&archive, 1).unwrap();
+        let mut source = FileReplaySource::new(&recording, 512).unwrap();
+        while let Some(order) = source.next_event() {
+            recorder.record_event(order).unwrap();
+        }
+        recorder.shutdown().unwrap();
+        let archived = verify_archive(&archive, &trail, None).unwrap();
+        assert!(archived.divergence.is_none());
+        assert_eq!(archived.fingerprint, verification.fingerprint);
+    }
+
+    #[test]
+    fn sessions_are_replayed_by_the_simulator() {
+        let dir = temp_dir("session");
+        // Friday session compressed into nanoseconds, sim time 0 is midnight
+        let mut session = SessionCalendar::new(Date::parse("2024-03-01").unwrap(), 0);
+        (
+            session.pre_open,
+            session.opening_auction,
+            session.market_open,
+        ) = (10, 20, 30);
+        (
+            session.closing_auction,
+            session.market_close,
+            session.end_of_day,
+        ) = (40, 50, 60);
+        let orders = [
+            limit(0, OrderSide::Bid, 150, 5),
+            limit(1, OrderSide::Bid, 150, 25),
+            limit(2, OrderSide::Ask, 140, 26),
+            limit(3, OrderSide::Ask, 150, 35),
+        ];
+        let (recording, trail) = record(&dir, &orders, &[], Some(session));
+        let verification = verify_recording(&recording, &trail, Some(session)).unwrap();
+        assert!(verification.divergence.is_none());
+        // Without the session the system events and the auction are missing
+        let verification = verify_recording(&recording, &trail, None).unwrap();
+        assert!(verification.divergence.is_some());
+    }
+
+    #[test]
+    fn verification_reports_first_divergent_event() {
+        let dir = temp_dir("diverges");
+        let orders = [
+            limit(0, OrderSide::Bid, 100, 0),
+            limit(1, OrderSide::Ask, 100, 1),
+        ];
+        let (recording, trail) = record(&dir, &orders, &[], None);
+        // Rewrite the recording so that the second order no longer crosses the first
+        let mut recorder = BinaryRecorder::new(&recording, 1).unwrap();
+        recorder.record_event(orders[0]).unwrap();
+        recorder
+            .record_event(limit(1, OrderSide::Ask, 101, 1))
+            .unwrap();
+        recorder.shutdown().unwrap();
+        let divergence = verify_recording(&recording, &trail, None)
+            .unwrap()
+            .divergence
+            .unwrap();
+        // The first order adds three entries, and the second is accepted the same way before it rests instead of trading
+        assert_eq!(divergence.index, 4);
+        let (event, _) = divergence.actual.unwrap();
+        assert!(matches!(event, TrailEvent::Market(_)));
+        assert_eq!(divergence.expected.unwrap().kind, TrailKind::MarketEvent);
+    }
+}
@@ -1 +1,14 @@
+pub mod agents;
+pub mod backtest;
+pub mod batch;
+pub mod calibration;
+pub mod data_generator;
+pub mod event_recorder;
+pub mod fingerprint;
 pub mod limit_order_book;
+pub mod order_script;
+pub mod scenario;
+pub mod session;
+pub mod simulator;
+pub mod stylized_facts;
+pub mod sweep;
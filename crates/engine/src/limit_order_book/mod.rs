@@ -2,7 +2,7 @@ use mm_core::lob_core::{
     OrderId, OrderQty, Price, Timestamp,
     market_events::{
         ClientEvent, ClientEventType, EventSink, L3Event, LiquidityFlag, MarketEvent,
-        MarketEventType, TradeEvent,
+        MarketEventType, SystemEventCode, TradeEvent,
     },
     market_orders::{LimitOrder, Order, OrderSide, OrderStatus, OrderType},
 };
@@ -10,7 +10,7 @@ use std::collections::{BTreeMap, HashMap, VecDeque};
 
 /// Struct representing a single price level within a limit order book. Contains a FIFO queue for order IDs, which should
 /// be used to look up orders from a table
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct PriceLevel {
     pub total_qty: u64,
     orders: VecDeque<OrderId>,
@@ -105,19 +105,78 @@ impl<T: EventSink> OrderBook<T> {
         // TODO: Update return type to be more informative
         let time = order.timestamp;
         let order: Option<LimitOrder> = match order.kind {
-            OrderType::Limit { .. } => self.add_order_and_emit_events(order, time),
+            OrderType::Limit { .. } => self.add_order_and_emit_events(order, time, true),
             OrderType::Market { .. } => self.execute_market_order_and_emit_events(order, time),
             OrderType::Cancel { old_id } => self.cancel_order_and_emit_events(old_id, order, time),
             OrderType::Update {
                 old_id,
                 qty: _,
                 price: _,
-            } => self.update_order_and_emit_events(old_id, order, time),
+            } => self.update_order_and_emit_events(old_id, order, time, true),
         };
         self.update_aggregates();
         order
     }
 
+    /// Copies the full state of the book into a new book that emits its events to another sink
+    pub fn fork<U: EventSink>(&self, event_sink: U) -> OrderBook<U> {
+        OrderBook {
+            orders: self.orders.clone(),
+            bid_orders: self.bid_orders.clone(),
+            ask_orders: self.ask_orders.clone(),
+            event_sink,
+            best_bid: self.best_bid,
+            best_ask: self.best_ask,
+            total_asks: self.total_asks,
+            total_bids: self.total_bids,
+            market_event_counter: self.market_event_counter,
+            client_event_counter: self.client_event_counter,
+        }
+    }
+
+    /// Mutable access to the event sink held by the book, used by owners that need to drain or inspect
+    /// events emitted during the last call to `process_order()`
+    pub fn event_sink_mut(&mut self) -> &mut T {
+        &mut self.event_sink
+    }
+
+    /// Iterates over all active limit orders resting in the book, in no particular order
+    pub fn resting_orders(&self) -> impl Iterator<Item = &LimitOrder> {
+        self.orders
+            .values()
+            .filter(|order| order.status == OrderStatus::Active && order.qty > 0)
+    }
+
+    /// Best bid and best ask as of the end of the last call to `process_order()`
+    pub fn bbo(&self) -> (Option<Price>, Option<Price>) {
+        (
+            Some(self.best_bid).filter(|price| *price != 0),
+            Some(self.best_ask).filter(|price| *price != 0),
+        )
+    }
+
+    /// Price and resting quantity of the non-empty bid levels, best first
+    pub fn bid_levels(&self) -> impl Iterator<Item = (Price, u64)> + '_ {
+        self.bid_orders
+            .iter()
+            .rev()
+            .filter(|(_, level)| level.total_qty > 0)
+            .map(|(price, level)| (*price, level.total_qty))
+    }
+
+    /// Price and resting quantity of the non-empty ask levels, best first
+    pub fn ask_levels(&self) -> impl Iterator<Item = (Price, u64)> + '_ {
+        self.ask_orders
+            .iter()
+            .filter(|(_, level)| level.total_qty > 0)
+            .map(|(price, level)| (*price, level.total_qty))
+    }
+
+    /// Total resting quantity of the bid and ask side
+    pub fn total_depth(&self) -> (u64, u64) {
+        (self.total_bids, self.total_asks)
+    }
+
     /// Prunes lazily removed bid orders and returns the current best bid
     /// Does not update the cached value of best bid
     pub fn best_bid(&mut self) -> Option<Price> {
@@ -170,11 +229,13 @@ impl<T: EventSink> OrderBook<T> {
 
     /// Executes a trade if a valid match can be made, see match_order() for details about matching.
     /// Adds an order to the side of the book specified in the order if any of the order's quantity is unmatched.
+    /// Without `matching` the whole order rests, even if it crosses the book.
     /// Possibly emits MarketEvents
     fn add_order_and_emit_events(
         &mut self,
         original_order: Order,
         time: Timestamp,
+        matching: bool,
     ) -> Option<LimitOrder> {
         let mut order = LimitOrder::new(original_order);
         if order.qty == 0 {
@@ -182,7 +243,9 @@ impl<T: EventSink> OrderBook<T> {
             return None;
         }
         self.accept_order(original_order, time, order.qty);
-        self.match_order(&mut order, time);
+        if matching {
+            self.match_order(&mut order, time);
+        }
         if order.qty == 0 {
             return Some(order);
         }
@@ -209,7 +272,7 @@ impl<T: EventSink> OrderBook<T> {
     }
 
     /// Updates an existing order by cancelling it and replacing it with a new order. Executes
-    /// a trade if a valid match can be made
+    /// a trade if a valid match can be made and `matching` is set
     ///
     /// Emits ClientEvents for the cancellation, the new order, any trades that are made, and acknowledgement of the update
     fn update_order_and_emit_events(
@@ -217,6 +280,7 @@ impl<T: EventSink> OrderBook<T> {
         old_id: OrderId,
         order: Order,
         time: Timestamp,
+        matching: bool,
     ) -> Option<LimitOrder> {
         let old_order = match self.orders.get_mut(&old_id) {
             Some(old_order) => old_order,
@@ -264,7 +328,9 @@ impl<T: EventSink> OrderBook<T> {
 
         // Adding the new
         let mut order: LimitOrder = LimitOrder::new(order);
-        self.match_order(&mut order, time);
+        if matching {
+            self.match_order(&mut order, time);
+        }
         if order.qty == 0 {
             return Some(order);
         }
@@ -410,6 +476,7 @@ impl<T: EventSink> OrderBook<T> {
                 let trade_volume: OrderQty = std::cmp::min(maker.qty, taker.qty);
                 maker.qty -= trade_volume;
                 taker.qty -= trade_volume;
+                level.total_qty -= trade_volume as u64;
 
                 event_sink.push_event(MarketEvent::new(
                     *market_event_counter,
@@ -466,8 +533,176 @@ impl<T: EventSink> OrderBook<T> {
         }
     }
 
+    /// Runs a call auction over the orders collected during an auction phase. Limit orders and updates join the book in
+    /// arrival order without matching, cancels apply at once. A single clearing price is then picked: the price that
+    /// maximizes the executed volume, then minimizes the volume left unmatched at it, then the lowest such price.
+    /// Market orders, bids at or above it and asks at or below it all trade at that price, market orders first and
+    /// then in price-time priority, until one side runs out. Of two orders that trade, the one submitted last is
+    /// reported as the aggressor. Returns the clearing price, None if nothing crosses
+    pub fn uncross(&mut self, orders: &[Order], time: Timestamp) -> Option<Price> {
+        let mut market_orders = Vec::new();
+        for order in orders {
+            match order.kind {
+                OrderType::Limit { .. } => {
+                    self.add_order_and_emit_events(*order, time, false);
+                }
+                OrderType::Update { old_id, .. } => {
+                    self.update_order_and_emit_events(old_id, *order, time, false);
+                }
+                OrderType::Cancel { old_id } => {
+                    self.cancel_order_and_emit_events(old_id, *order, time);
+                }
+                OrderType::Market { qty: 0 } => self.reject_order(*order, time),
+                OrderType::Market { .. } => market_orders.push(LimitOrder::new(*order)),
+            }
+        }
+        let price = self.clearing_price(&market_orders);
+        if let Some(price) = price {
+            let (market_bids, market_asks): (Vec<LimitOrder>, Vec<LimitOrder>) = market_orders
+                .into_iter()
+                .partition(|order| order.side == OrderSide::Bid);
+            let bids = self.auction_side(market_bids, self.bid_orders.range(price..).rev());
+            let asks = self.auction_side(market_asks, self.ask_orders.range(..=price));
+            self.execute_auction(bids, asks, price, time);
+        }
+        self.update_aggregates();
+        price
+    }
+
+    /// Price maximizing the volume executed in a call auction over the book and the given market orders, see uncross()
+    fn clearing_price(&self, market_orders: &[LimitOrder]) -> Option<Price> {
+        let market_qty = |side: OrderSide| -> u64 {
+            market_orders
+                .iter()
+                .filter(|order| order.side == side)
+                .map(|order| order.qty as u64)
+                .sum()
+        };
+        let (market_bids, market_asks) = (market_qty(OrderSide::Bid), market_qty(OrderSide::Ask));
+        let mut best: Option<(u64, u64, Price)> = None;
+        for (price, _) in self.bid_levels().chain(self.ask_levels()) {
+            let demand = market_bids
+                + self
+                    .bid_orders
+                    .range(price..)
+                    .map(|(_, level)| level.total_qty)
+                    .sum::<u64>();
+            let supply = market_asks
+                + self
+                    .ask_orders
+                    .range(..=price)
+                    .map(|(_, level)| level.total_qty)
+                    .sum::<u64>();
+            let (volume, imbalance) = (demand.min(supply), demand.abs_diff(supply));
+            let better = match best {
+                None => volume > 0,
+                Some((best_volume, best_imbalance, best_price)) => {
+                    (volume, best_imbalance, best_price) > (best_volume, imbalance, price)
+                }
+            };
+            if better {
+                best = Some((volume, imbalance, price));
+            }
+        }
+        best.map(|(_, _, price)| price)
+    }
+
+    /// Orders of one side that take part in a call auction: its market orders in arrival order, then the active orders
+    /// of the given levels in price-time priority. Resting orders are paired with their id
+    fn auction_side<'a>(
+        &self,
+        market_orders: Vec<LimitOrder>,
+        levels: impl Iterator<Item = (&'a Price, &'a PriceLevel)>,
+    ) -> Vec<(Option<OrderId>, LimitOrder)> {
+        let mut side: Vec<(Option<OrderId>, LimitOrder)> = market_orders
+            .into_iter()
+            .map(|order| (None, order))
+            .collect();
+        for (_, level) in levels {
+            side.extend(
+                level
+                    .orders
+                    .iter()
+                    .filter_map(|id| self.orders.get(id))
+                    .filter(|order| order.status == OrderStatus::Active && order.qty > 0)
+                    .map(|order| (Some(order.order_id), *order)),
+            );
+        }
+        side
+    }
+
+    /// Trades the bids of a call auction against its asks at the clearing price, and writes the remaining quantity of
+    /// resting orders back into the book. Market orders that are not filled lapse
+    fn execute_auction(
+        &mut self,
+        mut bids: Vec<(Option<OrderId>, LimitOrder)>,
+        mut asks: Vec<(Option<OrderId>, LimitOrder)>,
+        price: Price,
+        time: Timestamp,
+    ) {
+        let (mut i, mut j) = (0, 0);
+        while i < bids.len() && j < asks.len() {
+            let (bid, ask) = (&mut bids[i].1, &mut asks[j].1);
+            let quantity = bid.qty.min(ask.qty);
+            bid.qty -= quantity;
+            ask.qty -= quantity;
+            let (taker, maker) = match bid.order_id > ask.order_id {
+                true => (*bid, *ask),
+                false => (*ask, *bid),
+            };
+            self.event_sink.push_event(MarketEvent::new(
+                self.market_event_counter,
+                time,
+                MarketEventType::Trade(TradeEvent {
+                    price,
+                    quantity,
+                    aggressor_side: taker.side,
+                    maker_id: maker.order_id,
+                }),
+            ));
+            self.market_event_counter = self.market_event_counter.wrapping_add(1);
+            self.fill_order(&taker, LiquidityFlag::Taker, time);
+            self.fill_order(&maker, LiquidityFlag::Maker, time);
+            if bids[i].1.qty == 0 {
+                i += 1;
+            }
+            if asks[j].1.qty == 0 {
+                j += 1;
+            }
+        }
+        for (id, order) in bids.iter().chain(&asks) {
+            let Some(resting) = id.and_then(|id| self.orders.get_mut(&id)) else {
+                continue;
+            };
+            let level = match resting.side {
+                OrderSide::Bid => self.bid_orders.get_mut(&resting.price),
+                OrderSide::Ask => self.ask_orders.get_mut(&resting.price),
+            };
+            // NOTE: The level exists, the order is resting in it
+            level.unwrap().total_qty -= (resting.qty - order.qty) as u64;
+            resting.qty = order.qty;
+        }
+    }
+
+    /// Emits the client event of an order that traded, a fill or a partial fill with its remaining quantity
+    fn fill_order(&mut self, order: &LimitOrder, liquidity_flag: LiquidityFlag, time: Timestamp) {
+        self.event_sink.push_client_event(ClientEvent {
+            client_id: order.client_id,
+            id: self.client_event_counter,
+            timestamp: time,
+            order_id: order.order_id,
+            order_side: order.side,
+            kind: match order.qty == 0 {
+                true => ClientEventType::Filled,
+                false => ClientEventType::PartiallyFilled(order.qty),
+            },
+            liquidity_flag,
+        });
+        self.client_event_counter += 1;
+    }
+
     /// Gets the total quantity at a given price level
-    fn get_qty(&self, price: Price, side: OrderSide) -> u64 {
+    pub fn get_qty(&self, price: Price, side: OrderSide) -> u64 {
         match side {
             OrderSide::Ask => self
                 .ask_orders
@@ -503,8 +738,18 @@ impl<T: EventSink> OrderBook<T> {
         self.best_bid = self.best_bid().unwrap_or(0);
     }
 
-    /// Emits a client event rejecting an order
-    fn reject_order(&mut self, order: Order, time: Timestamp) {
+    /// Emits a session-wide system event on the market data feed
+    pub fn publish_system_event(&mut self, code: SystemEventCode, time: Timestamp) {
+        self.event_sink.push_event(MarketEvent::new(
+            self.market_event_counter,
+            time,
+            MarketEventType::System(code),
+        ));
+        self.market_event_counter = self.market_event_counter.wrapping_add(1);
+    }
+
+    /// Emits a client event rejecting an order, used by owners of the book that refuse an order before matching
+    pub fn reject_order(&mut self, order: Order, time: Timestamp) {
         self.event_sink.push_client_event(ClientEvent {
             client_id: order.client_id,
             id: self.client_event_counter,
@@ -876,6 +1121,44 @@ mod tests {
         assert_eq!(book.best_ask(), Some(105));
     }
 
+    #[test]
+    fn level_qty_and_bbo_track_trades() {
+        let (event_feeds, _) = create_event_feeds(32);
+        let mut book = OrderBook::new(event_feeds);
+        book.process_order(Order::new(
+            0,
+            0,
+            OrderSide::Ask,
+            0,
+            OrderType::Limit { qty: 5, price: 100 },
+        ));
+        book.process_order(Order::new(
+            0,
+            1,
+            OrderSide::Ask,
+            1,
+            OrderType::Limit { qty: 5, price: 105 },
+        ));
+        book.process_order(Order::new(
+            0,
+            2,
+            OrderSide::Bid,
+            2,
+            OrderType::Limit { qty: 3, price: 95 },
+        ));
+        book.process_order(Order::new(
+            0,
+            3,
+            OrderSide::Bid,
+            3,
+            OrderType::Market { qty: 6 },
+        ));
+        assert_eq!(book.get_qty(100, OrderSide::Ask), 0);
+        assert_eq!(book.get_qty(105, OrderSide::Ask), 4);
+        assert_eq!(book.get_qty(95, OrderSide::Bid), 3);
+        assert_eq!(book.bbo(), (Some(95), Some(105)));
+    }
+
     #[test]
     fn market_order_single_level() {
         let (event_feeds, consumer_feeds) = create_event_feeds(32);
@@ -1224,4 +1507,29 @@ mod tests {
 
         assert!(client_events.try_pop().is_none());
     }
+
+    #[test]
+    fn uncross_trades_at_a_single_clearing_price() {
+        let (event_feeds, (_, mut trade_events, _)) = create_event_feeds(32);
+        let mut book = OrderBook::new(event_feeds);
+        let limit = |order_id, side, qty, price| {
+            Order::new(0, order_id, side, 1, OrderType::Limit { qty, price })
+        };
+        let orders = [
+            limit(0, OrderSide::Bid, 5, 102),
+            limit(1, OrderSide::Bid, 5, 100),
+            limit(2, OrderSide::Ask, 4, 98),
+            limit(3, OrderSide::Ask, 4, 101),
+            Order::new(0, 4, OrderSide::Bid, 1, OrderType::Market { qty: 2 }),
+        ];
+        assert_eq!(book.uncross(&orders, 1), Some(101));
+
+        let trades: Vec<TradeEvent> = trade_events.pop_iter().collect();
+        assert!(trades.iter().all(|trade| trade.price == 101));
+        let quantities: Vec<OrderQty> = trades.iter().map(|trade| trade.quantity).collect();
+        assert_eq!(quantities, [2, 2, 3]);
+        assert_eq!(book.best_bid(), Some(100));
+        assert_eq!(book.best_ask(), Some(101));
+        assert_eq!(book.get_qty(101, OrderSide::Ask), 1);
+    }
 }
@@ -1,9 +1,12 @@
 use clap::Parser;
 use mm_core::fix_core::messages::execution_report::ExecutionReport;
 use mm_core::fix_core::messages::{FIXEvent, FIXPayload, ReportMessage};
-use mm_core::lob_core::market_events::{ClientEvent, SingleEventFeed};
-use mm_core::lob_core::{market_events::MarketEvent, market_orders::Order};
-use rand::SeedableRng;
+use mm_core::lob_core::market_events::{ClientEvent, EventSink, SingleEventFeed};
+use mm_core::lob_core::{
+    market_events::MarketEvent,
+    market_orders::{Order, OrderType},
+};
+use rand::{RngExt, SeedableRng};
 use rand_chacha::ChaCha8Rng;
 use ringbuf::{HeapRb, traits::*};
 use std::net::SocketAddr;
@@ -14,28 +17,56 @@ use std::sync::{
 use std::thread;
 use std::time::{Duration, Instant};
 
-use crate::data_generator::event_source::{
-    ConstantPoissonSource, EventSource, FileReplaySource, SourceFunction,
-};
-use crate::data_generator::order_generators::GaussianOrderGenerator;
-use crate::data_generator::rate_controllers::ConstantPoissonRate;
-use crate::data_generator::type_selectors::UniformTypeSelector;
 use crate::fix::engine::FixEngine;
 use crate::moldudp64::engine::MoldEngine;
-use crate::simulator::DynamicSimulator;
-use crate::simulator::latency_config::{LatencyConfig, SimJitter};
+use engine::batch::{
+    RunStats, StatsFeed, collect_run_stats, run_parallel, write_runs_csv, write_summary_csv,
+};
+use engine::calibration::{Calibration, CalibrationFormat};
+use engine::data_generator::csv_replay::{CsvLayout, CsvReplaySource};
+use engine::data_generator::event_source::{
+    EventSource, FileReplaySource, HawkesSource, MergedSource, RandomSource, SourceFunction,
+};
+use engine::data_generator::itch_replay::ItchReplaySource;
+use engine::data_generator::live_orders::TargetSelection;
+use engine::data_generator::mmap_replay::MmapReplaySource;
+use engine::data_generator::order_generators::{
+    GaussianOrderGenerator, OrderGenerator, ReferencePriceGenerator,
+};
+use engine::data_generator::rate_controllers::{
+    ConstantPoissonRate, NANOSECONDS_PER_SECOND, PiecewiseRate, ThinnedPoissonRate, UShapedRate,
+};
+use engine::data_generator::toxic_flow::{Fundamental, InformedSource, ToxicSource};
+use engine::simulator::latency_config::{LatencyConfig, SimJitter};
+use engine::simulator::scheduler::SimEvent;
+use engine::simulator::{DynamicSimulator, SimTime};
+use engine::stylized_facts::{FactsFeed, collect_facts, facts_from_recording};
+use engine::sweep::{ParamRange, SweepResult, grid, write_sweep_csv, write_sweep_json};
 
-use crate::cli_args::{Args, EventSourceType, validate};
-use crate::event_recorder::{BinaryRecorder, RecorderEnum, RecorderType, TextRecorder};
+use crate::cli_args::{
+    Args, EventSourceType, SourceSpec, apply_param, book_model, fundamental, hawkes_rate,
+    order_sizes, replay_start, session_calendar, target_selection, type_selector, validate,
+};
 use crate::logging::log;
+use engine::agents::{
+    Agent, market_maker::MarketMakerAgent, momentum::MomentumAgent,
+    zero_intelligence::ZeroIntelligenceAgent,
+};
+use engine::event_recorder::{
+    ArchiveRecorder, BinaryRecorder, RecorderEnum, RecorderType, TextRecorder,
+};
+use engine::fingerprint::{
+    FingerprintFeed, TrailEvent, TrailWriter, trail_path, verify_archive, verify_recording,
+};
+use engine::order_script::OrderScript;
+use engine::scenario::Scenario;
+use engine::scenario::regimes::RegimeModel;
+use engine::session::SessionCalendar;
 
 mod cli_args;
-mod data_generator;
-mod event_recorder;
 mod fix;
 mod logging;
 mod moldudp64;
-mod simulator;
 
 const BUFFER_SIZE: usize = 1 << 24;
 
@@ -47,8 +78,95 @@ fn main() {
     }
     logging::set_enabled(args.logging);
 
+    if let EventSourceType::Verify {
+        file_name,
+        trail,
+        archive,
+    } = &args.event_source
+    {
+        let trail = trail.clone().unwrap_or_else(|| trail_path(file_name));
+        if let Err(e) = run_verify(file_name, &trail, *archive, session_calendar(&args)) {
+            eprintln!("{}", e);
+        }
+        return;
+    }
+
+    if let EventSourceType::Calibrate {
+        file_name,
+        source,
+        format,
+        output,
+    } = &args.event_source
+    {
+        if let Err(e) = run_calibrate(
+            &args,
+            file_name.as_deref(),
+            source.as_deref(),
+            *format,
+            output.as_deref(),
+        ) {
+            eprintln!("{}", e);
+        }
+        return;
+    }
+
+    if let EventSourceType::Facts {
+        file_name,
+        source,
+        interval,
+        lags,
+        output,
+    } = &args.event_source
+    {
+        let interval = interval * 1_000_000;
+        if let Err(e) = run_facts(
+            &args,
+            file_name.as_deref(),
+            source.as_deref(),
+            interval,
+            *lags,
+            output,
+        ) {
+            eprintln!("{}", e);
+        }
+        return;
+    }
+
+    let scenario = match &args.event_source {
+        EventSourceType::Scenario { file_name } => match Scenario::load(file_name) {
+            Ok(scenario) => {
+                log(format!(
+                    "Loaded scenario phases: {}",
+                    scenario.phase_names().join(", ")
+                )
+                .as_str());
+                Some(scenario)
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        },
+        _ => None,
+    };
+
+    let seed = args.seed.or(scenario.as_ref().and_then(|s| s.seed));
+    if args.batch.is_some() || !args.sweep.is_empty() {
+        let base_seed = seed.unwrap_or_else(|| rand::rng().random());
+        let result = match args.batch {
+            Some(runs) if args.sweep.is_empty() => {
+                run_batch(&args, scenario.as_ref(), base_seed, runs)
+            }
+            _ => run_sweep(&args, scenario.as_ref(), base_seed),
+        };
+        if let Err(e) = result {
+            eprintln!("{}", e);
+        }
+        return;
+    }
+
     log("Setting RNG seed");
-    let rng = match args.seed {
+    let rng = match seed {
         Some(seed) => ChaCha8Rng::seed_from_u64(seed),
         None => ChaCha8Rng::try_from_rng(&mut rand::rng())
             .expect("failed to get a seed from OS entropy"),
@@ -81,68 +199,81 @@ fn main() {
         jitter: SimJitter::from(&args),
     };
 
-    let source = match args.event_source {
-        EventSourceType::Poisson {
-            count,
-            order_rate,
-            bid_rate,
-            new_limit_rate,
-            cancel_rate,
-            market_rate,
-            update_rate,
-            bid_avg_price,
-            bid_price_dev,
-            ask_avg_price,
-            ask_price_dev,
-        } => {
-            let mut source = ConstantPoissonSource::new(
-                ConstantPoissonRate::new(order_rate),
-                UniformTypeSelector::new(
-                    bid_rate,
-                    new_limit_rate,
-                    market_rate,
-                    cancel_rate,
-                    update_rate,
-                ),
-                GaussianOrderGenerator::new(
-                    bid_avg_price,
-                    bid_price_dev,
-                    ask_avg_price,
-                    ask_price_dev,
-                ),
-                rng.clone(),
-                count,
-            );
-            SourceFunction::new(Box::new(move || source.next_event()))
+    let agents = spawn_agents(&args, &rng);
+    let session = session_calendar(&args);
+
+    let source = match build_source(&args, scenario.as_ref(), &rng) {
+        Ok(source) => source,
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
         }
-        EventSourceType::File {
-            file_name,
-            batch_size,
-        } => {
-            let mut source = match FileReplaySource::new(&file_name, batch_size) {
-                Ok(source) => source,
+    };
+
+    let mut trail_writer = match args.record_type {
+        Some(RecorderType::Binary | RecorderType::Archive) => {
+            match TrailWriter::new(&trail_path(&args.record_file)) {
+                Ok(writer) => Some(writer),
                 Err(e) => {
                     eprintln!("{}", e);
                     return;
                 }
-            };
-            SourceFunction::new(Box::new(move || source.next_event()))
+            }
         }
+        _ => None,
     };
 
     let mut sim = DynamicSimulator::new(
         source,
-        SingleEventFeed::new(market_event_prod, client_event_prod),
+        FingerprintFeed::new(
+            SingleEventFeed::new(market_event_prod, client_event_prod),
+            trail_writer.is_some(),
+        ),
         user_order_cons,
         latency_settings,
         rng.clone(),
         args.real_time,
     );
+    for agent in agents {
+        sim.add_agent(agent);
+    }
+    if let Some(session) = session {
+        log(format!("Trading session starts on {}", session.date).as_str());
+        sim.set_session(session);
+    }
+    match scenario_events(&args, scenario.as_ref()) {
+        Ok(events) => {
+            for (time, event) in events {
+                sim.schedule(time, event);
+            }
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            return;
+        }
+    }
+    if let Some(path) = &args.orders {
+        match OrderScript::load(path).and_then(|script| {
+            script.schedule(|time, event| sim.schedule(time, event))?;
+            Ok(script)
+        }) {
+            Ok(script) => {
+                log(format!("Scheduled {} scripted orders", script.orders.len()).as_str());
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                return;
+            }
+        }
+    }
     log("Spawned simulator");
 
     let mold_ready = Arc::new(AtomicBool::new(false));
     let ready = Arc::clone(&mold_ready);
-    let mut mold_engine = MoldEngine::start(Arc::clone(&running));
+    let mut mold_engine = MoldEngine::start(
+        Arc::clone(&running),
+        session.map_or(args.session_start.unwrap_or(0), |session| session.start),
+    );
     let broadcast_running = Arc::clone(&running);
     let event_broadcast_thread = thread::spawn(move || {
         ready.store(true, Ordering::Release);
@@ -229,6 +360,10 @@ fn main() {
                     TextRecorder::new(args.record_file.as_str(), args.record_batch_size)
                         .expect("Failed to create recorder"),
                 ),
+                RecorderType::Archive => RecorderEnum::Archive(
+                    ArchiveRecorder::new(args.record_file.as_str(), args.record_batch_size)
+                        .expect("Failed to create recorder"),
+                ),
             };
             let mut recorder_cons = recorder_cons.unwrap();
             ready.store(true, Ordering::Release);
@@ -277,6 +412,15 @@ fn main() {
                 {
                     log("failed to log an order, queue may be full");
                 }
+                let fingerprint = sim.event_sink_mut();
+                fingerprint.push_order(event);
+                if let Some(writer) = &mut trail_writer {
+                    for (_, entry) in fingerprint.drain_trail() {
+                        if let Err(e) = writer.write(entry) {
+                            log(format!("failed to write fingerprint trail: {}", e).as_str());
+                        }
+                    }
+                }
             }
             Err(msg) => {
                 log(&msg);
@@ -287,10 +431,19 @@ fn main() {
     }
     let elapsed = time.elapsed();
     running.store(false, Ordering::Relaxed);
+    if let Some(mut writer) = trail_writer
+        && let Err(e) = writer.shutdown()
+    {
+        log(format!("failed to write fingerprint trail: {}", e).as_str());
+    }
+    let fingerprint = sim.event_sink_mut().fingerprint();
 
     if !args.benchmark {
         log("Job finished");
         log(format!("Simulation covered {} steps", sim_step_count).as_str());
+        if let skipped @ 1.. = sim.source_mut().skipped() {
+            log(format!("Skipped {} input rows that could not be replayed", skipped).as_str());
+        }
         log(format!(
             "Sim time: {}s ({}ns)",
             sim.time() as f64 / 1_000_000_000.0,
@@ -303,6 +456,11 @@ fn main() {
             elapsed.as_nanos()
         )
         .as_str());
+        println!(
+            "Fingerprint: {:016x} ({} entries)",
+            fingerprint.value(),
+            fingerprint.count()
+        );
     } else {
         println!("{},{},{}", sim_step_count, elapsed.as_nanos(), sim.time());
     }
@@ -313,3 +471,664 @@ fn main() {
         let _ = t.join();
     }
 }
+
+/// Fits the poisson source to a recording and writes the configuration to `output`, or to stdout
+fn run_calibrate(
+    args: &Args,
+    recording: Option<&str>,
+    spec: Option<&str>,
+    format: CalibrationFormat,
+    output: Option<&str>,
+) -> Result<(), String> {
+    let calibration = match (recording, spec) {
+        (Some(path), _) => Calibration::from_recording(path)?,
+        (None, Some(spec)) => {
+            let spec = SourceSpec::parse(spec)?;
+            let scenario = match &spec.source {
+                EventSourceType::Scenario { file_name } => Some(Scenario::load(file_name)?),
+                _ => None,
+            };
+            let seed = args
+                .seed
+                .or(scenario.as_ref().and_then(|s| s.seed))
+                .unwrap_or_else(|| rand::rng().random());
+            let mut run = args.clone();
+            run.event_source = spec.source;
+            let mut source =
+                build_source(&run, scenario.as_ref(), &ChaCha8Rng::seed_from_u64(seed))?;
+            let calibration =
+                Calibration::fit(&mut source).map_err(|e| format!("calibrate: {e}"))?;
+            report_skipped(&source);
+            calibration
+        }
+        (None, None) => return Err("calibrate: a recording or a --source is needed".into()),
+    };
+    let text = match format {
+        CalibrationFormat::Cli => calibration.to_cli() + "\n",
+        CalibrationFormat::Scenario => calibration.to_scenario(),
+    };
+    match output {
+        Some(path) => std::fs::write(path, text).map_err(|e| format!("calibrate: {path}: {e}")),
+        None => {
+            print!("{text}");
+            Ok(())
+        }
+    }
+}
+
+/// Tells how many input rows a replayed file had that could not be read
+fn report_skipped(source: &impl EventSource) {
+    if let skipped @ 1.. = source.skipped() {
+        eprintln!("Skipped {skipped} input rows that could not be replayed");
+    }
+}
+
+/// Replays a recording against its fingerprint trail and prints the final fingerprint, or the first divergent entry
+fn run_verify(
+    recording: &str,
+    trail: &str,
+    archive: bool,
+    session: Option<SessionCalendar>,
+) -> Result<(), String> {
+    let verification = match archive {
+        true => verify_archive(recording, trail, session)?,
+        false => verify_recording(recording, trail, session)?,
+    };
+    let fingerprint = verification.fingerprint;
+    match verification.divergence {
+        None => println!(
+            "Recording matches its trail, fingerprint: {:016x} ({} entries)",
+            fingerprint.value(),
+            fingerprint.count()
+        ),
+        Some(divergence) => {
+            println!("Divergence at entry {}", divergence.index);
+            match divergence.expected {
+                Some(entry) => println!("  expected: {:?} {:016x}", entry.kind, entry.hash),
+                None => println!("  expected: end of trail"),
+            }
+            match divergence.actual {
+                Some((event, entry)) => {
+                    println!("  actual:   {:?} {:016x}", entry.kind, entry.hash);
+                    match event {
+                        TrailEvent::Order(order) => println!("  {:?}", order),
+                        TrailEvent::Market(event) => println!("  {:?}", event),
+                        TrailEvent::Client(event) => println!("  {:?}", event),
+                    }
+                }
+                None => println!("  actual:   end of recording"),
+            }
+            return Err("verify: recording diverged from its trail".into());
+        }
+    }
+    Ok(())
+}
+
+/// Builds the reference agents requested on the command line. Each agent draws from its own ChaCha stream so that
+/// adding agents does not change the random numbers seen by the event source
+fn spawn_agents(args: &Args, rng: &ChaCha8Rng) -> Vec<Box<dyn Agent>> {
+    let mut agents: Vec<Box<dyn Agent>> = Vec::new();
+    let price = args.agent_ref_price;
+    for i in 0..args.zi_agents {
+        let mut agent_rng = rng.clone();
+        agent_rng.set_stream(i as u64 + 1);
+        agents.push(Box::new(ZeroIntelligenceAgent::new(
+            100.0,
+            price.saturating_sub(price / 10),
+            price.saturating_add(price / 10),
+            20,
+            50,
+            agent_rng,
+        )));
+    }
+    for _ in 0..args.market_maker_agents {
+        agents.push(Box::new(MarketMakerAgent::new(
+            price,
+            (price / 200).max(1),
+            10,
+            200,
+            10_000_000,
+        )));
+    }
+    for _ in 0..args.momentum_agents {
+        agents.push(Box::new(MomentumAgent::new(
+            0.2, 0.02, 1.0, 5, 100, 50_000_000,
+        )));
+    }
+    agents
+}
+
+/// Builds a poisson source with the given order generator, picking the rate controller from the source's arguments
+fn poisson_source<G: OrderGenerator + Clone + 'static>(
+    source: &EventSourceType,
+    order_generator: G,
+    selection: TargetSelection,
+    rng: &ChaCha8Rng,
+) -> Result<SourceFunction, String> {
+    let EventSourceType::Poisson {
+        count,
+        order_rate,
+        rate_schedule,
+        rate_period,
+        u_shape,
+        day_length,
+        volume_csv,
+        ..
+    } = source
+    else {
+        return Err("poisson: not a poisson source".into());
+    };
+    let type_selector = type_selector(source)?;
+    let schedule = match volume_csv {
+        Some(path) => Some(PiecewiseRate::load_minute_volumes(path, *order_rate)?),
+        None if !rate_schedule.is_empty() => {
+            Some(PiecewiseRate::new(rate_schedule.clone(), *rate_period)?)
+        }
+        None => None,
+    };
+    let source = if let Some(schedule) = schedule {
+        let source = RandomSource::new(
+            ThinnedPoissonRate::new(schedule),
+            type_selector,
+            order_generator,
+            rng.clone(),
+            *count,
+        )
+        .with_target_selection(selection);
+        SourceFunction::from_source(source)
+    } else if let Some(edge_ratio) = u_shape {
+        let source = RandomSource::new(
+            ThinnedPoissonRate::new(UShapedRate::new(*order_rate, *edge_ratio, *day_length)?),
+            type_selector,
+            order_generator,
+            rng.clone(),
+            *count,
+        )
+        .with_target_selection(selection);
+        SourceFunction::from_source(source)
+    } else {
+        let source = RandomSource::new(
+            ConstantPoissonRate::new(*order_rate),
+            type_selector,
+            order_generator,
+            rng.clone(),
+            *count,
+        )
+        .with_target_selection(selection);
+        SourceFunction::from_source(source)
+    };
+    Ok(source)
+}
+
+/// Scripted events of the scenario being run, and of every scenario merged into the source
+fn scenario_events(
+    args: &Args,
+    scenario: Option<&Scenario>,
+) -> Result<Vec<(SimTime, SimEvent)>, String> {
+    let mut events = scenario.map(Scenario::scheduled_events).unwrap_or_default();
+    if let EventSourceType::Merge { sources } = &args.event_source {
+        for spec in sources {
+            if let EventSourceType::Scenario { file_name } = SourceSpec::parse(spec)?.source {
+                events.extend(Scenario::load(&file_name)?.scheduled_events());
+            }
+        }
+    }
+    Ok(events)
+}
+
+/// Random streams of merged sources start here, clear of the streams used by agents
+const MERGE_STREAM_BASE: u64 = 1 << 32;
+
+/// Builds the event source selected on the command line. The scenario must be loaded when the scenario subcommand is
+/// used
+fn build_source(
+    args: &Args,
+    scenario: Option<&Scenario>,
+    rng: &ChaCha8Rng,
+) -> Result<SourceFunction, String> {
+    let source = match &args.event_source {
+        EventSourceType::Poisson {
+            bid_avg_price,
+            bid_price_dev,
+            ask_avg_price,
+            ask_price_dev,
+            ..
+        } => match fundamental(&args.event_source) {
+            Some((mid, process)) => {
+                let order_generator = ReferencePriceGenerator::new(
+                    Fundamental::shared(mid, process, rng),
+                    bid_avg_price - mid,
+                    *bid_price_dev,
+                    ask_avg_price - mid,
+                    *ask_price_dev,
+                )
+                .with_sizes(order_sizes(args)?);
+                poisson_source(
+                    &args.event_source,
+                    order_generator,
+                    target_selection(args)?,
+                    rng,
+                )?
+            }
+            None => {
+                let order_generator = GaussianOrderGenerator::new(
+                    *bid_avg_price,
+                    *bid_price_dev,
+                    *ask_avg_price,
+                    *ask_price_dev,
+                )
+                .with_sizes(order_sizes(args)?);
+                poisson_source(
+                    &args.event_source,
+                    order_generator,
+                    target_selection(args)?,
+                    rng,
+                )?
+            }
+        },
+        EventSourceType::Hawkes {
+            count,
+            baseline,
+            excitation,
+            decay,
+            bid_rate,
+            bid_avg_price,
+            bid_price_dev,
+            ask_avg_price,
+            ask_price_dev,
+        } => {
+            let source = HawkesSource::new(
+                hawkes_rate(baseline, excitation, decay)?,
+                vec![
+                    OrderType::Limit { qty: 0, price: 0 },
+                    OrderType::Market { qty: 0 },
+                    OrderType::Cancel { old_id: 0 },
+                    OrderType::Update {
+                        old_id: 0,
+                        qty: 0,
+                        price: 0,
+                    },
+                ],
+                *bid_rate,
+                GaussianOrderGenerator::new(
+                    *bid_avg_price,
+                    *bid_price_dev,
+                    *ask_avg_price,
+                    *ask_price_dev,
+                )
+                .with_sizes(order_sizes(args)?),
+                rng.clone(),
+                *count,
+            )
+            .with_target_selection(target_selection(args)?);
+            SourceFunction::from_source(source)
+        }
+        EventSourceType::Book {
+            count, order_rate, ..
+        } => {
+            let (type_selector, order_generator) = book_model(&args.event_source)?;
+            let source = RandomSource::new(
+                ConstantPoissonRate::new(*order_rate),
+                type_selector,
+                order_generator.with_sizes(order_sizes(args)?),
+                rng.clone(),
+                *count,
+            )
+            .with_target_selection(target_selection(args)?);
+            SourceFunction::from_source(source)
+        }
+        EventSourceType::Informed {
+            count,
+            rate,
+            horizon,
+            signal_noise,
+            threshold,
+            ..
+        } => {
+            // NOTE: The fundamental draws from a stream of its own, so its path does not depend on the trader's orders
+            // and is the one followed by a background source with the same price process
+            let (price, process) =
+                fundamental(&args.event_source).ok_or("informed: no price process")?;
+            let source = InformedSource::new(
+                Fundamental::shared(price, process, rng),
+                *rate,
+                (horizon * NANOSECONDS_PER_SECOND as f64) as u64,
+                *signal_noise,
+                *threshold,
+                rng.clone(),
+                *count,
+            )?
+            .with_sizes(order_sizes(args)?);
+            SourceFunction::from_source(source)
+        }
+        EventSourceType::Toxic {
+            pattern,
+            count,
+            episode_rate,
+            burst,
+            spacing,
+            reference_price,
+        } => {
+            let source = ToxicSource::new(
+                *pattern,
+                *episode_rate,
+                *burst,
+                spacing * 1_000,
+                *reference_price,
+                rng.clone(),
+                *count,
+            )?
+            .with_sizes(order_sizes(args)?);
+            SourceFunction::from_source(source)
+        }
+        EventSourceType::File {
+            file_name,
+            batch_size,
+        } => {
+            let source =
+                FileReplaySource::new(file_name, *batch_size).map_err(|e| e.to_string())?;
+            SourceFunction::from_source(source)
+        }
+        EventSourceType::Archive {
+            file_name,
+            from_index,
+            from_time,
+        } => {
+            let mut source = MmapReplaySource::open(file_name)?;
+            if let Some(index) = from_index {
+                source.seek(*index);
+            }
+            if let Some(time) = from_time {
+                source.seek_time((time * NANOSECONDS_PER_SECOND as f64) as SimTime);
+            }
+            SourceFunction::from_source(source)
+        }
+        EventSourceType::Itch {
+            file_name,
+            symbol,
+            count,
+            price_scale,
+        } => {
+            let source = ItchReplaySource::open(
+                file_name,
+                symbol,
+                replay_start(args),
+                *price_scale,
+                *count,
+            )?;
+            SourceFunction::from_source(source)
+        }
+        EventSourceType::Lobster {
+            file_name,
+            count,
+            price_scale,
+        } => {
+            let source =
+                CsvReplaySource::open_lobster(file_name, replay_start(args), *price_scale, *count)?;
+            SourceFunction::from_source(source)
+        }
+        EventSourceType::Csv {
+            file_name,
+            columns,
+            no_header,
+            delimiter,
+            time_unit,
+            price_scale,
+            count,
+        } => {
+            let layout = CsvLayout {
+                columns: columns.clone().unwrap_or_default(),
+                header: !no_header,
+                delimiter: *delimiter,
+                time_unit: *time_unit,
+            };
+            let source = CsvReplaySource::open_generic(
+                file_name,
+                &layout,
+                replay_start(args),
+                *price_scale,
+                *count,
+            )?;
+            SourceFunction::from_source(source)
+        }
+        EventSourceType::Scenario { .. } => {
+            let source = scenario
+                .ok_or("scenario: no scenario loaded")?
+                .source(rng.clone())
+                .with_target_selection(target_selection(args)?);
+            SourceFunction::from_source(source)
+        }
+        EventSourceType::Regimes { file_name, labels } => {
+            let mut source = RegimeModel::load(file_name)?
+                .source(rng.clone())
+                .with_target_selection(target_selection(args)?);
+            if let Some(labels) = labels {
+                source = source.with_labels(labels)?;
+            }
+            SourceFunction::from_source(source)
+        }
+        EventSourceType::Merge { sources } => {
+            let mut merged = MergedSource::new();
+            for (i, spec) in sources.iter().enumerate() {
+                let spec = SourceSpec::parse(spec)?;
+                let child_scenario = match &spec.source {
+                    EventSourceType::Scenario { file_name } => Some(Scenario::load(file_name)?),
+                    _ => None,
+                };
+                // The first source keeps the run's stream so that merging a single source changes nothing
+                let mut child_rng = rng.clone();
+                if i > 0 {
+                    child_rng.set_stream(MERGE_STREAM_BASE + i as u64);
+                }
+                let mut child_args = args.clone();
+                child_args.event_source = spec.source;
+                merged.add_source(
+                    build_source(&child_args, child_scenario.as_ref(), &child_rng)?,
+                    spec.clients.unwrap_or(SourceSpec::default_clients(i)),
+                )?;
+            }
+            SourceFunction::from_source(merged)
+        }
+        EventSourceType::Verify { .. } => {
+            return Err("verify: recordings are not replayed as an event source".into());
+        }
+        EventSourceType::Calibrate { .. } => {
+            return Err("calibrate: recordings are fitted without a simulation".into());
+        }
+        EventSourceType::Facts { .. } => {
+            return Err("facts: the source to analyse is given with --source".into());
+        }
+    };
+    Ok(source)
+}
+
+/// Runs a single headless simulation of the configured source and agents with the given seed, and returns its
+/// statistics. Nothing is published over the network
+fn run_headless(args: &Args, scenario: Option<&Scenario>, seed: u64) -> Result<RunStats, String> {
+    let mut sim = headless_simulator(args, scenario, seed, StatsFeed::default())?;
+    Ok(collect_run_stats(&mut sim, seed, args.stats_interval))
+}
+
+/// Builds a simulation of the configured source and agents with the given seed that publishes its events to `sink`
+fn headless_simulator<S: EventSink>(
+    args: &Args,
+    scenario: Option<&Scenario>,
+    seed: u64,
+    sink: S,
+) -> Result<DynamicSimulator<S, ChaCha8Rng>, String> {
+    let rng = ChaCha8Rng::seed_from_u64(seed);
+    let source = build_source(args, scenario, &rng)?;
+    let (_, user_order_cons) = HeapRb::<Order>::new(1).split();
+    let mut sim = DynamicSimulator::new(
+        source,
+        sink,
+        user_order_cons,
+        LatencyConfig {
+            latency: args.sim_latency,
+            jitter: SimJitter::from(args),
+        },
+        rng.clone(),
+        false,
+    );
+    for agent in spawn_agents(args, &rng) {
+        sim.add_agent(agent);
+    }
+    if let Some(session) = session_calendar(args) {
+        sim.set_session(session);
+    }
+    for (time, event) in scenario_events(args, scenario)? {
+        sim.schedule(time, event);
+    }
+    if let Some(path) = &args.orders {
+        OrderScript::load(path)?.schedule(|time, event| sim.schedule(time, event))?;
+    }
+    Ok(sim)
+}
+
+/// Computes the stylized facts of a recording, or of a headless run of the source in `spec`, and writes the report
+fn run_facts(
+    args: &Args,
+    recording: Option<&str>,
+    spec: Option<&str>,
+    interval: SimTime,
+    lags: usize,
+    output: &str,
+) -> Result<(), String> {
+    let facts = match (recording, spec) {
+        (Some(path), _) => facts_from_recording(path, interval, lags)?,
+        (None, Some(spec)) => {
+            let spec = SourceSpec::parse(spec)?;
+            let scenario = match &spec.source {
+                EventSourceType::Scenario { file_name } => Some(Scenario::load(file_name)?),
+                _ => None,
+            };
+            let seed = args
+                .seed
+                .or(scenario.as_ref().and_then(|s| s.seed))
+                .unwrap_or_else(|| rand::rng().random());
+            let mut run = args.clone();
+            run.event_source = spec.source;
+            let mut sim = headless_simulator(&run, scenario.as_ref(), seed, FactsFeed::default())?;
+            let facts = collect_facts(&mut sim, interval, lags);
+            report_skipped(sim.source_mut());
+            facts
+        }
+        (None, None) => return Err("facts: a recording or a --source is needed".into()),
+    };
+    facts
+        .write_csv(output)
+        .map_err(|e| format!("facts: {output}: {e}"))?;
+    println!(
+        "{} samples, {} trades: return kurtosis {:.3}, return acf(1) {:.3}, |return| acf(1) {:.3}, sign acf(1) {:.3}, \
+         mean spread {:.2}",
+        facts.samples,
+        facts.trades,
+        facts.return_kurtosis,
+        facts.return_acf.first().copied().unwrap_or_default(),
+        facts.abs_return_acf.first().copied().unwrap_or_default(),
+        facts.sign_acf.first().copied().unwrap_or_default(),
+        facts.spread_mean,
+    );
+    println!("Wrote stylized facts to {output}");
+    Ok(())
+}
+
+/// Number of worker threads for batch runs and sweeps
+fn worker_threads(args: &Args) -> usize {
+    args.batch_threads.unwrap_or_else(|| {
+        thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1)
+    })
+}
+
+/// Runs independent headless simulations with consecutive seeds across worker threads, then writes the statistics of
+/// every run and their aggregate
+fn run_batch(
+    args: &Args,
+    scenario: Option<&Scenario>,
+    base_seed: u64,
+    runs: usize,
+) -> Result<(), String> {
+    let threads = worker_threads(args);
+    log(format!(
+        "Running {} simulations on {} threads, seeds {}..{}",
+        runs,
+        threads.min(runs),
+        base_seed,
+        base_seed.wrapping_add(runs as u64)
+    )
+    .as_str());
+    let results = run_parallel(runs, threads, |i| {
+        let seed = base_seed.wrapping_add(i as u64);
+        let stats = run_headless(args, scenario, seed)?;
+        log(format!("Run with seed {} finished: {}", seed, stats.to_csv_row()).as_str());
+        Ok::<RunStats, String>(stats)
+    });
+    let results = results.into_iter().collect::<Result<Vec<_>, _>>()?;
+    if let Some(path) = &args.batch_runs_output {
+        write_runs_csv(path, &results).map_err(|e| format!("{}: {}", path, e))?;
+    }
+    write_summary_csv(&args.batch_output, &results)
+        .map_err(|e| format!("{}: {}", args.batch_output, e))?;
+    log(format!("Wrote batch statistics to {}", args.batch_output).as_str());
+    Ok(())
+}
+
+/// Runs every configuration of a parameter sweep headless, --batch times each, and writes one result row per
+/// configuration. Every configuration uses the same seeds, so differences between rows come from the parameters
+fn run_sweep(args: &Args, scenario: Option<&Scenario>, base_seed: u64) -> Result<(), String> {
+    let ranges = args
+        .sweep
+        .iter()
+        .map(|spec| ParamRange::parse(spec))
+        .collect::<Result<Vec<_>, _>>()?;
+    let configs = grid(&ranges);
+    let config_args = configs
+        .iter()
+        .map(|config| {
+            let mut config_args = args.clone();
+            for (name, value) in config {
+                apply_param(&mut config_args, name, *value)?;
+            }
+            Ok(config_args)
+        })
+        .collect::<Result<Vec<Args>, String>>()?;
+    let runs = args.batch.unwrap_or(1);
+    let threads = worker_threads(args);
+    log(format!(
+        "Sweeping {} configurations with {} runs each on {} threads",
+        configs.len(),
+        runs,
+        threads
+    )
+    .as_str());
+    let results = run_parallel(configs.len() * runs, threads, |job| {
+        run_headless(
+            &config_args[job / runs],
+            scenario,
+            base_seed.wrapping_add((job % runs) as u64),
+        )
+    });
+    let mut results = results.into_iter();
+    let sweep_results = configs
+        .into_iter()
+        .map(|params| {
+            Ok(SweepResult {
+                params,
+                runs: results
+                    .by_ref()
+                    .take(runs)
+                    .collect::<Result<Vec<_>, String>>()?,
+            })
+        })
+        .collect::<Result<Vec<_>, String>>()?;
+    let path = args.sweep_output.as_str();
+    match path.ends_with(".json") {
+        true => write_sweep_json(path, &sweep_results),
+        false => write_sweep_csv(path, &sweep_results),
+    }
+    .map_err(|e| format!("{}: {}", path, e))?;
+    log(format!("Wrote sweep results to {}", path).as_str());
+    Ok(())
+}
@@ -1,9 +1,11 @@
 use crate::moldudp64::sequencerpublisher::SequencerPublisher;
 use bytes::Bytes;
+use engine::session::NS_PER_DAY;
 use mm_core::{
     itch_core::messages::{
         add_order::AddOrder, order_cancel::OrderCancel,
         order_executed_with_price::OrderExecutedWithPrice, order_replace::OrderReplace,
+        system_event::SystemEvent,
     },
     lob_core::{
         market_events::{L3EventExtra, MarketEvent, MarketEventType},
@@ -28,11 +30,14 @@ pub struct MoldEngine {
     l3_tx: HeapProd<Event>,
     trade_tx: HeapProd<Event>,
     current_tracking_number: u16,
+    start_of_day: u64,
 }
 
 impl MoldEngine {
     /// Initializes the engine and spawns background threads for L3 and Trade multicast publishers.
-    pub fn start(running: Arc<AtomicBool>) -> Self {
+    /// `start_of_day` is the time of day at sim time 0 in nanoseconds since midnight, ITCH timestamps are sim times
+    /// shifted by it.
+    pub fn start(running: Arc<AtomicBool>, start_of_day: u64) -> Self {
         let (l3_tx, l3_rx) = HeapRb::<Event>::new(1 << 24).split();
         let (trade_tx, trade_rx) = HeapRb::<Event>::new(1 << 24).split();
 
@@ -57,6 +62,7 @@ impl MoldEngine {
             l3_tx,
             trade_tx,
             current_tracking_number: 1,
+            start_of_day,
         }
     }
 
@@ -107,6 +113,7 @@ impl MoldEngine {
     }
 
     pub fn push(&mut self, event: MarketEvent) {
+        let timestamp = (self.start_of_day + event.timestamp) % NS_PER_DAY;
         match event.kind {
             MarketEventType::L3(e) => match e.kind {
                 OrderType::Limit { qty, price } => {
@@ -116,7 +123,7 @@ impl MoldEngine {
                         &mut buf,
                         0, // PLACEHOLDER
                         self.current_tracking_number,
-                        event.timestamp,
+                        timestamp,
                         e.order_id,
                         e.side as u8,
                         qty,
@@ -135,7 +142,7 @@ impl MoldEngine {
                         &mut buf,
                         0, // PLACEHOLDER
                         self.current_tracking_number,
-                        event.timestamp,
+                        timestamp,
                         e.order_id,
                         e.side as u8,
                         qty,
@@ -160,7 +167,7 @@ impl MoldEngine {
                         &mut buf,
                         0, // PLACEHOLDER
                         self.current_tracking_number,
-                        event.timestamp,
+                        timestamp,
                         old_id,
                         cancel_qty,
                     );
@@ -175,7 +182,7 @@ impl MoldEngine {
                         &mut buf,
                         0, // PLACEHOLDER
                         self.current_tracking_number,
-                        event.timestamp,
+                        timestamp,
                         old_id,
                         e.order_id,
                         qty,
@@ -193,7 +200,7 @@ impl MoldEngine {
                     &mut buf,
                     0, // PLACEHOLDER
                     self.current_tracking_number,
-                    event.timestamp,
+                    timestamp,
                     e.maker_id,
                     e.quantity,
                     0,    // PLACEHOLDER
@@ -205,6 +212,23 @@ impl MoldEngine {
 
                 Self::push_event(&mut self.trade_tx, &buf);
             }
+            MarketEventType::System(code) => {
+                let mut buf = [0u8; 12];
+
+                SystemEvent::encode_into(
+                    &mut buf,
+                    0,
+                    self.current_tracking_number,
+                    timestamp,
+                    code as u8,
+                );
+
+                self.current_tracking_number = self.current_tracking_number.wrapping_add(1);
+
+                // NOTE: Session events go out on both channels so that trade-only subscribers see them as well
+                Self::push_event(&mut self.l3_tx, &buf);
+                Self::push_event(&mut self.trade_tx, &buf);
+            }
         }
     }
 }
@@ -220,7 +244,7 @@ mod tests {
     #[test]
     #[ignore]
     fn send_orders() {
-        let mut server = MoldEngine::start(Arc::new(AtomicBool::new(true)));
+        let mut server = MoldEngine::start(Arc::new(AtomicBool::new(true)), 0);
         std::thread::sleep(std::time::Duration::from_millis(250));
         let mut i = 0;
 
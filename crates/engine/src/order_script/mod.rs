@@ -0,0 +1,397 @@
+use crate::scenario::{SideConfig, to_sim_time};
+use crate::simulator::SimTime;
+use crate::simulator::scheduler::SimEvent;
+use mm_core::lob_core::{
+    ClientId, OrderId, OrderQty, Price,
+    market_orders::{Order, OrderSide, OrderType},
+};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// A file of user orders injected at exact sim times, alongside the synthetic source and any connected clients.
+/// Orders are scheduled in file order, and cancels and updates refer to earlier orders by name. An update takes over
+/// the name of the order it replaces unless it is given its own, and the expiry of the order it replaces. All times
+/// are in seconds of sim time
+///
+/// ```toml
+/// client_id = 9000
+///
+/// [[order]]
+/// kind = "limit"
+/// name = "bid"
+/// time = 1.0
+/// side = "bid"
+/// qty = 500
+/// price = 995
+/// expire_after = 10.0
+///
+/// [[order]]
+/// kind = "market"
+/// time = 3.0
+/// side = "ask"
+/// qty = 10000
+///
+/// [[order]]
+/// kind = "cancel"
+/// time = 4.0
+/// target = "bid"
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct OrderScript {
+    /// Client id that orders are sent under, unless an order sets its own
+    pub client_id: ClientId,
+    #[serde(rename = "order", default)]
+    pub orders: Vec<ScriptedOrder>,
+}
+
+/// A single scripted order
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ScriptedOrder {
+    /// A limit order, canceled after `expire_after` seconds if given
+    Limit {
+        time: f64,
+        side: SideConfig,
+        qty: OrderQty,
+        price: Price,
+        name: Option<String>,
+        client_id: Option<ClientId>,
+        expire_after: Option<f64>,
+    },
+    Market {
+        time: f64,
+        side: SideConfig,
+        qty: OrderQty,
+        client_id: Option<ClientId>,
+    },
+    /// Replaces the order named `target` with a new quantity and price, on the same side
+    Update {
+        time: f64,
+        target: String,
+        qty: OrderQty,
+        price: Price,
+        name: Option<String>,
+        client_id: Option<ClientId>,
+    },
+    Cancel {
+        time: f64,
+        target: String,
+        client_id: Option<ClientId>,
+    },
+}
+impl ScriptedOrder {
+    fn time(&self) -> f64 {
+        match self {
+            ScriptedOrder::Limit { time, .. }
+            | ScriptedOrder::Market { time, .. }
+            | ScriptedOrder::Update { time, .. }
+            | ScriptedOrder::Cancel { time, .. } => *time,
+        }
+    }
+    fn client_id(&self) -> Option<ClientId> {
+        match self {
+            ScriptedOrder::Limit { client_id, .. }
+            | ScriptedOrder::Market { client_id, .. }
+            | ScriptedOrder::Update { client_id, .. }
+            | ScriptedOrder::Cancel { client_id, .. } => *client_id,
+        }
+    }
+}
+
+impl OrderScript {
+    /// Reads and validates an order script from a TOML file
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("orders: {path}: {e}"))?;
+        Self::parse(&text).map_err(|e| format!("orders: {path}: {e}"))
+    }
+    /// Parses and validates an order script from TOML text
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let script: OrderScript = toml::from_str(text).map_err(|e| e.to_string())?;
+        script.validate()?;
+        Ok(script)
+    }
+    /// Validate parameters that cannot be expressed through the file format itself. Targets must name an order
+    /// listed earlier in the file
+    pub fn validate(&self) -> Result<(), String> {
+        let mut names: Vec<&str> = Vec::new();
+        for (i, order) in self.orders.iter().enumerate() {
+            if !order.time().is_finite() || order.time() < 0.0 {
+                return Err(format!("order #{i}: `time` must be finite and >= 0.0"));
+            }
+            match order {
+                ScriptedOrder::Limit {
+                    qty,
+                    price,
+                    name,
+                    expire_after,
+                    ..
+                } => {
+                    if *qty == 0 || *price == 0 {
+                        return Err(format!("order #{i}: `qty` and `price` must be > 0"));
+                    }
+                    if expire_after.is_some_and(|after| !after.is_finite() || after <= 0.0) {
+                        return Err(format!(
+                            "order #{i}: `expire_after` must be finite and > 0.0"
+                        ));
+                    }
+                    names.extend(name.as_deref());
+                }
+                ScriptedOrder::Market { qty, .. } if *qty == 0 => {
+                    return Err(format!("order #{i}: `qty` must be > 0"));
+                }
+                ScriptedOrder::Market { .. } => {}
+                ScriptedOrder::Update {
+                    target,
+                    qty,
+                    price,
+                    name,
+                    ..
+                } => {
+                    if !names.contains(&target.as_str()) {
+                        return Err(format!("order #{i}: unknown target `{target}`"));
+                    }
+                    if *qty == 0 || *price == 0 {
+                        return Err(format!("order #{i}: `qty` and `price` must be > 0"));
+                    }
+                    names.extend(name.as_deref());
+                }
+                ScriptedOrder::Cancel { target, .. } => {
+                    if !names.contains(&target.as_str()) {
+                        return Err(format!("order #{i}: unknown target `{target}`"));
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+    /// Hands every scripted order, and the expiries of orders with a lifetime, to a scheduling function such as
+    /// `Simulator::schedule`. The function returns the engine order id assigned to each order, which later cancels
+    /// and updates refer to. Expiries are handed over last, once the order they cancel is known
+    pub fn schedule(
+        &self,
+        mut schedule: impl FnMut(SimTime, SimEvent) -> Option<OrderId>,
+    ) -> Result<(), String> {
+        // Engine id and side of every named order
+        let mut named: HashMap<&str, (OrderId, OrderSide)> = HashMap::new();
+        // Pending expiries, and the expiry that each order replacing an expiring order inherited
+        let mut expiries: Vec<(SimTime, Order)> = Vec::new();
+        let mut expiring: HashMap<OrderId, usize> = HashMap::new();
+        for (i, scripted) in self.orders.iter().enumerate() {
+            let resolve = |target: &str| {
+                named.get(target).copied().ok_or_else(|| {
+                    format!("orders: order #{i}: target `{target}` was not scheduled")
+                })
+            };
+            let time = to_sim_time(scripted.time());
+            let client_id = scripted.client_id().unwrap_or(self.client_id);
+            let (side, kind, name) = match scripted {
+                ScriptedOrder::Limit {
+                    side,
+                    qty,
+                    price,
+                    name,
+                    ..
+                } => (
+                    (*side).into(),
+                    OrderType::Limit {
+                        qty: *qty,
+                        price: *price,
+                    },
+                    name.as_deref(),
+                ),
+                ScriptedOrder::Market { side, qty, .. } => {
+                    ((*side).into(), OrderType::Market { qty: *qty }, None)
+                }
+                ScriptedOrder::Update {
+                    target,
+                    qty,
+                    price,
+                    name,
+                    ..
+                } => {
+                    let (old_id, side) = resolve(target)?;
+                    (
+                        side,
+                        OrderType::Update {
+                            old_id,
+                            qty: *qty,
+                            price: *price,
+                        },
+                        Some(name.as_deref().unwrap_or(target)),
+                    )
+                }
+                ScriptedOrder::Cancel { target, .. } => {
+                    let (old_id, side) = resolve(target)?;
+                    (side, OrderType::Cancel { old_id }, None)
+                }
+            };
+            let mut order = Order::new(
+                client_id, 0, // NOTE: Use a junk value, simulator sets this on receipt
+                side, time, kind,
+            );
+            let Some(order_id) = schedule(time, SimEvent::Order(order)) else {
+                continue;
+            };
+            order.order_id = order_id;
+            if let Some(name) = name {
+                named.insert(name, (order_id, side));
+            }
+            match (scripted, order.kind) {
+                (
+                    ScriptedOrder::Limit {
+                        expire_after: Some(after),
+                        ..
+                    },
+                    _,
+                ) => {
+                    expiring.insert(order_id, expiries.len());
+                    expiries.push((time + to_sim_time(*after), order));
+                }
+                (ScriptedOrder::Update { .. }, OrderType::Update { old_id, .. }) => {
+                    if let Some(expiry) = expiring.remove(&old_id)
+                        && time < expiries[expiry].0
+                    {
+                        expiring.insert(order_id, expiry);
+                        expiries[expiry].1 = order;
+                    }
+                }
+                _ => {}
+            }
+        }
+        for (time, order) in expiries {
+            schedule(time, SimEvent::Expiry(order));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_generator::{
+        event_source::RandomSource, order_generators::GaussianOrderGenerator,
+        rate_controllers::ConstantPoissonRate, type_selectors::UniformTypeSelector,
+    };
+    use crate::simulator::{Simulator, latency_config::LatencyConfig, latency_config::SimJitter};
+    use mm_core::lob_core::market_events::{
+        ClientEvent, ClientEventType, MarketEvent, SingleEventFeed,
+    };
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use ringbuf::{HeapRb, traits::*};
+
+    const EXAMPLE: &str = include_str!("../../../../scenarios/large_sell_orders.toml");
+
+    #[test]
+    fn example_script_schedules_orders_and_expiry() {
+        let script = OrderScript::parse(EXAMPLE).unwrap();
+        let mut scheduled = Vec::new();
+        script
+            .schedule(|time, event| {
+                let id = matches!(event, SimEvent::Order(_)).then_some(scheduled.len() as OrderId);
+                scheduled.push((time, event));
+                id
+            })
+            .unwrap();
+        assert_eq!(scheduled.len(), 5);
+        // The bid expires as the order that replaced it
+        assert!(
+            matches!(scheduled[4], (11_000_000_000, SimEvent::Expiry(order)) if order.order_id == 2)
+        );
+        let kinds: Vec<OrderType> = scheduled
+            .iter()
+            .filter_map(|(_, event)| match event {
+                SimEvent::Order(order) => Some(order.kind),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            kinds[2..],
+            [
+                OrderType::Update {
+                    old_id: 0,
+                    qty: 300,
+                    price: 990
+                },
+                // The update took over the name
+                OrderType::Cancel { old_id: 2 },
+            ]
+        );
+    }
+
+    #[test]
+    fn scripted_orders_reach_the_book() {
+        let (_, user_order_cons) = HeapRb::<Order>::new(1).split();
+        let (market_event_prod, _market_event_cons) = HeapRb::<MarketEvent>::new(1 << 16).split();
+        let (client_event_prod, mut client_event_cons) =
+            HeapRb::<ClientEvent>::new(1 << 16).split();
+        // Synthetic flow is slow and far from the scripted prices
+        let mut sim = Simulator::new(
+            RandomSource::new(
+                ConstantPoissonRate::new(0.001),
+                UniformTypeSelector::new(0.5, 1.0, 0.0, 0.0, 0.0),
+                GaussianOrderGenerator::new(100.0, 1.0, 200.0, 1.0),
+                ChaCha8Rng::seed_from_u64(0),
+                None,
+            ),
+            SingleEventFeed::new(market_event_prod, client_event_prod),
+            user_order_cons,
+            LatencyConfig {
+                latency: 0,
+                jitter: SimJitter::None,
+            },
+            ChaCha8Rng::seed_from_u64(0),
+            false,
+        );
+        let script = OrderScript::parse(
+            "client_id = 7\n\
+             [[order]]\nkind = \"limit\"\nname = \"ask\"\ntime = 1.0\nside = \"ask\"\nqty = 10\nprice = 1000\n\
+             [[order]]\nkind = \"market\"\ntime = 2.0\nside = \"bid\"\nqty = 4\nclient_id = 8\n\
+             [[order]]\nkind = \"cancel\"\ntime = 3.0\ntarget = \"ask\"\n",
+        )
+        .unwrap();
+        script
+            .schedule(|time, event| sim.schedule(time, event))
+            .unwrap();
+        sim.run_until(5_000_000_000).unwrap();
+        let events: Vec<(ClientId, ClientEventType)> =
+            std::iter::from_fn(|| client_event_cons.try_pop())
+                .map(|event| (event.client_id, event.kind))
+                .filter(|(client_id, _)| [7, 8].contains(client_id))
+                .collect();
+        assert_eq!(
+            events,
+            [
+                (7, ClientEventType::Accepted(10)),
+                (8, ClientEventType::Filled),
+                (7, ClientEventType::PartiallyFilled(6)),
+                (7, ClientEventType::Canceled),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_scripts_are_rejected() {
+        assert!(OrderScript::parse("").is_err());
+        assert!(
+            OrderScript::parse(
+                "client_id = 1\n[[order]]\nkind = \"cancel\"\ntime = 1.0\ntarget = \"x\"\n"
+            )
+            .is_err()
+        );
+        assert!(
+            OrderScript::parse(
+                "client_id = 1\n[[order]]\nkind = \"market\"\ntime = 1.0\nside = \"bid\"\nqty = 0\n"
+            )
+            .is_err()
+        );
+        assert!(OrderScript::parse("client_id = 1\n[[order]]\nkind = \"market\"\ntime = -1.0\nside = \"bid\"\nqty = 1\n").is_err());
+        assert!(
+            OrderScript::parse(
+                "client_id = 1\n[[order]]\nkind = \"market\"\ntime = nan\nside = \"bid\"\nqty = 1\n"
+            )
+            .is_err()
+        );
+    }
+}
@@ -0,0 +1,382 @@
+use crate::data_generator::event_source::{ScenarioPhase, ScenarioSource};
+use crate::data_generator::order_generators::GaussianOrderGenerator;
+use crate::data_generator::rate_controllers::{ConstantPoissonRate, NANOSECONDS_PER_SECOND};
+use crate::data_generator::type_selectors::UniformTypeSelector;
+use crate::simulator::SimTime;
+use crate::simulator::scheduler::{MarketPhase, SimEvent};
+use mm_core::lob_core::{
+    OrderQty,
+    market_orders::{Order, OrderSide, OrderType},
+};
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+
+pub mod regimes;
+
+/// Converts a time in seconds, as written in scenario files, to sim time
+pub(crate) fn to_sim_time(seconds: f64) -> SimTime {
+    (seconds * NANOSECONDS_PER_SECOND as f64) as SimTime
+}
+
+/// A scenario file. Describes a timeline of market phases with their own generator parameters, scripted events at
+/// given sim times, and when the run should end. All times are in seconds of sim time
+///
+/// ```toml
+/// seed = 7
+///
+/// [end]
+/// time = 60.0
+///
+/// [[phase]]
+/// name = "calm"
+/// start = 0.0
+/// order_rate = 50000.0
+///
+/// [[phase]]
+/// name = "volatile"
+/// start = 30.0
+/// bid_price_dev = 200.0
+/// ask_price_dev = 200.0
+///
+/// [[event]]
+/// kind = "halt"
+/// time = 45.0
+/// duration = 5.0
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    /// RNG seed used when `--seed` is not passed on the command line
+    pub seed: Option<u64>,
+    #[serde(default)]
+    pub end: EndCondition,
+    #[serde(rename = "phase")]
+    pub phases: Vec<PhaseConfig>,
+    #[serde(rename = "event", default)]
+    pub events: Vec<ScriptedEvent>,
+}
+
+/// When the scenario stops generating orders, if neither is given the run continues indefinitely
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EndCondition {
+    /// Sim time in seconds after which no more orders are generated
+    pub time: Option<f64>,
+    /// Number of orders to generate
+    pub orders: Option<u64>,
+}
+
+/// Generator parameters for one phase, fields that are left out take the same defaults as the `poisson` subcommand
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PhaseConfig {
+    pub name: String,
+    pub start: f64,
+    pub order_rate: f64,
+    pub bid_rate: f64,
+    pub new_limit_rate: f64,
+    pub cancel_rate: f64,
+    pub market_rate: f64,
+    pub update_rate: f64,
+    pub bid_avg_price: f64,
+    pub bid_price_dev: f64,
+    pub ask_avg_price: f64,
+    pub ask_price_dev: f64,
+}
+impl Default for PhaseConfig {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            start: 0.0,
+            order_rate: 100_000.0,
+            bid_rate: 0.5,
+            new_limit_rate: 0.5,
+            cancel_rate: 0.4,
+            market_rate: 0.05,
+            update_rate: 0.05,
+            bid_avg_price: 1000.0,
+            bid_price_dev: 50.0,
+            ask_avg_price: 1000.0,
+            ask_price_dev: 50.0,
+        }
+    }
+}
+impl PhaseConfig {
+    /// Validates the generator parameters, `what` names the kind of section in error messages
+    pub(crate) fn validate_as(&self, what: &str) -> Result<(), String> {
+        let name = &self.name;
+        for (field, value) in [
+            ("bid_rate", self.bid_rate),
+            ("new_limit_rate", self.new_limit_rate),
+            ("cancel_rate", self.cancel_rate),
+            ("market_rate", self.market_rate),
+            ("update_rate", self.update_rate),
+        ] {
+            if !(0.0..=1.0).contains(&value) {
+                return Err(format!(
+                    "{what} `{name}`: `{field}` must be between 0 and 1"
+                ));
+            }
+        }
+        for (field, value) in [
+            ("order_rate", self.order_rate),
+            ("bid_avg_price", self.bid_avg_price),
+            ("bid_price_dev", self.bid_price_dev),
+            ("ask_avg_price", self.ask_avg_price),
+            ("ask_price_dev", self.ask_price_dev),
+        ] {
+            if !value.is_finite() || value <= 0.0 {
+                return Err(format!("{what} `{name}`: `{field}` must be > 0.0"));
+            }
+        }
+        if self.new_limit_rate + self.cancel_rate + self.market_rate + self.update_rate <= 0.0 {
+            return Err(format!(
+                "{what} `{name}`: order type rates must not all be 0"
+            ));
+        }
+        Ok(())
+    }
+    pub(crate) fn to_phase(&self) -> ScenarioPhase {
+        ScenarioPhase {
+            start: to_sim_time(self.start),
+            rate_controller: ConstantPoissonRate::new(self.order_rate),
+            type_selector: UniformTypeSelector::new(
+                self.bid_rate,
+                self.new_limit_rate,
+                self.market_rate,
+                self.cancel_rate,
+                self.update_rate,
+            ),
+            order_generator: GaussianOrderGenerator::new(
+                self.bid_avg_price,
+                self.bid_price_dev,
+                self.ask_avg_price,
+                self.ask_price_dev,
+            ),
+        }
+    }
+}
+
+/// Side of the book as written in scenario files
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SideConfig {
+    Bid,
+    Ask,
+}
+impl From<SideConfig> for OrderSide {
+    fn from(side: SideConfig) -> Self {
+        match side {
+            SideConfig::Bid => OrderSide::Bid,
+            SideConfig::Ask => OrderSide::Ask,
+        }
+    }
+}
+
+/// Event scripted to happen at a given sim time
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case", deny_unknown_fields)]
+pub enum ScriptedEvent {
+    /// A single large market order, `side` is the side of the aggressor so an `ask` sweep sells into the bids
+    Sweep {
+        time: f64,
+        side: SideConfig,
+        qty: OrderQty,
+    },
+    /// Cancels a fraction of the resting orders, on one side of the book or on both if `side` is left out
+    Withdraw {
+        time: f64,
+        fraction: f64,
+        side: Option<SideConfig>,
+    },
+    /// Halts trading, resuming after `duration` seconds if given
+    Halt { time: f64, duration: Option<f64> },
+    /// Resumes continuous trading after a halt
+    Resume { time: f64 },
+}
+impl ScriptedEvent {
+    fn time(&self) -> f64 {
+        match self {
+            ScriptedEvent::Sweep { time, .. }
+            | ScriptedEvent::Withdraw { time, .. }
+            | ScriptedEvent::Halt { time, .. }
+            | ScriptedEvent::Resume { time } => *time,
+        }
+    }
+    fn validate(&self) -> Result<(), String> {
+        // NOTE: NaN and infinite times would saturate to the first or last sim time
+        if !self.time().is_finite() || self.time() < 0.0 {
+            return Err("scripted event: `time` must be >= 0.0".into());
+        }
+        match self {
+            ScriptedEvent::Sweep { qty, .. } if *qty == 0 => Err("sweep: `qty` must be > 0".into()),
+            ScriptedEvent::Withdraw { fraction, .. } if !(0.0..=1.0).contains(fraction) => {
+                Err("withdraw: `fraction` must be between 0 and 1".into())
+            }
+            ScriptedEvent::Halt {
+                duration: Some(duration),
+                ..
+            } if !duration.is_finite() || *duration <= 0.0 => {
+                Err("halt: `duration` must be > 0.0".into())
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Scenario {
+    /// Reads and validates a scenario from a TOML file
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("scenario: {path}: {e}"))?;
+        Self::parse(&text).map_err(|e| format!("scenario: {path}: {e}"))
+    }
+    /// Parses and validates a scenario from TOML text
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let scenario: Scenario = toml::from_str(text).map_err(|e| e.to_string())?;
+        scenario.validate()?;
+        Ok(scenario)
+    }
+    /// Validate parameters that cannot be expressed through the file format itself
+    pub fn validate(&self) -> Result<(), String> {
+        match self.phases.first() {
+            None => return Err("at least one `[[phase]]` is required".into()),
+            Some(first) if first.start != 0.0 => {
+                return Err("the first phase must start at 0.0".into());
+            }
+            Some(_) => {}
+        }
+        if !self.phases.windows(2).all(|p| p[0].start < p[1].start) {
+            return Err("phases must be listed in order of strictly increasing `start`".into());
+        }
+        for phase in &self.phases {
+            phase.validate_as("phase")?;
+        }
+        for event in &self.events {
+            event.validate()?;
+        }
+        if let Some(time) = self.end.time
+            && (!time.is_finite() || time <= 0.0)
+        {
+            return Err("end: `time` must be > 0.0".into());
+        }
+        Ok(())
+    }
+    /// Builds the event source generating the scenario's synthetic order flow
+    pub fn source(&self, rng: ChaCha8Rng) -> ScenarioSource {
+        ScenarioSource::new(
+            self.phases.iter().map(PhaseConfig::to_phase).collect(),
+            self.end.time.map(to_sim_time),
+            self.end.orders,
+            rng,
+        )
+    }
+    /// Converts the scripted events into events for the Simulator's scheduler
+    pub fn scheduled_events(&self) -> Vec<(SimTime, SimEvent)> {
+        let mut events = Vec::with_capacity(self.events.len());
+        for event in &self.events {
+            let time = to_sim_time(event.time());
+            match *event {
+                ScriptedEvent::Sweep { side, qty, .. } => events.push((
+                    time,
+                    SimEvent::Shock(vec![Order::new(
+                        0,
+                        0, // NOTE: Use a junk value, simulator sets this on receipt
+                        side.into(),
+                        time,
+                        OrderType::Market { qty },
+                    )]),
+                )),
+                ScriptedEvent::Withdraw { fraction, side, .. } => events.push((
+                    time,
+                    SimEvent::Withdraw {
+                        fraction,
+                        side: side.map(OrderSide::from),
+                    },
+                )),
+                ScriptedEvent::Halt { duration, .. } => {
+                    events.push((time, SimEvent::Phase(MarketPhase::Halted)));
+                    if let Some(duration) = duration {
+                        events.push((
+                            time + to_sim_time(duration),
+                            SimEvent::Phase(MarketPhase::Continuous),
+                        ));
+                    }
+                }
+                ScriptedEvent::Resume { .. } => {
+                    events.push((time, SimEvent::Phase(MarketPhase::Continuous)))
+                }
+            }
+        }
+        events
+    }
+    /// Names of the phases in timeline order, unnamed phases are shown by their index
+    pub fn phase_names(&self) -> Vec<String> {
+        self.phases
+            .iter()
+            .enumerate()
+            .map(|(i, phase)| match phase.name.is_empty() {
+                true => format!("#{i}"),
+                false => phase.name.clone(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXAMPLE: &str = include_str!("../../../../scenarios/flash_crash.toml");
+
+    #[test]
+    fn example_scenario_parses() {
+        let scenario = Scenario::parse(EXAMPLE).unwrap();
+        assert_eq!(scenario.phase_names(), vec!["calm", "crash", "recovery"]);
+        assert!(!scenario.scheduled_events().is_empty());
+    }
+
+    #[test]
+    fn omitted_phase_fields_take_defaults() {
+        let scenario = Scenario::parse("[[phase]]\norder_rate = 5.0\n").unwrap();
+        let phase = &scenario.phases[0];
+        assert_eq!(phase.order_rate, 5.0);
+        assert_eq!(phase.bid_avg_price, 1000.0);
+        assert_eq!(scenario.phase_names(), vec!["#0"]);
+    }
+
+    #[test]
+    fn halt_with_duration_schedules_resume() {
+        let scenario =
+            Scenario::parse("[[phase]]\n[[event]]\nkind = \"halt\"\ntime = 1.0\nduration = 0.5\n")
+                .unwrap();
+        assert_eq!(
+            scenario.scheduled_events(),
+            vec![
+                (1_000_000_000, SimEvent::Phase(MarketPhase::Halted)),
+                (1_500_000_000, SimEvent::Phase(MarketPhase::Continuous)),
+            ]
+        );
+    }
+
+    #[test]
+    fn invalid_scenarios_are_rejected() {
+        assert!(Scenario::parse("").is_err());
+        assert!(Scenario::parse("[[phase]]\nstart = 1.0\n").is_err());
+        assert!(Scenario::parse("[[phase]]\n[[phase]]\n").is_err());
+        assert!(Scenario::parse("[[phase]]\nbid_rate = 1.5\n").is_err());
+        assert!(Scenario::parse("[[phase]]\nunknown = 1\n").is_err());
+        assert!(
+            Scenario::parse(
+                "[[phase]]\n[[event]]\nkind = \"withdraw\"\ntime = 1.0\nfraction = 2.0\n"
+            )
+            .is_err()
+        );
+        for event in [
+            "kind = \"withdraw\"\ntime = nan\nfraction = 0.5",
+            "kind = \"sweep\"\ntime = inf\nside = \"bid\"\nqty = 10",
+            "kind = \"halt\"\ntime = 1.0\nduration = nan",
+        ] {
+            assert!(Scenario::parse(&format!("[[phase]]\n[[event]]\n{event}\n")).is_err());
+        }
+    }
+}
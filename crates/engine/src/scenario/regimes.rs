@@ -0,0 +1,276 @@
+use crate::data_generator::event_source::{Regime, RegimeSource};
+use crate::scenario::{EndCondition, PhaseConfig, to_sim_time};
+use rand_chacha::ChaCha8Rng;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+
+/// Shortest mean time in seconds that a regime lasts, so that the source keeps generating orders between switches
+const MIN_MEAN_DURATION: f64 = 0.001;
+
+/// A regime file. Describes named regimes with their own generator parameters, how long the market stays in each and
+/// where it goes next. All times are in seconds of sim time
+///
+/// ```toml
+/// initial = "calm"
+///
+/// [end]
+/// time = 600.0
+///
+/// [[regime]]
+/// name = "calm"
+/// mean_duration = 60.0
+/// transitions = { volatile = 2.0, illiquid = 1.0 }
+///
+/// [[regime]]
+/// name = "volatile"
+/// mean_duration = 10.0
+/// order_rate = 300000.0
+/// bid_price_dev = 200.0
+/// ask_price_dev = 200.0
+/// transitions = { calm = 1.0 }
+/// ```
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct RegimeModel {
+    /// Name of the regime the run starts in, the first regime if left out
+    pub initial: Option<String>,
+    #[serde(default)]
+    pub end: EndCondition,
+    #[serde(rename = "regime")]
+    pub regimes: Vec<RegimeConfig>,
+}
+
+/// Generator parameters for one regime, fields that are left out take the same defaults as the `poisson` subcommand.
+/// `transitions` maps regime names to the relative odds of switching there, if left out every other regime is equally
+/// likely
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RegimeConfig {
+    pub name: String,
+    pub mean_duration: f64,
+    pub transitions: Option<BTreeMap<String, f64>>,
+    pub order_rate: f64,
+    pub bid_rate: f64,
+    pub new_limit_rate: f64,
+    pub cancel_rate: f64,
+    pub market_rate: f64,
+    pub update_rate: f64,
+    pub bid_avg_price: f64,
+    pub bid_price_dev: f64,
+    pub ask_avg_price: f64,
+    pub ask_price_dev: f64,
+}
+impl Default for RegimeConfig {
+    fn default() -> Self {
+        let phase = PhaseConfig::default();
+        Self {
+            name: String::new(),
+            mean_duration: 60.0,
+            transitions: None,
+            order_rate: phase.order_rate,
+            bid_rate: phase.bid_rate,
+            new_limit_rate: phase.new_limit_rate,
+            cancel_rate: phase.cancel_rate,
+            market_rate: phase.market_rate,
+            update_rate: phase.update_rate,
+            bid_avg_price: phase.bid_avg_price,
+            bid_price_dev: phase.bid_price_dev,
+            ask_avg_price: phase.ask_avg_price,
+            ask_price_dev: phase.ask_price_dev,
+        }
+    }
+}
+impl RegimeConfig {
+    fn phase(&self) -> PhaseConfig {
+        PhaseConfig {
+            name: self.name.clone(),
+            start: 0.0,
+            order_rate: self.order_rate,
+            bid_rate: self.bid_rate,
+            new_limit_rate: self.new_limit_rate,
+            cancel_rate: self.cancel_rate,
+            market_rate: self.market_rate,
+            update_rate: self.update_rate,
+            bid_avg_price: self.bid_avg_price,
+            bid_price_dev: self.bid_price_dev,
+            ask_avg_price: self.ask_avg_price,
+            ask_price_dev: self.ask_price_dev,
+        }
+    }
+}
+
+impl RegimeModel {
+    /// Reads and validates a regime model from a TOML file
+    pub fn load(path: &str) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("regimes: {path}: {e}"))?;
+        Self::parse(&text).map_err(|e| format!("regimes: {path}: {e}"))
+    }
+    /// Parses and validates a regime model from TOML text
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let model: RegimeModel = toml::from_str(text).map_err(|e| e.to_string())?;
+        model.validate()?;
+        Ok(model)
+    }
+    /// Validate parameters that cannot be expressed through the file format itself
+    pub fn validate(&self) -> Result<(), String> {
+        if self.regimes.is_empty() {
+            return Err("at least one `[[regime]]` is required".into());
+        }
+        for (i, regime) in self.regimes.iter().enumerate() {
+            let name = &regime.name;
+            if name.is_empty() {
+                return Err("every regime needs a `name`".into());
+            }
+            if self.regimes[..i].iter().any(|other| other.name == *name) {
+                return Err(format!("regime `{name}` is defined twice"));
+            }
+            if regime.mean_duration.is_nan() || regime.mean_duration < MIN_MEAN_DURATION {
+                return Err(format!(
+                    "regime `{name}`: `mean_duration` must be >= {MIN_MEAN_DURATION}"
+                ));
+            }
+            regime.phase().validate_as("regime")?;
+        }
+        for regime in &self.regimes {
+            for (target, odds) in regime.transitions.iter().flatten() {
+                if self.index(target).is_none() {
+                    return Err(format!(
+                        "regime `{}`: unknown regime `{target}` in `transitions`",
+                        regime.name
+                    ));
+                }
+                if odds.is_nan() || *odds < 0.0 {
+                    return Err(format!(
+                        "regime `{}`: transition odds must be >= 0.0",
+                        regime.name
+                    ));
+                }
+            }
+        }
+        if let Some(initial) = &self.initial
+            && self.index(initial).is_none()
+        {
+            return Err(format!("unknown `initial` regime `{initial}`"));
+        }
+        if let Some(time) = self.end.time
+            && (!time.is_finite() || time <= 0.0)
+        {
+            return Err("end: `time` must be > 0.0".into());
+        }
+        Ok(())
+    }
+    fn index(&self, name: &str) -> Option<usize> {
+        self.regimes.iter().position(|regime| regime.name == name)
+    }
+    /// Builds the event source generating the model's synthetic order flow
+    pub fn source(&self, rng: ChaCha8Rng) -> RegimeSource {
+        let regimes = self
+            .regimes
+            .iter()
+            .map(|config| {
+                let phase = config.phase().to_phase();
+                let transitions = match &config.transitions {
+                    Some(odds) => self
+                        .regimes
+                        .iter()
+                        .map(|target| odds.get(&target.name).copied().unwrap_or(0.0))
+                        .collect(),
+                    None => vec![1.0; self.regimes.len()],
+                };
+                Regime {
+                    name: config.name.clone(),
+                    rate_controller: phase.rate_controller,
+                    type_selector: phase.type_selector,
+                    order_generator: phase.order_generator,
+                    mean_duration: to_sim_time(config.mean_duration),
+                    transitions,
+                }
+            })
+            .collect();
+        let initial = self
+            .initial
+            .as_ref()
+            .and_then(|name| self.index(name))
+            .unwrap_or(0);
+        RegimeSource::new(
+            regimes,
+            initial,
+            self.end.time.map(to_sim_time),
+            self.end.orders,
+            rng,
+        )
+    }
+    /// Names of the regimes, indexed like the regimes of the source
+    pub fn regime_names(&self) -> Vec<String> {
+        self.regimes
+            .iter()
+            .map(|regime| regime.name.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::data_generator::event_source::EventSource;
+    use rand::SeedableRng;
+
+    const EXAMPLE: &str = include_str!("../../../../scenarios/regimes.toml");
+
+    #[test]
+    fn example_regimes_parse() {
+        let model = RegimeModel::parse(EXAMPLE).unwrap();
+        assert_eq!(model.regime_names(), vec!["calm", "volatile", "illiquid"]);
+        let mut source = model.source(ChaCha8Rng::seed_from_u64(0));
+        assert!(source.next_event().is_some());
+        assert_eq!(source.regime_name(), "calm");
+    }
+
+    #[test]
+    fn switches_are_written_as_labels() {
+        let path = std::env::temp_dir().join(format!("regime_labels_{}.csv", std::process::id()));
+        let model = RegimeModel::parse(
+            "[[regime]]\nname = \"a\"\nmean_duration = 0.01\n\
+             [[regime]]\nname = \"b\"\nmean_duration = 0.01\n",
+        )
+        .unwrap();
+        let mut source = model
+            .source(ChaCha8Rng::seed_from_u64(0))
+            .with_labels(path.to_str().unwrap())
+            .unwrap();
+        // Orders keep their client id, the regimes are only in the labels
+        while source.switches().len() < 4 {
+            assert_eq!(source.next_event().unwrap().client_id, 0);
+        }
+        let names = source.regime_names();
+        let expected: Vec<String> = std::iter::once("time,regime".to_string())
+            .chain(
+                source
+                    .switches()
+                    .iter()
+                    .map(|(time, regime)| format!("{time},{}", names[*regime])),
+            )
+            .collect();
+        drop(source);
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(text.lines().collect::<Vec<_>>(), expected);
+    }
+
+    #[test]
+    fn invalid_regimes_are_rejected() {
+        for text in [
+            "",
+            "[[regime]]\nmean_duration = 1.0",
+            "[[regime]]\nname = \"a\"\n[[regime]]\nname = \"a\"",
+            "[[regime]]\nname = \"a\"\nmean_duration = 0.0",
+            "[[regime]]\nname = \"a\"\nmean_duration = 1e-9",
+            "[[regime]]\nname = \"a\"\ntransitions = { b = 1.0 }",
+            "[[regime]]\nname = \"a\"\nbid_rate = 2.0",
+            "initial = \"b\"\n[[regime]]\nname = \"a\"",
+            "[end]\ntime = nan\n[[regime]]\nname = \"a\"",
+        ] {
+            assert!(RegimeModel::parse(text).is_err(), "{text}");
+        }
+    }
+}
@@ -0,0 +1,291 @@
+use crate::simulator::SimTime;
+use crate::simulator::scheduler::{MarketPhase, SimEvent};
+use mm_core::lob_core::market_events::SystemEventCode;
+use std::fmt;
+
+/// Nanoseconds in a calendar day
+pub const NS_PER_DAY: u64 = 86_400 * 1_000_000_000;
+
+/// Calendar date, used to label the trading day that sim time falls on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Date {
+    pub year: i32,
+    pub month: u32,
+    pub day: u32,
+}
+impl Date {
+    /// Parses a date of the form YYYY-MM-DD
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let invalid = || format!("`{}` is not a date of the form YYYY-MM-DD", s);
+        let mut parts = s.trim().splitn(3, '-');
+        let mut next = || parts.next().ok_or_else(invalid);
+        let year = next()?.parse().map_err(|_| invalid())?;
+        let month = next()?.parse().map_err(|_| invalid())?;
+        let day = next()?.parse().map_err(|_| invalid())?;
+        let date = Self { year, month, day };
+        // NOTE: Round-tripping through the day count rejects days past the end of the month
+        if !(1..=12).contains(&month) || day == 0 || Self::from_days(date.days()) != date {
+            return Err(invalid());
+        }
+        Ok(date)
+    }
+    /// The date a number of days later
+    pub fn add_days(&self, days: u64) -> Self {
+        Self::from_days(self.days() + days as i64)
+    }
+    /// Saturdays and Sundays have no trading session
+    pub fn is_weekend(&self) -> bool {
+        // NOTE: 1970-01-01 was a Thursday, so Saturday and Sunday are 2 and 3 days into the week
+        matches!(self.days().rem_euclid(7), 2 | 3)
+    }
+    /// Days since 1970-01-01, using Howard Hinnant's days_from_civil
+    fn days(&self) -> i64 {
+        let year = self.year as i64 - (self.month <= 2) as i64;
+        let era = year.div_euclid(400);
+        let year_of_era = year - era * 400;
+        let month = self.month as i64;
+        let day_of_year =
+            (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + self.day as i64 - 1;
+        let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+        era * 146_097 + day_of_era - 719_468
+    }
+    /// Inverse of days()
+    fn from_days(days: i64) -> Self {
+        let days = days + 719_468;
+        let era = days.div_euclid(146_097);
+        let day_of_era = days - era * 146_097;
+        let year_of_era =
+            (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+        let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+        let mp = (5 * day_of_year + 2) / 153;
+        let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+        let year = (year_of_era + era * 400 + (month <= 2) as i64) as i32;
+        Self { year, month, day }
+    }
+}
+impl fmt::Display for Date {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}-{:02}", self.year, self.month, self.day)
+    }
+}
+
+/// Parses a time of day of the form HH:MM, HH:MM:SS or HH:MM:SS.fffffffff into nanoseconds since midnight
+pub fn parse_time_of_day(s: &str) -> Result<u64, String> {
+    let invalid = || format!("`{}` is not a time of day of the form HH:MM[:SS[.f]]", s);
+    let (clock, fraction) = s.trim().split_once('.').unwrap_or((s.trim(), ""));
+    let fields: Vec<u64> = clock
+        .split(':')
+        .map(|field| field.parse().map_err(|_| invalid()))
+        .collect::<Result<_, _>>()?;
+    let (hours, minutes, seconds) = match fields[..] {
+        [hours, minutes] => (hours, minutes, 0),
+        [hours, minutes, seconds] => (hours, minutes, seconds),
+        _ => return Err(invalid()),
+    };
+    if hours > 23 || minutes > 59 || seconds > 59 || fraction.len() > 9 {
+        return Err(invalid());
+    }
+    let nanos = match fraction {
+        "" => 0,
+        digits => {
+            digits.parse::<u64>().map_err(|_| invalid())? * 10u64.pow(9 - digits.len() as u32)
+        }
+    };
+    Ok(((hours * 60 + minutes) * 60 + seconds) * 1_000_000_000 + nanos)
+}
+
+/// Trading day laid over sim time. Sim time 0 falls on `date` at time of day `start`, and every weekday runs the same
+/// sequence of phases, given as nanoseconds since midnight. Weekends have no session
+///
+/// Phases: closed until `pre_open`, pre-open until `opening_auction`, the opening auction until `market_open`,
+/// continuous trading until `closing_auction`, the closing auction until `market_close`, post-close until
+/// `end_of_day`, then closed again
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionCalendar {
+    pub date: Date,
+    pub start: u64,
+    pub pre_open: u64,
+    pub opening_auction: u64,
+    pub market_open: u64,
+    pub closing_auction: u64,
+    pub market_close: u64,
+    pub end_of_day: u64,
+}
+impl SessionCalendar {
+    /// Calendar with Nasdaq-like hours: pre-open from 04:00, the opening auction from 09:28, continuous trading from
+    /// 09:30, the closing auction from 15:50, the close at 16:00 and post-close until 20:00
+    pub fn new(date: Date, start: u64) -> Self {
+        const HOUR: u64 = 3_600_000_000_000;
+        const MINUTE: u64 = 60_000_000_000;
+        Self {
+            date,
+            start,
+            pre_open: 4 * HOUR,
+            opening_auction: 9 * HOUR + 28 * MINUTE,
+            market_open: 9 * HOUR + 30 * MINUTE,
+            closing_auction: 15 * HOUR + 50 * MINUTE,
+            market_close: 16 * HOUR,
+            end_of_day: 20 * HOUR,
+        }
+    }
+    /// Checks that the phases are in order and fall within a day
+    pub fn validate(&self) -> Result<(), String> {
+        let times = [
+            self.pre_open,
+            self.opening_auction,
+            self.market_open,
+            self.closing_auction,
+            self.market_close,
+            self.end_of_day,
+        ];
+        if times.windows(2).any(|pair| pair[0] > pair[1]) {
+            return Err("session: phase times must be in chronological order".into());
+        }
+        if self.start >= NS_PER_DAY || self.end_of_day >= NS_PER_DAY {
+            return Err("session: times must be before midnight".into());
+        }
+        Ok(())
+    }
+    /// Nanoseconds since midnight at a sim time, the timestamp ITCH messages carry
+    pub fn time_of_day(&self, time: SimTime) -> u64 {
+        (self.start + time) % NS_PER_DAY
+    }
+    /// Number of calendar days between the start of the simulation and a sim time
+    pub fn day(&self, time: SimTime) -> u64 {
+        (self.start + time) / NS_PER_DAY
+    }
+    /// Calendar date at a sim time
+    pub fn date_at(&self, time: SimTime) -> Date {
+        self.date.add_days(self.day(time))
+    }
+    /// Trading phase at a sim time
+    pub fn phase_at(&self, time: SimTime) -> MarketPhase {
+        if self.date_at(time).is_weekend() {
+            return MarketPhase::Closed;
+        }
+        match self.time_of_day(time) {
+            t if t < self.pre_open => MarketPhase::Closed,
+            t if t < self.opening_auction => MarketPhase::PreOpen,
+            t if t < self.market_open => MarketPhase::OpeningAuction,
+            t if t < self.closing_auction => MarketPhase::Continuous,
+            t if t < self.market_close => MarketPhase::ClosingAuction,
+            t if t < self.end_of_day => MarketPhase::PostClose,
+            _ => MarketPhase::Closed,
+        }
+    }
+    /// First day after `day` that has a session
+    pub fn next_trading_day(&self, day: u64) -> u64 {
+        (day + 1..)
+            .find(|day| !self.date.add_days(*day).is_weekend())
+            .unwrap()
+    }
+    /// Phase changes and system events of a day that happen at or after sim time `from`, in the order they should run.
+    /// System events that already passed are repeated at `from` so that a simulation starting mid-session still
+    /// announces the session, unless the whole session is over
+    pub fn day_events(&self, day: u64, from: SimTime) -> Vec<(SimTime, SimEvent)> {
+        if self.date.add_days(day).is_weekend() {
+            return Vec::new();
+        }
+        use SystemEventCode::*;
+        let system = |code| SimEvent::System(code);
+        let phase = |phase| SimEvent::Phase(phase);
+        let timeline = [
+            (self.pre_open, system(StartOfMessages)),
+            (self.pre_open, system(StartOfSystemHours)),
+            (self.pre_open, phase(MarketPhase::PreOpen)),
+            (self.opening_auction, phase(MarketPhase::OpeningAuction)),
+            // NOTE: The opening auction uncrosses on the phase change, after market hours are announced
+            (self.market_open, system(StartOfMarketHours)),
+            (self.market_open, phase(MarketPhase::Continuous)),
+            (self.closing_auction, phase(MarketPhase::ClosingAuction)),
+            (self.market_close, phase(MarketPhase::PostClose)),
+            (self.market_close, system(EndOfMarketHours)),
+            (self.end_of_day, phase(MarketPhase::Closed)),
+            (self.end_of_day, system(EndOfSystemHours)),
+            (self.end_of_day, system(EndOfMessages)),
+        ];
+        let sim_time = |time_of_day: u64| (day * NS_PER_DAY + time_of_day).checked_sub(self.start);
+        if sim_time(self.end_of_day).is_none_or(|end| end < from) {
+            return Vec::new();
+        }
+        timeline
+            .into_iter()
+            .filter_map(|(time_of_day, event)| match sim_time(time_of_day) {
+                Some(time) if time >= from => Some((time, event)),
+                _ => matches!(event, SimEvent::System(_)).then_some((from, event)),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HOUR: u64 = 3_600_000_000_000;
+
+    #[test]
+    fn dates_and_times_parse() {
+        let date = Date::parse("2024-02-28").unwrap();
+        assert_eq!(date.add_days(1).to_string(), "2024-02-29");
+        assert_eq!(date.add_days(2).to_string(), "2024-03-01");
+        assert!(Date::parse("2023-02-29").is_err());
+        assert!(Date::parse("2024-13-01").is_err());
+        assert!(Date::parse("2024-1").is_err());
+        // 2024-03-02 was a Saturday
+        assert!(date.add_days(3).is_weekend());
+        assert!(!date.add_days(5).is_weekend());
+        assert_eq!(
+            parse_time_of_day("09:30").unwrap(),
+            9 * HOUR + 1_800_000_000_000
+        );
+        assert_eq!(parse_time_of_day("00:00:01.5").unwrap(), 1_500_000_000);
+        assert!(parse_time_of_day("24:00").is_err());
+        assert!(parse_time_of_day("9").is_err());
+    }
+
+    #[test]
+    fn phases_follow_the_clock() {
+        // Friday 2024-03-01, starting at 09:00
+        let calendar = SessionCalendar::new(Date::parse("2024-03-01").unwrap(), 9 * HOUR);
+        assert_eq!(calendar.phase_at(0), MarketPhase::PreOpen);
+        assert_eq!(calendar.phase_at(HOUR), MarketPhase::Continuous);
+        assert_eq!(calendar.time_of_day(HOUR), 10 * HOUR);
+        assert_eq!(calendar.phase_at(7 * HOUR - 1), MarketPhase::ClosingAuction);
+        assert_eq!(calendar.phase_at(8 * HOUR), MarketPhase::PostClose);
+        assert_eq!(calendar.phase_at(12 * HOUR), MarketPhase::Closed);
+        // Saturday at 10:00
+        assert_eq!(calendar.date_at(25 * HOUR).to_string(), "2024-03-02");
+        assert_eq!(calendar.phase_at(25 * HOUR), MarketPhase::Closed);
+        assert_eq!(calendar.next_trading_day(0), 3);
+    }
+
+    #[test]
+    fn session_starting_mid_day_repeats_past_system_events() {
+        let calendar = SessionCalendar::new(Date::parse("2024-03-01").unwrap(), 10 * HOUR);
+        let events = calendar.day_events(0, 0);
+        let codes: Vec<(SimTime, u8)> = events
+            .iter()
+            .filter_map(|(time, event)| match event {
+                SimEvent::System(code) => Some((*time, *code as u8)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            codes,
+            vec![
+                (0, b'O'),
+                (0, b'S'),
+                (0, b'Q'),
+                (6 * HOUR, b'M'),
+                (10 * HOUR, b'E'),
+                (10 * HOUR, b'C')
+            ]
+        );
+        assert!(!events.contains(&(0, SimEvent::Phase(MarketPhase::PreOpen))));
+        assert!(calendar.day_events(0, 11 * HOUR).is_empty());
+        assert!(calendar.day_events(1, 0).is_empty());
+        assert_eq!(calendar.day_events(3, 0)[0].0, 3 * NS_PER_DAY - 6 * HOUR);
+    }
+}
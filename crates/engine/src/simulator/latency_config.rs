@@ -1,4 +1,3 @@
-use crate::cli_args::Args;
 use crate::simulator::SimTime;
 use clap::ValueEnum;
 use rand::Rng;
@@ -30,20 +29,6 @@ impl SimJitter {
     }
 }
 
-impl From<&Args> for SimJitter {
-    fn from(args: &Args) -> Self {
-        match args.sim_jitter_type {
-            JitterKind::None => SimJitter::None,
-            JitterKind::Uniform => SimJitter::Uniform(
-                Uniform::new_inclusive(args.low.unwrap(), args.high.unwrap()).unwrap(),
-            ),
-            JitterKind::Normal => {
-                SimJitter::Normal(Normal::new(args.mean.unwrap(), args.std_dev.unwrap()).unwrap())
-            }
-        }
-    }
-}
-
 /// Struct containing simulated latency effects
 #[derive(Clone, Copy, Debug)]
 pub struct LatencyConfig {
@@ -1,12 +1,20 @@
 pub mod latency_config;
+pub mod scheduler;
 
+use crate::agents::{AGENT_CLIENT_ID_BASE, Agent, AgentContext, AgentFeed};
+use crate::data_generator::book_view::BookView;
 use crate::data_generator::event_source::{EventSource, SourceEnum, SourceFunction};
+use crate::limit_order_book::OrderBook;
+use crate::session::SessionCalendar;
 use crate::simulator::latency_config::LatencyConfig;
-use engine::limit_order_book::OrderBook;
-use mm_core::lob_core::{market_events::EventSink, market_orders::Order};
-use rand::Rng;
+use crate::simulator::scheduler::{MarketPhase, Scheduler, SimEvent};
+use mm_core::lob_core::{
+    ClientId, OrderId,
+    market_events::{EventSink, MarketEventType, SystemEventCode},
+    market_orders::{LimitOrder, Order, OrderSide, OrderType},
+};
+use rand::{Rng, RngExt};
 use ringbuf::{HeapCons, traits::*};
-use std::collections::BinaryHeap;
 use std::time::{Duration, Instant};
 
 const USER_ORDER_INGRESS: usize = 1024;
@@ -18,16 +26,25 @@ pub type SimTime = u64;
 /// Object that owns the simulation, responsible for managing simulation time
 pub struct Simulator<E: EventSource, S: EventSink, R: Rng> {
     time: SimTime,
-    limit_order_book: OrderBook<S>,
-    orders: BinaryHeap<Order>,
+    phase: MarketPhase,
+    session: Option<SessionCalendar>,
+    auction_orders: Vec<Order>,
+    limit_order_book: OrderBook<AgentFeed<S>>,
+    scheduler: Scheduler,
     source: E,
+    book_view: BookView,
+    pending_source_event: Option<u64>,
     user_orders: HeapCons<Order>,
     user_order_buffer: Vec<Order>,
-    id_counter: u64,
+    id_counter: OrderId,
+    agents: Vec<Box<dyn Agent>>,
+    agent_orders: Vec<Order>,
+    agent_wakeups: Vec<SimTime>,
     latency_settings: LatencyConfig,
     rng: R,
     real_time: Instant,
     is_real_time: bool,
+    replay: bool,
 }
 impl<E: EventSource, S: EventSink, R: Rng> Simulator<E, S, R> {
     pub fn new(
@@ -38,60 +55,390 @@ impl<E: EventSource, S: EventSink, R: Rng> Simulator<E, S, R> {
         rng: R,
         is_real_time: bool,
     ) -> Self {
+        let mut feed = AgentFeed::new(event_sink);
+        feed.set_capture(source.tracks_orders() || source.reads_book());
         Self {
             time: 0,
-            limit_order_book: OrderBook::new(event_sink),
-            orders: BinaryHeap::with_capacity(SIM_HEAP_CAPACITY),
+            phase: MarketPhase::Continuous,
+            session: None,
+            auction_orders: Vec::new(),
+            limit_order_book: OrderBook::new(feed),
+            scheduler: Scheduler::with_capacity(SIM_HEAP_CAPACITY),
             latency_settings,
             source,
+            book_view: BookView::default(),
+            pending_source_event: None,
             user_orders,
             user_order_buffer: vec![Order::default(); USER_ORDER_INGRESS],
             id_counter: 0,
+            agents: Vec::new(),
+            agent_orders: Vec::new(),
+            agent_wakeups: Vec::new(),
             rng,
             real_time: Instant::now(),
             is_real_time,
+            replay: false,
         }
     }
-    /// Takes a single step in the simulation. Generates a single order from its held order source,
-    /// drains a batch of user orders, inserts them all into a heap, and selects the one with the lowest timestamp
+    /// Registers an in-process agent and schedules its first wake-up at the current sim time.
+    /// Returns the client id that the agent's orders and client events are tagged with
+    pub fn add_agent(&mut self, agent: Box<dyn Agent>) -> ClientId {
+        let index = self.agents.len();
+        self.agents.push(agent);
+        self.scheduler.schedule(self.time, SimEvent::Wakeup(index));
+        self.limit_order_book.event_sink_mut().set_capture(true);
+        AGENT_CLIENT_ID_BASE + index as ClientId
+    }
+    /// Schedules an event on the simulation clock. Orders are assigned an engine order id when scheduled, which is
+    /// returned. Times in the past are clamped to the current sim time
+    pub fn schedule(&mut self, time: SimTime, event: SimEvent) -> Option<OrderId> {
+        let time = time.max(self.time);
+        match event {
+            SimEvent::Order(mut order) => {
+                order.timestamp = time;
+                let order_id = self.id_counter;
+                self.push_order(order);
+                Some(order_id)
+            }
+            event => {
+                self.scheduler.schedule(time, event);
+                None
+            }
+        }
+    }
+    /// Takes a single step in the simulation. Drains a batch of user orders into the scheduler, keeps exactly one
+    /// order from the held event source pending, and runs scheduled events in time order until one order has been
+    /// processed by the book. Returns that order
     pub fn step(&mut self) -> Result<Order, String> {
         self.drain_user_orders();
-        if let Some(synth_order) = self.generate_single_order() {
-            self.orders.push(synth_order);
-            let mut event = self.orders.pop().unwrap();
-            event.order_id = self.id_counter;
-            self.id_counter += 1;
-            if self.is_real_time {
-                self.pace(event.timestamp);
+        loop {
+            if let Some(order) = self.run_next_event()? {
+                return Ok(order);
+            }
+        }
+    }
+    /// Runs every event scheduled up to and including sim time `time`, then moves the clock to `time`. Used to pause
+    /// a simulation at an exact instant, for example before forking it. Returns the number of orders processed, or an
+    /// error if the source ran out before `time` once the events scheduled until then have run
+    pub fn run_until(&mut self, time: SimTime) -> Result<u64, String> {
+        self.drain_user_orders();
+        let mut processed = 0;
+        loop {
+            let exhausted = self.fill_pending_source_event().err();
+            if self.scheduler.peek_time().is_none_or(|next| next > time) {
+                if let Some(error) = exhausted {
+                    return Err(error);
+                }
+                break;
+            }
+            if self.run_scheduled_event().is_some() {
+                processed += 1;
             }
-            self.process_event(event);
-            Ok(event)
-        } else {
-            Err("Reached end of event stream".to_string())
         }
+        self.time = self.time.max(time);
+        Ok(processed)
+    }
+    /// Lays a trading calendar over sim time. The market moves to the calendar's phase at the current time, and the
+    /// phase changes and system events of every trading day are scheduled one day at a time
+    pub fn set_session(&mut self, session: SessionCalendar) {
+        self.session = Some(session);
+        self.set_phase(session.phase_at(self.time));
+        self.schedule_session_day(session.day(self.time));
+    }
+    /// Lets orders of the event source keep the engine ids they carry instead of numbering them, used to replay a
+    /// recording of a run
+    pub fn set_replay(&mut self, replay: bool) {
+        self.replay = replay;
     }
     /// Getter for the current simulation time
     pub fn time(&self) -> SimTime {
         self.time
     }
-    /// Attempts to read a batch of user orders from the queue and inserts them into the order heap
+    /// Getter for the current trading phase
+    pub fn phase(&self) -> MarketPhase {
+        self.phase
+    }
+    /// Replaces the simulated latency applied to user and agent orders from now on
+    pub fn set_latency(&mut self, latency_settings: LatencyConfig) {
+        self.latency_settings = latency_settings;
+    }
+    /// Mutable access to the event source, used to change the parameters of a running source
+    pub fn source_mut(&mut self) -> &mut E {
+        &mut self.source
+    }
+    /// Read-only view of the order book
+    pub fn order_book(&self) -> &OrderBook<AgentFeed<S>> {
+        &self.limit_order_book
+    }
+    /// Mutable access to the event sink that receives every event emitted by the order book
+    pub fn event_sink_mut(&mut self) -> &mut S {
+        self.limit_order_book.event_sink_mut().inner_mut()
+    }
+    /// Assigns the next engine order id to an order and schedules it at its timestamp, returning the scheduler
+    /// sequence number. Ids are handed out in insertion order
+    fn push_order(&mut self, mut order: Order) -> u64 {
+        order.order_id = self.id_counter;
+        self.id_counter += 1;
+        self.scheduler
+            .schedule(order.timestamp, SimEvent::Order(order))
+    }
+    /// Makes sure an order from the event source is waiting in the scheduler
+    fn fill_pending_source_event(&mut self) -> Result<(), String> {
+        if self.pending_source_event.is_none() {
+            if self.source.reads_book() {
+                self.book_view.refresh(&self.limit_order_book);
+                self.source.on_book(&self.book_view);
+            }
+            match self.generate_single_order() {
+                Some(mut synth_order) => {
+                    if self.replay {
+                        // NOTE: Orders the simulator creates itself are numbered after the replayed ones
+                        self.id_counter = self.id_counter.max(synth_order.order_id + 1);
+                    } else {
+                        synth_order.order_id = self.id_counter;
+                        self.id_counter += 1;
+                    }
+                    self.source.on_submitted(&synth_order);
+                    self.pending_source_event = Some(
+                        self.scheduler
+                            .schedule(synth_order.timestamp, SimEvent::Order(synth_order)),
+                    );
+                }
+                None => return Err("Reached end of event stream".to_string()),
+            }
+        }
+        Ok(())
+    }
+    /// Pops the earliest scheduled event and runs it. Returns the order if the event was an order processed by the book
+    fn run_next_event(&mut self) -> Result<Option<Order>, String> {
+        self.fill_pending_source_event()?;
+        // NOTE: The pending source order guarantees the scheduler is not empty
+        Ok(self.run_scheduled_event())
+    }
+    /// Runs the earliest scheduled event, which must exist. Returns the order if the event was an order processed by
+    /// the book
+    fn run_scheduled_event(&mut self) -> Option<Order> {
+        let entry = self.scheduler.pop().unwrap();
+        if self.pending_source_event == Some(entry.seq) {
+            self.pending_source_event = None;
+        }
+        self.time = self.time.max(entry.time);
+        match entry.event {
+            SimEvent::Order(event) => {
+                if self.is_real_time {
+                    self.pace(event.timestamp);
+                }
+                self.process_event(event);
+                self.dispatch_agent_events();
+                return Some(event);
+            }
+            SimEvent::Wakeup(index) => self.wake_agent(index),
+            SimEvent::Phase(phase) => {
+                self.set_phase(phase);
+                self.dispatch_agent_events();
+            }
+            SimEvent::System(code) => {
+                self.limit_order_book.publish_system_event(code, self.time);
+                self.dispatch_agent_events();
+                if code == SystemEventCode::EndOfMessages
+                    && let Some(session) = self.session
+                {
+                    self.schedule_session_day(session.next_trading_day(session.day(self.time)));
+                }
+            }
+            SimEvent::Expiry(order) => {
+                let cancel = Order::new(
+                    order.client_id,
+                    0, // NOTE: Use a junk value, push_order() sets this
+                    order.side,
+                    self.time,
+                    OrderType::Cancel {
+                        old_id: order.order_id,
+                    },
+                );
+                self.push_order(cancel);
+            }
+            SimEvent::Shock(orders) => {
+                for mut order in orders {
+                    order.timestamp = self.time;
+                    self.push_order(order);
+                }
+            }
+            SimEvent::Withdraw { fraction, side } => self.withdraw_liquidity(fraction, side),
+        }
+        None
+    }
+    /// Attempts to read a batch of user orders from the queue and inserts them into the scheduler
     fn drain_user_orders(&mut self) {
         for i in 0..self.user_orders.pop_slice(&mut self.user_order_buffer) {
             let mut order = self.user_order_buffer[i];
             order.timestamp = self.time
                 + self.latency_settings.latency
                 + self.latency_settings.jitter.sample(&mut self.rng);
-            self.orders.push(order)
+            self.push_order(order);
         }
     }
     /// Generates a single event from the artificial event source
     fn generate_single_order(&mut self) -> Option<Order> {
         self.source.next_event()
     }
-    /// Update the simulation time to the timestamp of the order, and pass the order into the limit order book
+    /// Update the simulation time to the timestamp of the order, and pass the order into the limit order book.
+    /// Cancels always reach the book. During an auction every other order is collected until the uncross, and outside
+    /// continuous trading it is rejected
     fn process_event(&mut self, event: Order) {
         self.time = event.timestamp;
-        self.limit_order_book.process_order(event);
+        match (self.phase, event.kind) {
+            (MarketPhase::Continuous, _) | (_, OrderType::Cancel { .. }) => {
+                self.limit_order_book.process_order(event);
+            }
+            (phase, _) if phase.is_auction() => self.auction_orders.push(event),
+            _ => self.limit_order_book.reject_order(event, self.time),
+        }
+    }
+    /// Moves the market into a new phase, uncrossing the collected orders when an auction ends
+    fn set_phase(&mut self, phase: MarketPhase) {
+        let uncross = self.phase.is_auction() && self.phase != phase;
+        self.phase = phase;
+        if uncross {
+            self.uncross();
+        }
+    }
+    /// Releases the orders collected during an auction into the book at the current time, where they trade at a
+    /// single clearing price, see OrderBook::uncross()
+    fn uncross(&mut self) {
+        let mut orders = std::mem::take(&mut self.auction_orders);
+        for order in orders.iter_mut() {
+            order.timestamp = self.time;
+        }
+        self.limit_order_book.uncross(&orders, self.time);
+        orders.clear();
+        self.auction_orders = orders;
+    }
+    /// Schedules the phase changes and system events of a trading day, from the current time on. Days whose session
+    /// already ended move on to the next trading day
+    fn schedule_session_day(&mut self, day: u64) {
+        let Some(session) = self.session else {
+            return;
+        };
+        let events = session.day_events(day, self.time);
+        if events.is_empty() {
+            self.schedule_session_day(session.next_trading_day(day));
+            return;
+        }
+        for (time, event) in events {
+            self.scheduler.schedule(time, event);
+        }
+    }
+    /// Schedules cancels for a random fraction of the resting orders. Orders are visited in id order so the
+    /// selection only depends on the simulator's rng
+    fn withdraw_liquidity(&mut self, fraction: f64, side: Option<OrderSide>) {
+        let mut resting: Vec<LimitOrder> = self
+            .limit_order_book
+            .resting_orders()
+            .filter(|order| side.is_none_or(|side| order.side == side))
+            .copied()
+            .collect();
+        resting.sort_unstable_by_key(|order| order.order_id);
+        for order in resting {
+            if self.rng.random_bool(fraction) {
+                self.push_order(Order::new(
+                    order.client_id,
+                    0, // NOTE: Use a junk value, push_order() sets this
+                    order.side,
+                    self.time,
+                    OrderType::Cancel {
+                        old_id: order.order_id,
+                    },
+                ));
+            }
+        }
+    }
+    /// Runs the wake-up callback of an agent
+    fn wake_agent(&mut self, index: usize) {
+        let mut ctx = AgentContext::new(
+            self.time,
+            AGENT_CLIENT_ID_BASE + index as ClientId,
+            &mut self.id_counter,
+            &mut self.agent_orders,
+            &mut self.agent_wakeups,
+        );
+        self.agents[index].on_wakeup(&mut ctx);
+        self.collect_agent_actions(index);
+    }
+    /// Hands the events emitted by the last processed order to the agents and the event source. Market events go to
+    /// every agent, client events only to the agent owning the order, or to the source for every other client
+    fn dispatch_agent_events(&mut self) {
+        let sink = self.limit_order_book.event_sink_mut();
+        for event in &sink.market_events {
+            if let MarketEventType::Trade(trade) = &event.kind {
+                self.book_view.record_trade(trade);
+            }
+        }
+        if self.agents.is_empty() {
+            for event in &sink.client_events {
+                self.source.on_client_event(event);
+            }
+            sink.market_events.clear();
+            sink.client_events.clear();
+            return;
+        }
+        let market_events = std::mem::take(&mut sink.market_events);
+        let client_events = std::mem::take(&mut sink.client_events);
+        for event in &market_events {
+            for index in 0..self.agents.len() {
+                let mut ctx = AgentContext::new(
+                    self.time,
+                    AGENT_CLIENT_ID_BASE + index as ClientId,
+                    &mut self.id_counter,
+                    &mut self.agent_orders,
+                    &mut self.agent_wakeups,
+                );
+                self.agents[index].on_market_event(event, &mut ctx);
+                self.collect_agent_actions(index);
+            }
+        }
+        for event in &client_events {
+            let Some(index) = event
+                .client_id
+                .checked_sub(AGENT_CLIENT_ID_BASE)
+                .map(|index| index as usize)
+                .filter(|index| *index < self.agents.len())
+            else {
+                self.source.on_client_event(event);
+                continue;
+            };
+            let mut ctx = AgentContext::new(
+                self.time,
+                event.client_id,
+                &mut self.id_counter,
+                &mut self.agent_orders,
+                &mut self.agent_wakeups,
+            );
+            self.agents[index].on_client_event(event, &mut ctx);
+            self.collect_agent_actions(index);
+        }
+        // Hand the buffers back so their allocations are reused
+        let sink = self.limit_order_book.event_sink_mut();
+        sink.market_events = market_events;
+        sink.market_events.clear();
+        sink.client_events = client_events;
+        sink.client_events.clear();
+    }
+    /// Moves orders and wake-ups requested by an agent during a callback into the scheduler.
+    /// Agent orders already carry their engine order id and experience the same simulated latency as user orders
+    fn collect_agent_actions(&mut self, index: usize) {
+        for i in 0..self.agent_orders.len() {
+            let mut order = self.agent_orders[i];
+            order.timestamp = self.time
+                + self.latency_settings.latency
+                + self.latency_settings.jitter.sample(&mut self.rng);
+            self.scheduler
+                .schedule(order.timestamp, SimEvent::Order(order));
+        }
+        self.agent_orders.clear();
+        for time in self.agent_wakeups.drain(..) {
+            self.scheduler.schedule(time, SimEvent::Wakeup(index));
+        }
     }
     /// Wait until wall clock time reaches the current simulation time
     fn pace(&self, next_event_time: SimTime) {
@@ -101,6 +448,70 @@ impl<E: EventSource, S: EventSink, R: Rng> Simulator<E, S, R> {
         std::thread::sleep(Duration::from_nanos(real_time_delta));
     }
 }
+impl<E: EventSource + Clone, S: EventSink, R: Rng + Clone> Simulator<E, S, R> {
+    /// Copies the full state of the simulation: the book, every pending scheduled event, the event source with its
+    /// rng, the agents, the order id counter and the simulator's own rng. The fork emits events to its own sink, reads
+    /// user orders from its own queue, and evolves independently of the original. Without further input both produce
+    /// identical events
+    pub fn fork<U: EventSink>(
+        &self,
+        event_sink: U,
+        user_orders: HeapCons<Order>,
+    ) -> Simulator<E, U, R> {
+        self.fork_with(self.source.clone(), event_sink, user_orders)
+    }
+}
+impl<S: EventSink, R: Rng + Clone> Simulator<SourceFunction, S, R> {
+    /// Forks a simulation whose source was picked at run time, see `fork`. Fails if the source cannot be copied, such
+    /// as a replay of a file or a merge with one among its inputs
+    pub fn try_fork<U: EventSink>(
+        &self,
+        event_sink: U,
+        user_orders: HeapCons<Order>,
+    ) -> Result<Simulator<SourceFunction, U, R>, String> {
+        let source = self
+            .source
+            .fork_source()
+            .ok_or("fork: the event source cannot be copied")?;
+        Ok(self.fork_with(source, event_sink, user_orders))
+    }
+}
+impl<E: EventSource, S: EventSink, R: Rng + Clone> Simulator<E, S, R> {
+    /// Copies the state of the simulation around a copy of its event source
+    fn fork_with<U: EventSink>(
+        &self,
+        source: E,
+        event_sink: U,
+        user_orders: HeapCons<Order>,
+    ) -> Simulator<E, U, R> {
+        let mut feed = AgentFeed::new(event_sink);
+        feed.set_capture(
+            !self.agents.is_empty() || self.source.tracks_orders() || self.source.reads_book(),
+        );
+        Simulator {
+            time: self.time,
+            phase: self.phase,
+            session: self.session,
+            auction_orders: self.auction_orders.clone(),
+            limit_order_book: self.limit_order_book.fork(feed),
+            scheduler: self.scheduler.clone(),
+            source,
+            book_view: self.book_view.clone(),
+            pending_source_event: self.pending_source_event,
+            user_orders,
+            user_order_buffer: vec![Order::default(); USER_ORDER_INGRESS],
+            id_counter: self.id_counter,
+            agents: self.agents.iter().map(|agent| agent.clone_box()).collect(),
+            agent_orders: Vec::new(),
+            agent_wakeups: Vec::new(),
+            latency_settings: self.latency_settings,
+            rng: self.rng.clone(),
+            real_time: self.real_time,
+            is_real_time: self.is_real_time,
+            replay: self.replay,
+        }
+    }
+}
 /// A specific typedef of Simulator, where the EventSource is a struct that wraps around a function pointer
 /// This allows the source type to be picked dynamically at run-time, but comes with a performance penalty for
 /// virtual calls
@@ -114,13 +525,19 @@ pub type EnumSimulator<S, R> = Simulator<SourceEnum, S, R>;
 mod tests {
     use super::*;
     use crate::{
+        agents::{market_maker::MarketMakerAgent, zero_intelligence::ZeroIntelligenceAgent},
         data_generator::{
-            event_source::RandomSource, order_generators::GaussianOrderGenerator,
-            rate_controllers::ConstantPoissonRate, type_selectors::UniformTypeSelector,
+            event_source::{ConstantPoissonSource, MergedSource, RandomSource},
+            order_generators::GaussianOrderGenerator,
+            rate_controllers::ConstantPoissonRate,
+            type_selectors::UniformTypeSelector,
         },
+        session::Date,
         simulator::latency_config::SimJitter,
     };
-    use mm_core::lob_core::market_events::{ClientEvent, MarketEvent, NullFeeds, SingleEventFeed};
+    use mm_core::lob_core::market_events::{
+        ClientEvent, ClientEventType, MarketEvent, MarketEventType, NullFeeds, SingleEventFeed,
+    };
     use rand::SeedableRng;
     use rand_chacha::ChaCha8Rng;
     use ringbuf::HeapRb;
@@ -205,4 +622,425 @@ mod tests {
         }
         assert!(saw_greater_than_zero);
     }
+
+    fn agent_simulation(seed: u64) -> Simulator<ConstantPoissonSource, NullFeeds, ChaCha8Rng> {
+        let (_, user_order_cons) = HeapRb::<Order>::new(SIM_HEAP_CAPACITY).split();
+        let mut sim = Simulator::new(
+            RandomSource::new(
+                ConstantPoissonRate::new(10_000.0),
+                UniformTypeSelector::new(0.5, 0.4, 0.3, 0.2, 0.1),
+                GaussianOrderGenerator::new(1000.0, 10.0, 1000.0, 10.0),
+                ChaCha8Rng::seed_from_u64(seed),
+                None,
+            ),
+            NullFeeds {},
+            user_order_cons,
+            LatencyConfig {
+                latency: 1_000,
+                jitter: SimJitter::None,
+            },
+            ChaCha8Rng::seed_from_u64(seed),
+            false,
+        );
+        sim.add_agent(Box::new(ZeroIntelligenceAgent::new(
+            5_000.0,
+            980,
+            1020,
+            10,
+            20,
+            ChaCha8Rng::seed_from_u64(seed + 1),
+        )));
+        sim.add_agent(Box::new(MarketMakerAgent::new(1000, 2, 5, 50, 100_000)));
+        sim
+    }
+
+    #[test]
+    fn agents_trade_deterministically() {
+        let run = |seed| {
+            let mut sim = agent_simulation(seed);
+            (0..20_000).map(|_| sim.step().unwrap()).collect::<Vec<_>>()
+        };
+        let first = run(7);
+        assert_eq!(first, run(7));
+        let agent_orders = first
+            .iter()
+            .filter(|order| order.client_id >= AGENT_CLIENT_ID_BASE)
+            .count();
+        assert!(agent_orders > 1_000, "agent orders: {agent_orders}");
+        assert!(first.windows(2).all(|o| o[0].timestamp <= o[1].timestamp));
+    }
+
+    #[test]
+    fn earliest_order_is_processed_first() {
+        let (mut user_order_prod, user_order_cons) =
+            HeapRb::<Order>::new(SIM_HEAP_CAPACITY).split();
+        let mut sim = Simulator::new(
+            RandomSource::new(
+                ConstantPoissonRate::new(1.0),
+                UniformTypeSelector::new(0.5, 0.4, 0.3, 0.2, 0.1),
+                GaussianOrderGenerator::new(150.0, 30.0, 150.0, 30.0),
+                ChaCha8Rng::seed_from_u64(0),
+                None,
+            ),
+            NullFeeds {},
+            user_order_cons,
+            LatencyConfig {
+                latency: 10,
+                jitter: SimJitter::None,
+            },
+            ChaCha8Rng::seed_from_u64(67),
+            false,
+        );
+        let _ = user_order_prod.try_push(Order::default());
+        let order = sim.step().unwrap();
+        assert_eq!(order.timestamp, 10);
+        assert!(sim.step().unwrap().timestamp > 10);
+    }
+
+    /// Simulation with a source that produces roughly one non-crossing limit order per second
+    fn quiet_simulation() -> (
+        Simulator<ConstantPoissonSource, SingleEventFeed, ChaCha8Rng>,
+        HeapCons<ClientEvent>,
+        HeapCons<MarketEvent>,
+    ) {
+        let (_, user_order_cons) = HeapRb::<Order>::new(SIM_HEAP_CAPACITY).split();
+        let (market_event_prod, market_event_cons) = HeapRb::<MarketEvent>::new(1 << 16).split();
+        let (client_event_prod, client_event_cons) = HeapRb::<ClientEvent>::new(1 << 16).split();
+        let sim = Simulator::new(
+            RandomSource::new(
+                ConstantPoissonRate::new(1.0),
+                UniformTypeSelector::new(0.5, 1.0, 0.0, 0.0, 0.0),
+                GaussianOrderGenerator::new(100.0, 1.0, 200.0, 1.0),
+                ChaCha8Rng::seed_from_u64(0),
+                None,
+            ),
+            SingleEventFeed::new(market_event_prod, client_event_prod),
+            user_order_cons,
+            LatencyConfig {
+                latency: 0,
+                jitter: SimJitter::None,
+            },
+            ChaCha8Rng::seed_from_u64(67),
+            false,
+        );
+        (sim, client_event_cons, market_event_cons)
+    }
+
+    #[test]
+    fn scheduled_events_run_in_time_order() {
+        let (mut sim, _, _) = quiet_simulation();
+        let resting = Order::new(
+            1,
+            0,
+            OrderSide::Bid,
+            0,
+            OrderType::Limit { qty: 5, price: 50 },
+        );
+        sim.schedule(10, SimEvent::Order(resting));
+        sim.schedule(
+            20,
+            SimEvent::Expiry(Order {
+                order_id: 0,
+                ..resting
+            }),
+        );
+        sim.schedule(30, SimEvent::Shock(vec![resting, resting]));
+        let times: Vec<SimTime> = (0..4).map(|_| sim.step().unwrap().timestamp).collect();
+        assert_eq!(times, vec![10, 20, 30, 30]);
+    }
+
+    #[test]
+    fn halted_market_rejects_new_orders() {
+        let (mut sim, mut client_event_cons, _) = quiet_simulation();
+        let order = Order::new(
+            1,
+            0,
+            OrderSide::Ask,
+            0,
+            OrderType::Limit { qty: 5, price: 50 },
+        );
+        sim.schedule(5, SimEvent::Phase(MarketPhase::Halted));
+        sim.schedule(10, SimEvent::Order(order));
+        sim.schedule(15, SimEvent::Phase(MarketPhase::Continuous));
+        sim.schedule(20, SimEvent::Order(order));
+        sim.step().unwrap();
+        assert_eq!(sim.phase(), MarketPhase::Halted);
+        assert_eq!(
+            client_event_cons.try_pop().unwrap().kind,
+            ClientEventType::Rejected
+        );
+        sim.step().unwrap();
+        assert_eq!(sim.phase(), MarketPhase::Continuous);
+        assert_eq!(
+            client_event_cons.try_pop().unwrap().kind,
+            ClientEventType::Accepted(5)
+        );
+    }
+
+    #[test]
+    fn session_announces_phases_and_uncrosses_auctions() {
+        let (mut sim, mut client_event_cons, mut market_event_cons) = quiet_simulation();
+        // Friday session compressed into nanoseconds, sim time 0 is midnight
+        let mut session = SessionCalendar::new(Date::parse("2024-03-01").unwrap(), 0);
+        (
+            session.pre_open,
+            session.opening_auction,
+            session.market_open,
+        ) = (10, 20, 30);
+        (
+            session.closing_auction,
+            session.market_close,
+            session.end_of_day,
+        ) = (40, 50, 60);
+        sim.set_session(session);
+        assert_eq!(sim.phase(), MarketPhase::Closed);
+        let limit = |side, price| Order::new(1, 0, side, 0, OrderType::Limit { qty: 5, price });
+        sim.schedule(5, SimEvent::Order(limit(OrderSide::Bid, 150)));
+        sim.schedule(25, SimEvent::Order(limit(OrderSide::Bid, 150)));
+        sim.schedule(26, SimEvent::Order(limit(OrderSide::Ask, 140)));
+        sim.run_until(35).unwrap();
+        assert_eq!(sim.phase(), MarketPhase::Continuous);
+        assert_eq!(
+            client_event_cons.try_pop().unwrap().kind,
+            ClientEventType::Rejected
+        );
+        let events: Vec<_> = market_event_cons
+            .pop_iter()
+            .map(|event| (event.timestamp, event.kind))
+            .collect();
+        let codes: Vec<u8> = events
+            .iter()
+            .filter_map(|(_, kind)| match kind {
+                MarketEventType::System(code) => Some(*code as u8),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(codes, b"OSQ");
+        // The auction orders trade once market hours are announced
+        assert!(matches!(events[2], (30, MarketEventType::System(_))));
+        let trades: Vec<_> = events
+            .iter()
+            .filter_map(|(time, kind)| match kind {
+                MarketEventType::Trade(trade) => Some((*time, trade.price, trade.quantity)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(trades, [(30, 140, 5)]);
+        sim.run_until(100).unwrap();
+        assert_eq!(sim.phase(), MarketPhase::Closed);
+        let codes: Vec<u8> = market_event_cons
+            .pop_iter()
+            .filter_map(|event| match event.kind {
+                MarketEventType::System(code) => Some(code as u8),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(codes, b"MEC");
+    }
+
+    #[test]
+    fn run_until_stops_at_time() {
+        let (mut sim, _, _) = quiet_simulation();
+        let order = Order::new(
+            1,
+            0,
+            OrderSide::Bid,
+            0,
+            OrderType::Limit { qty: 5, price: 50 },
+        );
+        for time in [10, 20, 30] {
+            sim.schedule(time, SimEvent::Order(order));
+        }
+        assert_eq!(sim.run_until(25), Ok(2));
+        assert_eq!(sim.time(), 25);
+        assert_eq!(sim.step().unwrap().timestamp, 30);
+    }
+
+    #[test]
+    fn run_until_drains_events_after_the_source_ends() {
+        let (_, user_order_cons) = HeapRb::<Order>::new(SIM_HEAP_CAPACITY).split();
+        let mut sim = DynamicSimulator::new(
+            SourceFunction::new(Box::new(|| None)),
+            NullFeeds {},
+            user_order_cons,
+            LatencyConfig {
+                latency: 0,
+                jitter: SimJitter::None,
+            },
+            ChaCha8Rng::seed_from_u64(0),
+            false,
+        );
+        let order = Order::new(
+            1,
+            0,
+            OrderSide::Bid,
+            0,
+            OrderType::Limit { qty: 5, price: 50 },
+        );
+        for time in [10, 20, 30] {
+            sim.schedule(time, SimEvent::Order(order));
+        }
+        assert!(sim.run_until(25).is_err());
+        assert_eq!(sim.time(), 20);
+        assert_eq!(sim.order_book().bid_levels().next(), Some((50, 10)));
+    }
+
+    #[test]
+    fn dynamic_simulations_fork_copyable_sources() {
+        let source = || {
+            RandomSource::new(
+                ConstantPoissonRate::new(10_000.0),
+                UniformTypeSelector::new(0.5, 0.4, 0.3, 0.2, 0.1),
+                GaussianOrderGenerator::new(1000.0, 10.0, 1000.0, 10.0),
+                ChaCha8Rng::seed_from_u64(3),
+                None,
+            )
+        };
+        let mut merged = MergedSource::new();
+        merged
+            .add_source(SourceFunction::from_source(source()), 0..100)
+            .unwrap();
+        let simulation = |source| {
+            let (_, user_order_cons) = HeapRb::<Order>::new(SIM_HEAP_CAPACITY).split();
+            let mut sim = DynamicSimulator::new(
+                source,
+                NullFeeds {},
+                user_order_cons,
+                LatencyConfig {
+                    latency: 1_000,
+                    jitter: SimJitter::None,
+                },
+                ChaCha8Rng::seed_from_u64(3),
+                false,
+            );
+            sim.add_agent(Box::new(MarketMakerAgent::new(1000, 2, 5, 50, 100_000)));
+            sim.run_until(50_000_000).unwrap();
+            sim
+        };
+        for source in [
+            SourceFunction::from_source(source()),
+            SourceFunction::from_source(merged),
+        ] {
+            let mut sim = simulation(source);
+            let (_, user_order_cons) = HeapRb::<Order>::new(SIM_HEAP_CAPACITY).split();
+            let mut fork = sim.try_fork(NullFeeds {}, user_order_cons).unwrap();
+            for _ in 0..5_000 {
+                assert_eq!(sim.step(), fork.step());
+            }
+        }
+        // A plain function cannot be copied
+        let mut time = 0;
+        let sim = simulation(SourceFunction::new(Box::new(move || {
+            time += 1_000_000;
+            Some(Order::new(
+                0,
+                0,
+                OrderSide::Bid,
+                time,
+                OrderType::Market { qty: 1 },
+            ))
+        })));
+        let (_, user_order_cons) = HeapRb::<Order>::new(SIM_HEAP_CAPACITY).split();
+        assert!(sim.try_fork(NullFeeds {}, user_order_cons).is_err());
+    }
+
+    #[test]
+    fn forked_simulation_matches_original() {
+        let mut sim = agent_simulation(3);
+        sim.run_until(50_000_000).unwrap();
+        let (_, user_order_cons) = HeapRb::<Order>::new(SIM_HEAP_CAPACITY).split();
+        let mut fork = sim.fork(NullFeeds {}, user_order_cons);
+        for _ in 0..5_000 {
+            assert_eq!(sim.step(), fork.step());
+        }
+    }
+
+    #[test]
+    fn branches_share_source_orders() {
+        let mut sim = agent_simulation(3);
+        sim.run_until(50_000_000).unwrap();
+        let (_, user_order_cons) = HeapRb::<Order>::new(SIM_HEAP_CAPACITY).split();
+        let mut branch = sim.fork(NullFeeds {}, user_order_cons);
+        branch.schedule(
+            branch.time(),
+            SimEvent::Order(Order::new(
+                99,
+                0,
+                OrderSide::Bid,
+                0,
+                OrderType::Market { qty: 500 },
+            )),
+        );
+        let steps = |sim: &mut Simulator<ConstantPoissonSource, NullFeeds, ChaCha8Rng>| {
+            (0..5_000).map(|_| sim.step().unwrap()).collect::<Vec<_>>()
+        };
+        let source_orders = |orders: &[Order]| {
+            orders
+                .iter()
+                .filter(|order| order.client_id == 0)
+                .map(|order| (order.timestamp, order.side))
+                .collect::<Vec<_>>()
+        };
+        let (original, branched) = (steps(&mut sim), steps(&mut branch));
+        assert_ne!(original, branched);
+        let (original, branched) = (source_orders(&original), source_orders(&branched));
+        let len = original.len().min(branched.len());
+        assert!(len > 500);
+        assert_eq!(original[..len], branched[..len]);
+    }
+
+    /// Counts how the book answered cancels
+    #[derive(Default)]
+    struct CancelOutcomes {
+        canceled: u64,
+        rejected: u64,
+    }
+    impl EventSink for CancelOutcomes {
+        fn push_event(&mut self, _event: MarketEvent) {}
+        fn push_client_event(&mut self, event: ClientEvent) {
+            match event.kind {
+                ClientEventType::Canceled => self.canceled += 1,
+                ClientEventType::Rejected => self.rejected += 1,
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn cancels_target_live_orders() {
+        let source = || {
+            RandomSource::new(
+                ConstantPoissonRate::new(10_000.0),
+                UniformTypeSelector::new(0.5, 0.6, 0.05, 0.25, 0.1),
+                GaussianOrderGenerator::new(995.0, 5.0, 1005.0, 5.0),
+                ChaCha8Rng::seed_from_u64(5),
+                None,
+            )
+        };
+        let rejected_share = |source: SourceFunction| {
+            let (_, user_order_cons) = HeapRb::<Order>::new(SIM_HEAP_CAPACITY).split();
+            let mut sim = Simulator::new(
+                source,
+                CancelOutcomes::default(),
+                user_order_cons,
+                LatencyConfig {
+                    latency: 0,
+                    jitter: SimJitter::None,
+                },
+                ChaCha8Rng::seed_from_u64(5),
+                false,
+            );
+            for _ in 0..20_000 {
+                sim.step().unwrap();
+            }
+            let outcomes = sim.event_sink_mut();
+            outcomes.rejected as f64 / (outcomes.canceled + outcomes.rejected) as f64
+        };
+        // A plain function never hears back from the book, so its cancels pick ids blindly
+        let mut blind = source();
+        let blind = rejected_share(SourceFunction::new(Box::new(move || blind.next_event())));
+        let tracked = rejected_share(SourceFunction::from_source(source()));
+        assert!(blind > 0.5, "{blind}");
+        assert!(tracked < 0.05, "{tracked}");
+    }
 }
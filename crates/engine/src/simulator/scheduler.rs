@@ -0,0 +1,168 @@
+use crate::simulator::SimTime;
+use mm_core::lob_core::{
+    market_events::SystemEventCode,
+    market_orders::{Order, OrderSide},
+};
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+/// Trading phase of the simulated market. Orders are matched as they arrive during continuous trading. During the
+/// auctions orders are collected without matching and uncrossed when the auction ends. In every other phase orders
+/// that would add or take liquidity are rejected, cancels are still accepted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MarketPhase {
+    Continuous,
+    Halted,
+    PreOpen,
+    OpeningAuction,
+    ClosingAuction,
+    PostClose,
+    Closed,
+}
+impl MarketPhase {
+    pub fn is_auction(&self) -> bool {
+        matches!(
+            self,
+            MarketPhase::OpeningAuction | MarketPhase::ClosingAuction
+        )
+    }
+}
+
+/// Typed event that can be scheduled on the Simulator's clock
+#[derive(Debug, Clone, PartialEq)]
+pub enum SimEvent {
+    /// Transition of the market into a new trading phase
+    Phase(MarketPhase),
+    /// Session event published on the market data feed
+    System(SystemEventCode),
+    /// Expiry of a resting order, the held order identifies the order to cancel
+    Expiry(Order),
+    /// A batch of orders injected into the book at once
+    Shock(Vec<Order>),
+    /// Cancellation of a random fraction of the resting orders, optionally restricted to one side of the book
+    Withdraw {
+        fraction: f64,
+        side: Option<OrderSide>,
+    },
+    /// Wake-up of the agent registered at the held index
+    Wakeup(usize),
+    /// An order arriving at the book
+    Order(Order),
+}
+impl SimEvent {
+    /// Rank used to break ties between events scheduled at the same time, lower ranks run first. Phase changes
+    /// apply before anything else at that instant, and orders run after every timer that could react to them
+    fn priority(&self) -> u8 {
+        match self {
+            SimEvent::Phase(_) | SimEvent::System(_) => 0,
+            SimEvent::Expiry(_) => 1,
+            SimEvent::Shock(_) | SimEvent::Withdraw { .. } => 2,
+            SimEvent::Wakeup(_) => 3,
+            SimEvent::Order(_) => 4,
+        }
+    }
+}
+
+/// Entry in the scheduler's queue. The sequence number is unique and increases with every scheduled event, so entries
+/// are totally ordered by (time, priority, seq) and the run is deterministic regardless of heap internals
+#[derive(Debug, Clone)]
+pub struct ScheduledEvent {
+    pub time: SimTime,
+    pub seq: u64,
+    pub event: SimEvent,
+    priority: u8,
+}
+impl PartialEq for ScheduledEvent {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+impl Eq for ScheduledEvent {}
+impl PartialOrd for ScheduledEvent {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+impl Ord for ScheduledEvent {
+    /// Reversed so that the max-heap pops the earliest event first
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .time
+            .cmp(&self.time)
+            .then_with(|| other.priority.cmp(&self.priority))
+            .then_with(|| other.seq.cmp(&self.seq))
+    }
+}
+
+/// Discrete-event scheduler, a priority queue of typed events ordered by time with deterministic tie-breaking
+#[derive(Debug, Clone, Default)]
+pub struct Scheduler {
+    queue: BinaryHeap<ScheduledEvent>,
+    seq: u64,
+}
+impl Scheduler {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            queue: BinaryHeap::with_capacity(capacity),
+            seq: 0,
+        }
+    }
+    /// Schedules an event at a given time, returning its sequence number
+    pub fn schedule(&mut self, time: SimTime, event: SimEvent) -> u64 {
+        let seq = self.seq;
+        self.seq += 1;
+        self.queue.push(ScheduledEvent {
+            time,
+            seq,
+            priority: event.priority(),
+            event,
+        });
+        seq
+    }
+    /// Time of the earliest scheduled event
+    pub fn peek_time(&self) -> Option<SimTime> {
+        self.queue.peek().map(|entry| entry.time)
+    }
+    /// Removes and returns the earliest scheduled event
+    pub fn pop(&mut self) -> Option<ScheduledEvent> {
+        self.queue.pop()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pops_in_time_order() {
+        let mut scheduler = Scheduler::default();
+        scheduler.schedule(30, SimEvent::Wakeup(0));
+        scheduler.schedule(10, SimEvent::Wakeup(1));
+        scheduler.schedule(20, SimEvent::Wakeup(2));
+        let times: Vec<SimTime> = std::iter::from_fn(|| scheduler.pop())
+            .map(|e| e.time)
+            .collect();
+        assert_eq!(times, vec![10, 20, 30]);
+    }
+
+    #[test]
+    fn ties_break_on_priority_then_insertion() {
+        let mut scheduler = Scheduler::default();
+        scheduler.schedule(5, SimEvent::Order(Order::default()));
+        scheduler.schedule(5, SimEvent::Wakeup(1));
+        scheduler.schedule(5, SimEvent::Wakeup(0));
+        scheduler.schedule(5, SimEvent::Phase(MarketPhase::Halted));
+        let events: Vec<SimEvent> = std::iter::from_fn(|| scheduler.pop())
+            .map(|e| e.event)
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                SimEvent::Phase(MarketPhase::Halted),
+                SimEvent::Wakeup(1),
+                SimEvent::Wakeup(0),
+                SimEvent::Order(Order::default()),
+            ]
+        );
+    }
+}
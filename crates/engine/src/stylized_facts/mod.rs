@@ -0,0 +1,374 @@
+use crate::data_generator::event_source::{EventSource, FileReplaySource};
+use crate::limit_order_book::OrderBook;
+use crate::simulator::{SimTime, Simulator};
+use mm_core::lob_core::market_events::{ClientEvent, EventSink, MarketEvent, MarketEventType};
+use mm_core::lob_core::market_orders::OrderSide;
+use rand::Rng;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Number of price levels per side in the depth profile
+pub const DEPTH_LEVELS: usize = 10;
+
+/// EventSink that keeps the sign of every trade, +1 for buyer-initiated trades
+#[derive(Debug, Default)]
+pub struct FactsFeed {
+    signs: Vec<f64>,
+}
+impl EventSink for FactsFeed {
+    fn push_event(&mut self, event: MarketEvent) {
+        if let MarketEventType::Trade(trade) = event.kind {
+            self.signs.push(match trade.aggressor_side {
+                OrderSide::Bid => 1.0,
+                OrderSide::Ask => -1.0,
+            });
+        }
+    }
+    fn push_client_event(&mut self, _event: ClientEvent) {}
+}
+
+/// Book state left by the last order
+#[derive(Debug)]
+struct BookSnapshot {
+    /// Last uncrossed mid, held while the book is one sided or crossed
+    mid: Option<f64>,
+    spread: Option<f64>,
+    bid_depth: [f64; DEPTH_LEVELS],
+    ask_depth: [f64; DEPTH_LEVELS],
+}
+
+/// Samples the book on a fixed sim time grid. Each grid point sees the book as the last order before it left it, so
+/// skipped points repeat the state and returns stay evenly spaced
+#[derive(Debug)]
+struct BookSampler {
+    interval: SimTime,
+    next_sample: SimTime,
+    current: BookSnapshot,
+    mids: Vec<f64>,
+    spreads: Vec<f64>,
+    bid_depth: [f64; DEPTH_LEVELS],
+    ask_depth: [f64; DEPTH_LEVELS],
+    samples: u64,
+}
+impl BookSampler {
+    fn new(interval: SimTime) -> Self {
+        Self {
+            interval: interval.max(1),
+            next_sample: interval.max(1),
+            current: BookSnapshot {
+                mid: None,
+                spread: None,
+                bid_depth: [0.0; DEPTH_LEVELS],
+                ask_depth: [0.0; DEPTH_LEVELS],
+            },
+            mids: Vec::new(),
+            spreads: Vec::new(),
+            bid_depth: [0.0; DEPTH_LEVELS],
+            ask_depth: [0.0; DEPTH_LEVELS],
+            samples: 0,
+        }
+    }
+    /// Samples every grid point up to `time` from the current snapshot, call before the order at `time` is applied
+    fn advance(&mut self, time: SimTime) {
+        while self.next_sample <= time {
+            self.next_sample += self.interval;
+            self.samples += 1;
+            for level in 0..DEPTH_LEVELS {
+                self.bid_depth[level] += self.current.bid_depth[level];
+                self.ask_depth[level] += self.current.ask_depth[level];
+            }
+            self.spreads.extend(self.current.spread);
+            self.mids.extend(self.current.mid);
+        }
+    }
+    /// Takes the state of the book after an order
+    fn snapshot<T: EventSink>(&mut self, book: &OrderBook<T>) {
+        self.current.bid_depth = [0.0; DEPTH_LEVELS];
+        self.current.ask_depth = [0.0; DEPTH_LEVELS];
+        for (level, (_, qty)) in book.bid_levels().take(DEPTH_LEVELS).enumerate() {
+            self.current.bid_depth[level] = qty as f64;
+        }
+        for (level, (_, qty)) in book.ask_levels().take(DEPTH_LEVELS).enumerate() {
+            self.current.ask_depth[level] = qty as f64;
+        }
+        // NOTE: The book can be momentarily crossed by a lazily cancelled level, the last mid is held meanwhile
+        self.current.spread = None;
+        if let (Some(bid), Some(ask)) = book.bbo()
+            && ask > bid
+        {
+            self.current.spread = Some((ask - bid) as f64);
+            self.current.mid = Some((ask + bid) as f64 / 2.0);
+        }
+    }
+}
+
+/// Stylized facts of a market: the shape of the distribution of mid price returns and their autocorrelations, the
+/// spread and the average depth at each level, and the autocorrelation of trade signs. Returns are log returns of the
+/// mid between consecutive points of a fixed sim time grid, autocorrelations are given for lags 1 to `lags`
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct StylizedFacts {
+    pub samples: u64,
+    pub trades: u64,
+    pub returns: u64,
+    pub return_std: f64,
+    /// Excess kurtosis of returns, 0 for gaussian returns and positive for fat tails
+    pub return_kurtosis: f64,
+    /// Autocorrelation of returns, close to 0 in real markets
+    pub return_acf: Vec<f64>,
+    /// Autocorrelation of absolute returns, positive and slowly decaying when volatility clusters
+    pub abs_return_acf: Vec<f64>,
+    /// Autocorrelation of trade signs, positive and slowly decaying when order flow is persistent
+    pub sign_acf: Vec<f64>,
+    pub spread_mean: f64,
+    pub spread_median: f64,
+    pub spread_p90: f64,
+    /// Mean resting quantity at each bid level, best first, counting a missing level as empty
+    pub bid_depth: Vec<f64>,
+    pub ask_depth: Vec<f64>,
+}
+impl StylizedFacts {
+    fn compute(sampler: BookSampler, signs: &[f64], lags: usize) -> Self {
+        let returns: Vec<f64> = sampler
+            .mids
+            .windows(2)
+            .map(|m| (m[1] / m[0]).ln())
+            .collect();
+        let abs_returns: Vec<f64> = returns.iter().map(|r| r.abs()).collect();
+        let mut spreads = sampler.spreads;
+        spreads.sort_by(f64::total_cmp);
+        let samples = sampler.samples.max(1) as f64;
+        let (mean, variance) = moments(&returns);
+        let kurtosis = match variance > 0.0 {
+            true => {
+                returns.iter().map(|r| (r - mean).powi(4)).sum::<f64>()
+                    / returns.len() as f64
+                    / (variance * variance)
+                    - 3.0
+            }
+            false => 0.0,
+        };
+        Self {
+            samples: sampler.samples,
+            trades: signs.len() as u64,
+            returns: returns.len() as u64,
+            return_std: variance.sqrt(),
+            return_kurtosis: kurtosis,
+            return_acf: (1..=lags)
+                .map(|lag| autocorrelation(&returns, lag))
+                .collect(),
+            abs_return_acf: (1..=lags)
+                .map(|lag| autocorrelation(&abs_returns, lag))
+                .collect(),
+            sign_acf: (1..=lags).map(|lag| autocorrelation(signs, lag)).collect(),
+            spread_mean: moments(&spreads).0,
+            spread_median: quantile(&spreads, 0.5),
+            spread_p90: quantile(&spreads, 0.9),
+            bid_depth: sampler.bid_depth.iter().map(|qty| qty / samples).collect(),
+            ask_depth: sampler.ask_depth.iter().map(|qty| qty / samples).collect(),
+        }
+    }
+    /// Named facts in report order
+    pub fn metrics(&self) -> Vec<(String, f64)> {
+        let mut metrics: Vec<(String, f64)> = vec![
+            ("samples".into(), self.samples as f64),
+            ("trades".into(), self.trades as f64),
+            ("returns".into(), self.returns as f64),
+            ("return_std".into(), self.return_std),
+            ("return_kurtosis".into(), self.return_kurtosis),
+        ];
+        let series = [
+            ("return_acf", &self.return_acf),
+            ("abs_return_acf", &self.abs_return_acf),
+            ("sign_acf", &self.sign_acf),
+        ];
+        for (name, values) in series {
+            for (lag, value) in values.iter().enumerate() {
+                metrics.push((format!("{name}_{}", lag + 1), *value));
+            }
+        }
+        metrics.push(("spread_mean".into(), self.spread_mean));
+        metrics.push(("spread_median".into(), self.spread_median));
+        metrics.push(("spread_p90".into(), self.spread_p90));
+        for (name, values) in [
+            ("bid_depth", &self.bid_depth),
+            ("ask_depth", &self.ask_depth),
+        ] {
+            for (level, value) in values.iter().enumerate() {
+                metrics.push((format!("{name}_{}", level + 1), *value));
+            }
+        }
+        metrics
+    }
+    /// Writes the facts as `metric,value` rows, so reports of different configurations can be joined on the metric
+    pub fn write_csv(&self, path: &str) -> io::Result<()> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        writeln!(writer, "metric,value")?;
+        for (name, value) in self.metrics() {
+            writeln!(writer, "{name},{value}")?;
+        }
+        writer.flush()
+    }
+}
+
+/// Mean and population variance
+fn moments(values: &[f64]) -> (f64, f64) {
+    if values.is_empty() {
+        return (0.0, 0.0);
+    }
+    let n = values.len() as f64;
+    let mean = values.iter().sum::<f64>() / n;
+    (
+        mean,
+        values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n,
+    )
+}
+
+/// Sample autocorrelation at `lag`, 0 when the series is too short or constant
+fn autocorrelation(values: &[f64], lag: usize) -> f64 {
+    if values.len() <= lag {
+        return 0.0;
+    }
+    let (mean, variance) = moments(values);
+    if variance == 0.0 {
+        return 0.0;
+    }
+    let covariance = values
+        .iter()
+        .zip(&values[lag..])
+        .map(|(a, b)| (a - mean) * (b - mean))
+        .sum::<f64>()
+        / values.len() as f64;
+    covariance / variance
+}
+
+/// Quantile of sorted values by the nearest rank, 0 for no values
+fn quantile(sorted: &[f64], q: f64) -> f64 {
+    match sorted.len() {
+        0 => 0.0,
+        n => sorted[((q * n as f64).ceil() as usize).clamp(1, n) - 1],
+    }
+}
+
+/// Steps a simulation until its source is exhausted, sampling the book every `interval` nanoseconds of sim time, and
+/// returns the stylized facts of the run
+pub fn collect_facts<E: EventSource, R: Rng>(
+    sim: &mut Simulator<E, FactsFeed, R>,
+    interval: SimTime,
+    lags: usize,
+) -> StylizedFacts {
+    let mut sampler = BookSampler::new(interval);
+    while sim.step().is_ok() {
+        // The step already applied the order, but the snapshot still holds the book from before it
+        sampler.advance(sim.time());
+        sampler.snapshot(sim.order_book());
+    }
+    StylizedFacts::compute(sampler, &sim.event_sink_mut().signs, lags)
+}
+
+/// Replays a binary recording through a fresh order book and returns its stylized facts. Orders are processed as
+/// recorded, so rejections that depended on the market phase of the original run are not reproduced
+pub fn facts_from_recording(
+    path: &str,
+    interval: SimTime,
+    lags: usize,
+) -> Result<StylizedFacts, String> {
+    let mut source = FileReplaySource::new(path, 512).map_err(|e| format!("facts: {path}: {e}"))?;
+    let mut book = OrderBook::new(FactsFeed::default());
+    let mut sampler = BookSampler::new(interval);
+    while let Some(order) = source.next_event() {
+        sampler.advance(order.timestamp);
+        book.process_order(order);
+        sampler.snapshot(&book);
+    }
+    Ok(StylizedFacts::compute(
+        sampler,
+        &book.event_sink_mut().signs,
+        lags,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        data_generator::{
+            event_source::ConstantPoissonSource, order_generators::GaussianOrderGenerator,
+            rate_controllers::ConstantPoissonRate, type_selectors::UniformTypeSelector,
+        },
+        simulator::latency_config::{LatencyConfig, SimJitter},
+    };
+    use mm_core::lob_core::market_orders::{Order, OrderType};
+    use rand::SeedableRng;
+    use rand_chacha::ChaCha8Rng;
+    use ringbuf::{HeapRb, traits::*};
+
+    #[test]
+    fn statistics_match_known_series() {
+        let alternating: Vec<f64> = (0..1000)
+            .map(|i| if i % 2 == 0 { 1.0 } else { -1.0 })
+            .collect();
+        assert!((autocorrelation(&alternating, 1) + 1.0).abs() < 0.01);
+        assert!((autocorrelation(&alternating, 2) - 1.0).abs() < 0.01);
+        assert_eq!(autocorrelation(&[1.0, 1.0, 1.0], 1), 0.0);
+        let sorted: Vec<f64> = (1..=10).map(f64::from).collect();
+        assert_eq!(quantile(&sorted, 0.5), 5.0);
+        assert_eq!(quantile(&sorted, 0.9), 9.0);
+    }
+
+    #[test]
+    fn grid_points_see_the_book_before_the_next_order() {
+        let mut book = OrderBook::new(FactsFeed::default());
+        let mut sampler = BookSampler::new(10);
+        let orders = [
+            (1, OrderSide::Bid, 100),
+            (2, OrderSide::Ask, 102),
+            (35, OrderSide::Bid, 101),
+        ];
+        for (time, side, price) in orders {
+            sampler.advance(time);
+            book.process_order(Order::new(
+                0,
+                0,
+                side,
+                time,
+                OrderType::Limit { qty: 1, price },
+            ));
+            sampler.snapshot(&book);
+        }
+        // Points 10, 20 and 30 all precede the bid at 35
+        assert_eq!(sampler.samples, 3);
+        assert_eq!(sampler.mids, [101.0; 3]);
+        assert_eq!(sampler.spreads, [2.0; 3]);
+        assert_eq!(sampler.bid_depth[0], 3.0);
+    }
+
+    #[test]
+    fn facts_of_a_simulation() {
+        let source = ConstantPoissonSource::new(
+            ConstantPoissonRate::new(100_000.0),
+            UniformTypeSelector::new(0.5, 0.5, 0.05, 0.4, 0.05),
+            GaussianOrderGenerator::new(1000.0, 20.0, 1000.0, 20.0),
+            ChaCha8Rng::seed_from_u64(0),
+            Some(200_000),
+        );
+        let (_, user_orders) = HeapRb::<Order>::new(1).split();
+        let mut sim = Simulator::new(
+            source,
+            FactsFeed::default(),
+            user_orders,
+            LatencyConfig {
+                latency: 0,
+                jitter: SimJitter::None,
+            },
+            ChaCha8Rng::seed_from_u64(0),
+            false,
+        );
+        let facts = collect_facts(&mut sim, 10_000_000, 5);
+        assert!(facts.samples > 150 && facts.returns > 20, "{facts:?}");
+        assert!(facts.trades > 0 && facts.spread_mean > 0.0);
+        assert_eq!(facts.sign_acf.len(), 5);
+        assert!(facts.bid_depth[0] > 0.0 && facts.ask_depth[0] > 0.0);
+        // 5 leading counts and std, 3 series of 5 lags, 3 spread facts and 2 depth profiles
+        assert_eq!(facts.metrics().len(), 5 + 15 + 3 + 2 * DEPTH_LEVELS);
+    }
+}
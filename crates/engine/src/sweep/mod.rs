@@ -0,0 +1,194 @@
+use crate::batch::{RunStats, Summary};
+use serde_json::{Map, Value};
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+
+/// Values taken by one swept parameter
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParamRange {
+    pub name: String,
+    pub values: Vec<f64>,
+}
+impl ParamRange {
+    /// Parses `name=start:stop:step`, an inclusive range, or `name=v1,v2,...`, an explicit list of values
+    pub fn parse(spec: &str) -> Result<Self, String> {
+        let (name, values) = spec
+            .split_once('=')
+            .ok_or(format!("sweep: `{}` is not of the form name=values", spec))?;
+        let name = name.trim();
+        if name.is_empty() {
+            return Err(format!("sweep: `{}` has no parameter name", spec));
+        }
+        let parse = |s: &str| {
+            s.trim()
+                .parse::<f64>()
+                .map_err(|_| format!("sweep: `{}` is not a number", s))
+        };
+        let values = match values.split(':').collect::<Vec<_>>()[..] {
+            [start, stop, step] => {
+                let (start, stop, step) = (parse(start)?, parse(stop)?, parse(step)?);
+                if !(start.is_finite() && stop.is_finite() && step.is_finite())
+                    || step <= 0.0
+                    || start > stop
+                {
+                    return Err(format!(
+                        "sweep: `{}` needs a positive step and start <= stop",
+                        name
+                    ));
+                }
+                // NOTE: Values are computed from the index rather than accumulated, and a last value within rounding
+                // of the stop value is replaced by it, so the stop value is neither dropped nor duplicated
+                let steps = (stop - start) / step;
+                let (count, exact) = match steps.round() {
+                    rounded if (steps - rounded).abs() <= 1e-9 * rounded.max(1.0) => {
+                        (rounded, true)
+                    }
+                    _ => (steps.floor(), false),
+                };
+                let mut values: Vec<f64> = (0..=count as usize)
+                    .map(|i| start + i as f64 * step)
+                    .collect();
+                if exact {
+                    *values.last_mut().unwrap() = stop;
+                }
+                values
+            }
+            [list] => list.split(',').map(parse).collect::<Result<Vec<_>, _>>()?,
+            _ => return Err(format!("sweep: `{}` is not a valid range", spec)),
+        };
+        Ok(Self {
+            name: name.to_string(),
+            values,
+        })
+    }
+}
+
+/// Cartesian product of all the ranges, the first range varies slowest. Every configuration lists the parameters in
+/// the same order as the ranges
+pub fn grid(ranges: &[ParamRange]) -> Vec<Vec<(String, f64)>> {
+    let mut configs: Vec<Vec<(String, f64)>> = vec![Vec::new()];
+    for range in ranges {
+        configs = configs
+            .into_iter()
+            .flat_map(|config| {
+                range.values.iter().map(move |value| {
+                    let mut config = config.clone();
+                    config.push((range.name.clone(), *value));
+                    config
+                })
+            })
+            .collect();
+    }
+    configs
+}
+
+/// Runs of a single configuration of a sweep
+#[derive(Debug, Clone)]
+pub struct SweepResult {
+    pub params: Vec<(String, f64)>,
+    pub runs: Vec<RunStats>,
+}
+impl SweepResult {
+    /// Flat row of the parameter values, the number of runs, and the mean and 95% confidence interval of every run
+    /// statistic
+    fn row(&self) -> Vec<(String, f64)> {
+        let mut row = self.params.clone();
+        row.push(("runs".to_string(), self.runs.len() as f64));
+        let Some(first) = self.runs.first() else {
+            return row;
+        };
+        for (column, (name, _)) in first.metrics().iter().enumerate() {
+            let samples: Vec<f64> = self
+                .runs
+                .iter()
+                .map(|run| run.metrics()[column].1)
+                .collect();
+            let summary = Summary::from_samples(&samples);
+            row.push((format!("{}_mean", name), summary.mean));
+            row.push((format!("{}_ci95_low", name), summary.ci_low));
+            row.push((format!("{}_ci95_high", name), summary.ci_high));
+        }
+        row
+    }
+}
+
+/// Writes one row per configuration
+pub fn write_sweep_csv(path: &str, results: &[SweepResult]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let rows: Vec<_> = results.iter().map(SweepResult::row).collect();
+    if let Some(first) = rows.first() {
+        let header: Vec<&str> = first.iter().map(|(name, _)| name.as_str()).collect();
+        writeln!(writer, "{}", header.join(","))?;
+    }
+    for row in rows {
+        let values: Vec<String> = row.iter().map(|(_, value)| value.to_string()).collect();
+        writeln!(writer, "{}", values.join(","))?;
+    }
+    writer.flush()
+}
+
+/// Writes a JSON array with one flat object per configuration, using the same keys as the CSV columns
+pub fn write_sweep_json(path: &str, results: &[SweepResult]) -> io::Result<()> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    let rows: Vec<Value> = results
+        .iter()
+        .map(|result| {
+            Value::Object(
+                result
+                    .row()
+                    .into_iter()
+                    .map(|(name, value)| (name, Value::from(value)))
+                    .collect::<Map<_, _>>(),
+            )
+        })
+        .collect();
+    serde_json::to_writer_pretty(&mut writer, &rows)?;
+    writeln!(writer)?;
+    writer.flush()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_parse() {
+        let range = ParamRange::parse("order_rate=1000:3000:500").unwrap();
+        assert_eq!(range.name, "order_rate");
+        assert_eq!(range.values, vec![1000.0, 1500.0, 2000.0, 2500.0, 3000.0]);
+        let range = ParamRange::parse("cancel_rate=0.1:0.3:0.1").unwrap();
+        assert_eq!(range.values.len(), 3);
+        assert_eq!(range.values[2], 0.3);
+        let range = ParamRange::parse("cancel_rate=0:1:0.1").unwrap();
+        assert_eq!(range.values.len(), 11);
+        assert_eq!(range.values[3], 3.0 * 0.1);
+        assert_eq!(range.values[10], 1.0);
+        let range = ParamRange::parse("order_rate=0:10:4").unwrap();
+        assert_eq!(range.values, vec![0.0, 4.0, 8.0]);
+        let range = ParamRange::parse("sim_latency=0,500,2000").unwrap();
+        assert_eq!(range.values, vec![0.0, 500.0, 2000.0]);
+        assert!(ParamRange::parse("order_rate").is_err());
+        assert!(ParamRange::parse("order_rate=3:1:1").is_err());
+        assert!(ParamRange::parse("order_rate=1:3:0").is_err());
+        assert!(ParamRange::parse("order_rate=1:x:1").is_err());
+        assert!(ParamRange::parse("order_rate=0:inf:1").is_err());
+    }
+
+    #[test]
+    fn grid_is_cartesian_product() {
+        let configs = grid(&[
+            ParamRange::parse("a=1,2").unwrap(),
+            ParamRange::parse("b=10,20,30").unwrap(),
+        ]);
+        assert_eq!(configs.len(), 6);
+        assert_eq!(
+            configs[0],
+            vec![("a".to_string(), 1.0), ("b".to_string(), 10.0)]
+        );
+        assert_eq!(
+            configs[5],
+            vec![("a".to_string(), 2.0), ("b".to_string(), 30.0)]
+        );
+        assert_eq!(grid(&[]), vec![Vec::new()]);
+    }
+}
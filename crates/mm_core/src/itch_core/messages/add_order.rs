@@ -1,4 +1,4 @@
-use crate::itch_core::helpers::encode_u48;
+use crate::itch_core::helpers::{decode_u48, encode_u48};
 use crate::itch_core::messages::ITCH_MESSAGE_TYPE_ADD_ORDER;
 
 /// An Add Order Message indicates that a new order has been accepted by the Nasdaq system and was added to the displayable book.
@@ -6,9 +6,25 @@ use crate::itch_core::messages::ITCH_MESSAGE_TYPE_ADD_ORDER;
 /// The message includes a day-unique Order Reference Number used by Nasdaq to track the order.
 /// Nasdaq supports two variations of the Add Order message format.
 /// This message is generated for unattributed orders accepted by the Nasdaq system.
-#[repr(C, packed)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct AddOrder {}
+pub struct AddOrder {
+    /// Locate code identifying the security
+    pub stock_locate: u16,
+    /// Nasdaq internal tracking number
+    pub tracking_number: u16,
+    /// Nanoseconds since midnight
+    pub timestamp: u64,
+    /// The unique reference number assigned to the new order at the time of receipt
+    pub order_reference_number: u64,
+    /// The type of order being added: "B" = Buy Order, "S" = Sell Order
+    pub buy_sell_indicator: u8,
+    /// The total number of shares associated with the order being added to the book
+    pub shares: u32,
+    /// Stock symbol, right padded with spaces
+    pub stock: [u8; 8],
+    /// The display price of the new order
+    pub price: u32,
+}
 
 impl AddOrder {
     /// Encodes an AddOrder message directly into a provided byte buffer.
@@ -45,4 +61,22 @@ impl AddOrder {
         buf[24..32].copy_from_slice(&stock);
         buf[32..36].copy_from_slice(&price.to_be_bytes());
     }
+
+    /// Decodes an AddOrder message, None if `buf` is shorter than 36 bytes or holds another message type
+    #[inline(always)]
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < 36 || buf[0] != ITCH_MESSAGE_TYPE_ADD_ORDER {
+            return None;
+        }
+        Some(Self {
+            stock_locate: u16::from_be_bytes(buf[1..3].try_into().ok()?),
+            tracking_number: u16::from_be_bytes(buf[3..5].try_into().ok()?),
+            timestamp: decode_u48(buf[5..11].try_into().ok()?),
+            order_reference_number: u64::from_be_bytes(buf[11..19].try_into().ok()?),
+            buy_sell_indicator: buf[19],
+            shares: u32::from_be_bytes(buf[20..24].try_into().ok()?),
+            stock: buf[24..32].try_into().ok()?,
+            price: u32::from_be_bytes(buf[32..36].try_into().ok()?),
+        })
+    }
 }
@@ -0,0 +1,108 @@
+use crate::itch_core::helpers::{decode_u48, encode_u48};
+use crate::itch_core::messages::ITCH_MESSAGE_TYPE_ADD_ORDER_MPID;
+
+/// An Add Order with MPID Attribution Message indicates that a new attributed order has been accepted by the Nasdaq system and was added to the displayable book.
+///
+/// This message is generated for orders whose market participant chose to display its MPID.
+/// It is laid out like the Add Order Message followed by the attribution.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AddOrderMPID {
+    /// Locate code identifying the security
+    pub stock_locate: u16,
+    /// Nasdaq internal tracking number
+    pub tracking_number: u16,
+    /// Nanoseconds since midnight
+    pub timestamp: u64,
+    /// The unique reference number assigned to the new order at the time of receipt
+    pub order_reference_number: u64,
+    /// The type of order being added: "B" = Buy Order, "S" = Sell Order
+    pub buy_sell_indicator: u8,
+    /// The total number of shares associated with the order being added to the book
+    pub shares: u32,
+    /// Stock symbol, right padded with spaces
+    pub stock: [u8; 8],
+    /// The display price of the new order
+    pub price: u32,
+    /// Nasdaq market participant identifier associated with the entered order
+    pub attribution: [u8; 4],
+}
+
+impl AddOrderMPID {
+    /// Encodes an AddOrderMPID message directly into a provided byte buffer.
+    ///
+    /// # Arguments
+    /// * `buf` - The destination byte slice (must be at least 40 bytes)
+    /// * `stock_locate` - Locate code identifying the security
+    /// * `tracking_number` - Nasdaq internal tracking number
+    /// * `timestamp` - Nanoseconds since midnight
+    /// * `order_reference_number` - The unique reference number assigned to the new order at the time of receipt
+    /// * `buy_sell_indicator` - The type of order being added: "B" = Buy Order, "S" = Sell Order
+    /// * `shares` - The total number of shares associated with the order being added to the book
+    /// * `stock` - Stock symbol, right padded with spaces
+    /// * `price` - The display price of the new order
+    /// * `attribution` - Nasdaq market participant identifier associated with the entered order
+    #[inline(always)]
+    #[allow(clippy::too_many_arguments)]
+    pub fn encode_into(
+        buf: &mut [u8],
+        stock_locate: u16,
+        tracking_number: u16,
+        timestamp: u64,
+        order_reference_number: u64,
+        buy_sell_indicator: u8,
+        shares: u32,
+        stock: [u8; 8],
+        price: u32,
+        attribution: [u8; 4],
+    ) {
+        buf[0] = ITCH_MESSAGE_TYPE_ADD_ORDER_MPID;
+        buf[1..3].copy_from_slice(&stock_locate.to_be_bytes());
+        buf[3..5].copy_from_slice(&tracking_number.to_be_bytes());
+        buf[5..11].copy_from_slice(&encode_u48(timestamp));
+        buf[11..19].copy_from_slice(&order_reference_number.to_be_bytes());
+        buf[19] = buy_sell_indicator;
+        buf[20..24].copy_from_slice(&shares.to_be_bytes());
+        buf[24..32].copy_from_slice(&stock);
+        buf[32..36].copy_from_slice(&price.to_be_bytes());
+        buf[36..40].copy_from_slice(&attribution);
+    }
+
+    /// Decodes an AddOrderMPID message, None if `buf` is shorter than 40 bytes or holds another message type
+    #[inline(always)]
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < 40 || buf[0] != ITCH_MESSAGE_TYPE_ADD_ORDER_MPID {
+            return None;
+        }
+        Some(Self {
+            stock_locate: u16::from_be_bytes(buf[1..3].try_into().ok()?),
+            tracking_number: u16::from_be_bytes(buf[3..5].try_into().ok()?),
+            timestamp: decode_u48(buf[5..11].try_into().ok()?),
+            order_reference_number: u64::from_be_bytes(buf[11..19].try_into().ok()?),
+            buy_sell_indicator: buf[19],
+            shares: u32::from_be_bytes(buf[20..24].try_into().ok()?),
+            stock: buf[24..32].try_into().ok()?,
+            price: u32::from_be_bytes(buf[32..36].try_into().ok()?),
+            attribution: buf[36..40].try_into().ok()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::itch_core::messages::add_order::AddOrder;
+
+    #[test]
+    fn decode_reverses_encode() {
+        let mut buf = [0u8; 40];
+        AddOrderMPID::encode_into(&mut buf, 1, 2, 3, 4, b'S', 5, *b"AAPL    ", 6, *b"NSDQ");
+        let add = AddOrderMPID::decode(&buf).unwrap();
+        assert_eq!(
+            (add.order_reference_number, add.shares, add.price),
+            (4, 5, 6)
+        );
+        assert_eq!((add.stock, add.attribution), (*b"AAPL    ", *b"NSDQ"));
+        assert_eq!(AddOrderMPID::decode(&buf[..39]), None);
+        assert_eq!(AddOrder::decode(&buf), None);
+    }
+}
@@ -1,28 +1,35 @@
 use crate::itch_core::messages::{
-    add_order::AddOrder, order_cancel::OrderCancel, order_delete::OrderDelete,
-    order_executed::OrderExecuted, order_executed_with_price::OrderExecutedWithPrice,
-    order_replace::OrderReplace,
+    add_order::AddOrder, add_order_mpid::AddOrderMPID, order_cancel::OrderCancel,
+    order_delete::OrderDelete, order_executed::OrderExecuted,
+    order_executed_with_price::OrderExecutedWithPrice, order_replace::OrderReplace,
+    system_event::SystemEvent,
 };
 
 pub mod add_order;
+pub mod add_order_mpid;
 pub mod order_cancel;
 pub mod order_delete;
 pub mod order_executed;
 pub mod order_executed_with_price;
 pub mod order_replace;
+pub mod system_event;
 
 pub const ITCH_MESSAGE_TYPE_ADD_ORDER: u8 = b'A';
+pub const ITCH_MESSAGE_TYPE_ADD_ORDER_MPID: u8 = b'F';
 pub const ITCH_MESSAGE_TYPE_ORDER_CANCEL: u8 = b'X';
 pub const ITCH_MESSAGE_TYPE_ORDER_DELETE: u8 = b'D';
 pub const ITCH_MESSAGE_TYPE_ORDER_EXECUTED_WITH_PRICE: u8 = b'C';
 pub const ITCH_MESSAGE_TYPE_ORDER_EXECUTED: u8 = b'E';
 pub const ITCH_MESSAGE_TYPE_ORDER_REPLACE: u8 = b'U';
+pub const ITCH_MESSAGE_TYPE_SYSTEM_EVENT: u8 = b'S';
 
 pub enum ItchEvent {
     AddOrder(AddOrder),
+    AddOrderMPID(AddOrderMPID),
     OrderCancel(OrderCancel),
     OrderDelete(OrderDelete),
     OrderExecuted(OrderExecuted),
     OrderExecutedWithPrice(OrderExecutedWithPrice),
     OrderReplace(OrderReplace),
+    SystemEvent(SystemEvent),
 }
@@ -1,10 +1,20 @@
-use crate::itch_core::helpers::encode_u48;
+use crate::itch_core::helpers::{decode_u48, encode_u48};
 use crate::itch_core::messages::ITCH_MESSAGE_TYPE_ORDER_CANCEL;
 
 /// This message is sent whenever an order on the book is modified as a result of a partial cancellation.
-#[repr(C, packed)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct OrderCancel {}
+pub struct OrderCancel {
+    /// Locate code identifying the security
+    pub stock_locate: u16,
+    /// Nasdaq internal tracking number
+    pub tracking_number: u16,
+    /// Nanoseconds since midnight
+    pub timestamp: u64,
+    /// The reference number of the order being canceled
+    pub order_reference_number: u64,
+    /// The number of shares being removed from the display size of the order as a result of a cancellation
+    pub canceled_shares: u32,
+}
 
 impl OrderCancel {
     /// Encodes an OrderCancel message directly into a provided byte buffer.
@@ -32,4 +42,19 @@ impl OrderCancel {
         buf[11..19].copy_from_slice(&order_reference_number.to_be_bytes());
         buf[19..23].copy_from_slice(&canceled_shares.to_be_bytes());
     }
+
+    /// Decodes an OrderCancel message, None if `buf` is shorter than 23 bytes or holds another message type
+    #[inline(always)]
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < 23 || buf[0] != ITCH_MESSAGE_TYPE_ORDER_CANCEL {
+            return None;
+        }
+        Some(Self {
+            stock_locate: u16::from_be_bytes(buf[1..3].try_into().ok()?),
+            tracking_number: u16::from_be_bytes(buf[3..5].try_into().ok()?),
+            timestamp: decode_u48(buf[5..11].try_into().ok()?),
+            order_reference_number: u64::from_be_bytes(buf[11..19].try_into().ok()?),
+            canceled_shares: u32::from_be_bytes(buf[19..23].try_into().ok()?),
+        })
+    }
 }
@@ -1,12 +1,20 @@
-use crate::itch_core::helpers::encode_u48;
+use crate::itch_core::helpers::{decode_u48, encode_u48};
 use crate::itch_core::messages::ITCH_MESSAGE_TYPE_ORDER_DELETE;
 
 /// This message is sent whenever an order on the book is being cancelled.
 ///
 /// All remaining shares are no longer accessible, so the order must be removed from the book.
-#[repr(C, packed)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct OrderDelete {}
+pub struct OrderDelete {
+    /// Locate code identifying the security
+    pub stock_locate: u16,
+    /// Nasdaq internal tracking number
+    pub tracking_number: u16,
+    /// Nanoseconds since midnight
+    pub timestamp: u64,
+    /// The reference number of the order being canceled
+    pub order_reference_number: u64,
+}
 
 impl OrderDelete {
     /// Encodes an OrderDelete message directly into a provided byte buffer.
@@ -31,4 +39,18 @@ impl OrderDelete {
         buf[5..11].copy_from_slice(&encode_u48(timestamp));
         buf[11..19].copy_from_slice(&order_reference_number.to_be_bytes());
     }
+
+    /// Decodes an OrderDelete message, None if `buf` is shorter than 19 bytes or holds another message type
+    #[inline(always)]
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < 19 || buf[0] != ITCH_MESSAGE_TYPE_ORDER_DELETE {
+            return None;
+        }
+        Some(Self {
+            stock_locate: u16::from_be_bytes(buf[1..3].try_into().ok()?),
+            tracking_number: u16::from_be_bytes(buf[3..5].try_into().ok()?),
+            timestamp: decode_u48(buf[5..11].try_into().ok()?),
+            order_reference_number: u64::from_be_bytes(buf[11..19].try_into().ok()?),
+        })
+    }
 }
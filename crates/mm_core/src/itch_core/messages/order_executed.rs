@@ -1,4 +1,4 @@
-use crate::itch_core::helpers::encode_u48;
+use crate::itch_core::helpers::{decode_u48, encode_u48};
 use crate::itch_core::messages::ITCH_MESSAGE_TYPE_ORDER_EXECUTED;
 
 /// This message is sent whenever an order on the book is executed in whole or in part.
@@ -7,9 +7,21 @@ use crate::itch_core::messages::ITCH_MESSAGE_TYPE_ORDER_EXECUTED;
 /// Multiple Order Executed Messages on the same order are cumulative.
 /// By combining the executions from both types of Order Executed Messages and the Trade Message, it is possible to build a complete view of all non-cross executions that happen on Nasdaq.
 /// Cross execution information is available in one bulk print per symbol via the Cross Trade Message.
-#[repr(C, packed)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct OrderExecuted {}
+pub struct OrderExecuted {
+    /// Locate code identifying the security
+    pub stock_locate: u16,
+    /// Nasdaq internal tracking number
+    pub tracking_number: u16,
+    /// Nanoseconds since midnight
+    pub timestamp: u64,
+    /// The unique reference number assigned to the new order at the time of receipt
+    pub order_reference_number: u64,
+    /// The number of shares executed
+    pub executed_shares: u32,
+    /// The Nasdaq generated day unique Match Number of this execution
+    pub match_number: u64,
+}
 
 impl OrderExecuted {
     /// Encodes an OrderExecuted message directly into a provided byte buffer.
@@ -40,4 +52,20 @@ impl OrderExecuted {
         buf[19..23].copy_from_slice(&executed_shares.to_be_bytes());
         buf[23..31].copy_from_slice(&match_number.to_be_bytes());
     }
+
+    /// Decodes an OrderExecuted message, None if `buf` is shorter than 31 bytes or holds another message type
+    #[inline(always)]
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < 31 || buf[0] != ITCH_MESSAGE_TYPE_ORDER_EXECUTED {
+            return None;
+        }
+        Some(Self {
+            stock_locate: u16::from_be_bytes(buf[1..3].try_into().ok()?),
+            tracking_number: u16::from_be_bytes(buf[3..5].try_into().ok()?),
+            timestamp: decode_u48(buf[5..11].try_into().ok()?),
+            order_reference_number: u64::from_be_bytes(buf[11..19].try_into().ok()?),
+            executed_shares: u32::from_be_bytes(buf[19..23].try_into().ok()?),
+            match_number: u64::from_be_bytes(buf[23..31].try_into().ok()?),
+        })
+    }
 }
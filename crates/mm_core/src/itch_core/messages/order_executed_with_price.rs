@@ -1,4 +1,4 @@
-use crate::itch_core::helpers::encode_u48;
+use crate::itch_core::helpers::{decode_u48, encode_u48};
 use crate::itch_core::messages::ITCH_MESSAGE_TYPE_ORDER_EXECUTED_WITH_PRICE;
 
 /// This message is sent whenever an order on the book is executed in whole or in part at a price different from the initial display price.
@@ -9,9 +9,25 @@ use crate::itch_core::messages::ITCH_MESSAGE_TYPE_ORDER_EXECUTED_WITH_PRICE;
 /// Executions may be marked as non-printable.
 /// If the execution is marked as non-printed, it means the shares will be included into a later bulk print (e.g., in the case of cross executions).
 /// If a firm is looking to use the data in time-and-sales displays or volume calculations, Nasdaq recommends that firms ignore messages marked as non-printable to prevent double counting.
-#[repr(C, packed)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct OrderExecutedWithPrice {}
+pub struct OrderExecutedWithPrice {
+    /// Locate code identifying the security
+    pub stock_locate: u16,
+    /// Nasdaq internal tracking number
+    pub tracking_number: u16,
+    /// Nanoseconds since midnight
+    pub timestamp: u64,
+    /// The unique reference number assigned to the new order at the time of receipt
+    pub order_reference_number: u64,
+    /// The number of shares executed
+    pub executed_shares: u32,
+    /// The Nasdaq generated day unique Match Number of this execution
+    pub match_number: u64,
+    /// Indicates if the execution should be reflected on time and sales displays: "N" = Non-Printable, "Y" = Printable
+    pub printable: u8,
+    /// The Price at which the order execution occurred
+    pub execution_price: u32,
+}
 
 impl OrderExecutedWithPrice {
     /// Encodes an OrderExecutedWithPrice message directly into a provided byte buffer.
@@ -48,4 +64,22 @@ impl OrderExecutedWithPrice {
         buf[31] = printable;
         buf[32..36].copy_from_slice(&execution_price.to_be_bytes());
     }
+
+    /// Decodes an OrderExecutedWithPrice message, None if `buf` is shorter than 36 bytes or holds another message type
+    #[inline(always)]
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < 36 || buf[0] != ITCH_MESSAGE_TYPE_ORDER_EXECUTED_WITH_PRICE {
+            return None;
+        }
+        Some(Self {
+            stock_locate: u16::from_be_bytes(buf[1..3].try_into().ok()?),
+            tracking_number: u16::from_be_bytes(buf[3..5].try_into().ok()?),
+            timestamp: decode_u48(buf[5..11].try_into().ok()?),
+            order_reference_number: u64::from_be_bytes(buf[11..19].try_into().ok()?),
+            executed_shares: u32::from_be_bytes(buf[19..23].try_into().ok()?),
+            match_number: u64::from_be_bytes(buf[23..31].try_into().ok()?),
+            printable: buf[31],
+            execution_price: u32::from_be_bytes(buf[32..36].try_into().ok()?),
+        })
+    }
 }
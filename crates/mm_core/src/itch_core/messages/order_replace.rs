@@ -1,4 +1,4 @@
-use crate::itch_core::helpers::encode_u48;
+use crate::itch_core::helpers::{decode_u48, encode_u48};
 use crate::itch_core::messages::ITCH_MESSAGE_TYPE_ORDER_REPLACE;
 
 /// This message is sent whenever an order on the book has been cancel-replaced.
@@ -7,9 +7,23 @@ use crate::itch_core::messages::ITCH_MESSAGE_TYPE_ORDER_REPLACE;
 /// New order details are provided for the replacement, along with a new order reference number which will be used henceforth.
 /// Since the side, stock symbol, and attribution (if any) cannot be changed by an Order Replace event, these fields are not included in the message.
 /// Firms should retain the side, stock symbol, and MPID from the original Add Order message.
-#[repr(C, packed)]
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
-pub struct OrderReplace {}
+pub struct OrderReplace {
+    /// Locate code identifying the security
+    pub stock_locate: u16,
+    /// Nasdaq internal tracking number
+    pub tracking_number: u16,
+    /// Nanoseconds since midnight
+    pub timestamp: u64,
+    /// The original order reference number of the order being replaced
+    pub original_order_reference_number: u64,
+    /// The new reference number for this order at time of replacement
+    pub new_order_reference_number: u64,
+    /// The new total displayed quantity
+    pub shares: u32,
+    /// The new display price for the order
+    pub price: u32,
+}
 
 impl OrderReplace {
     /// Encodes an OrderReplace message directly into a provided byte buffer.
@@ -43,4 +57,21 @@ impl OrderReplace {
         buf[27..31].copy_from_slice(&shares.to_be_bytes());
         buf[31..35].copy_from_slice(&price.to_be_bytes());
     }
+
+    /// Decodes an OrderReplace message, None if `buf` is shorter than 35 bytes or holds another message type
+    #[inline(always)]
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < 35 || buf[0] != ITCH_MESSAGE_TYPE_ORDER_REPLACE {
+            return None;
+        }
+        Some(Self {
+            stock_locate: u16::from_be_bytes(buf[1..3].try_into().ok()?),
+            tracking_number: u16::from_be_bytes(buf[3..5].try_into().ok()?),
+            timestamp: decode_u48(buf[5..11].try_into().ok()?),
+            original_order_reference_number: u64::from_be_bytes(buf[11..19].try_into().ok()?),
+            new_order_reference_number: u64::from_be_bytes(buf[19..27].try_into().ok()?),
+            shares: u32::from_be_bytes(buf[27..31].try_into().ok()?),
+            price: u32::from_be_bytes(buf[31..35].try_into().ok()?),
+        })
+    }
 }
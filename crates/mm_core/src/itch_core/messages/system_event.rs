@@ -0,0 +1,57 @@
+use crate::itch_core::helpers::{decode_u48, encode_u48};
+use crate::itch_core::messages::ITCH_MESSAGE_TYPE_SYSTEM_EVENT;
+
+/// This message is used to signal a market or data feed handler event.
+///
+/// Event codes: `O` start of messages, `S` start of system hours, `Q` start of market hours, `M` end of market hours,
+/// `E` end of system hours and `C` end of messages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SystemEvent {
+    /// Always 0 for system events
+    pub stock_locate: u16,
+    /// Nasdaq internal tracking number
+    pub tracking_number: u16,
+    /// Nanoseconds since midnight
+    pub timestamp: u64,
+    /// The type of the system event
+    pub event_code: u8,
+}
+
+impl SystemEvent {
+    /// Encodes a SystemEvent message directly into a provided byte buffer.
+    ///
+    /// # Arguments
+    /// * `buf` - The destination byte slice (must be at least 12 bytes)
+    /// * `stock_locate` - Always 0 for system events
+    /// * `tracking_number` - Nasdaq internal tracking number
+    /// * `timestamp` - Nanoseconds since midnight
+    /// * `event_code` - The type of the system event
+    #[inline(always)]
+    pub fn encode_into(
+        buf: &mut [u8],
+        stock_locate: u16,
+        tracking_number: u16,
+        timestamp: u64,
+        event_code: u8,
+    ) {
+        buf[0] = ITCH_MESSAGE_TYPE_SYSTEM_EVENT;
+        buf[1..3].copy_from_slice(&stock_locate.to_be_bytes());
+        buf[3..5].copy_from_slice(&tracking_number.to_be_bytes());
+        buf[5..11].copy_from_slice(&encode_u48(timestamp));
+        buf[11] = event_code;
+    }
+
+    /// Decodes a SystemEvent message, None if `buf` is shorter than 12 bytes or holds another message type
+    #[inline(always)]
+    pub fn decode(buf: &[u8]) -> Option<Self> {
+        if buf.len() < 12 || buf[0] != ITCH_MESSAGE_TYPE_SYSTEM_EVENT {
+            return None;
+        }
+        Some(Self {
+            stock_locate: u16::from_be_bytes(buf[1..3].try_into().ok()?),
+            tracking_number: u16::from_be_bytes(buf[3..5].try_into().ok()?),
+            timestamp: decode_u48(buf[5..11].try_into().ok()?),
+            event_code: buf[11],
+        })
+    }
+}
@@ -137,11 +137,39 @@ impl MarketEvent {
     }
 }
 
+/// Code of a session-wide system event, the values are the ITCH System Event codes
+#[repr(u8)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum SystemEventCode {
+    StartOfMessages = b'O',
+    StartOfSystemHours = b'S',
+    StartOfMarketHours = b'Q',
+    EndOfMarketHours = b'M',
+    EndOfSystemHours = b'E',
+    EndOfMessages = b'C',
+}
+impl TryFrom<u8> for SystemEventCode {
+    type Error = ();
+    fn try_from(b: u8) -> Result<Self, Self::Error> {
+        match b {
+            b'O' => Ok(SystemEventCode::StartOfMessages),
+            b'S' => Ok(SystemEventCode::StartOfSystemHours),
+            b'Q' => Ok(SystemEventCode::StartOfMarketHours),
+            b'M' => Ok(SystemEventCode::EndOfMarketHours),
+            b'E' => Ok(SystemEventCode::EndOfSystemHours),
+            b'C' => Ok(SystemEventCode::EndOfMessages),
+            _ => Err(()),
+        }
+    }
+}
+
 /// Enum containing type specific information about a MarketEvent
 #[derive(Copy, Clone, Debug)]
 pub enum MarketEventType {
     L3(L3Event),
     Trade(TradeEvent),
+    /// Change of the trading session, not tied to any order
+    System(SystemEventCode),
 }
 
 /// Impl for structs defining a way for a OrderBook to emit events
@@ -177,6 +205,8 @@ impl EventSink for SeparateEventFeeds {
         match event.kind {
             MarketEventType::L3(event) => while self.l3_events.try_push(event).is_err() {},
             MarketEventType::Trade(event) => while self.trade_events.try_push(event).is_err() {},
+            // NOTE: There is no queue for session events, consumers of separate feeds only track the book
+            MarketEventType::System(_) => {}
         }
     }
     fn push_client_event(&mut self, event: ClientEvent) {
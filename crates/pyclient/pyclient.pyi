@@ -243,6 +243,11 @@ class PyMarketEventType:
     @staticmethod
     def trade(price: builtins.int, quantity: builtins.int, aggressor_side: PyOrderSide, maker_id: builtins.int) -> PyMarketEventType: ...
     def is_trade(self) -> builtins.bool: ...
+    def is_system_event(self) -> builtins.bool: ...
+    def system_event_code(self) -> typing.Optional[builtins.str]:
+        r"""
+        ITCH code of a system event, one of O, S, Q, M, E or C
+        """
     def trade_price(self) -> typing.Optional[builtins.int]: ...
     def trade_quantity(self) -> typing.Optional[builtins.int]: ...
 
@@ -312,6 +312,18 @@ mod pyclient {
             matches!(self.inner, MarketEventType::Trade(_))
         }
 
+        fn is_system_event(&self) -> bool {
+            matches!(self.inner, MarketEventType::System(_))
+        }
+
+        /// ITCH code of a system event, one of O, S, Q, M, E or C
+        fn system_event_code(&self) -> Option<char> {
+            match self.inner {
+                MarketEventType::System(code) => Some(code as u8 as char),
+                _ => None,
+            }
+        }
+
         fn trade_price(&self) -> Option<Price> {
             match self.inner {
                 MarketEventType::Trade(trade) => Some(trade.price),
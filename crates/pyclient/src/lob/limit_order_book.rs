@@ -38,6 +38,9 @@ impl OrderBook {
                 }
             }
             MarketEventType::Trade(e) => self.handle_trade(e),
+            MarketEventType::System(_) => {
+                // Session events carry no book state
+            }
         }
     }
 
@@ -4,10 +4,13 @@ use mm_core::{
         messages::{
             ITCH_MESSAGE_TYPE_ADD_ORDER, ITCH_MESSAGE_TYPE_ORDER_CANCEL,
             ITCH_MESSAGE_TYPE_ORDER_EXECUTED_WITH_PRICE, ITCH_MESSAGE_TYPE_ORDER_REPLACE,
+            ITCH_MESSAGE_TYPE_SYSTEM_EVENT,
         },
     },
     lob_core::{
-        market_events::{L3Event, L3EventExtra, MarketEvent, MarketEventType, TradeEvent},
+        market_events::{
+            L3Event, L3EventExtra, MarketEvent, MarketEventType, SystemEventCode, TradeEvent,
+        },
         market_orders::{OrderSide, OrderType},
     },
 };
@@ -300,6 +303,21 @@ impl ReceiverHandler {
                     }),
                 })
             }
+            ITCH_MESSAGE_TYPE_SYSTEM_EVENT => {
+                if message_data.len() < 12 {
+                    return None;
+                }
+
+                let id = u16::from_be_bytes(message_data[3..5].try_into().ok()?);
+                let timestamp = decode_u48(message_data[5..11].try_into().ok()?);
+                let code = SystemEventCode::try_from(message_data[11]).ok()?;
+
+                Some(MarketEvent {
+                    id,
+                    timestamp,
+                    kind: MarketEventType::System(code),
+                })
+            }
             _ => None,
         }
     }
@@ -316,6 +334,7 @@ mod tests {
     use mm_core::{
         itch_core::messages::{
             add_order::AddOrder, order_executed_with_price::OrderExecutedWithPrice,
+            system_event::SystemEvent,
         },
         lob_core::market_orders::OrderType,
     };
@@ -381,6 +400,21 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_event_system_event() {
+        let mut buf = [0u8; 12];
+        SystemEvent::encode_into(&mut buf, 0, 7, 34_200_000_000_000, b'Q');
+        let event = ReceiverHandler::parse_event(&buf).expect("err");
+
+        assert_eq!(event.timestamp, 34_200_000_000_000);
+        match event.kind {
+            MarketEventType::System(code) => assert_eq!(code, SystemEventCode::StartOfMarketHours),
+            _ => panic!("wrong event"),
+        }
+        buf[11] = b'Z';
+        assert!(ReceiverHandler::parse_event(&buf).is_none());
+    }
+
     #[test]
     fn test_handle_packet_ignores_short_packet() {
         let (mut h, mut rx) = make_handler();